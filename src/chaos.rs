@@ -0,0 +1,99 @@
+//! # `chaos`
+//!
+//! Random network faults injected by the server mockers, reproducible from a seed.
+
+use std::time::Duration;
+
+use crate::rng::Rng;
+
+/// Random faults applied to the data sent to the client, set in [`UdpMocker::chaos`](crate::UdpMocker::chaos)
+/// or [`TcpMocker::chaos`](crate::TcpMocker::chaos).
+///
+/// The TCP server mocker only applies [`ChaosConfig::fragment_max_size`], the UDP server mocker all the other faults.
+///
+/// All the random decisions are taken from the given `seed`: running the same exchange with the same seed
+/// injects the same faults. When a fault is enabled, the seed is logged when the [`ServerMocker`](crate::ServerMocker) starts,
+/// and written in its panic messages and in the [`ErrorReport`](crate::ErrorReport)s, so that a failure found
+/// under chaos testing can be replayed.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use socket_server_mocker::{ChaosConfig, ServerMocker, UdpMocker};
+///
+/// let server = ServerMocker::new_with_opts(UdpMocker::default().chaos(ChaosConfig {
+///     drop_probability: 0.1,
+///     duplicate_probability: 0.05,
+///     max_delay: Duration::from_millis(20),
+///     ..ChaosConfig::with_seed(42)
+/// }))
+/// .unwrap();
+/// assert_eq!(Some(42), server.options().chaos.replay_seed());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosConfig {
+    /// Seed of the random generator, random by default
+    pub seed: u64,
+    /// Probability, between 0 and 1, that a datagram sent to the client is silently dropped
+    pub drop_probability: f32,
+    /// Drop the datagrams received from the client with the same probability as well
+    pub drop_inbound: bool,
+    /// Probability, between 0 and 1, that a datagram sent to the client is sent twice
+    pub duplicate_probability: f32,
+    /// Probability, between 0 and 1, that a datagram sent to the client is held back
+    /// and sent after the next ones, to test client reordering
+    pub reorder_probability: f32,
+    /// Number of datagrams sent before a held back datagram. Held back datagrams are sent anyway
+    /// before the server waits for the client, or when the exchange ends.
+    pub reorder_window: usize,
+    /// Probability, between 0 and 1, that a single bit of a datagram sent to the client is flipped
+    pub corrupt_probability: f32,
+    /// Maximum random delay applied before sending each datagram
+    pub max_delay: Duration,
+    /// Split every TCP message sent to the client into writes of 1 to this number of bytes, with a short pause between them,
+    /// to test clients expecting a whole message from a single read
+    pub fragment_max_size: Option<usize>,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self::with_seed(Rng::from_random_seed().next_u64())
+    }
+}
+
+impl ChaosConfig {
+    /// No fault, with the given seed for the faults enabled afterwards
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            seed,
+            drop_probability: 0.0,
+            drop_inbound: false,
+            duplicate_probability: 0.0,
+            reorder_probability: 0.0,
+            reorder_window: 1,
+            corrupt_probability: 0.0,
+            max_delay: Duration::ZERO,
+            fragment_max_size: None,
+        }
+    }
+
+    /// Check if any fault is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.drop_probability > 0.0
+            || self.duplicate_probability > 0.0
+            || (self.reorder_probability > 0.0 && self.reorder_window > 0)
+            || self.corrupt_probability > 0.0
+            || !self.max_delay.is_zero()
+            || self.fragment_max_size.is_some()
+    }
+
+    /// Seed to replay the injected faults, if any fault is enabled
+    pub fn replay_seed(&self) -> Option<u64> {
+        self.is_enabled().then_some(self.seed)
+    }
+
+    /// Random generator taking the fault decisions
+    pub(crate) fn rng(&self) -> Rng {
+        Rng::new(self.seed)
+    }
+}
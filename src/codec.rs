@@ -0,0 +1,61 @@
+//! # `codec`
+//!
+//! Typed messages, encoded to and decoded from the bytes exchanged with the clients.
+
+/// Encode the responses sent to the clients and decode the requests they send.
+///
+/// Given to [`ServerMocker::new_with_codec`](crate::ServerMocker::new_with_codec), so that responses are written
+/// with [`ServerMocker::send_typed`](crate::ServerMocker::send_typed) and requests are popped with
+/// [`ServerMocker::pop_received_typed`](crate::ServerMocker::pop_received_typed),
+/// keeping the binary encoding out of the tests.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::Codec;
+///
+/// /// Numbers written as 4 bytes big endian integers
+/// struct NumberCodec;
+///
+/// impl Codec for NumberCodec {
+///     type Request = u32;
+///     type Response = u32;
+///
+///     fn encode(&self, response: &u32) -> Vec<u8> {
+///         response.to_be_bytes().to_vec()
+///     }
+///
+///     fn decode(&self, data: &[u8]) -> Result<u32, String> {
+///         let bytes = data.try_into().map_err(|_| format!("expected 4 bytes, got {}", data.len()))?;
+///         Ok(u32::from_be_bytes(bytes))
+///     }
+/// }
+/// ```
+pub trait Codec {
+    /// Messages received from the clients
+    type Request;
+    /// Messages sent to the clients
+    type Response;
+
+    /// Bytes sent to the client for the given response
+    fn encode(&self, response: &Self::Response) -> Vec<u8>;
+
+    /// Request received from the client as the given bytes, or a description of why they can't be decoded
+    fn decode(&self, data: &[u8]) -> Result<Self::Request, String>;
+}
+
+/// Default codec, keeping the messages as raw bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RawCodec;
+
+impl Codec for RawCodec {
+    type Request = Vec<u8>;
+    type Response = Vec<u8>;
+
+    fn encode(&self, response: &Vec<u8>) -> Vec<u8> {
+        response.clone()
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(data.to_vec())
+    }
+}
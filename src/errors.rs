@@ -6,11 +6,14 @@
 //!
 //! If so, errors can be retrieved with [`ServerMocker::pop_server_error`](crate::ServerMocker::pop_server_error) method.
 
+use std::fmt;
 use std::io;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::mpsc::SendError;
 
-use crate::Instruction;
+use crate::hex::preview;
+use crate::{Instruction, Times};
 
 /// Represents an error raised by a server mocker.
 ///
@@ -21,24 +24,78 @@ use crate::Instruction;
 pub enum ServerMockerError {
     #[error("{}: Failed to bind TCP listener to {0}: {1}", self.fatal_str())]
     UnableToBindListener(SocketAddr, io::Error),
+    #[error("{}: Failed to resolve the address to listen on: {0}", self.fatal_str())]
+    UnableToResolveAddress(io::Error),
     #[error("{}: Failed to get local address of a listener: {0}", self.fatal_str())]
     UnableToGetLocalAddress(io::Error),
     #[error("{}: Failed to accept incoming connection on {0}: {1}", self.fatal_str())]
     UnableToAcceptConnection(SocketAddr, io::Error),
+    #[error("{}: Failed to connect to upstream server {0}: {1}", self.fatal_str())]
+    UnableToConnectToUpstream(SocketAddr, io::Error),
     #[error("{}: Failed to send instructions list to TCP server mocker: {0}", self.fatal_str())]
     UnableToSendInstructions(SendError<Vec<Instruction>>),
     #[error("{}: Failed to set read timeout on TCP stream: {0}", self.fatal_str())]
     UnableToSetReadTimeout(io::Error),
+    #[error("{}: Server mocker stopped: {0}", self.fatal_str())]
+    ServerStopped(String),
+    #[error("{}: Failed to spawn the server thread: {0}", self.fatal_str())]
+    UnableToSpawnThread(io::Error),
+    #[error("{}: Server thread panicked: {0}", self.fatal_str())]
+    ServerThreadPanicked(String),
     #[error("{}: Failed to read from TCP stream: {0}", self.fatal_str())]
     UnableToReadTcpStream(io::Error),
+    #[error("{}: Read interrupted by {0} after receiving {} bytes{}", self.fatal_str(), .1.len(), data_preview(.1))]
+    ReadInterrupted(ReadInterruption, Vec<u8>),
+    #[error("{}: Client {} the connection", self.fatal_str(), if *.graceful { "closed" } else { "reset" })]
+    ClientDisconnected { graceful: bool },
     #[error("{}: Failed to write to TCP stream: {0}", self.fatal_str())]
     UnableToWriteTcpStream(io::Error),
+    #[error("{}: Failed to set {0} on TCP stream: {1}", self.fatal_str())]
+    UnableToSetSocketOption(&'static str, io::Error),
     #[error("{}: Failed to receive message from client: {0}", self.fatal_str())]
     UnableToReadUdpStream(io::Error),
     #[error("{}: SendMessage instruction received before a ReceiveMessage", self.fatal_str())]
     GotSendMessageBeforeReceiveMessage,
     #[error("{}: Failed to send message to client: {0}", self.fatal_str())]
     FailedToSendUdpMessage(io::Error),
+    #[error("{}: {0} instruction isn't supported by this server mocker", self.fatal_str())]
+    UnsupportedInstruction(&'static str),
+    #[error("{}: Repeated instructions were expected to run {0}, but ran {1} times", self.fatal_str())]
+    UnexpectedRepeatCount(Times, usize),
+    #[error("{}: Last received message doesn't match the template pattern {0:?}", self.fatal_str())]
+    UnmatchedTemplatePattern(String),
+    #[error("{}: Received {} bytes of unexpected data from client{}", self.fatal_str(), .0.len(), data_preview(.0))]
+    UnexpectedData(Vec<u8>),
+    #[error("{}: Received messages don't match the expected sequence:\n{0}", self.fatal_str())]
+    MessageSequenceMismatch(String),
+    #[error("{}: Failed to read instruction script {0:?}: {1}", self.fatal_str())]
+    UnableToReadScript(PathBuf, io::Error),
+    #[error("{}: Invalid instruction script {0:?}: {1}", self.fatal_str())]
+    InvalidScript(PathBuf, String),
+    #[error("{}: Failed to write file {0:?}: {1}", self.fatal_str())]
+    UnableToWriteFile(PathBuf, io::Error),
+    #[error("{}: Failed to read file {0:?}: {1}", self.fatal_str())]
+    UnableToReadFile(PathBuf, io::Error),
+    #[error("{}: Invalid pcap capture {0:?}: {1}", self.fatal_str())]
+    InvalidPcap(PathBuf, String),
+    #[error("{}: Invalid HAR file {0:?}: {1}", self.fatal_str())]
+    InvalidHar(PathBuf, String),
+    #[error("{}: Invalid stub mapping {0:?}: {1}", self.fatal_str())]
+    InvalidStubMapping(PathBuf, String),
+    #[error("{}: Invalid OpenAPI specification {0:?}: {1}", self.fatal_str())]
+    InvalidOpenApiSpec(PathBuf, String),
+    #[error("{}: No message received before the timeout", self.fatal_str())]
+    NoMessageReceived,
+    #[error("{}: Received message is not the expected JSON: {0}{}", self.fatal_str(), data_preview(.1))]
+    InvalidJsonMessage(String, Vec<u8>),
+    #[error("{}: Failed to decode received message: {0}{}", self.fatal_str(), data_preview(.1))]
+    UnableToDecodeMessage(String, Vec<u8>),
+    #[error("{}: Received message is not a syslog message{}", self.fatal_str(), data_preview(.0))]
+    InvalidSyslogMessage(Vec<u8>),
+    #[error("{}: Failed to generate certificate: {0}", self.fatal_str())]
+    UnableToGenerateCertificate(String),
+    #[error("{}: Failed to configure TLS: {0}", self.fatal_str())]
+    UnableToConfigureTls(String),
 }
 
 impl ServerMockerError {
@@ -46,16 +103,43 @@ impl ServerMockerError {
     pub fn is_fatal(&self) -> bool {
         match self {
             ServerMockerError::UnableToBindListener(_, _)
+            | ServerMockerError::UnableToResolveAddress(_)
             | ServerMockerError::UnableToGetLocalAddress(_)
             | ServerMockerError::UnableToAcceptConnection(_, _)
-            | ServerMockerError::UnableToSetReadTimeout(_) => true,
+            | ServerMockerError::UnableToConnectToUpstream(_, _)
+            | ServerMockerError::UnableToSetReadTimeout(_)
+            | ServerMockerError::UnableToSpawnThread(_)
+            | ServerMockerError::UnableToConfigureTls(_)
+            | ServerMockerError::ServerStopped(_)
+            | ServerMockerError::ServerThreadPanicked(_) => true,
 
             ServerMockerError::UnableToSendInstructions(_)
             | ServerMockerError::UnableToReadTcpStream(_)
+            | ServerMockerError::ReadInterrupted(_, _)
+            | ServerMockerError::ClientDisconnected { .. }
             | ServerMockerError::UnableToWriteTcpStream(_)
+            | ServerMockerError::UnableToSetSocketOption(_, _)
             | ServerMockerError::UnableToReadUdpStream(_)
             | ServerMockerError::GotSendMessageBeforeReceiveMessage
-            | ServerMockerError::FailedToSendUdpMessage(_) => false,
+            | ServerMockerError::FailedToSendUdpMessage(_)
+            | ServerMockerError::UnsupportedInstruction(_)
+            | ServerMockerError::UnexpectedRepeatCount(_, _)
+            | ServerMockerError::UnmatchedTemplatePattern(_)
+            | ServerMockerError::UnexpectedData(_)
+            | ServerMockerError::MessageSequenceMismatch(_)
+            | ServerMockerError::UnableToReadScript(_, _)
+            | ServerMockerError::InvalidScript(_, _)
+            | ServerMockerError::UnableToWriteFile(_, _)
+            | ServerMockerError::UnableToReadFile(_, _)
+            | ServerMockerError::InvalidPcap(_, _)
+            | ServerMockerError::InvalidHar(_, _)
+            | ServerMockerError::InvalidStubMapping(_, _)
+            | ServerMockerError::InvalidOpenApiSpec(_, _)
+            | ServerMockerError::NoMessageReceived
+            | ServerMockerError::InvalidJsonMessage(_, _)
+            | ServerMockerError::UnableToDecodeMessage(_, _)
+            | ServerMockerError::InvalidSyslogMessage(_)
+            | ServerMockerError::UnableToGenerateCertificate(_) => false,
         }
     }
 
@@ -67,3 +151,71 @@ impl ServerMockerError {
         }
     }
 }
+
+/// Error raised by the server thread, with the context in which it was raised,
+/// see [`ServerMocker::pop_server_error_with_context`](crate::ServerMocker::pop_server_error_with_context)
+#[derive(Debug)]
+pub struct ErrorReport {
+    /// The error itself
+    pub error: ServerMockerError,
+    /// Index of the instruction being executed, counting every instruction added to the server mocker,
+    /// `None` if the error was raised outside of any instruction
+    pub instruction_index: Option<usize>,
+    /// Summary of the instruction being executed, e.g. `ReceiveExactBytes(4)`
+    pub instruction: Option<String>,
+    /// Address of the client exchanging with the server mocker, if any
+    pub client_addr: Option<SocketAddr>,
+    /// Seed of the random faults injected by the server, if any, to replay the exchange
+    pub chaos_seed: Option<u64>,
+    /// Bytes received from the clients before the error was raised
+    pub bytes_received: usize,
+    /// Bytes sent to the clients before the error was raised
+    pub bytes_sent: usize,
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (", self.error)?;
+        if let (Some(index), Some(instruction)) = (self.instruction_index, &self.instruction) {
+            write!(f, "instruction #{index} {instruction}, ")?;
+        }
+        if let Some(client_addr) = self.client_addr {
+            write!(f, "client {client_addr}, ")?;
+        }
+        if let Some(seed) = self.chaos_seed {
+            write!(f, "chaos seed {seed}, ")?;
+        }
+        write!(
+            f,
+            "after {} bytes received and {} bytes sent)",
+            self.bytes_received, self.bytes_sent
+        )
+    }
+}
+
+/// Truncated and escaped preview of the data involved in an error, empty if there is no data
+fn data_preview(data: &[u8]) -> String {
+    if data.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", preview(data))
+    }
+}
+
+/// Reason why a message couldn't be received completely, see [`ServerMockerError::ReadInterrupted`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadInterruption {
+    /// The client closed the connection
+    ConnectionClosed,
+    /// Nothing more was received before the read timeout
+    Timeout,
+}
+
+impl fmt::Display for ReadInterruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadInterruption::ConnectionClosed => write!(f, "the client closing the connection"),
+            ReadInterruption::Timeout => write!(f, "a timeout"),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! # `framing`
+//!
+//! Split the data received from the client into protocol messages.
+
+use std::fmt;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+/// Split the data received from the client into messages.
+///
+/// Set in [`TcpMocker::framer`](crate::TcpMocker::framer) or [`UdpMocker::framer`](crate::UdpMocker::framer),
+/// so that a message popped with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message)
+/// is exactly one protocol message, however the client writes were coalesced or split on the way.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::Framer;
+///
+/// /// Messages terminated by a semicolon
+/// struct SemicolonFramer;
+///
+/// impl Framer for SemicolonFramer {
+///     fn split(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+///         let end = buf.iter().position(|&byte| byte == b';')?;
+///         Some(buf.drain(..=end).collect())
+///     }
+/// }
+/// ```
+pub trait Framer: Send {
+    /// Remove the first complete message from the beginning of `buf` and return it.
+    ///
+    /// Return None if `buf` doesn't hold a complete message yet:
+    /// more data is then read from the client and appended to `buf` before calling this method again.
+    fn split(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>>;
+
+    /// Called when the client closed the connection while `buf` still holds data not split into a message.
+    ///
+    /// Return the last message if the remaining data is complete,
+    /// or None (the default) to report it as [`ServerMockerError::ReadInterrupted`](crate::ServerMockerError::ReadInterrupted).
+    fn finish(&mut self, _buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+impl fmt::Debug for dyn Framer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Framer")
+    }
+}
+
+/// Built-in framings, set with [`TcpMocker::framing`](crate::TcpMocker::framing)
+/// or [`UdpMocker::framing`](crate::UdpMocker::framing)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// One message per line, terminated by `\n` or `\r\n` (SMTP, Redis inline commands, FTP...).
+    ///
+    /// The line terminator is kept in the message.
+    Line,
+    /// Binary messages starting with their length, the header being kept in the message.
+    ///
+    /// The whole message is `header_len + length + length_adjustment` bytes long,
+    /// `length` being read from the first `header_len` bytes.
+    ///
+    /// # Example
+    /// ```
+    /// # use socket_server_mocker::{Endianness, Framing};
+    /// // PostgreSQL startup message: the 4 bytes length includes itself
+    /// Framing::LengthPrefixed {
+    ///     header_len: 4,
+    ///     endianness: Endianness::Big,
+    ///     length_adjustment: -4,
+    /// };
+    /// // MySQL packet: 3 bytes length, followed by a sequence id byte not counted in the length
+    /// Framing::LengthPrefixed {
+    ///     header_len: 3,
+    ///     endianness: Endianness::Little,
+    ///     length_adjustment: 1,
+    /// };
+    /// ```
+    LengthPrefixed {
+        /// Size of the length field, at the beginning of the message
+        header_len: usize,
+        /// Byte order of the length field
+        endianness: Endianness,
+        /// Number of bytes to add to the length to get the size of the message after the length field
+        length_adjustment: isize,
+    },
+    /// Syslog messages over TCP, either octet-counted or terminated by `\n` (non-transparent framing), see RFC 6587.
+    ///
+    /// The octet count and the trailer are removed from the message,
+    /// so it can be parsed with [`protocols::syslog::parse`](crate::protocols::syslog::parse).
+    Syslog,
+}
+
+/// Byte order of a length field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    /// Most significant byte first, aka network byte order
+    Big,
+    /// Least significant byte first
+    Little,
+}
+
+impl Framing {
+    /// Create a new framer implementing this framing
+    pub(crate) fn framer(self) -> Arc<Mutex<dyn Framer>> {
+        match self {
+            Framing::Line => Arc::new(Mutex::new(DelimiterFramer(b"\n".to_vec()))),
+            Framing::LengthPrefixed {
+                header_len,
+                endianness,
+                length_adjustment,
+            } => Arc::new(Mutex::new(LengthPrefixedFramer {
+                header_len,
+                endianness,
+                length_adjustment,
+            })),
+            Framing::Syslog => Arc::new(Mutex::new(SyslogFramer)),
+        }
+    }
+}
+
+/// Framer delivering messages terminated by a delimiter, the delimiter being kept in the message
+pub(crate) struct DelimiterFramer(pub(crate) Vec<u8>);
+
+impl Framer for DelimiterFramer {
+    fn split(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        let delimiter = self.0.as_slice();
+        let start = if delimiter.is_empty() {
+            0
+        } else {
+            buf.windows(delimiter.len()).position(|w| w == delimiter)?
+        };
+        Some(buf.drain(..start + delimiter.len()).collect())
+    }
+}
+
+/// Framer implementing [`Framing::LengthPrefixed`]
+struct LengthPrefixedFramer {
+    header_len: usize,
+    endianness: Endianness,
+    length_adjustment: isize,
+}
+
+impl Framer for LengthPrefixedFramer {
+    fn split(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        let header = buf.get(..self.header_len)?;
+        let fold = |length: u64, &byte: &u8| (length << 8) | u64::from(byte);
+        let length = match self.endianness {
+            Endianness::Big => header.iter().fold(0, fold),
+            Endianness::Little => header.iter().rev().fold(0, fold),
+        };
+        // A negative message size is considered as an empty message
+        let message_len =
+            (self.header_len as i128 + i128::from(length) + self.length_adjustment as i128)
+                .max(self.header_len as i128);
+        let message_len = usize::try_from(message_len).unwrap_or(usize::MAX);
+        if buf.len() < message_len {
+            return None;
+        }
+        Some(buf.drain(..message_len).collect())
+    }
+}
+
+/// Framer implementing [`Framing::Syslog`]
+struct SyslogFramer;
+
+impl Framer for SyslogFramer {
+    fn split(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        // Octet-counted messages start with their length, other ones with their priority
+        if buf.first()?.is_ascii_digit() {
+            let space = buf.iter().position(|&byte| byte == b' ')?;
+            let length: usize = std::str::from_utf8(&buf[..space]).ok()?.parse().ok()?;
+            if buf.len() <= space + length {
+                return None;
+            }
+            let message = buf[space + 1..=space + length].to_vec();
+            buf.drain(..=space + length);
+            Some(message)
+        } else {
+            let end = buf.iter().position(|&byte| byte == b'\n')?;
+            let mut message: Vec<u8> = buf.drain(..=end).collect();
+            message.pop();
+            Some(message)
+        }
+    }
+
+    fn finish(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        // The trailer of the last message may be omitted
+        (!buf.is_empty() && !buf[0].is_ascii_digit()).then(|| mem::take(buf))
+    }
+}
+
+/// Framer delivering messages of a fixed size
+pub(crate) struct ExactBytesFramer(pub(crate) usize);
+
+impl Framer for ExactBytesFramer {
+    fn split(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        if buf.len() < self.0 {
+            return None;
+        }
+        Some(buf.drain(..self.0).collect())
+    }
+}
+
+/// Framer never splitting the data, the message ending when the client closes the connection
+pub(crate) struct UntilCloseFramer;
+
+impl Framer for UntilCloseFramer {
+    fn split(&mut self, _buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn finish(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        Some(mem::take(buf))
+    }
+}
@@ -0,0 +1,121 @@
+//! # `har`
+//!
+//! HTTP mocks replaying the entries of a HAR (HTTP Archive) file exported by a browser,
+//! available with the `serde` feature.
+
+use std::fs;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::http::{url_path, HttpMock};
+use crate::ServerMockerError::{self, InvalidHar, UnableToReadFile};
+
+/// Response headers not replayed: the body is replayed decoded and unchunked,
+/// and the connection is kept open for the next requests
+const SKIPPED_HEADERS: [&str; 5] = [
+    "content-length",
+    "content-encoding",
+    "transfer-encoding",
+    "connection",
+    "keep-alive",
+];
+
+#[derive(Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Deserialize)]
+struct HarRequest {
+    method: String,
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct HarResponse {
+    status: u16,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    #[serde(default)]
+    content: HarContent,
+}
+
+#[derive(Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize, Default)]
+struct HarContent {
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    encoding: Option<String>,
+}
+
+/// Read one mock per entry of the given HAR file, in order
+pub(crate) fn load_har(path: &Path) -> Result<Vec<HttpMock>, ServerMockerError> {
+    let content = fs::read_to_string(path).map_err(|e| UnableToReadFile(path.to_owned(), e))?;
+    let har: Har =
+        serde_json::from_str(&content).map_err(|e| InvalidHar(path.to_owned(), e.to_string()))?;
+    let mut mocks: Vec<HttpMock> = har
+        .log
+        .entries
+        .into_iter()
+        .map(|entry| {
+            let text = entry.response.content.text.unwrap_or_default();
+            let body = match entry.response.content.encoding.as_deref() {
+                Some("base64") => STANDARD
+                    .decode(text)
+                    .map_err(|e| InvalidHar(path.to_owned(), e.to_string()))?,
+                _ => text.into_bytes(),
+            };
+            Ok(HttpMock {
+                status: entry.response.status,
+                headers: entry
+                    .response
+                    .headers
+                    .into_iter()
+                    // HTTP/2 pseudo headers can't be replayed in HTTP/1.1
+                    .filter(|header| {
+                        !header.name.starts_with(':')
+                            && !SKIPPED_HEADERS.contains(&header.name.to_ascii_lowercase().as_str())
+                    })
+                    .map(|header| (header.name, header.value))
+                    .collect(),
+                body,
+                ..HttpMock::new(entry.request.method, url_path(&entry.request.url))
+            })
+        })
+        .collect::<Result<_, ServerMockerError>>()?;
+
+    // Successive entries of the same request are served in turn, the last one being repeated
+    let repeated: Vec<bool> = (0..mocks.len())
+        .map(|index| {
+            mocks[index + 1..]
+                .iter()
+                .any(|next| next.method == mocks[index].method && next.path == mocks[index].path)
+        })
+        .collect();
+    for (mock, repeated) in mocks.iter_mut().zip(repeated) {
+        if repeated {
+            mock.max_uses = Some(1);
+        }
+    }
+    Ok(mocks)
+}
@@ -0,0 +1,125 @@
+//! # `hex`
+//!
+//! Helpers to render binary payloads in a human-readable way, used in error and panic messages.
+
+use std::fmt::Write;
+
+/// Number of bytes displayed on each line of a hex dump
+const BYTES_PER_LINE: usize = 16;
+
+/// Render the given data as an `xxd`-style hex dump: offset, hex bytes and printable ASCII characters.
+pub(crate) fn hex_dump(data: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line_index, line) in data.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(dump, "{:08x}: ", line_index * BYTES_PER_LINE);
+        for position in 0..BYTES_PER_LINE {
+            match line.get(position) {
+                Some(byte) => {
+                    let _ = write!(dump, "{byte:02x}");
+                }
+                None => dump.push_str("  "),
+            }
+            if position % 2 == 1 {
+                dump.push(' ');
+            }
+        }
+        dump.push(' ');
+        dump.extend(line.iter().map(|&byte| printable(byte)));
+        dump.push('\n');
+    }
+    dump
+}
+
+/// Printable representation of a byte, `.` if it isn't a visible ASCII character
+fn printable(byte: u8) -> char {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        char::from(byte)
+    } else {
+        '.'
+    }
+}
+
+/// Maximum number of bytes rendered by [`preview`]
+const PREVIEW_MAX_LEN: usize = 64;
+
+/// Render the given data as an escaped byte string literal, truncated if it's too long.
+pub(crate) fn preview(data: &[u8]) -> String {
+    if data.len() <= PREVIEW_MAX_LEN {
+        format!("b\"{}\"", data.escape_ascii())
+    } else {
+        format!(
+            "b\"{}\"... ({} bytes)",
+            data[..PREVIEW_MAX_LEN].escape_ascii(),
+            data.len()
+        )
+    }
+}
+
+/// Number of bytes displayed on each line of a hex diff
+const DIFF_BYTES_PER_LINE: usize = 8;
+
+/// Number of lines displayed before and after the first difference of a hex diff
+const DIFF_CONTEXT_LINES: usize = 1;
+
+/// Render a side-by-side hex diff of the expected and received data around their first difference,
+/// the lines that differ being marked with `!`. Returns `None` if the data are equal.
+pub(crate) fn hex_diff(expected: &[u8], received: &[u8]) -> Option<String> {
+    let first_difference = expected
+        .iter()
+        .zip(received)
+        .position(|(expected_byte, received_byte)| expected_byte != received_byte)
+        .or_else(|| {
+            (expected.len() != received.len()).then(|| expected.len().min(received.len()))
+        })?;
+    let difference_line = first_difference / DIFF_BYTES_PER_LINE;
+    let line_count = expected
+        .len()
+        .max(received.len())
+        .div_ceil(DIFF_BYTES_PER_LINE);
+
+    let mut diff = String::new();
+    let _ = writeln!(
+        diff,
+        "first difference at offset {first_difference} (0x{first_difference:x})"
+    );
+    let _ = writeln!(diff, "  offset    {:<35}received", "expected");
+    for line in difference_line.saturating_sub(DIFF_CONTEXT_LINES)
+        ..(difference_line + DIFF_CONTEXT_LINES + 1).min(line_count)
+    {
+        let offset = line * DIFF_BYTES_PER_LINE;
+        let expected_line = diff_line(expected, offset);
+        let received_line = diff_line(received, offset);
+        let marker = if expected_line == received_line {
+            ' '
+        } else {
+            '!'
+        };
+        let mut row = format!("{marker} {offset:08x}  ");
+        write_diff_column(&mut row, expected_line);
+        row.push_str("  ");
+        write_diff_column(&mut row, received_line);
+        diff.push_str(row.trim_end());
+        diff.push('\n');
+    }
+    Some(diff)
+}
+
+/// Bytes of the hex diff line starting at `offset`, possibly empty
+fn diff_line(data: &[u8], offset: usize) -> &[u8] {
+    let start = offset.min(data.len());
+    &data[start..(offset + DIFF_BYTES_PER_LINE).min(data.len())]
+}
+
+/// Write the hex bytes and printable ASCII characters of a hex diff line, padded to the width of a full line
+fn write_diff_column(row: &mut String, line: &[u8]) {
+    for position in 0..DIFF_BYTES_PER_LINE {
+        match line.get(position) {
+            Some(byte) => {
+                let _ = write!(row, "{byte:02x} ");
+            }
+            None => row.push_str("   "),
+        }
+    }
+    let ascii: String = line.iter().map(|&byte| printable(byte)).collect();
+    let _ = write!(row, " {ascii:<DIFF_BYTES_PER_LINE$}");
+}
@@ -0,0 +1,168 @@
+//! # `hooks`
+//!
+//! Callbacks invoked by the server thread on the lifecycle events of the exchange.
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use socket2::Socket;
+
+use crate::ServerMockerError;
+
+/// Callback invoked with the address of a client
+type ClientHook = Arc<dyn Fn(SocketAddr) + Send + Sync>;
+
+/// Callback invoked with a message exchanged with a client
+type MessageHook = Arc<dyn Fn(&[u8]) + Send + Sync>;
+
+/// Callback invoked with an error raised by the server
+type ErrorHook = Arc<dyn Fn(&ServerMockerError) + Send + Sync>;
+
+/// Callback invoked with the socket of the server before it's bound
+type SocketHook = Arc<dyn Fn(&Socket) -> io::Result<()> + Send + Sync>;
+
+/// Callbacks invoked from the server thread on the lifecycle events of the exchange,
+/// set in [`TcpMocker::hooks`](crate::TcpMocker::hooks) or [`UdpMocker::hooks`](crate::UdpMocker::hooks).
+///
+/// They let tests react to events as they happen, without polling the message and error queues.
+/// Callbacks run on the server thread, which waits for them to return: long computations delay the exchange.
+///
+/// # Example
+/// ```
+/// use std::net::TcpStream;
+/// use std::sync::mpsc;
+/// use socket_server_mocker::{Hooks, ServerMocker, TcpMocker};
+///
+/// let (connect_tx, connect_rx) = mpsc::channel();
+/// let server = ServerMocker::new_with_opts(TcpMocker::default().hooks(
+///     Hooks::default().on_connect(move |client_addr| connect_tx.send(client_addr).unwrap()),
+/// ))
+/// .unwrap();
+///
+/// let client = TcpStream::connect(server.socket_address()).unwrap();
+/// assert_eq!(client.local_addr().unwrap(), connect_rx.recv().unwrap());
+/// ```
+#[derive(Clone, Default)]
+pub struct Hooks {
+    connect: Option<ClientHook>,
+    disconnect: Option<ClientHook>,
+    message_received: Option<MessageHook>,
+    message_sent: Option<MessageHook>,
+    error: Option<ErrorHook>,
+    socket: Option<SocketHook>,
+}
+
+impl Hooks {
+    /// Call `hook` when a TCP connection is accepted, or when a datagram is received from a new UDP client
+    #[must_use]
+    pub fn on_connect(mut self, hook: impl Fn(SocketAddr) + Send + Sync + 'static) -> Self {
+        self.connect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` when the server is done with a TCP connection, never for UDP clients
+    #[must_use]
+    pub fn on_disconnect(mut self, hook: impl Fn(SocketAddr) + Send + Sync + 'static) -> Self {
+        self.disconnect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` with each message received by a receive instruction
+    #[must_use]
+    pub fn on_message_received(mut self, hook: impl Fn(&[u8]) + Send + Sync + 'static) -> Self {
+        self.message_received = Some(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` with each message sent to a client, before it's sent
+    #[must_use]
+    pub fn on_message_sent(mut self, hook: impl Fn(&[u8]) + Send + Sync + 'static) -> Self {
+        self.message_sent = Some(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` with each error raised by the server, before it's available from
+    /// [`ServerMocker::pop_server_error`](crate::ServerMocker::pop_server_error)
+    #[must_use]
+    pub fn on_error(mut self, hook: impl Fn(&ServerMockerError) + Send + Sync + 'static) -> Self {
+        self.error = Some(Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` with the listening socket before it's bound, to set platform-specific options
+    /// that the server mocker doesn't model, such as `IP_FREEBIND`, `TCP_USER_TIMEOUT` or the buffer sizes.
+    /// The options modeled by the server mocker are already applied, so they can be overridden.
+    /// An error fails the creation of the server mocker with [`ServerMockerError::UnableToBindListener`].
+    ///
+    /// Unlike the other hooks, it's called from the thread creating the server mocker.
+    ///
+    /// # Example
+    /// ```
+    /// use socket_server_mocker::{Hooks, ServerMocker, TcpMocker};
+    ///
+    /// let server = ServerMocker::new_with_opts(TcpMocker::default().hooks(
+    ///     Hooks::default().on_socket(|socket| socket.set_recv_buffer_size(64 * 1024)),
+    /// ))
+    /// .unwrap();
+    /// ```
+    #[must_use]
+    pub fn on_socket(
+        mut self,
+        hook: impl Fn(&Socket) -> io::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.socket = Some(Arc::new(hook));
+        self
+    }
+
+    pub(crate) fn socket_created(&self, socket: &Socket) -> io::Result<()> {
+        match &self.socket {
+            Some(hook) => hook(socket),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn connected(&self, client_addr: SocketAddr) {
+        if let Some(hook) = &self.connect {
+            hook(client_addr);
+        }
+    }
+
+    pub(crate) fn disconnected(&self, client_addr: SocketAddr) {
+        if let Some(hook) = &self.disconnect {
+            hook(client_addr);
+        }
+    }
+
+    pub(crate) fn received(&self, message: &[u8]) {
+        if let Some(hook) = &self.message_received {
+            hook(message);
+        }
+    }
+
+    pub(crate) fn sent(&self, message: &[u8]) {
+        if let Some(hook) = &self.message_sent {
+            hook(message);
+        }
+    }
+
+    pub(crate) fn raised(&self, error: &ServerMockerError) {
+        if let Some(hook) = &self.error {
+            hook(error);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("connect", &self.connect.is_some())
+            .field("disconnect", &self.disconnect.is_some())
+            .field("message_received", &self.message_received.is_some())
+            .field("message_sent", &self.message_sent.is_some())
+            .field("error", &self.error.is_some())
+            .field("socket", &self.socket.is_some())
+            .finish()
+    }
+}
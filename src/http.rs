@@ -0,0 +1,341 @@
+//! # `http`
+//!
+//! Canned HTTP/1.1 responses served depending on the method, the path, the headers and the body of the requests.
+
+use std::fmt::Write;
+#[cfg(feature = "serde")]
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use crate::openapi;
+use crate::Instruction::{self, ReceiveMessage, Repeat, StopExchange};
+#[cfg(feature = "serde")]
+use crate::ServerMockerError;
+use crate::{Matcher, Times};
+
+/// Response sent to the requests matching no mock
+const NOT_FOUND_RESPONSE: &[u8] = b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+
+/// Canned HTTP response served to the requests matching a method, a path, and optionally headers and body,
+/// see [`ServerMocker::add_http_mocks`](crate::ServerMocker::add_http_mocks).
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::{HttpMock, Matcher};
+///
+/// let mock = HttpMock::new("GET", "/api/users/42")
+///     .header("Content-Type", "application/json")
+///     .body(r#"{"id": 42, "name": "alice"}"#);
+/// assert!(mock.matches(b"GET /api/users/42?verbose=true HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+/// assert!(!mock.matches(b"DELETE /api/users/42 HTTP/1.1\r\nHost: localhost\r\n\r\n"));
+///
+/// let mock = HttpMock::new("POST", "/login")
+///     .request_header("Content-Type", "application/json")
+///     .request_body(Matcher::Contains(b"\"user\":\"alice\"".to_vec()))
+///     .status(401);
+/// assert!(mock.matches(b"POST /login HTTP/1.1\r\ncontent-type: application/json\r\n\r\n{\"user\":\"alice\"}"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpMock {
+    /// Method of the matched requests, such as `GET`, or `ANY` to match any method
+    pub method: String,
+    /// Path of the matched requests. If it has no query string, the query string of the requests is ignored.
+    /// Segments written `{name}`, as in `OpenAPI` path templates, match any single segment.
+    pub path: String,
+    /// Headers the matched requests must have, with these exact values. Header names are case insensitive.
+    pub request_headers: Vec<(String, String)>,
+    /// Matchers the body of the matched requests must all satisfy
+    pub request_body: Vec<Matcher>,
+    /// Maximum number of times this mock is served, the next matching mocks being served afterward
+    pub max_uses: Option<usize>,
+    /// Status code of the response, `200` by default
+    pub status: u16,
+    /// Headers of the response, `Content-Length` being added if missing
+    pub headers: Vec<(String, String)>,
+    /// Body of the response
+    pub body: Vec<u8>,
+    /// Delay before sending the response
+    pub delay: Option<Duration>,
+}
+
+impl HttpMock {
+    /// Mock answering the requests with the given method and path with an empty `200 OK` response
+    pub fn new(method: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            method: method.into(),
+            path: path.into(),
+            request_headers: Vec::new(),
+            request_body: Vec::new(),
+            max_uses: None,
+            status: 200,
+            headers: Vec::new(),
+            body: Vec::new(),
+            delay: None,
+        }
+    }
+
+    /// Generate one mock per operation of an `OpenAPI` 3 specification, in JSON or YAML
+    /// (`.yaml` or `.yml` extension), answering with the example payload of its first success response.
+    ///
+    /// Examples are taken from the `example` or `examples` of the response, or from the schema,
+    /// a payload being synthesized from the schema types if there is no example.
+    /// The path of the first server prefixes all the paths, and path parameters match any segment.
+    ///
+    /// Mocks are tried in order, so that responses can be overridden by putting mocks before the generated ones.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use socket_server_mocker::{HttpMock, ServerMocker};
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// let mut mocks = vec![HttpMock::new("GET", "/users/0").status(404)];
+    /// mocks.extend(HttpMock::from_openapi("tests/fixtures/openapi.yaml").unwrap());
+    /// server.add_http_mocks(mocks).unwrap();
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn from_openapi(path: impl AsRef<Path>) -> Result<Vec<Self>, ServerMockerError> {
+        openapi::load_openapi(path.as_ref())
+    }
+
+    /// Only match the requests having the given header value
+    #[must_use]
+    pub fn request_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.request_headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Only match the requests whose body satisfies the given matcher
+    #[must_use]
+    pub fn request_body(mut self, matcher: Matcher) -> Self {
+        self.request_body.push(matcher);
+        self
+    }
+
+    /// Serve this mock at most `max_uses` times
+    #[must_use]
+    pub fn max_uses(mut self, max_uses: usize) -> Self {
+        self.max_uses = Some(max_uses);
+        self
+    }
+
+    /// Set the status code of the response
+    #[must_use]
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Add a header to the response
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the body of the response
+    #[must_use]
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Wait for the given delay before sending the response
+    #[must_use]
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Check if the given raw HTTP request matches this mock
+    pub fn matches(&self, request: &[u8]) -> bool {
+        HttpRequest::parse(request).is_some_and(|request| self.matches_request(&request))
+    }
+
+    /// Raw HTTP/1.1 response of this mock
+    pub fn response(&self) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {} {}\r\n",
+            self.status,
+            reason_phrase(self.status)
+        );
+        for (name, value) in &self.headers {
+            let _ = write!(response, "{name}: {value}\r\n");
+        }
+        if !self
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        {
+            let _ = write!(response, "Content-Length: {}\r\n", self.body.len());
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        response.extend_from_slice(&self.body);
+        response
+    }
+
+    fn matches_request(&self, request: &HttpRequest<'_>) -> bool {
+        let target = url_path(request.target);
+        let target = if self.path.contains('?') {
+            target
+        } else {
+            target.split('?').next().unwrap_or(target)
+        };
+        (self.method == "ANY" || request.method.eq_ignore_ascii_case(&self.method))
+            && matches_path(&self.path, target)
+            && self.request_headers.iter().all(|(name, value)| {
+                request.headers.iter().any(|(header, header_value)| {
+                    header.eq_ignore_ascii_case(name) && header_value == value
+                })
+            })
+            && self
+                .request_body
+                .iter()
+                .all(|matcher| matcher.matches(request.body))
+    }
+}
+
+/// Check if the request target matches the given path, whose `{name}` segments match any segment
+fn matches_path(path: &str, target: &str) -> bool {
+    if !path.contains('{') {
+        return path == target;
+    }
+    let mut path_segments = path.split('/');
+    let mut target_segments = target.split('/');
+    loop {
+        match (path_segments.next(), target_segments.next()) {
+            (None, None) => return true,
+            (Some(path_segment), Some(target_segment))
+                if path_segment == target_segment
+                    || (path_segment.starts_with('{')
+                        && path_segment.ends_with('}')
+                        && !target_segment.is_empty()) => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Head and body of a raw HTTP request
+struct HttpRequest<'a> {
+    method: &'a str,
+    target: &'a str,
+    headers: Vec<(&'a str, &'a str)>,
+    body: &'a [u8],
+}
+
+impl<'a> HttpRequest<'a> {
+    /// Parse the given raw HTTP request, `None` if it doesn't start with a request line
+    fn parse(request: &'a [u8]) -> Option<Self> {
+        let (head, body) = match request.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(head_end) => (&request[..head_end], &request[head_end + 4..]),
+            None => (request, &[][..]),
+        };
+        let mut lines = std::str::from_utf8(head).ok()?.split("\r\n");
+        let mut request_line = lines.next()?.split(' ');
+        let method = request_line.next()?;
+        let target = request_line.next()?;
+        if !request_line.next()?.starts_with("HTTP/") {
+            return None;
+        }
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim(), value.trim()))
+            .collect();
+        Some(Self {
+            method,
+            target,
+            headers,
+            body,
+        })
+    }
+}
+
+/// Instructions serving the given mocks until the client stops sending requests, then closing the connection.
+///
+/// Each request is answered by the first matching mock which hasn't reached its [`HttpMock::max_uses`].
+pub(crate) fn http_instructions(mocks: Vec<HttpMock>) -> Vec<Instruction> {
+    let mut uses = vec![0; mocks.len()];
+    let responder = move |request: Option<Vec<u8>>| {
+        let request = request?;
+        // Ignore the end of a request split over several reads
+        let request = HttpRequest::parse(&request)?;
+        let available = (0..mocks.len()).find(|&index| {
+            mocks[index]
+                .max_uses
+                .map_or(true, |max_uses| uses[index] < max_uses)
+                && mocks[index].matches_request(&request)
+        });
+        let Some(index) = available else {
+            return Some(NOT_FOUND_RESPONSE.to_vec());
+        };
+        uses[index] += 1;
+        if let Some(delay) = mocks[index].delay {
+            thread::sleep(delay);
+        }
+        Some(mocks[index].response())
+    };
+    vec![
+        Repeat {
+            times: Times::at_least(0),
+            instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+        },
+        StopExchange,
+    ]
+}
+
+/// Body of the given message if it is an HTTP request, the whole message otherwise
+#[cfg(feature = "serde")]
+pub(crate) fn message_body(message: &[u8]) -> &[u8] {
+    HttpRequest::parse(message).map_or(message, |request| request.body)
+}
+
+/// Path and query string of the given URL, which may be absolute or already a path
+pub(crate) fn url_path(url: &str) -> &str {
+    let url = url.split('#').next().unwrap_or(url);
+    match url.find("://") {
+        Some(scheme_end) => {
+            let authority_and_path = &url[scheme_end + 3..];
+            authority_and_path
+                .find('/')
+                .map_or("/", |path_start| &authority_and_path[path_start..])
+        }
+        None => url,
+    }
+}
+
+/// Reason phrase of the common status codes
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        410 => "Gone",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
@@ -2,11 +2,71 @@
 //!
 //! Instructions sent by the testing code to the mocked server.
 
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::bytes::Regex;
+
+use crate::hex::preview;
+use crate::server_mocker::IDLE_POLL_INTERVAL;
+use crate::Matcher;
+use crate::ServerMockerError::{self, UnmatchedTemplatePattern};
+
+/// Closure computing the message to send from the last received message, see [`Instruction::SendMessageFromClosure`].
+///
+/// Shared so that instructions can be cloned, the clones keeping the same state.
+pub type MessageResponder = Arc<Mutex<dyn FnMut(Option<Vec<u8>>) -> Option<Vec<u8>> + Send>>;
+
+/// Responder always sending the given message, whatever was received
+pub(crate) fn constant_responder(message: Vec<u8>) -> MessageResponder {
+    Arc::new(Mutex::new(move |_| Some(message.clone())))
+}
+
 /// Type of network instruction executed by the server mocker.
-#[derive(Debug, Clone, PartialEq)]
+///
+/// With the `serde` feature, instructions can be loaded from JSON or YAML scripts,
+/// see [`ServerMocker::load_script`](crate::ServerMocker::load_script).
+/// Instructions holding a function or a closure can't be serialized.
+///
+/// Instructions holding a closure are equal only to their clones.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     /// Send given message to the client
+    #[cfg_attr(feature = "serde", serde(with = "crate::script::payload"))]
     SendMessage(Vec<u8>),
+    /// Send given message to the given client address, in UDP only.
+    ///
+    /// Unlike [`Instruction::SendMessage`], no message needs to be received first,
+    /// so that unsolicited datagrams (notifications, server push) can be sent to a known client.
+    /// The TCP server mocker raises [`ServerMockerError::UnsupportedInstruction`](crate::ServerMockerError::UnsupportedInstruction).
+    ///
+    /// # Example
+    /// ```
+    /// # use std::net::{SocketAddr, UdpSocket};
+    /// # use socket_server_mocker::{Instruction::{SendMessageTo, StopExchange}, ServerMocker};
+    /// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// let server = ServerMocker::udp().unwrap();
+    /// server.add_mock_instructions(vec![
+    ///     SendMessageTo(client.local_addr().unwrap(), b"NOTIFY".to_vec()),
+    ///     StopExchange,
+    /// ]).unwrap();
+    ///
+    /// let mut buffer = [0; 8];
+    /// let (received_size, sender_addr) = client.recv_from(&mut buffer).unwrap();
+    /// assert_eq!(b"NOTIFY", &buffer[..received_size]);
+    /// assert_eq!(server.socket_address(), sender_addr);
+    /// ```
+    SendMessageTo(
+        SocketAddr,
+        #[cfg_attr(feature = "serde", serde(with = "crate::script::payload"))] Vec<u8>,
+    ),
     /// Send a message to the client depending on the last received message
     ///
     /// If the given function returns None, no message is sent
@@ -26,17 +86,886 @@ pub enum Instruction {
     ///   }
     /// });
     /// ```
+    #[cfg_attr(feature = "serde", serde(skip))]
     SendMessageDependingOnLastReceivedMessage(fn(Option<Vec<u8>>) -> Option<Vec<u8>>),
+    /// Same as [`Instruction::SendMessageDependingOnLastReceivedMessage`], but the closure can capture
+    /// its environment and keep a state between calls, e.g. when repeated.
+    ///
+    /// If the closure returns None, no message is sent
+    ///
+    /// # Example
+    /// ```
+    /// # use socket_server_mocker::{Instruction::{self, ReceiveMessage, Repeat}, Times};
+    /// // Answer each request with an increasing sequence number
+    /// let mut sequence_number = 0u32;
+    /// Repeat {
+    ///     times: Times::exactly(3),
+    ///     instructions: vec![
+    ///         ReceiveMessage,
+    ///         Instruction::send_from_closure(move |_| {
+    ///             sequence_number += 1;
+    ///             Some(sequence_number.to_be_bytes().to_vec())
+    ///         }),
+    ///     ],
+    /// };
+    /// ```
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SendMessageFromClosure(MessageResponder),
+    /// Send a message built from parts of the last received message, instead of a closure copying bytes around.
+    ///
+    /// `pattern` is matched against the last received message, and `$name` or `${name}` placeholders of `template`
+    /// are replaced by the corresponding named capture groups (`$$` for a literal `$`).
+    /// Use the `(?s-u)` flags to match arbitrary binary data.
+    ///
+    /// If no message has been received, or if it doesn't match the pattern, nothing is sent and
+    /// [`ServerMockerError::UnmatchedTemplatePattern`](crate::ServerMockerError::UnmatchedTemplatePattern) is raised.
+    ///
+    /// # Example
+    /// ```
+    /// # use socket_server_mocker::Instruction;
+    /// // Echo the request ID of an HTTP request
+    /// Instruction::send_template(
+    ///     r"(?i)\r\nX-Request-Id: (?P<id>[^\r]+)\r\n",
+    ///     "HTTP/1.1 200 OK\r\nX-Request-Id: ${id}\r\nContent-Length: 0\r\n\r\n",
+    /// );
+    /// // Copy the transaction ID of a DNS query, in its first two bytes
+    /// Instruction::send_template(r"(?s-u)^(?P<id>.{2})", b"${id}\x81\x80\x00\x00".to_vec());
+    /// ```
+    SendTemplate {
+        /// Regular expression matched against the last received message
+        #[cfg_attr(feature = "serde", serde(with = "crate::script::regex"))]
+        pattern: Regex,
+        /// Message to send, with placeholders for the named capture groups of `pattern`
+        #[cfg_attr(feature = "serde", serde(with = "crate::script::payload"))]
+        template: Vec<u8>,
+    },
+    /// Send the given message in several chunks of `chunk_size` bytes, waiting `interval` between each chunk.
+    ///
+    /// Useful to exercise clients that assume a single read returns a whole protocol message.
+    /// In UDP, each chunk is sent as a separate datagram.
+    SendMessageChunked {
+        /// Whole message to send
+        #[cfg_attr(feature = "serde", serde(with = "crate::script::payload"))]
+        message: Vec<u8>,
+        /// Maximum size of each chunk
+        chunk_size: usize,
+        /// Delay between two chunks
+        #[cfg_attr(feature = "serde", serde(with = "crate::script::millis"))]
+        interval: Duration,
+    },
+    /// Send the content of the given fixture file, read when the instruction is executed.
+    ///
+    /// Useful for large canned responses (certificate chains, firmware blobs, big JSON bodies)
+    /// that shouldn't be embedded in every instruction list.
+    /// A missing file is reported as [`ServerMockerError::UnableToReadFile`](crate::ServerMockerError::UnableToReadFile).
+    SendFile(PathBuf),
+    /// Same as [`Instruction::SendFile`], but the content is sent in several chunks like [`Instruction::SendMessageChunked`]
+    SendFileChunked {
+        /// Path of the file to send
+        path: PathBuf,
+        /// Maximum size of each chunk
+        chunk_size: usize,
+        /// Delay between two chunks
+        #[cfg_attr(feature = "serde", serde(with = "crate::script::millis"))]
+        interval: Duration,
+    },
+    /// Send only the first `bytes_to_send` bytes of the given message, then stop the exchange
+    /// and close the connection in case of TCP.
+    ///
+    /// Useful to test client handling of truncated responses or interrupted downloads.
+    SendPartialThenClose {
+        /// Whole message, of which only a prefix is sent
+        #[cfg_attr(feature = "serde", serde(with = "crate::script::payload"))]
+        message: Vec<u8>,
+        /// Number of bytes actually sent before closing
+        bytes_to_send: usize,
+    },
     /// Wait for a message to be received.
     ///
+    /// In TCP, the message is whatever the client sent at once, unless a [`TcpMocker::framer`](crate::TcpMocker::framer) is set.
+    ///
     /// The message could be recovered with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message)
     ReceiveMessage,
-    /// Wait for a message to be received with a maximum size (useful in UDP).
+    /// Wait for a message to be received with a maximum size.
     ///
-    /// If the message is bigger than the given size, the message is truncated.
+    /// In TCP, reading stops at the given size, the remaining data being kept for the next receive instruction,
+    /// regardless of the [`TcpMocker::framer`](crate::TcpMocker::framer).
+    /// In UDP, if the datagram is bigger than the given size, the message is truncated.
     ///
     /// The message could be recovered with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message)
     ReceiveMessageWithMaxSize(usize),
+    /// Wait for the given number of messages, each one received like [`Instruction::ReceiveMessage`],
+    /// for clients sending bursts of datagrams per logical operation.
+    ///
+    /// Each message could be recovered with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message)
+    ReceiveMessages(usize),
+    /// Wait until exactly the given number of bytes has been received, whatever the number of reads it takes.
+    ///
+    /// Bytes received beyond this size are kept for the next receive instruction.
+    ///
+    /// The message could be recovered with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message)
+    ReceiveExactBytes(usize),
+    /// Wait until the given delimiter has been received, e.g. `\r\n.\r\n` at the end of SMTP DATA.
+    ///
+    /// The message includes the delimiter. Bytes received after the delimiter are kept for the next receive instruction.
+    ///
+    /// The message could be recovered with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message)
+    #[cfg_attr(feature = "serde", serde(with = "crate::script::payload"))]
+    ReceiveUntilDelimiter(Vec<u8>),
+    /// Wait until the client closes its write side, and receive everything it sent as a single message
+    /// (HTTP/1.0 uploads, file push clients...).
+    ///
+    /// In UDP, an empty datagram ends the message.
+    ///
+    /// The message could be recovered with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message)
+    ReceiveUntilClose,
+    /// Receive everything the client sends during the given duration as a single message,
+    /// possibly empty (metrics emitters, batched writers...).
+    ///
+    /// The message could be recovered with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message)
+    #[cfg_attr(feature = "serde", serde(with = "crate::script::millis"))]
+    ReceiveFor(Duration),
+    /// Same as [`Instruction::ReceiveUntilClose`], but the data is delivered in chunks as soon as it's read,
+    /// instead of being buffered into a single message. Useful to test big uploads without ballooning memory.
+    ///
+    /// Chunks could be recovered with [`ServerMocker::pop_received_chunk`](crate::ServerMocker::pop_received_chunk),
+    /// and are not available through [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message).
+    ReceiveChunksUntilClose,
+    /// Run the given instructions repeatedly, as many times as allowed by `times`.
+    ///
+    /// Repetition stops when the upper bound is reached, or when the client stops sending before a repetition
+    /// receives anything. Any other error of the repeated instructions, e.g. a client stopping in the middle
+    /// of a repetition, is raised as is.
+    /// If the number of fully executed repetitions doesn't match `times`,
+    /// a [`ServerMockerError::UnexpectedRepeatCount`](crate::ServerMockerError::UnexpectedRepeatCount) is raised,
+    /// failing [`ServerMocker::assert_no_more_messages`](crate::ServerMocker::assert_no_more_messages)
+    /// or the drop of the server mocker if it isn't popped.
+    ///
+    /// Repeated instructions should start with a receive instruction,
+    /// otherwise an unbounded repetition would never stop.
+    ///
+    /// # Example
+    /// ```
+    /// # use socket_server_mocker::{Instruction::{Repeat, ReceiveMessage, SendMessage}, Times};
+    /// // The client is expected to retry exactly 3 times
+    /// Repeat {
+    ///     times: Times::exactly(3),
+    ///     instructions: vec![ReceiveMessage, SendMessage(b"503 busy".to_vec())],
+    /// };
+    /// ```
+    Repeat {
+        /// Allowed number of repetitions
+        times: Times,
+        /// Instructions to repeat
+        instructions: Vec<Instruction>,
+    },
+    /// Execute `if_true` or `if_false` depending on the last received message.
+    ///
+    /// If no message has been received yet, `if_false` is executed.
+    /// The chosen instructions stop at the first error, which is then raised.
+    ///
+    /// # Example
+    /// ```
+    /// # use socket_server_mocker::Instruction::{Branch, SendMessage, StopExchange};
+    /// // Answer differently to SMTP EHLO and HELO greetings
+    /// Branch {
+    ///     predicate: |last_received_message| last_received_message.starts_with(b"EHLO"),
+    ///     if_true: vec![SendMessage(b"250-localhost\r\n250 8BITMIME\r\n".to_vec())],
+    ///     if_false: vec![SendMessage(b"250 localhost\r\n".to_vec())],
+    /// };
+    /// ```
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Branch {
+        /// Condition evaluated on the last received message
+        predicate: fn(&[u8]) -> bool,
+        /// Instructions executed if the predicate returns true
+        if_true: Vec<Instruction>,
+        /// Instructions executed if the predicate returns false, or if no message has been received yet
+        if_false: Vec<Instruction>,
+    },
+    /// Serve each route to its own peer, in UDP only, to impersonate several servers in a single test.
+    ///
+    /// The first datagram of a peer selects the first route matching it, whose instructions are then executed:
+    /// their receive instructions only accept this peer's datagrams, so that the responses are sent to this peer.
+    /// Routes are served one peer at a time, datagrams of other peers being kept for their own route.
+    /// The instruction ends once every route has been served, or at the first error, which is then raised.
+    ///
+    /// Datagrams of peers matching no route are ignored, or reported as
+    /// [`ServerMockerError::UnexpectedData`](crate::ServerMockerError::UnexpectedData) in strict mode.
+    /// The TCP server mocker raises [`ServerMockerError::UnsupportedInstruction`](crate::ServerMockerError::UnsupportedInstruction).
+    ///
+    /// # Example
+    /// ```
+    /// # use socket_server_mocker::{Instruction::{ReceiveMessage, RouteByPeer, SendMessage}, Matcher, PeerRoute, PeerSelector};
+    /// RouteByPeer(vec![
+    ///     PeerRoute {
+    ///         peer: PeerSelector::FirstMessage(Matcher::StartsWith(b"DNS".to_vec())),
+    ///         instructions: vec![ReceiveMessage, SendMessage(b"DNS answer".to_vec())],
+    ///     },
+    ///     PeerRoute {
+    ///         peer: PeerSelector::FirstMessage(Matcher::StartsWith(b"NTP".to_vec())),
+    ///         instructions: vec![ReceiveMessage, SendMessage(b"NTP answer".to_vec())],
+    ///     },
+    /// ]);
+    /// ```
+    #[cfg_attr(feature = "serde", serde(skip))]
+    RouteByPeer(Vec<PeerRoute>),
+    /// Execute the given instructions, sending every message one byte at a time, waiting `byte_interval` between each byte.
+    ///
+    /// Slowloris-style response: a client with a per-read timeout keeps receiving data
+    /// whereas a client with an overall deadline gives up.
+    /// In UDP, each byte is sent in its own datagram.
+    ///
+    /// # Example
+    /// ```
+    /// # use std::time::Duration;
+    /// # use socket_server_mocker::Instruction::{SendMessage, SlowDrip};
+    /// SlowDrip {
+    ///     byte_interval: Duration::from_millis(100),
+    ///     instructions: vec![SendMessage(b"HTTP/1.1 200 OK\r\n\r\n".to_vec())],
+    /// };
+    /// ```
+    SlowDrip {
+        /// Delay between two bytes sent to the client
+        #[cfg_attr(feature = "serde", serde(with = "crate::script::millis"))]
+        byte_interval: Duration,
+        /// Instructions whose messages are sent slowly
+        instructions: Vec<Instruction>,
+    },
+    /// Wait for the given duration before executing the next instruction.
+    ///
+    /// Useful to simulate a slow server and exercise client read timeouts.
+    #[cfg_attr(feature = "serde", serde(with = "crate::script::millis"))]
+    Pause(Duration),
+    /// Read and discard everything the client sends during the given duration, without answering.
+    ///
+    /// Simulates a hung server that keeps the connection open,
+    /// to exercise client read timeouts rather than connection failures.
+    /// Discarded data is neither reported nor available through
+    /// [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message).
+    #[cfg_attr(feature = "serde", serde(with = "crate::script::millis"))]
+    Silence(Duration),
+    /// Wait for the given duration, expecting the client not to send anything meanwhile.
+    ///
+    /// Any data received during this period is reported as
+    /// [`ServerMockerError::UnexpectedData`](crate::ServerMockerError::UnexpectedData).
+    #[cfg_attr(feature = "serde", serde(with = "crate::script::millis"))]
+    ExpectNoMessage(Duration),
+    /// From now on, answer the data received from the client outside of a receive instruction
+    /// with the message computed by the closure from this data, nothing being sent if it returns `None`.
+    ///
+    /// Replaces [`TcpMocker::default_response`](crate::TcpMocker::default_response) or
+    /// [`UdpMocker::default_response`](crate::UdpMocker::default_response), see
+    /// [`ServerMocker::default_response`](crate::ServerMocker::default_response) and
+    /// [`ServerMocker::default_response_from_closure`](crate::ServerMocker::default_response_from_closure).
+    #[cfg_attr(feature = "serde", serde(skip))]
+    SetDefaultResponse(MessageResponder),
     /// Stop the exchange with the client, close the connection in case of TCP
     StopExchange,
 }
+
+impl Instruction {
+    /// Build a [`Instruction::SendTemplate`] instruction
+    ///
+    /// # Panics
+    /// Panics if `pattern` isn't a valid regular expression.
+    pub fn send_template(pattern: &str, template: impl Into<Vec<u8>>) -> Self {
+        Instruction::SendTemplate {
+            pattern: Regex::new(pattern).expect("invalid template pattern"),
+            template: template.into(),
+        }
+    }
+
+    /// Build a [`Instruction::SendMessageFromClosure`] instruction
+    pub fn send_from_closure(
+        responder: impl FnMut(Option<Vec<u8>>) -> Option<Vec<u8>> + Send + 'static,
+    ) -> Self {
+        Instruction::SendMessageFromClosure(Arc::new(Mutex::new(responder)))
+    }
+
+    /// Short human-readable description of the instruction, without its whole payload
+    pub(crate) fn summary(&self) -> String {
+        match self {
+            Instruction::SendMessage(message) => format!("SendMessage ({} bytes)", message.len()),
+            Instruction::SendMessageTo(addr, message) => {
+                format!("SendMessageTo({addr}, {} bytes)", message.len())
+            }
+            Instruction::SendMessageDependingOnLastReceivedMessage(_) => {
+                "SendMessageDependingOnLastReceivedMessage".to_string()
+            }
+            Instruction::SendMessageFromClosure(_) => "SendMessageFromClosure".to_string(),
+            Instruction::SendTemplate { pattern, .. } => format!("SendTemplate({pattern})"),
+            Instruction::SendMessageChunked {
+                message,
+                chunk_size,
+                interval,
+            } => format!(
+                "SendMessageChunked ({} bytes in chunks of {chunk_size} every {interval:?})",
+                message.len()
+            ),
+            Instruction::SendFile(path) => format!("SendFile({})", path.display()),
+            Instruction::SendFileChunked {
+                path,
+                chunk_size,
+                interval,
+            } => format!(
+                "SendFileChunked ({} in chunks of {chunk_size} every {interval:?})",
+                path.display()
+            ),
+            Instruction::SendPartialThenClose {
+                message,
+                bytes_to_send,
+            } => format!(
+                "SendPartialThenClose ({bytes_to_send} of {} bytes)",
+                message.len()
+            ),
+            Instruction::ReceiveMessage => "ReceiveMessage".to_string(),
+            Instruction::ReceiveMessageWithMaxSize(max_size) => {
+                format!("ReceiveMessageWithMaxSize({max_size})")
+            }
+            Instruction::ReceiveMessages(count) => format!("ReceiveMessages({count})"),
+            Instruction::ReceiveExactBytes(size) => format!("ReceiveExactBytes({size})"),
+            Instruction::ReceiveUntilDelimiter(delimiter) => {
+                format!("ReceiveUntilDelimiter({})", preview(delimiter))
+            }
+            Instruction::ReceiveUntilClose => "ReceiveUntilClose".to_string(),
+            Instruction::ReceiveFor(duration) => format!("ReceiveFor({duration:?})"),
+            Instruction::ReceiveChunksUntilClose => "ReceiveChunksUntilClose".to_string(),
+            Instruction::Repeat {
+                times,
+                instructions,
+            } => format!("Repeat {} instructions {times}", instructions.len()),
+            Instruction::Branch {
+                if_true, if_false, ..
+            } => format!(
+                "Branch ({} or {} instructions)",
+                if_true.len(),
+                if_false.len()
+            ),
+            Instruction::RouteByPeer(routes) => format!("RouteByPeer ({} routes)", routes.len()),
+            Instruction::SlowDrip {
+                byte_interval,
+                instructions,
+            } => format!(
+                "SlowDrip {} instructions, one byte every {byte_interval:?}",
+                instructions.len()
+            ),
+            Instruction::Pause(duration) => format!("Pause({duration:?})"),
+            Instruction::Silence(duration) => format!("Silence({duration:?})"),
+            Instruction::ExpectNoMessage(duration) => format!("ExpectNoMessage({duration:?})"),
+            Instruction::SetDefaultResponse(_) => "SetDefaultResponse".to_string(),
+            Instruction::StopExchange => "StopExchange".to_string(),
+        }
+    }
+
+    /// Check if this instruction waits for data from the client
+    pub(crate) fn is_receive(&self) -> bool {
+        matches!(
+            self,
+            Instruction::ReceiveMessage
+                | Instruction::ReceiveMessageWithMaxSize(_)
+                | Instruction::ReceiveMessages(_)
+                | Instruction::ReceiveExactBytes(_)
+                | Instruction::ReceiveUntilDelimiter(_)
+                | Instruction::ReceiveUntilClose
+                | Instruction::ReceiveFor(_)
+                | Instruction::ReceiveChunksUntilClose
+                | Instruction::Silence(_)
+                | Instruction::ExpectNoMessage(_)
+                | Instruction::RouteByPeer(_)
+        )
+    }
+
+    /// Enter the span covering the execution of this instruction by the server thread
+    #[cfg(feature = "tracing")]
+    pub(crate) fn enter_span(&self) -> tracing::span::EnteredSpan {
+        let span = tracing::debug_span!("instruction", instruction = %self.summary()).entered();
+        tracing::debug!("executing instruction");
+        span
+    }
+}
+
+impl fmt::Debug for Instruction {
+    // A single arm per instruction
+    #[allow(clippy::too_many_lines)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::SendMessage(message) => {
+                f.debug_tuple("SendMessage").field(message).finish()
+            }
+            Instruction::SendMessageTo(addr, message) => f
+                .debug_tuple("SendMessageTo")
+                .field(addr)
+                .field(message)
+                .finish(),
+            Instruction::SendMessageDependingOnLastReceivedMessage(function) => f
+                .debug_tuple("SendMessageDependingOnLastReceivedMessage")
+                .field(function)
+                .finish(),
+            Instruction::SendMessageFromClosure(_) => f
+                .debug_tuple("SendMessageFromClosure")
+                .field(&format_args!("<closure>"))
+                .finish(),
+            Instruction::SendTemplate { pattern, template } => f
+                .debug_struct("SendTemplate")
+                .field("pattern", pattern)
+                .field("template", template)
+                .finish(),
+            Instruction::SendMessageChunked {
+                message,
+                chunk_size,
+                interval,
+            } => f
+                .debug_struct("SendMessageChunked")
+                .field("message", message)
+                .field("chunk_size", chunk_size)
+                .field("interval", interval)
+                .finish(),
+            Instruction::SendFile(path) => f.debug_tuple("SendFile").field(path).finish(),
+            Instruction::SendFileChunked {
+                path,
+                chunk_size,
+                interval,
+            } => f
+                .debug_struct("SendFileChunked")
+                .field("path", path)
+                .field("chunk_size", chunk_size)
+                .field("interval", interval)
+                .finish(),
+            Instruction::SendPartialThenClose {
+                message,
+                bytes_to_send,
+            } => f
+                .debug_struct("SendPartialThenClose")
+                .field("message", message)
+                .field("bytes_to_send", bytes_to_send)
+                .finish(),
+            Instruction::ReceiveMessage => f.write_str("ReceiveMessage"),
+            Instruction::ReceiveMessageWithMaxSize(max_size) => f
+                .debug_tuple("ReceiveMessageWithMaxSize")
+                .field(max_size)
+                .finish(),
+            Instruction::ReceiveMessages(count) => {
+                f.debug_tuple("ReceiveMessages").field(count).finish()
+            }
+            Instruction::ReceiveExactBytes(size) => {
+                f.debug_tuple("ReceiveExactBytes").field(size).finish()
+            }
+            Instruction::ReceiveUntilDelimiter(delimiter) => f
+                .debug_tuple("ReceiveUntilDelimiter")
+                .field(delimiter)
+                .finish(),
+            Instruction::ReceiveUntilClose => f.write_str("ReceiveUntilClose"),
+            Instruction::ReceiveFor(duration) => {
+                f.debug_tuple("ReceiveFor").field(duration).finish()
+            }
+            Instruction::ReceiveChunksUntilClose => f.write_str("ReceiveChunksUntilClose"),
+            Instruction::Repeat {
+                times,
+                instructions,
+            } => f
+                .debug_struct("Repeat")
+                .field("times", times)
+                .field("instructions", instructions)
+                .finish(),
+            Instruction::Branch {
+                predicate,
+                if_true,
+                if_false,
+            } => f
+                .debug_struct("Branch")
+                .field("predicate", predicate)
+                .field("if_true", if_true)
+                .field("if_false", if_false)
+                .finish(),
+            Instruction::RouteByPeer(routes) => f.debug_tuple("RouteByPeer").field(routes).finish(),
+            Instruction::SlowDrip {
+                byte_interval,
+                instructions,
+            } => f
+                .debug_struct("SlowDrip")
+                .field("byte_interval", byte_interval)
+                .field("instructions", instructions)
+                .finish(),
+            Instruction::Pause(duration) => f.debug_tuple("Pause").field(duration).finish(),
+            Instruction::Silence(duration) => f.debug_tuple("Silence").field(duration).finish(),
+            Instruction::ExpectNoMessage(duration) => {
+                f.debug_tuple("ExpectNoMessage").field(duration).finish()
+            }
+            Instruction::SetDefaultResponse(_) => f
+                .debug_tuple("SetDefaultResponse")
+                .field(&format_args!("<closure>"))
+                .finish(),
+            Instruction::StopExchange => f.write_str("StopExchange"),
+        }
+    }
+}
+
+impl PartialEq for Instruction {
+    // A single arm per instruction
+    #[allow(clippy::too_many_lines)]
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Instruction::SendMessage(message), Instruction::SendMessage(other_message)) => {
+                message == other_message
+            }
+            (
+                Instruction::SendMessageTo(addr, message),
+                Instruction::SendMessageTo(other_addr, other_message),
+            ) => addr == other_addr && message == other_message,
+            (
+                Instruction::SendMessageDependingOnLastReceivedMessage(function),
+                Instruction::SendMessageDependingOnLastReceivedMessage(other_function),
+            ) => *function as usize == *other_function as usize,
+            (
+                Instruction::SendMessageFromClosure(responder),
+                Instruction::SendMessageFromClosure(other_responder),
+            )
+            | (
+                Instruction::SetDefaultResponse(responder),
+                Instruction::SetDefaultResponse(other_responder),
+            ) => Arc::ptr_eq(responder, other_responder),
+            (
+                Instruction::SendTemplate { pattern, template },
+                Instruction::SendTemplate {
+                    pattern: other_pattern,
+                    template: other_template,
+                },
+            ) => pattern.as_str() == other_pattern.as_str() && template == other_template,
+            (
+                Instruction::SendMessageChunked {
+                    message,
+                    chunk_size,
+                    interval,
+                },
+                Instruction::SendMessageChunked {
+                    message: other_message,
+                    chunk_size: other_chunk_size,
+                    interval: other_interval,
+                },
+            ) => {
+                message == other_message
+                    && chunk_size == other_chunk_size
+                    && interval == other_interval
+            }
+            (Instruction::SendFile(path), Instruction::SendFile(other_path)) => path == other_path,
+            (
+                Instruction::SendFileChunked {
+                    path,
+                    chunk_size,
+                    interval,
+                },
+                Instruction::SendFileChunked {
+                    path: other_path,
+                    chunk_size: other_chunk_size,
+                    interval: other_interval,
+                },
+            ) => path == other_path && chunk_size == other_chunk_size && interval == other_interval,
+            (
+                Instruction::SendPartialThenClose {
+                    message,
+                    bytes_to_send,
+                },
+                Instruction::SendPartialThenClose {
+                    message: other_message,
+                    bytes_to_send: other_bytes_to_send,
+                },
+            ) => message == other_message && bytes_to_send == other_bytes_to_send,
+            (
+                Instruction::ReceiveMessageWithMaxSize(max_size),
+                Instruction::ReceiveMessageWithMaxSize(other_max_size),
+            ) => max_size == other_max_size,
+            (Instruction::ReceiveMessages(count), Instruction::ReceiveMessages(other_count)) => {
+                count == other_count
+            }
+            (Instruction::ReceiveExactBytes(size), Instruction::ReceiveExactBytes(other_size)) => {
+                size == other_size
+            }
+            (
+                Instruction::ReceiveUntilDelimiter(delimiter),
+                Instruction::ReceiveUntilDelimiter(other_delimiter),
+            ) => delimiter == other_delimiter,
+            (Instruction::ReceiveFor(duration), Instruction::ReceiveFor(other_duration))
+            | (Instruction::Pause(duration), Instruction::Pause(other_duration))
+            | (Instruction::Silence(duration), Instruction::Silence(other_duration))
+            | (
+                Instruction::ExpectNoMessage(duration),
+                Instruction::ExpectNoMessage(other_duration),
+            ) => duration == other_duration,
+            (
+                Instruction::Repeat {
+                    times,
+                    instructions,
+                },
+                Instruction::Repeat {
+                    times: other_times,
+                    instructions: other_instructions,
+                },
+            ) => times == other_times && instructions == other_instructions,
+            (
+                Instruction::Branch {
+                    predicate,
+                    if_true,
+                    if_false,
+                },
+                Instruction::Branch {
+                    predicate: other_predicate,
+                    if_true: other_if_true,
+                    if_false: other_if_false,
+                },
+            ) => {
+                *predicate as usize == *other_predicate as usize
+                    && if_true == other_if_true
+                    && if_false == other_if_false
+            }
+            (Instruction::RouteByPeer(routes), Instruction::RouteByPeer(other_routes)) => {
+                routes == other_routes
+            }
+            (
+                Instruction::SlowDrip {
+                    byte_interval,
+                    instructions,
+                },
+                Instruction::SlowDrip {
+                    byte_interval: other_byte_interval,
+                    instructions: other_instructions,
+                },
+            ) => byte_interval == other_byte_interval && instructions == other_instructions,
+            // Instructions without data, or different instructions
+            _ => mem::discriminant(self) == mem::discriminant(other),
+        }
+    }
+}
+
+/// What the server does when no instruction is received within `rx_timeout`
+/// ([`TcpMocker::rx_timeout`](crate::TcpMocker::rx_timeout) or [`UdpMocker::rx_timeout`](crate::UdpMocker::rx_timeout))
+/// and [`Instruction::StopExchange`] hasn't been sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdlePolicy {
+    /// Stop the server thread, closing the connection abruptly: a client sending data the server didn't read
+    /// may get a connection reset
+    #[default]
+    StopServer,
+    /// Keep waiting for new instructions until the server mocker is dropped,
+    /// so that tests can add instructions in several batches however slow they are
+    KeepConnectionOpen,
+    /// Stop the server thread, but first close the TCP connection for writing and wait for the client
+    /// to close its side (up to `net_timeout`), so that the client reads a clean end of stream.
+    /// Same as [`IdlePolicy::StopServer`] for UDP.
+    CloseConnectionGracefully,
+}
+
+/// What the server does once it executed all the queued instructions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Finish {
+    /// Wait for more instructions, see [`IdlePolicy`]
+    #[default]
+    WaitForInstructions,
+    /// Stop the exchange as soon as no more instructions are queued, as if the script ended with
+    /// [`Instruction::StopExchange`], but close the TCP connection gracefully like [`IdlePolicy::CloseConnectionGracefully`].
+    ///
+    /// The instructions added before the queued ones are all executed are run too, while adding instructions
+    /// afterwards fails with [`ServerMockerError::ServerStopped`](crate::ServerMockerError::ServerStopped).
+    CloseGracefully,
+}
+
+/// Instructions served to a single peer by an [`Instruction::RouteByPeer`] block
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerRoute {
+    /// Peer served by this route
+    pub peer: PeerSelector,
+    /// Instructions executed for this peer
+    pub instructions: Vec<Instruction>,
+}
+
+/// Select the peer served by a [`PeerRoute`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerSelector {
+    /// The peer sending from the given address
+    Address(SocketAddr),
+    /// The first peer whose first datagram matches
+    FirstMessage(Matcher),
+}
+
+impl PeerSelector {
+    /// Check if the peer sending the given first datagram from the given address is selected
+    pub(crate) fn selects(&self, peer_addr: SocketAddr, first_datagram: &[u8]) -> bool {
+        match self {
+            PeerSelector::Address(addr) => *addr == peer_addr,
+            PeerSelector::FirstMessage(matcher) => matcher.matches(first_datagram),
+        }
+    }
+}
+
+/// Number of times an [`Instruction::Repeat`] block is expected to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Times {
+    min: usize,
+    max: Option<usize>,
+}
+
+impl Times {
+    /// Expect exactly `n` repetitions
+    pub fn exactly(n: usize) -> Self {
+        Self {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    /// Expect at least `n` repetitions, repeating until the client stops
+    pub fn at_least(n: usize) -> Self {
+        Self { min: n, max: None }
+    }
+
+    /// Expect at most `n` repetitions
+    pub fn at_most(n: usize) -> Self {
+        Self {
+            min: 0,
+            max: Some(n),
+        }
+    }
+
+    /// Minimum number of expected repetitions
+    pub fn min(&self) -> usize {
+        self.min
+    }
+
+    /// Maximum number of expected repetitions, if any
+    pub fn max(&self) -> Option<usize> {
+        self.max
+    }
+
+    /// Check if the given number of repetitions satisfies this constraint
+    pub fn contains(&self, count: usize) -> bool {
+        count >= self.min && self.max.map_or(true, |max| count <= max)
+    }
+
+    /// Check if no more repetition is allowed after `count` repetitions
+    pub(crate) fn is_exhausted(&self, count: usize) -> bool {
+        self.max.is_some_and(|max| count >= max)
+    }
+}
+
+impl fmt::Display for Times {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max {
+            Some(max) if max == self.min => write!(f, "exactly {max} times"),
+            Some(max) => write!(f, "at most {max} times"),
+            None => write!(f, "at least {} times", self.min),
+        }
+    }
+}
+
+/// Instructions sent to the server thread but not executed yet, shared between
+/// the [`ServerMocker`](crate::ServerMocker) and its server thread.
+#[derive(Debug, Clone, Default)]
+pub struct PendingInstructions(Arc<Mutex<PendingInstructionsState>>);
+
+#[derive(Debug, Default)]
+struct PendingInstructionsState {
+    /// Index of the next instruction sent to the server
+    next_index: usize,
+    /// Index and summary of the instructions not executed yet, in execution order
+    summaries: VecDeque<(usize, String)>,
+    /// The server thread must stop without executing the pending instructions
+    aborted: bool,
+    /// The server thread executed its whole script and accepts no more instructions, see [`Finish::CloseGracefully`]
+    finished: bool,
+}
+
+impl PendingInstructions {
+    /// Register instructions about to be sent to the server thread, returning false if it finished its script
+    pub(crate) fn push(&self, instructions: &[Instruction]) -> bool {
+        let mut state = self.0.lock().unwrap();
+        if state.finished {
+            return false;
+        }
+        for instruction in instructions {
+            let index = state.next_index;
+            state.summaries.push_back((index, instruction.summary()));
+            state.next_index += 1;
+        }
+        true
+    }
+
+    /// Unregister the last `count` instructions, which couldn't be sent to the server thread
+    pub(crate) fn cancel(&self, count: usize) {
+        let mut state = self.0.lock().unwrap();
+        let remaining = state.summaries.len().saturating_sub(count);
+        state.summaries.truncate(remaining);
+        state.next_index -= count;
+    }
+
+    /// Mark the script as finished unless instructions registered by [`PendingInstructions::push`]
+    /// are still on their way to the server thread, returning whether it finished
+    pub(crate) fn finish(&self) -> bool {
+        let mut state = self.0.lock().unwrap();
+        state.finished = state.summaries.is_empty();
+        state.finished
+    }
+
+    /// Mark the oldest pending instruction as executed
+    pub(crate) fn pop_executed(&self) {
+        self.0.lock().unwrap().summaries.pop_front();
+    }
+
+    /// Index and summary of the oldest pending instruction, i.e. the one being executed by the server thread
+    pub(crate) fn executing(&self) -> Option<(usize, String)> {
+        self.0.lock().unwrap().summaries.front().cloned()
+    }
+
+    /// Drop the pending instructions, asking the server thread to stop before the next one
+    pub(crate) fn abort(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.summaries.clear();
+        state.aborted = true;
+    }
+
+    /// Whether the server thread must stop, see [`PendingInstructions::abort`]
+    pub(crate) fn is_aborted(&self) -> bool {
+        self.0.lock().unwrap().aborted
+    }
+
+    /// Wait for the server thread to execute all pending instructions,
+    /// as long as it executes at least one of them every `progress_timeout`.
+    ///
+    /// Return the summaries of the instructions that were never executed.
+    pub(crate) fn wait_for_execution(&self, progress_timeout: Duration) -> Vec<String> {
+        let mut last_pending_count = usize::MAX;
+        let mut last_progress = Instant::now();
+        loop {
+            let pending_count = self.0.lock().unwrap().summaries.len();
+            if pending_count == 0 {
+                return Vec::new();
+            }
+            if pending_count < last_pending_count {
+                last_pending_count = pending_count;
+                last_progress = Instant::now();
+            } else if last_progress.elapsed() >= progress_timeout {
+                let state = self.0.lock().unwrap();
+                return state
+                    .summaries
+                    .iter()
+                    .map(|(index, summary)| format!("#{index} {summary}"))
+                    .collect();
+            }
+            thread::sleep(IDLE_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Expand the template of a [`Instruction::SendTemplate`] with the captures of the last received message
+pub(crate) fn expand_template(
+    pattern: &Regex,
+    template: &[u8],
+    last_received_message: Option<&[u8]>,
+) -> Result<Vec<u8>, ServerMockerError> {
+    let captures = last_received_message
+        .and_then(|message| pattern.captures(message))
+        .ok_or_else(|| UnmatchedTemplatePattern(pattern.to_string()))?;
+    let mut message = Vec::new();
+    captures.expand(template, &mut message);
+    Ok(message)
+}
@@ -50,14 +50,66 @@
 //! assert!(server.pop_server_error().is_none());
 //! ```
 
+#[cfg(feature = "rcgen")]
+mod certificates;
+mod chaos;
+mod codec;
 mod errors;
+mod framing;
+#[cfg(feature = "serde")]
+mod har;
+mod hex;
+mod hooks;
+mod http;
 mod instructions;
+mod matcher;
+#[cfg(feature = "serde")]
+mod openapi;
+mod pcap;
+#[cfg(feature = "prost")]
+mod proto;
+pub mod protocols;
+mod recorder;
+mod rng;
+#[cfg(feature = "serde")]
+mod script;
 mod server_mocker;
+mod stream;
 mod tcp_server;
+mod timeline;
+#[cfg(feature = "tls")]
+mod tls_server;
+mod traffic;
+mod transcript;
 mod udp_server;
+#[cfg(feature = "serde")]
+mod wiremock;
 
-pub use errors::ServerMockerError;
-pub use instructions::Instruction;
+/// Re-exported for [`Hooks::on_socket`]
+pub use socket2;
+
+#[cfg(feature = "rcgen")]
+pub use certificates::TlsCertificates;
+pub use chaos::ChaosConfig;
+pub use codec::{Codec, RawCodec};
+pub use errors::{ErrorReport, ReadInterruption, ServerMockerError};
+pub use framing::{Endianness, Framer, Framing};
+pub use hooks::Hooks;
+pub use http::HttpMock;
+pub use instructions::{
+    Finish, IdlePolicy, Instruction, MessageResponder, PeerRoute, PeerSelector, Times,
+};
+pub use matcher::Matcher;
+#[cfg(feature = "prost")]
+pub use proto::decode_prost;
+pub use recorder::Recorder;
 pub use server_mocker::ServerMocker;
-pub use tcp_server::TcpMocker;
+#[cfg(feature = "futures")]
+pub use stream::{ErrorStream, MessageStream};
+pub use tcp_server::{ExcessConnections, Keepalive, TcpMocker};
+pub use timeline::{TimelineEntry, TimelineEvent};
+#[cfg(feature = "tls")]
+pub use tls_server::TlsMocker;
+pub use traffic::{Direction, ReceivedMessage, ServerStats, WireEvent};
+pub use transcript::{Transcript, TranscriptFormat};
 pub use udp_server::UdpMocker;
@@ -0,0 +1,143 @@
+//! # `matcher`
+//!
+//! Matchers used to verify the messages received by the server mocker.
+
+use std::fmt;
+use std::fmt::Write;
+
+use crate::hex::{hex_diff, preview};
+
+/// Describe the expected content of a message received by the server mocker.
+///
+/// Used by [`ServerMocker::verify_sequence`](crate::ServerMocker::verify_sequence).
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// The message must be exactly equal to the given bytes
+    Exact(Vec<u8>),
+    /// The message must start with the given bytes
+    StartsWith(Vec<u8>),
+    /// The message must contain the given bytes
+    Contains(Vec<u8>),
+    /// Any message matches
+    Any,
+    /// The message must satisfy the given predicate
+    Custom(fn(&[u8]) -> bool),
+}
+
+impl Matcher {
+    /// Check if the given message matches
+    pub fn matches(&self, message: &[u8]) -> bool {
+        match self {
+            Matcher::Exact(expected) => message == expected.as_slice(),
+            Matcher::StartsWith(prefix) => message.starts_with(prefix),
+            Matcher::Contains(needle) => {
+                needle.is_empty()
+                    || message
+                        .windows(needle.len())
+                        .any(|w| w == needle.as_slice())
+            }
+            Matcher::Any => true,
+            Matcher::Custom(predicate) => predicate(message),
+        }
+    }
+
+    /// Byte-level diff between the expected bytes and the part of the message compared to them, if relevant
+    fn diff(&self, message: &[u8]) -> Option<String> {
+        match self {
+            Matcher::Exact(expected) => hex_diff(expected, message),
+            Matcher::StartsWith(prefix) => {
+                hex_diff(prefix, &message[..prefix.len().min(message.len())])
+            }
+            Matcher::Contains(_) | Matcher::Any | Matcher::Custom(_) => None,
+        }
+    }
+}
+
+impl PartialEq for Matcher {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Matcher::Exact(expected), Matcher::Exact(other_expected))
+            | (Matcher::StartsWith(expected), Matcher::StartsWith(other_expected))
+            | (Matcher::Contains(expected), Matcher::Contains(other_expected)) => {
+                expected == other_expected
+            }
+            (Matcher::Any, Matcher::Any) => true,
+            // Same function
+            (Matcher::Custom(predicate), Matcher::Custom(other_predicate)) => {
+                *predicate as usize == *other_predicate as usize
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Matcher::Exact(expected) => write!(f, "exactly {}", preview(expected)),
+            Matcher::StartsWith(prefix) => write!(f, "starting with {}", preview(prefix)),
+            Matcher::Contains(needle) => write!(f, "containing {}", preview(needle)),
+            Matcher::Any => write!(f, "any message"),
+            Matcher::Custom(_) => write!(f, "matching a custom predicate"),
+        }
+    }
+}
+
+/// Check that `messages` match `matchers` in order, and build a diff-style report if they don't.
+///
+/// In lenient mode, messages that don't match the next matcher are ignored.
+pub(crate) fn verify_sequence(
+    messages: &[Vec<u8>],
+    matchers: &[Matcher],
+    lenient: bool,
+) -> Result<(), String> {
+    let mut report = String::new();
+    let mut success = true;
+    let mut messages_iter = messages.iter().enumerate();
+
+    for matcher in matchers {
+        loop {
+            match messages_iter.next() {
+                Some((index, message)) if matcher.matches(message) => {
+                    let _ = writeln!(report, "  [{index}] {}", preview(message));
+                    break;
+                }
+                Some((index, message)) if lenient => {
+                    let _ = writeln!(report, "~ [{index}] {} (ignored)", preview(message));
+                }
+                Some((index, message)) => {
+                    let _ = writeln!(report, "- [{index}] expected {matcher}");
+                    let _ = writeln!(report, "+ [{index}] received {}", preview(message));
+                    if let Some(diff) = matcher.diff(message) {
+                        for line in diff.lines() {
+                            let _ = writeln!(report, "    {line}");
+                        }
+                    }
+                    success = false;
+                    break;
+                }
+                None => {
+                    let _ = writeln!(report, "- expected {matcher}");
+                    let _ = writeln!(report, "+ no matching message received");
+                    success = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    for (index, message) in messages_iter {
+        if lenient {
+            let _ = writeln!(report, "~ [{index}] {} (ignored)", preview(message));
+        } else {
+            let _ = writeln!(report, "+ [{index}] unexpected {}", preview(message));
+            success = false;
+        }
+    }
+
+    if success {
+        Ok(())
+    } else {
+        Err(report)
+    }
+}
@@ -0,0 +1,195 @@
+//! # `openapi`
+//!
+//! HTTP mocks generated from the paths, methods and examples of an `OpenAPI` 3 specification,
+//! available with the `serde` feature.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::http::{url_path, HttpMock};
+use crate::ServerMockerError::{self, InvalidOpenApiSpec, UnableToReadFile};
+
+/// Methods of the operations of an `OpenAPI` path item
+const METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Maximum nesting of the payloads synthesized from a schema, to stop on recursive schemas
+const MAX_SCHEMA_DEPTH: usize = 8;
+
+/// Read one mock per operation of the given JSON or YAML specification,
+/// the format being chosen from the file extension
+pub(crate) fn load_openapi(path: &Path) -> Result<Vec<HttpMock>, ServerMockerError> {
+    let content = fs::read_to_string(path).map_err(|e| UnableToReadFile(path.to_owned(), e))?;
+    let is_yaml = path
+        .extension()
+        .is_some_and(|extension| extension == "yaml" || extension == "yml");
+    let spec: Value = if is_yaml {
+        serde_yaml::from_str(&content).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    }
+    .map_err(|e| InvalidOpenApiSpec(path.to_owned(), e))?;
+    openapi_mocks(&spec).map_err(|e| InvalidOpenApiSpec(path.to_owned(), e))
+}
+
+fn openapi_mocks(spec: &Value) -> Result<Vec<HttpMock>, String> {
+    if !spec
+        .get("openapi")
+        .and_then(Value::as_str)
+        .is_some_and(|version| version.starts_with('3'))
+    {
+        return Err("only OpenAPI 3 specifications are supported".to_string());
+    }
+    // The path of the first server prefixes all the paths
+    let base_path = spec
+        .pointer("/servers/0/url")
+        .and_then(Value::as_str)
+        .map_or("", |url| url_path(url).trim_end_matches('/'));
+    let paths = spec
+        .get("paths")
+        .and_then(Value::as_object)
+        .ok_or("missing `paths`")?;
+
+    let mut mocks = Vec::new();
+    for (path, path_item) in paths {
+        for method in METHODS {
+            let Some(operation) = path_item.get(method) else {
+                continue;
+            };
+            let mut mock = HttpMock::new(method.to_ascii_uppercase(), format!("{base_path}{path}"));
+            if let Some((status, response)) = success_response(operation) {
+                mock.status = status;
+                if let Some((media_type, example)) = response_example(spec, response) {
+                    mock = mock.header("Content-Type", media_type).body(example);
+                }
+            }
+            mocks.push(mock);
+        }
+    }
+    // Literal paths first, so that `/users/me` isn't shadowed by `/users/{id}`
+    mocks.sort_by_key(|mock| mock.path.contains('{'));
+    Ok(mocks)
+}
+
+/// Status code and definition of the first success response of the operation
+fn success_response(operation: &Value) -> Option<(u16, &Value)> {
+    let responses = operation.get("responses")?.as_object()?;
+    responses
+        .iter()
+        .filter_map(|(status, response)| Some((status.parse::<u16>().ok()?, response)))
+        .filter(|(status, _)| (200..300).contains(status))
+        .min_by_key(|(status, _)| *status)
+        .or_else(|| responses.get("default").map(|response| (200, response)))
+}
+
+/// Media type and example payload of the response, from its examples or synthesized from its schema
+fn response_example(spec: &Value, response: &Value) -> Option<(String, Vec<u8>)> {
+    let response = resolve(spec, response);
+    let content = response.get("content")?.as_object()?;
+    let (media_type, media) = content
+        .iter()
+        .find(|(media_type, _)| media_type.contains("json"))
+        .or_else(|| content.iter().next())?;
+    let example = media
+        .get("example")
+        .cloned()
+        .or_else(|| {
+            let examples = media.get("examples")?.as_object()?;
+            let example = resolve(spec, examples.values().next()?);
+            example.get("value").cloned()
+        })
+        .or_else(|| {
+            let schema = media.get("schema")?;
+            Some(schema_example(spec, schema, 0))
+        })?;
+    let body = match example {
+        // Plain text payloads are sent as is
+        Value::String(text) if !media_type.contains("json") => text.into_bytes(),
+        example => example.to_string().into_bytes(),
+    };
+    Some((media_type.clone(), body))
+}
+
+/// Example value of the given schema: its own example, or a value synthesized from its type
+fn schema_example(spec: &Value, schema: &Value, depth: usize) -> Value {
+    let schema = resolve(spec, schema);
+    if let Some(example) = schema.get("example").or_else(|| schema.get("default")) {
+        return example.clone();
+    }
+    if let Some(value) = schema
+        .get("enum")
+        .and_then(Value::as_array)
+        .and_then(|values| values.first())
+    {
+        return value.clone();
+    }
+    if depth >= MAX_SCHEMA_DEPTH {
+        return Value::Null;
+    }
+    if let Some(schema) = ["allOf", "oneOf", "anyOf"].iter().find_map(|composition| {
+        schema
+            .get(composition)
+            .and_then(Value::as_array)
+            .and_then(|schemas| schemas.first())
+    }) {
+        return schema_example(spec, schema, depth + 1);
+    }
+    match schema.get("type").and_then(Value::as_str) {
+        Some("object") | None if schema.get("properties").is_some() => {
+            let properties = schema
+                .get("properties")
+                .and_then(Value::as_object)
+                .map(|properties| {
+                    properties
+                        .iter()
+                        .map(|(name, property)| {
+                            (name.clone(), schema_example(spec, property, depth + 1))
+                        })
+                        .collect::<Map<String, Value>>()
+                })
+                .unwrap_or_default();
+            Value::Object(properties)
+        }
+        Some("object") => Value::Object(Map::new()),
+        Some("array") => Value::Array(
+            schema
+                .get("items")
+                .map(|items| vec![schema_example(spec, items, depth + 1)])
+                .unwrap_or_default(),
+        ),
+        Some("string") => Value::String(
+            match schema.get("format").and_then(Value::as_str) {
+                Some("date") => "2024-01-01",
+                Some("date-time") => "2024-01-01T00:00:00Z",
+                Some("uuid") => "00000000-0000-0000-0000-000000000000",
+                Some("email") => "user@example.com",
+                _ => "string",
+            }
+            .to_string(),
+        ),
+        Some("integer" | "number") => Value::from(0),
+        Some("boolean") => Value::Bool(true),
+        _ => Value::Null,
+    }
+}
+
+/// Follow the local `$ref` of the given object, if any
+fn resolve<'a>(spec: &'a Value, value: &'a Value) -> &'a Value {
+    let mut value = value;
+    // Bounded, in case of circular references
+    for _ in 0..MAX_SCHEMA_DEPTH {
+        match value
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| spec.pointer(pointer))
+        {
+            Some(referenced) => value = referenced,
+            None => break,
+        }
+    }
+    value
+}
@@ -0,0 +1,478 @@
+//! # `pcap`
+//!
+//! Write the traffic of the server mocker as a pcap capture, with synthesized IP, TCP and UDP headers,
+//! and read a capture of a real exchange back as server mocker instructions.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::traffic::{Direction, Packet, Transport};
+use crate::Instruction::{self, ReceiveExactBytes, ReceiveMessage, SendMessage, StopExchange};
+
+/// Magic number of pcap files with microsecond timestamps
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+/// Magic number of pcap files with nanosecond timestamps
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+/// Size of the pcap global header
+const GLOBAL_HEADER_SIZE: usize = 24;
+/// Size of the pcap record header
+const RECORD_HEADER_SIZE: usize = 16;
+
+/// BSD loopback encapsulation, as captured on the loopback interface of macOS
+const LINKTYPE_NULL: u32 = 0;
+/// Ethernet frames, as captured on the loopback interface of Linux
+const LINKTYPE_ETHERNET: u32 = 1;
+/// Link type of raw IPv4 or IPv6 packets, without link layer header
+const LINKTYPE_RAW: u32 = 101;
+/// Linux cooked capture, as captured on the `any` interface
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+/// Maximum size of the captured packets
+const SNAPLEN: u32 = 262_144;
+/// Maximum payload of a synthesized TCP segment, so that the IP packet length fits in 16 bits
+const MAX_SEGMENT_SIZE: usize = 65_000;
+
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+/// Next sequence numbers of a TCP connection
+struct TcpConnection {
+    client_seq: u32,
+    server_seq: u32,
+}
+
+/// Write the given packets exchanged with the server at `server_addr` as a pcap capture.
+///
+/// TCP connections start with a synthesized three-way handshake, so that dissectors can follow the streams.
+pub(crate) fn write_pcap(
+    mut writer: impl Write,
+    server_addr: SocketAddr,
+    packets: &[Packet],
+) -> io::Result<()> {
+    // Global header, in little endian
+    writer.write_all(&PCAP_MAGIC.to_le_bytes())?;
+    writer.write_all(&2_u16.to_le_bytes())?;
+    writer.write_all(&4_u16.to_le_bytes())?;
+    writer.write_all(&0_i32.to_le_bytes())?;
+    writer.write_all(&0_u32.to_le_bytes())?;
+    writer.write_all(&SNAPLEN.to_le_bytes())?;
+    writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+
+    let mut connections: HashMap<SocketAddr, TcpConnection> = HashMap::new();
+    for packet in packets {
+        let timestamp = packet
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let client = packet.client_addr;
+        let (source, destination) = match packet.direction {
+            Direction::Inbound => (client, server_addr),
+            Direction::Outbound => (server_addr, client),
+        };
+        if packet.transport == Transport::Udp {
+            let datagram = udp_datagram(source, destination, &packet.data);
+            write_record(&mut writer, timestamp, &datagram)?;
+            continue;
+        }
+
+        let connection = match connections.entry(client) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let handshake = [
+                    (client, server_addr, 0, 0, TCP_SYN),
+                    (server_addr, client, 0, 1, TCP_SYN | TCP_ACK),
+                    (client, server_addr, 1, 1, TCP_ACK),
+                ];
+                for (source, destination, seq, ack, flags) in handshake {
+                    let segment = tcp_segment(source, destination, seq, ack, flags, &[]);
+                    write_record(&mut writer, timestamp, &segment)?;
+                }
+                entry.insert(TcpConnection {
+                    client_seq: 1,
+                    server_seq: 1,
+                })
+            }
+        };
+        for data in packet.data.chunks(MAX_SEGMENT_SIZE) {
+            let length = u32::try_from(data.len()).unwrap_or(u32::MAX);
+            let (seq, ack) = match packet.direction {
+                Direction::Inbound => {
+                    let seq = connection.client_seq;
+                    connection.client_seq = seq.wrapping_add(length);
+                    (seq, connection.server_seq)
+                }
+                Direction::Outbound => {
+                    let seq = connection.server_seq;
+                    connection.server_seq = seq.wrapping_add(length);
+                    (seq, connection.client_seq)
+                }
+            };
+            let segment = tcp_segment(source, destination, seq, ack, TCP_PSH | TCP_ACK, data);
+            write_record(&mut writer, timestamp, &segment)?;
+        }
+    }
+    writer.flush()
+}
+
+/// Write a pcap record holding the given IP packet
+fn write_record(writer: &mut impl Write, timestamp: Duration, ip_packet: &[u8]) -> io::Result<()> {
+    let length = u32::try_from(ip_packet.len()).unwrap_or(u32::MAX);
+    let seconds = u32::try_from(timestamp.as_secs()).unwrap_or(u32::MAX);
+    writer.write_all(&seconds.to_le_bytes())?;
+    writer.write_all(&timestamp.subsec_micros().to_le_bytes())?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(ip_packet)
+}
+
+/// Synthesize an IP packet holding a TCP segment
+fn tcp_segment(
+    source: SocketAddr,
+    destination: SocketAddr,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut segment = Vec::with_capacity(20 + payload.len());
+    segment.extend_from_slice(&source.port().to_be_bytes());
+    segment.extend_from_slice(&destination.port().to_be_bytes());
+    segment.extend_from_slice(&seq.to_be_bytes());
+    segment.extend_from_slice(&ack.to_be_bytes());
+    // Data offset of 5 words, no option
+    segment.extend_from_slice(&[5 << 4, flags]);
+    segment.extend_from_slice(&u16::MAX.to_be_bytes());
+    // Checksum, then urgent pointer
+    segment.extend_from_slice(&[0; 4]);
+    segment.extend_from_slice(payload);
+    ip_packet(source, destination, PROTOCOL_TCP, segment, 16)
+}
+
+/// Synthesize an IP packet holding a UDP datagram
+fn udp_datagram(source: SocketAddr, destination: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let length = u16::try_from(8 + payload.len()).unwrap_or(u16::MAX);
+    let mut datagram = Vec::with_capacity(8 + payload.len());
+    datagram.extend_from_slice(&source.port().to_be_bytes());
+    datagram.extend_from_slice(&destination.port().to_be_bytes());
+    datagram.extend_from_slice(&length.to_be_bytes());
+    datagram.extend_from_slice(&[0; 2]);
+    datagram.extend_from_slice(payload);
+    ip_packet(source, destination, PROTOCOL_UDP, datagram, 6)
+}
+
+/// Wrap the given transport segment into an IPv4 packet, or an IPv6 packet if any of the addresses is IPv6,
+/// writing the segment checksum at `checksum_offset`
+fn ip_packet(
+    source: SocketAddr,
+    destination: SocketAddr,
+    protocol: u8,
+    mut segment: Vec<u8>,
+    checksum_offset: usize,
+) -> Vec<u8> {
+    let mut packet = match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            let total_length = u16::try_from(20 + segment.len()).unwrap_or(u16::MAX);
+            let mut header = Vec::with_capacity(20 + segment.len());
+            // Version 4, header of 5 words
+            header.extend_from_slice(&[0x45, 0]);
+            header.extend_from_slice(&total_length.to_be_bytes());
+            // Identification, then don't fragment flag
+            header.extend_from_slice(&[0, 0, 0x40, 0]);
+            // Time to live, protocol and header checksum
+            header.extend_from_slice(&[64, protocol, 0, 0]);
+            header.extend_from_slice(&source.octets());
+            header.extend_from_slice(&destination.octets());
+            let header_checksum = checksum(&[&header]);
+            header[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+
+            let segment_length = u16::try_from(segment.len()).unwrap_or(u16::MAX);
+            let mut pseudo_header = header[12..20].to_vec();
+            pseudo_header.extend_from_slice(&[0, protocol]);
+            pseudo_header.extend_from_slice(&segment_length.to_be_bytes());
+            write_checksum(&mut segment, checksum_offset, &pseudo_header, protocol);
+            header
+        }
+        (source, destination) => {
+            let payload_length = u16::try_from(segment.len()).unwrap_or(u16::MAX);
+            let mut header = Vec::with_capacity(40 + segment.len());
+            // Version 6, no traffic class nor flow label
+            header.extend_from_slice(&[0x60, 0, 0, 0]);
+            header.extend_from_slice(&payload_length.to_be_bytes());
+            // Next header and hop limit
+            header.extend_from_slice(&[protocol, 64]);
+            header.extend_from_slice(&ipv6_octets(source));
+            header.extend_from_slice(&ipv6_octets(destination));
+
+            let segment_length = u32::try_from(segment.len()).unwrap_or(u32::MAX);
+            let mut pseudo_header = header[8..40].to_vec();
+            pseudo_header.extend_from_slice(&segment_length.to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, protocol]);
+            write_checksum(&mut segment, checksum_offset, &pseudo_header, protocol);
+            header
+        }
+    };
+    packet.extend_from_slice(&segment);
+    packet
+}
+
+/// Compute the checksum of the segment with the given pseudo header, and write it at `offset`
+fn write_checksum(segment: &mut [u8], offset: usize, pseudo_header: &[u8], protocol: u8) {
+    let checksum = match checksum(&[pseudo_header, segment]) {
+        // A zero UDP checksum means no checksum
+        0 if protocol == PROTOCOL_UDP => u16::MAX,
+        checksum => checksum,
+    };
+    segment[offset..offset + 2].copy_from_slice(&checksum.to_be_bytes());
+}
+
+/// IPv6 address octets, IPv4 addresses being mapped to IPv6
+fn ipv6_octets(address: IpAddr) -> [u8; 16] {
+    match address {
+        IpAddr::V4(address) => address.to_ipv6_mapped().octets(),
+        IpAddr::V6(address) => address.octets(),
+    }
+}
+
+/// Internet checksum (RFC 1071) of the concatenation of the given data
+fn checksum(data: &[&[u8]]) -> u16 {
+    let mut sum = 0_u32;
+    let mut bytes = data.iter().flat_map(|chunk| chunk.iter());
+    while let Some(&high) = bytes.next() {
+        let low = bytes.next().copied().unwrap_or(0);
+        sum += u32::from(u16::from_be_bytes([high, low]));
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !u16::try_from(sum).unwrap_or(u16::MAX)
+}
+
+/// TCP segment or UDP datagram extracted from a captured packet
+struct Segment<'a> {
+    transport: Transport,
+    source: SocketAddr,
+    destination: SocketAddr,
+    /// Sequence number and SYN flag of TCP segments
+    tcp_seq: Option<(u32, bool)>,
+    payload: &'a [u8],
+}
+
+/// Build the instructions replaying the server listening on `server_port` in the given pcap capture.
+///
+/// Only the first TCP connection or UDP client of the server is replayed. The data sent by the client
+/// becomes receive instructions, and the data sent by the server becomes [`SendMessage`] instructions.
+/// Consecutive TCP segments in the same direction are merged, and retransmitted segments are skipped.
+pub(crate) fn read_instructions(
+    capture: &[u8],
+    server_port: u16,
+) -> Result<Vec<Instruction>, String> {
+    let header = capture
+        .get(..GLOBAL_HEADER_SIZE)
+        .ok_or("truncated pcap header")?;
+    let magic = [header[0], header[1], header[2], header[3]];
+    let little_endian = if [PCAP_MAGIC, PCAP_MAGIC_NANOS].contains(&u32::from_le_bytes(magic)) {
+        true
+    } else if [PCAP_MAGIC, PCAP_MAGIC_NANOS].contains(&u32::from_be_bytes(magic)) {
+        false
+    } else {
+        return Err("not a pcap capture (pcapng captures must be converted first)".to_string());
+    };
+    let read_u32 = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    };
+    let link_type = read_u32(&header[20..24]);
+
+    let mut client: Option<(Transport, SocketAddr)> = None;
+    let mut client_next_seq: Option<u32> = None;
+    let mut server_next_seq: Option<u32> = None;
+    let mut exchange: Vec<(Direction, Vec<u8>)> = Vec::new();
+    let mut records = &capture[GLOBAL_HEADER_SIZE..];
+    while !records.is_empty() {
+        let record_header = records
+            .get(..RECORD_HEADER_SIZE)
+            .ok_or("truncated record header")?;
+        let length = usize::try_from(read_u32(&record_header[8..12])).unwrap_or(usize::MAX);
+        let data = records[RECORD_HEADER_SIZE..]
+            .get(..length)
+            .ok_or("truncated record")?;
+        records = &records[RECORD_HEADER_SIZE + length..];
+
+        let Some(segment) = link_payload(link_type, data).and_then(parse_ip) else {
+            continue;
+        };
+        let (direction, peer) = if segment.destination.port() == server_port {
+            (Direction::Inbound, segment.source)
+        } else if segment.source.port() == server_port {
+            (Direction::Outbound, segment.destination)
+        } else {
+            continue;
+        };
+        if *client.get_or_insert((segment.transport, peer)) != (segment.transport, peer) {
+            continue;
+        }
+
+        if let Some((seq, syn)) = segment.tcp_seq {
+            let next_seq = match direction {
+                Direction::Inbound => &mut client_next_seq,
+                Direction::Outbound => &mut server_next_seq,
+            };
+            if syn {
+                *next_seq = Some(seq.wrapping_add(1));
+            }
+            if segment.payload.is_empty() {
+                continue;
+            }
+            // Segments starting before the expected sequence number were already captured
+            if next_seq.is_some_and(|next| seq.wrapping_sub(next) > u32::MAX / 2) {
+                continue;
+            }
+            let length = u32::try_from(segment.payload.len()).unwrap_or(u32::MAX);
+            *next_seq = Some(seq.wrapping_add(length));
+            match exchange.last_mut() {
+                Some((last_direction, last_data)) if *last_direction == direction => {
+                    last_data.extend_from_slice(segment.payload);
+                }
+                _ => exchange.push((direction, segment.payload.to_vec())),
+            }
+        } else {
+            exchange.push((direction, segment.payload.to_vec()));
+        }
+    }
+
+    let Some((transport, _)) = client else {
+        return Err(format!("no packet exchanged with port {server_port}"));
+    };
+    let mut instructions: Vec<Instruction> = exchange
+        .into_iter()
+        .map(|(direction, data)| match (direction, transport) {
+            (Direction::Inbound, Transport::Tcp) => ReceiveExactBytes(data.len()),
+            (Direction::Inbound, Transport::Udp) => ReceiveMessage,
+            (Direction::Outbound, _) => SendMessage(data),
+        })
+        .collect();
+    instructions.push(StopExchange);
+    Ok(instructions)
+}
+
+/// IP packet held by a captured frame, if any
+fn link_payload(link_type: u32, frame: &[u8]) -> Option<&[u8]> {
+    match link_type {
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(frame),
+        // The address family is in the byte order of the capturing host, the IP version is enough
+        LINKTYPE_NULL => frame.get(4..),
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ether_type = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+            while ether_type == ETHERTYPE_VLAN {
+                offset += 4;
+                ether_type = u16::from_be_bytes([*frame.get(offset)?, *frame.get(offset + 1)?]);
+            }
+            [ETHERTYPE_IPV4, ETHERTYPE_IPV6]
+                .contains(&ether_type)
+                .then(|| frame.get(offset + 2..))
+                .flatten()
+        }
+        LINKTYPE_LINUX_SLL => {
+            let protocol = u16::from_be_bytes([*frame.get(14)?, *frame.get(15)?]);
+            [ETHERTYPE_IPV4, ETHERTYPE_IPV6]
+                .contains(&protocol)
+                .then(|| frame.get(16..))
+                .flatten()
+        }
+        _ => None,
+    }
+}
+
+/// TCP segment or UDP datagram held by an IPv4 or IPv6 packet, without extension headers
+fn parse_ip(packet: &[u8]) -> Option<Segment<'_>> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_length = usize::from(packet[0] & 0x0F) * 4;
+            let header = packet.get(..header_length.max(20))?;
+            // The total length is zero for segments offloaded to the network card
+            let total_length = match usize::from(u16::from_be_bytes([header[2], header[3]])) {
+                0 => packet.len(),
+                total_length => total_length.min(packet.len()),
+            };
+            let source: [u8; 4] = header[12..16].try_into().ok()?;
+            let destination: [u8; 4] = header[16..20].try_into().ok()?;
+            parse_transport(
+                header[9],
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                packet.get(header_length..total_length)?,
+            )
+        }
+        6 => {
+            let header = packet.get(..40)?;
+            let payload_length = usize::from(u16::from_be_bytes([header[4], header[5]]));
+            let source: [u8; 16] = header[8..24].try_into().ok()?;
+            let destination: [u8; 16] = header[24..40].try_into().ok()?;
+            parse_transport(
+                header[6],
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                packet.get(40..(40 + payload_length).min(packet.len()))?,
+            )
+        }
+        _ => None,
+    }
+}
+
+/// TCP segment or UDP datagram exchanged between the given addresses
+fn parse_transport(
+    protocol: u8,
+    source: IpAddr,
+    destination: IpAddr,
+    segment: &[u8],
+) -> Option<Segment<'_>> {
+    let source_port = u16::from_be_bytes([*segment.first()?, *segment.get(1)?]);
+    let destination_port = u16::from_be_bytes([*segment.get(2)?, *segment.get(3)?]);
+    let (transport, tcp_seq, payload) = match protocol {
+        PROTOCOL_TCP => {
+            let header = segment.get(..20)?;
+            let seq = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+            let data_offset = usize::from(header[12] >> 4) * 4;
+            let syn = header[13] & TCP_SYN != 0;
+            (
+                Transport::Tcp,
+                Some((seq, syn)),
+                segment.get(data_offset..)?,
+            )
+        }
+        PROTOCOL_UDP => {
+            let header = segment.get(..8)?;
+            let length = usize::from(u16::from_be_bytes([header[4], header[5]]));
+            (
+                Transport::Udp,
+                None,
+                segment.get(8..length.min(segment.len()))?,
+            )
+        }
+        _ => return None,
+    };
+    Some(Segment {
+        transport,
+        source: SocketAddr::new(source, source_port),
+        destination: SocketAddr::new(destination, destination_port),
+        tcp_seq,
+        payload,
+    })
+}
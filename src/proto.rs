@@ -0,0 +1,38 @@
+//! # `proto`
+//!
+//! Decode the protobuf messages received from the client with prost.
+
+use prost::Message;
+
+/// Decode a protobuf message, sent as is or preceded by its length as a varint, as written by
+/// `prost::Message::encode_length_delimited`.
+///
+/// The message is considered length-delimited if it starts with a varint equal to the length of the rest of the message,
+/// and if the rest decodes successfully. Otherwise, the whole message is decoded.
+///
+/// # Example
+/// ```
+/// use prost::Message;
+/// use socket_server_mocker::decode_prost;
+///
+/// #[derive(Clone, PartialEq, Message)]
+/// struct Login {
+///     #[prost(string, tag = "1")]
+///     user: String,
+/// }
+///
+/// let login = Login { user: "alice".to_string() };
+/// assert_eq!(Ok(login.clone()), decode_prost::<Login>(&login.encode_to_vec()));
+/// assert_eq!(Ok(login.clone()), decode_prost::<Login>(&login.encode_length_delimited_to_vec()));
+/// ```
+pub fn decode_prost<M: Message + Default>(message: &[u8]) -> Result<M, String> {
+    let mut rest = message;
+    if let Ok(length) = prost::encoding::decode_varint(&mut rest) {
+        if usize::try_from(length) == Ok(rest.len()) {
+            if let Ok(decoded) = M::decode(rest) {
+                return Ok(decoded);
+            }
+        }
+    }
+    M::decode(message).map_err(|e| e.to_string())
+}
@@ -0,0 +1,591 @@
+//! # `amqp`
+//!
+//! AMQP 0-9-1 broker: connection and channel handshakes, then scripted deliveries to consumers
+//! and acknowledgements of published messages.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::Instruction::{self, ReceiveMessage, Repeat, StopExchange};
+use crate::Times;
+
+/// Header sent by the client before the first frame
+const PROTOCOL_HEADER: &[u8] = b"AMQP\x00\x00\x09\x01";
+
+/// Frame types, and the byte ending each frame
+const METHOD_FRAME: u8 = 1;
+const HEADER_FRAME: u8 = 2;
+const BODY_FRAME: u8 = 3;
+const FRAME_END: u8 = 0xce;
+/// Size of the frame header: type, channel and payload size
+const FRAME_HEADER_SIZE: usize = 7;
+
+/// Limits negotiated with the Connection.Tune method
+const CHANNEL_MAX: u16 = 2047;
+const FRAME_MAX: u32 = 131_072;
+
+/// Method classes
+const CONNECTION: u16 = 10;
+const CHANNEL: u16 = 20;
+const EXCHANGE: u16 = 40;
+const QUEUE: u16 = 50;
+const BASIC: u16 = 60;
+const CONFIRM: u16 = 85;
+
+/// Reply code closing the connection on a method which isn't mocked
+const NOT_IMPLEMENTED: u16 = 540;
+
+/// Build the instructions of an AMQP 0-9-1 broker, until the client disconnects.
+///
+/// The broker accepts any credentials and virtual host, then opens channels and declares queues,
+/// exchanges and bindings on demand. Scripted messages are delivered as soon as a client consumes
+/// their queue, and messages published by the client are acknowledged once confirms are selected.
+/// What the client published and acknowledged is available through [`AmqpMockBuilder::activity`].
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::amqp::AmqpMockBuilder;
+///
+/// let amqp_mock = AmqpMockBuilder::new()
+///     .deliver("tasks", b"first task".to_vec())
+///     .deliver("tasks", b"second task".to_vec());
+/// let activity = amqp_mock.activity();
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = amqp_mock.build();
+/// assert!(activity.acked_delivery_tags().is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct AmqpMockBuilder {
+    heartbeat: u16,
+    deliveries: Vec<(String, Vec<u8>)>,
+    activity: AmqpActivity,
+}
+
+impl Default for AmqpMockBuilder {
+    fn default() -> Self {
+        Self {
+            heartbeat: 60,
+            deliveries: Vec::new(),
+            activity: AmqpActivity::default(),
+        }
+    }
+}
+
+impl AmqpMockBuilder {
+    /// Broker with no scripted message, suggesting a 60 seconds heartbeat
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the heartbeat suggested to the client, in seconds, 0 disabling heartbeats.
+    ///
+    /// Heartbeat frames sent by the client are ignored.
+    #[must_use]
+    pub fn heartbeat(mut self, heartbeat: u16) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Deliver the given message to the first consumer of the given queue, through the default exchange
+    #[must_use]
+    pub fn deliver(mut self, queue: impl Into<String>, body: Vec<u8>) -> Self {
+        self.deliveries.push((queue.into(), body));
+        self
+    }
+
+    /// Messages published and deliveries acknowledged by the client, once the server mocker received them
+    pub fn activity(&self) -> AmqpActivity {
+        self.activity.clone()
+    }
+
+    /// Instructions answering the frames of the client until it disconnects, then stopping the exchange.
+    ///
+    /// Connection.Close is answered without closing the socket, which is left to the client.
+    /// The exchange also stops if the client stays silent longer than the `net_timeout` of the server mocker,
+    /// which should then exceed the pauses of the client between two requests.
+    pub fn build(self) -> Vec<Instruction> {
+        let mut exchange = AmqpExchange {
+            heartbeat: self.heartbeat,
+            deliveries: self.deliveries,
+            activity: self.activity,
+            pending: Vec::new(),
+            channels: HashMap::new(),
+            generated_names: 0,
+        };
+        let responder = move |message: Option<Vec<u8>>| exchange.respond(&message?);
+        vec![
+            Repeat {
+                times: Times::at_least(0),
+                instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+            },
+            StopExchange,
+        ]
+    }
+}
+
+/// Message published by the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmqpPublishedMessage {
+    /// Exchange the message was published to, empty for the default exchange
+    pub exchange: String,
+    /// Routing key of the message, the queue name with the default exchange
+    pub routing_key: String,
+    /// Content of the message
+    pub body: Vec<u8>,
+}
+
+/// Activity of the client recorded by the AMQP server mocker, shared with the test
+#[derive(Debug, Clone, Default)]
+pub struct AmqpActivity(Arc<Mutex<AmqpActivityLog>>);
+
+#[derive(Debug, Default)]
+struct AmqpActivityLog {
+    published: Vec<AmqpPublishedMessage>,
+    acked_delivery_tags: Vec<u64>,
+    rejected_delivery_tags: Vec<u64>,
+}
+
+impl AmqpActivity {
+    /// Messages published by the client so far, oldest first
+    pub fn published(&self) -> Vec<AmqpPublishedMessage> {
+        self.log().published.clone()
+    }
+
+    /// Delivery tags acknowledged by the client with Basic.Ack so far, scripted deliveries being tagged from 1
+    pub fn acked_delivery_tags(&self) -> Vec<u64> {
+        self.log().acked_delivery_tags.clone()
+    }
+
+    /// Delivery tags rejected by the client with Basic.Reject or Basic.Nack so far
+    pub fn rejected_delivery_tags(&self) -> Vec<u64> {
+        self.log().rejected_delivery_tags.clone()
+    }
+
+    fn log(&self) -> MutexGuard<'_, AmqpActivityLog> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// State of an open channel
+#[derive(Debug, Default)]
+struct ChannelState {
+    /// Last delivery tag sent on the channel
+    delivery_tag: u64,
+    /// Count of messages published since confirms were selected, `None` before
+    confirmed: Option<u64>,
+    /// Message being published, waiting for its content
+    publishing: Option<Publishing>,
+}
+
+/// Message whose Basic.Publish method was received, but not its whole content yet
+#[derive(Debug)]
+struct Publishing {
+    exchange: String,
+    routing_key: String,
+    body_size: Option<u64>,
+    body: Vec<u8>,
+}
+
+/// Frames received from the client, some possibly incomplete
+struct AmqpExchange {
+    heartbeat: u16,
+    /// Scripted messages not delivered yet, with their queue
+    deliveries: Vec<(String, Vec<u8>)>,
+    activity: AmqpActivity,
+    /// Start of a frame whose end wasn't received yet
+    pending: Vec<u8>,
+    channels: HashMap<u16, ChannelState>,
+    /// Count of generated queue names and consumer tags
+    generated_names: usize,
+}
+
+impl AmqpExchange {
+    /// Replies to the complete frames received so far, `None` if there's none
+    fn respond(&mut self, message: &[u8]) -> Option<Vec<u8>> {
+        self.pending.extend_from_slice(message);
+        let received = std::mem::take(&mut self.pending);
+        let mut rest = received.as_slice();
+        let mut reply = Vec::new();
+        loop {
+            if let Some(after_header) = rest.strip_prefix(PROTOCOL_HEADER) {
+                reply.extend(connection_start());
+                rest = after_header;
+                continue;
+            }
+            let Some(header) = rest.get(..FRAME_HEADER_SIZE) else {
+                break;
+            };
+            let payload_size = u32::from_be_bytes([header[3], header[4], header[5], header[6]]);
+            let Some(frame_size) = usize::try_from(payload_size)
+                .ok()
+                .map(|size| FRAME_HEADER_SIZE + size + 1)
+                .filter(|frame_size| *frame_size <= rest.len())
+            else {
+                break;
+            };
+            let channel = u16::from_be_bytes([header[1], header[2]]);
+            let payload = &rest[FRAME_HEADER_SIZE..frame_size - 1];
+            match header[0] {
+                METHOD_FRAME => self.method(channel, payload, &mut reply),
+                HEADER_FRAME => self.content_header(channel, payload, &mut reply),
+                BODY_FRAME => self.content_body(channel, payload, &mut reply),
+                // Heartbeats
+                _ => {}
+            }
+            rest = &rest[frame_size..];
+        }
+        self.pending = rest.to_vec();
+        (!reply.is_empty()).then_some(reply)
+    }
+
+    /// Answer the given method frame
+    fn method(&mut self, channel: u16, payload: &[u8], reply: &mut Vec<u8>) {
+        let mut arguments = Reader(payload);
+        let (Some(class_id), Some(method_id)) = (arguments.u16(), arguments.u16()) else {
+            return;
+        };
+        let answer = match (class_id, method_id) {
+            // Connection.StartOk, answered with Connection.Tune
+            (CONNECTION, 11) => {
+                let mut tune = CHANNEL_MAX.to_be_bytes().to_vec();
+                tune.extend_from_slice(&FRAME_MAX.to_be_bytes());
+                tune.extend_from_slice(&self.heartbeat.to_be_bytes());
+                Some((30, tune))
+            }
+            // Connection.Open and Connection.Close
+            (CONNECTION, 40) => Some((41, vec![0])),
+            (CONNECTION, 50) => Some((51, Vec::new())),
+            // Channel.Open and Channel.Close
+            (CHANNEL, 10) => {
+                self.channels.insert(channel, ChannelState::default());
+                Some((11, vec![0; 4]))
+            }
+            (CHANNEL, 40) => {
+                self.channels.remove(&channel);
+                Some((41, Vec::new()))
+            }
+            (EXCHANGE | QUEUE | BASIC | CONFIRM, _) => {
+                self.channel_method(channel, class_id, method_id, arguments, reply)
+            }
+            // Connection.TuneOk, among others
+            _ => None,
+        };
+        if let Some((answer_id, answer_arguments)) = answer {
+            reply.extend(method_frame(
+                channel,
+                class_id,
+                answer_id,
+                &answer_arguments,
+            ));
+        }
+    }
+
+    /// Answer a method of the exchange, queue, basic or confirm classes, returning the answer method and its arguments
+    fn channel_method(
+        &mut self,
+        channel: u16,
+        class_id: u16,
+        method_id: u16,
+        mut arguments: Reader<'_>,
+        reply: &mut Vec<u8>,
+    ) -> Option<(u16, Vec<u8>)> {
+        match (class_id, method_id) {
+            // Exchange.Declare
+            (EXCHANGE, 10) => {
+                arguments.skip_short_strings(2, 2);
+                no_wait_answer(&mut arguments, 4, 11, Vec::new())
+            }
+            // Queue.Declare, with the count of scripted messages not delivered yet
+            (QUEUE, 10) => {
+                let mut queue = arguments.skip(2).short_string().unwrap_or_default();
+                if queue.is_empty() {
+                    queue = self.generated_name("amq.gen-mock");
+                }
+                let message_count = self.deliveries.iter().filter(|(q, _)| *q == queue).count();
+                let mut declare_ok = Vec::new();
+                push_short_string(&mut declare_ok, &queue);
+                declare_ok.extend_from_slice(
+                    &u32::try_from(message_count)
+                        .unwrap_or(u32::MAX)
+                        .to_be_bytes(),
+                );
+                declare_ok.extend_from_slice(&0_u32.to_be_bytes());
+                no_wait_answer(&mut arguments, 4, 11, declare_ok)
+            }
+            // Queue.Bind
+            (QUEUE, 20) => {
+                arguments.skip_short_strings(2, 3);
+                no_wait_answer(&mut arguments, 0, 21, Vec::new())
+            }
+            // Basic.Qos
+            (BASIC, 10) => Some((11, Vec::new())),
+            // Basic.Consume, followed by the deliveries
+            (BASIC, 20) => {
+                let queue = arguments.skip(2).short_string().unwrap_or_default();
+                let mut consumer_tag = arguments.short_string().unwrap_or_default();
+                if consumer_tag.is_empty() {
+                    consumer_tag = self.generated_name("amq.ctag-mock");
+                }
+                let mut consume_ok = Vec::new();
+                push_short_string(&mut consume_ok, &consumer_tag);
+                if let Some((answer_id, answer_arguments)) =
+                    no_wait_answer(&mut arguments, 3, 21, consume_ok)
+                {
+                    reply.extend(method_frame(channel, BASIC, answer_id, &answer_arguments));
+                }
+                self.deliver(channel, &queue, &consumer_tag, reply);
+                None
+            }
+            // Basic.Cancel
+            (BASIC, 30) => {
+                let consumer_tag = arguments.short_string().unwrap_or_default();
+                let mut cancel_ok = Vec::new();
+                push_short_string(&mut cancel_ok, &consumer_tag);
+                no_wait_answer(&mut arguments, 0, 31, cancel_ok)
+            }
+            // Basic.Publish, followed by the content
+            (BASIC, 40) => {
+                let exchange = arguments.skip(2).short_string().unwrap_or_default();
+                let routing_key = arguments.short_string().unwrap_or_default();
+                self.channels.entry(channel).or_default().publishing = Some(Publishing {
+                    exchange,
+                    routing_key,
+                    body_size: None,
+                    body: Vec::new(),
+                });
+                None
+            }
+            // Basic.Ack, Basic.Reject and Basic.Nack
+            (BASIC, 80 | 90 | 120) => {
+                let delivery_tag = arguments.u64().unwrap_or_default();
+                let mut log = self.activity.log();
+                if method_id == 80 {
+                    log.acked_delivery_tags.push(delivery_tag);
+                } else {
+                    log.rejected_delivery_tags.push(delivery_tag);
+                }
+                None
+            }
+            // Confirm.Select
+            (CONFIRM, 10) => {
+                self.channels.entry(channel).or_default().confirmed = Some(0);
+                no_wait_answer(&mut arguments, 0, 11, Vec::new())
+            }
+            _ => {
+                let mut close = NOT_IMPLEMENTED.to_be_bytes().to_vec();
+                push_short_string(
+                    &mut close,
+                    &format!("NOT_IMPLEMENTED - {class_id}.{method_id}"),
+                );
+                close.extend_from_slice(&class_id.to_be_bytes());
+                close.extend_from_slice(&method_id.to_be_bytes());
+                reply.extend(method_frame(0, CONNECTION, 50, &close));
+                None
+            }
+        }
+    }
+
+    /// Deliver the scripted messages of the given queue to its new consumer
+    fn deliver(&mut self, channel: u16, queue: &str, consumer_tag: &str, reply: &mut Vec<u8>) {
+        let (delivered, kept) = std::mem::take(&mut self.deliveries)
+            .into_iter()
+            .partition(|(delivery_queue, _)| delivery_queue == queue);
+        self.deliveries = kept;
+        let state = self.channels.entry(channel).or_default();
+        for (_, body) in delivered {
+            state.delivery_tag += 1;
+            let mut deliver = Vec::new();
+            push_short_string(&mut deliver, consumer_tag);
+            deliver.extend_from_slice(&state.delivery_tag.to_be_bytes());
+            // Not redelivered, default exchange
+            deliver.extend_from_slice(&[0, 0]);
+            push_short_string(&mut deliver, queue);
+            reply.extend(method_frame(channel, BASIC, 60, &deliver));
+
+            // Class, weight, body size and no property
+            let mut header = BASIC.to_be_bytes().to_vec();
+            header.extend_from_slice(&[0, 0]);
+            header.extend_from_slice(&(body.len() as u64).to_be_bytes());
+            header.extend_from_slice(&[0, 0]);
+            reply.extend(frame(HEADER_FRAME, channel, &header));
+            // The frame header and end take 8 bytes of the maximum frame size
+            for chunk in body.chunks(FRAME_MAX as usize - 8) {
+                reply.extend(frame(BODY_FRAME, channel, chunk));
+            }
+        }
+    }
+
+    /// Read the body size of the message being published
+    fn content_header(&mut self, channel: u16, payload: &[u8], reply: &mut Vec<u8>) {
+        let body_size = Reader(payload).skip(4).u64();
+        if let Some(publishing) = self.publishing(channel) {
+            publishing.body_size = body_size;
+        }
+        self.publish_if_complete(channel, reply);
+    }
+
+    /// Append a part of the body of the message being published
+    fn content_body(&mut self, channel: u16, payload: &[u8], reply: &mut Vec<u8>) {
+        if let Some(publishing) = self.publishing(channel) {
+            publishing.body.extend_from_slice(payload);
+        }
+        self.publish_if_complete(channel, reply);
+    }
+
+    fn publishing(&mut self, channel: u16) -> Option<&mut Publishing> {
+        self.channels.get_mut(&channel)?.publishing.as_mut()
+    }
+
+    /// Record the message being published once its whole body is received, and acknowledge it if confirms are selected
+    fn publish_if_complete(&mut self, channel: u16, reply: &mut Vec<u8>) {
+        let Some(state) = self.channels.get_mut(&channel) else {
+            return;
+        };
+        let complete = state.publishing.as_ref().is_some_and(|publishing| {
+            publishing
+                .body_size
+                .is_some_and(|size| publishing.body.len() as u64 >= size)
+        });
+        if !complete {
+            return;
+        }
+        let Some(publishing) = state.publishing.take() else {
+            return;
+        };
+        self.activity.log().published.push(AmqpPublishedMessage {
+            exchange: publishing.exchange,
+            routing_key: publishing.routing_key,
+            body: publishing.body,
+        });
+        if let Some(confirmed) = &mut state.confirmed {
+            *confirmed += 1;
+            let mut ack = confirmed.to_be_bytes().to_vec();
+            // Not multiple
+            ack.push(0);
+            reply.extend(method_frame(channel, BASIC, 80, &ack));
+        }
+    }
+
+    /// Name generated by the server, for a queue or a consumer tag
+    fn generated_name(&mut self, prefix: &str) -> String {
+        self.generated_names += 1;
+        format!("{prefix}-{}", self.generated_names)
+    }
+}
+
+/// Connection.Start, replying to the protocol header
+fn connection_start() -> Vec<u8> {
+    let mut capabilities = Vec::new();
+    for capability in [
+        "publisher_confirms",
+        "consumer_cancel_notify",
+        "basic.nack",
+        "exchange_exchange_bindings",
+    ] {
+        push_short_string(&mut capabilities, capability);
+        capabilities.extend_from_slice(b"t\x01");
+    }
+    let mut properties = Vec::new();
+    push_short_string(&mut properties, "product");
+    properties.push(b'S');
+    push_long_string(&mut properties, b"socket-server-mocker");
+    push_short_string(&mut properties, "capabilities");
+    properties.push(b'F');
+    push_long_string(&mut properties, &capabilities);
+
+    // Protocol version 0-9
+    let mut arguments = vec![0, 9];
+    push_long_string(&mut arguments, &properties);
+    push_long_string(&mut arguments, b"PLAIN AMQPLAIN");
+    push_long_string(&mut arguments, b"en_US");
+    method_frame(0, CONNECTION, 10, &arguments)
+}
+
+/// Answer of a method, `None` if its no-wait bit is set.
+///
+/// The no-wait bit is the one at the given position in the octet read next.
+fn no_wait_answer(
+    arguments: &mut Reader<'_>,
+    no_wait_bit: u8,
+    answer_id: u16,
+    answer_arguments: Vec<u8>,
+) -> Option<(u16, Vec<u8>)> {
+    let bits = arguments.u8().unwrap_or_default();
+    (bits & (1 << no_wait_bit) == 0).then_some((answer_id, answer_arguments))
+}
+
+/// Cursor over the arguments of a method
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.0.len() < count {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn skip(&mut self, count: usize) -> &mut Self {
+        let _ = self.take(count);
+        self
+    }
+
+    /// Skip the given count of bytes, then the given count of short strings
+    fn skip_short_strings(&mut self, reserved: usize, count: usize) {
+        self.skip(reserved);
+        for _ in 0..count {
+            let _ = self.short_string();
+        }
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn short_string(&mut self) -> Option<String> {
+        let length = usize::from(self.u8()?);
+        Some(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+}
+
+/// Frame of the given type, with its header and end
+fn frame(frame_type: u8, channel: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![frame_type];
+    frame.extend_from_slice(&channel.to_be_bytes());
+    frame.extend_from_slice(
+        &u32::try_from(payload.len())
+            .unwrap_or(u32::MAX)
+            .to_be_bytes(),
+    );
+    frame.extend_from_slice(payload);
+    frame.push(FRAME_END);
+    frame
+}
+
+fn method_frame(channel: u16, class_id: u16, method_id: u16, arguments: &[u8]) -> Vec<u8> {
+    let mut payload = class_id.to_be_bytes().to_vec();
+    payload.extend_from_slice(&method_id.to_be_bytes());
+    payload.extend_from_slice(arguments);
+    frame(METHOD_FRAME, channel, &payload)
+}
+
+fn push_short_string(arguments: &mut Vec<u8>, value: &str) {
+    let value = &value.as_bytes()[..value.len().min(usize::from(u8::MAX))];
+    arguments.push(u8::try_from(value.len()).unwrap_or(u8::MAX));
+    arguments.extend_from_slice(value);
+}
+
+fn push_long_string(arguments: &mut Vec<u8>, value: &[u8]) {
+    arguments.extend_from_slice(&u32::try_from(value.len()).unwrap_or(u32::MAX).to_be_bytes());
+    arguments.extend_from_slice(value);
+}
@@ -0,0 +1,270 @@
+//! # `dhcp`
+//!
+//! Canned DHCP server answering the DISCOVER and REQUEST messages of a client with OFFER and ACK messages.
+
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use crate::Instruction::{self, ReceiveMessage, Repeat, StopExchange};
+use crate::Times;
+
+/// Size of the fixed part of a DHCP message, up to the magic cookie
+const FIXED_FIELDS_SIZE: usize = 236;
+/// Magic cookie starting the options, see RFC 2131
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// Option codes, see RFC 2132
+const PAD_OPTION: u8 = 0;
+const SUBNET_MASK_OPTION: u8 = 1;
+const ROUTER_OPTION: u8 = 3;
+const DNS_SERVERS_OPTION: u8 = 6;
+const REQUESTED_IP_OPTION: u8 = 50;
+const LEASE_TIME_OPTION: u8 = 51;
+const MESSAGE_TYPE_OPTION: u8 = 53;
+const SERVER_IDENTIFIER_OPTION: u8 = 54;
+const END_OPTION: u8 = 255;
+
+/// Message types, see RFC 2132
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+
+/// Build the instructions of a canned DHCP server leasing a single address, answering the messages of the client until it stops sending.
+///
+/// DISCOVER messages are answered with an OFFER of [`DhcpMockBuilder::offered_ip`], and REQUEST messages with an ACK,
+/// or a NAK if they request another address. Responses echo the transaction ID (xid), the flags,
+/// the relay agent address and the hardware address of the client.
+///
+/// The responses are sent back to the address the messages come from rather than broadcast,
+/// so the client under test must send to the server mocker from an address reachable on its interface.
+///
+/// # Example
+/// ```
+/// use std::net::Ipv4Addr;
+/// use std::time::Duration;
+/// use socket_server_mocker::protocols::dhcp::DhcpMockBuilder;
+///
+/// let builder = DhcpMockBuilder::new(Ipv4Addr::new(192, 168, 1, 100))
+///     .lease_time(Duration::from_secs(600))
+///     .router(Ipv4Addr::new(192, 168, 1, 1))
+///     .dns_servers(&[Ipv4Addr::new(192, 168, 1, 1)])
+///     // NTP servers
+///     .option(42, vec![192, 168, 1, 1]);
+/// assert!(builder.response(b"not a DHCP message").is_none());
+///
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = builder.build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct DhcpMockBuilder {
+    /// Address offered to the client
+    pub offered_ip: Ipv4Addr,
+    /// Address of the server, sent as the server identifier option, `192.168.1.1` by default
+    pub server_ip: Ipv4Addr,
+    /// Duration of the lease, one day by default
+    pub lease_time: Duration,
+    /// Subnet mask of the offered address, `255.255.255.0` by default
+    pub subnet_mask: Ipv4Addr,
+    /// Other options sent in the OFFER and ACK messages, as codes and raw values
+    pub options: Vec<(u8, Vec<u8>)>,
+}
+
+impl DhcpMockBuilder {
+    /// Server offering the given address with the default lease
+    pub fn new(offered_ip: Ipv4Addr) -> Self {
+        Self {
+            offered_ip,
+            server_ip: Ipv4Addr::new(192, 168, 1, 1),
+            lease_time: Duration::from_secs(86400),
+            subnet_mask: Ipv4Addr::new(255, 255, 255, 0),
+            options: Vec::new(),
+        }
+    }
+
+    /// Set the address of the server
+    #[must_use]
+    pub fn server_ip(mut self, server_ip: Ipv4Addr) -> Self {
+        self.server_ip = server_ip;
+        self
+    }
+
+    /// Set the duration of the lease, rounded down to the second
+    #[must_use]
+    pub fn lease_time(mut self, lease_time: Duration) -> Self {
+        self.lease_time = lease_time;
+        self
+    }
+
+    /// Set the subnet mask of the offered address
+    #[must_use]
+    pub fn subnet_mask(mut self, subnet_mask: Ipv4Addr) -> Self {
+        self.subnet_mask = subnet_mask;
+        self
+    }
+
+    /// Send the given default gateway
+    #[must_use]
+    pub fn router(self, router: Ipv4Addr) -> Self {
+        self.option(ROUTER_OPTION, router.octets().to_vec())
+    }
+
+    /// Send the given DNS servers
+    #[must_use]
+    pub fn dns_servers(self, dns_servers: &[Ipv4Addr]) -> Self {
+        let value = dns_servers.iter().flat_map(Ipv4Addr::octets).collect();
+        self.option(DNS_SERVERS_OPTION, value)
+    }
+
+    /// Send the given option, with its raw value
+    #[must_use]
+    pub fn option(mut self, code: u8, value: Vec<u8>) -> Self {
+        self.options.push((code, value));
+        self
+    }
+
+    /// Raw response to the given DHCP message, `None` if it isn't a DISCOVER or a REQUEST
+    pub fn response(&self, request: &[u8]) -> Option<Vec<u8>> {
+        let request = DhcpRequest::parse(request)?;
+        let message_type = match request.message_type {
+            DISCOVER => OFFER,
+            // The client may request the address it was offered, or renew its lease
+            REQUEST
+                if request
+                    .requested_ip
+                    .or(request.client_ip)
+                    .map_or(true, |ip| ip == self.offered_ip) =>
+            {
+                ACK
+            }
+            REQUEST => NAK,
+            _ => return None,
+        };
+
+        // op, htype, hlen and hops
+        let mut response = vec![2, request.fixed_fields[1], request.fixed_fields[2], 0];
+        // xid
+        response.extend_from_slice(&request.fixed_fields[4..8]);
+        // secs, then flags
+        response.extend_from_slice(&[0, 0]);
+        response.extend_from_slice(&request.fixed_fields[10..12]);
+        // ciaddr, yiaddr and siaddr
+        response.extend_from_slice(&[0; 4]);
+        if message_type == NAK {
+            response.extend_from_slice(&[0; 8]);
+        } else {
+            response.extend_from_slice(&self.offered_ip.octets());
+            response.extend_from_slice(&self.server_ip.octets());
+        }
+        // giaddr and chaddr, then empty sname and file
+        response.extend_from_slice(&request.fixed_fields[24..44]);
+        response.resize(FIXED_FIELDS_SIZE, 0);
+        response.extend_from_slice(&MAGIC_COOKIE);
+
+        push_option(&mut response, MESSAGE_TYPE_OPTION, &[message_type]);
+        push_option(
+            &mut response,
+            SERVER_IDENTIFIER_OPTION,
+            &self.server_ip.octets(),
+        );
+        if message_type != NAK {
+            let lease_secs = u32::try_from(self.lease_time.as_secs()).unwrap_or(u32::MAX);
+            push_option(&mut response, LEASE_TIME_OPTION, &lease_secs.to_be_bytes());
+            push_option(
+                &mut response,
+                SUBNET_MASK_OPTION,
+                &self.subnet_mask.octets(),
+            );
+            for (code, value) in &self.options {
+                push_option(&mut response, *code, value);
+            }
+        }
+        response.push(END_OPTION);
+        Some(response)
+    }
+
+    /// Instructions answering the DHCP messages of the client until it stops sending, then stopping the exchange.
+    ///
+    /// The received messages can still be retrieved with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message).
+    pub fn build(self) -> Vec<Instruction> {
+        let responder = move |request: Option<Vec<u8>>| self.response(&request?);
+        vec![
+            Repeat {
+                times: Times::at_least(0),
+                instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+            },
+            StopExchange,
+        ]
+    }
+}
+
+/// Append an option, split into several ones if its value is longer than 255 bytes, see RFC 3396
+fn push_option(message: &mut Vec<u8>, code: u8, value: &[u8]) {
+    for chunk in value.chunks(usize::from(u8::MAX)) {
+        message.push(code);
+        message.push(u8::try_from(chunk.len()).unwrap_or(u8::MAX));
+        message.extend_from_slice(chunk);
+    }
+    if value.is_empty() {
+        message.extend_from_slice(&[code, 0]);
+    }
+}
+
+/// Fields of a DHCP message sent by a client
+struct DhcpRequest<'a> {
+    /// Fixed part of the message, echoed in the response
+    fixed_fields: &'a [u8],
+    message_type: u8,
+    requested_ip: Option<Ipv4Addr>,
+    client_ip: Option<Ipv4Addr>,
+}
+
+impl<'a> DhcpRequest<'a> {
+    /// Parse the given raw DHCP message, `None` if it isn't a client message with a message type
+    fn parse(message: &'a [u8]) -> Option<Self> {
+        if message.len() < FIXED_FIELDS_SIZE {
+            return None;
+        }
+        let (fixed_fields, options) = message.split_at(FIXED_FIELDS_SIZE);
+        let options = options.strip_prefix(&MAGIC_COOKIE)?;
+        // BOOTREQUEST
+        if fixed_fields[0] != 1 {
+            return None;
+        }
+        let client_ip = ipv4_addr(&fixed_fields[12..16]).filter(|ip| !ip.is_unspecified());
+        let mut request = Self {
+            fixed_fields,
+            message_type: 0,
+            requested_ip: None,
+            client_ip,
+        };
+        let mut options = options;
+        while let [code, rest @ ..] = options {
+            match *code {
+                PAD_OPTION => options = rest,
+                END_OPTION => break,
+                _ => {
+                    let (&length, rest) = rest.split_first()?;
+                    let length = usize::from(length);
+                    if rest.len() < length {
+                        return None;
+                    }
+                    let (value, rest) = rest.split_at(length);
+                    match *code {
+                        MESSAGE_TYPE_OPTION => request.message_type = *value.first()?,
+                        REQUESTED_IP_OPTION => request.requested_ip = ipv4_addr(value),
+                        _ => {}
+                    }
+                    options = rest;
+                }
+            }
+        }
+        (request.message_type != 0).then_some(request)
+    }
+}
+
+/// IPv4 address of the given 4 bytes
+fn ipv4_addr(bytes: &[u8]) -> Option<Ipv4Addr> {
+    <[u8; 4]>::try_from(bytes).ok().map(Ipv4Addr::from)
+}
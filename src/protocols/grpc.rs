@@ -0,0 +1,343 @@
+//! # `grpc`
+//!
+//! gRPC server answering the calls of a client over cleartext HTTP/2 with scripted messages, metadata and statuses.
+
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::protocols::http2::{self, Http2Connection, Http2Request};
+use crate::Instruction::{self, ReceiveMessage, Repeat, StopExchange};
+use crate::Times;
+
+// Status codes, see the gRPC core documentation
+/// Success
+pub const OK: u32 = 0;
+/// The call was cancelled, typically by the caller
+pub const CANCELLED: u32 = 1;
+/// Unknown error
+pub const UNKNOWN: u32 = 2;
+/// The client specified an invalid argument
+pub const INVALID_ARGUMENT: u32 = 3;
+/// The deadline expired before the call could complete
+pub const DEADLINE_EXCEEDED: u32 = 4;
+/// The requested entity wasn't found
+pub const NOT_FOUND: u32 = 5;
+/// The entity the client attempted to create already exists
+pub const ALREADY_EXISTS: u32 = 6;
+/// The caller doesn't have permission to execute the call
+pub const PERMISSION_DENIED: u32 = 7;
+/// Some resource has been exhausted, e.g. a per-user quota
+pub const RESOURCE_EXHAUSTED: u32 = 8;
+/// The system isn't in a state required for the call
+pub const FAILED_PRECONDITION: u32 = 9;
+/// The call was aborted, typically because of a concurrency issue
+pub const ABORTED: u32 = 10;
+/// The call was attempted past the valid range
+pub const OUT_OF_RANGE: u32 = 11;
+/// The method isn't implemented or supported by the server
+pub const UNIMPLEMENTED: u32 = 12;
+/// Internal error
+pub const INTERNAL: u32 = 13;
+/// The service is currently unavailable, the call being safe to retry
+pub const UNAVAILABLE: u32 = 14;
+/// Unrecoverable data loss or corruption
+pub const DATA_LOSS: u32 = 15;
+/// The caller doesn't have valid authentication credentials
+pub const UNAUTHENTICATED: u32 = 16;
+
+/// Size of the prefix of a message, its compressed flag and its length
+const MESSAGE_PREFIX_SIZE: usize = 5;
+
+/// Answer of a gRPC call: the response messages, then the status and the trailing metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcResponse {
+    headers: Vec<(String, String)>,
+    messages: Vec<Vec<u8>>,
+    status: u32,
+    status_message: String,
+    trailers: Vec<(String, String)>,
+}
+
+impl GrpcResponse {
+    /// Successful response with a single encoded message, as for a unary call
+    pub fn ok(message: Vec<u8>) -> Self {
+        Self::stream(vec![message])
+    }
+
+    /// Successful response with the given encoded messages, as for a server streaming call
+    pub fn stream(messages: Vec<Vec<u8>>) -> Self {
+        Self {
+            headers: Vec::new(),
+            messages,
+            status: OK,
+            status_message: String::new(),
+            trailers: Vec::new(),
+        }
+    }
+
+    /// Failed response without message, sent as a trailers-only response
+    pub fn error(status: u32, status_message: impl Into<String>) -> Self {
+        Self::stream(Vec::new()).status(status, status_message)
+    }
+
+    /// Set the status sent after the messages, e.g. to fail a stream midway
+    #[must_use]
+    pub fn status(mut self, status: u32, status_message: impl Into<String>) -> Self {
+        self.status = status;
+        self.status_message = status_message.into();
+        self
+    }
+
+    /// Send the given initial metadata, the name being lowercase
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Send the given trailing metadata along with the status, the name being lowercase,
+    /// e.g. `grpc-status-details-bin` with rich error details
+    #[must_use]
+    pub fn trailer(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.trailers.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// Call received from the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcCall {
+    /// Path of the called method, e.g. `/helloworld.Greeter/SayHello`
+    pub path: String,
+    /// Metadata of the call, as lowercase names and values, pseudo-headers included
+    pub metadata: Vec<(String, String)>,
+    /// Encoded request messages, usually a single one
+    pub messages: Vec<Vec<u8>>,
+}
+
+impl GrpcCall {
+    /// First request message, the only one of a unary call
+    pub fn message(&self) -> Option<&[u8]> {
+        self.messages.first().map(Vec::as_slice)
+    }
+
+    /// Value of the metadata with the given lowercase name
+    pub fn metadata(&self, name: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(metadata_name, _)| metadata_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Calls received by the gRPC server mocker, shared with the test
+#[derive(Debug, Clone, Default)]
+pub struct GrpcCalls(Arc<Mutex<Vec<GrpcCall>>>);
+
+impl GrpcCalls {
+    /// Calls received so far, oldest first, including the ones to unknown methods
+    pub fn calls(&self) -> Vec<GrpcCall> {
+        self.log().clone()
+    }
+
+    fn log(&self) -> MutexGuard<'_, Vec<GrpcCall>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Answer of a method, given the call
+type Handler = Arc<dyn Fn(&GrpcCall) -> GrpcResponse + Send + Sync>;
+
+/// Build the instructions of a gRPC server, until the client disconnects.
+///
+/// The client must connect over cleartext HTTP/2 with prior knowledge, as tonic does for `http://` endpoints.
+/// Calls are matched on their exact path, `/package.Service/Method`, calls to other methods being answered
+/// with [`UNIMPLEMENTED`]. Messages are given and received encoded, e.g. with `prost::Message::encode_to_vec`
+/// and `prost::Message::decode`, compressed messages not being supported.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::grpc::{GrpcMockBuilder, GrpcResponse, UNAVAILABLE};
+///
+/// // Encoded `HelloReply { message: "Hello world" }`
+/// let reply = b"\x0a\x0bHello world".to_vec();
+/// let grpc_mock = GrpcMockBuilder::new()
+///     .respond("/helloworld.Greeter/SayHello", GrpcResponse::ok(reply))
+///     .respond_with("/helloworld.Greeter/SayGoodbye", |call| {
+///         if call.metadata("authorization").is_some() {
+///             GrpcResponse::ok(Vec::new())
+///         } else {
+///             GrpcResponse::error(UNAVAILABLE, "Try again later").trailer("retry-after", "1")
+///         }
+///     });
+/// let calls = grpc_mock.calls();
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = grpc_mock.build();
+/// ```
+#[derive(Clone, Default)]
+pub struct GrpcMockBuilder {
+    methods: Vec<(String, Handler)>,
+    calls: GrpcCalls,
+}
+
+impl GrpcMockBuilder {
+    /// Server implementing no method
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer every call to the given method with the given response
+    #[must_use]
+    pub fn respond(self, path: impl Into<String>, response: GrpcResponse) -> Self {
+        self.respond_with(path, move |_| response.clone())
+    }
+
+    /// Answer the calls to the given method with the response returned by the given closure
+    #[must_use]
+    pub fn respond_with(
+        mut self,
+        path: impl Into<String>,
+        handler: impl Fn(&GrpcCall) -> GrpcResponse + Send + Sync + 'static,
+    ) -> Self {
+        self.methods.push((path.into(), Arc::new(handler)));
+        self
+    }
+
+    /// Handle on the calls received by the server, to be retrieved before calling [`GrpcMockBuilder::build`]
+    pub fn calls(&self) -> GrpcCalls {
+        self.calls.clone()
+    }
+
+    /// Instructions answering the calls of the client until it disconnects, then stopping the exchange
+    pub fn build(self) -> Vec<Instruction> {
+        let mut exchange = GrpcExchange {
+            connection: Http2Connection::new(),
+            methods: self.methods,
+            calls: self.calls,
+        };
+        let responder = move |message: Option<Vec<u8>>| exchange.respond(&message?);
+        vec![
+            Repeat {
+                times: Times::at_least(0),
+                instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+            },
+            StopExchange,
+        ]
+    }
+}
+
+/// State of the conversation with the client
+struct GrpcExchange {
+    connection: Http2Connection,
+    methods: Vec<(String, Handler)>,
+    calls: GrpcCalls,
+}
+
+impl GrpcExchange {
+    /// Frames answering the data received, `None` if there's nothing to send
+    fn respond(&mut self, message: &[u8]) -> Option<Vec<u8>> {
+        let (mut frames, requests) = self.connection.receive(message);
+        for request in requests {
+            let response = self.answer(&request);
+            push_response(&mut frames, request.stream_id, &response);
+        }
+        (!frames.is_empty()).then_some(frames)
+    }
+
+    /// Response to the given request, from the handler of its method
+    fn answer(&self, request: &Http2Request) -> GrpcResponse {
+        if !request
+            .header("content-type")
+            .is_some_and(|content_type| content_type.starts_with("application/grpc"))
+        {
+            return GrpcResponse::error(UNKNOWN, "Not a gRPC request");
+        }
+        let Some(messages) = split_messages(&request.body) else {
+            return GrpcResponse::error(UNIMPLEMENTED, "Compressed messages aren't supported");
+        };
+        let call = GrpcCall {
+            path: request.header(":path").unwrap_or_default().to_string(),
+            metadata: request.headers.clone(),
+            messages,
+        };
+        self.calls.log().push(call.clone());
+        match self.methods.iter().find(|(path, _)| *path == call.path) {
+            Some((_, handler)) => handler(&call),
+            None => GrpcResponse::error(UNIMPLEMENTED, format!("Method not found: {}", call.path)),
+        }
+    }
+}
+
+/// Split a request body into its messages, `None` if one is compressed
+fn split_messages(mut body: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut messages = Vec::new();
+    while body.len() >= MESSAGE_PREFIX_SIZE {
+        if body[0] != 0 {
+            return None;
+        }
+        let length = u32::from_be_bytes([body[1], body[2], body[3], body[4]]);
+        let end = usize::try_from(length)
+            .unwrap_or(usize::MAX)
+            .saturating_add(MESSAGE_PREFIX_SIZE)
+            .min(body.len());
+        messages.push(body[MESSAGE_PREFIX_SIZE..end].to_vec());
+        body = &body[end..];
+    }
+    Some(messages)
+}
+
+/// Append the frames of the given response to the call of the given stream
+fn push_response(frames: &mut Vec<u8>, stream_id: u32, response: &GrpcResponse) {
+    let status = response.status.to_string();
+    let status_message = percent_encode(&response.status_message);
+    let mut trailers = vec![("grpc-status", status.as_str())];
+    if !status_message.is_empty() {
+        trailers.push(("grpc-message", &status_message));
+    }
+    trailers.extend(
+        response
+            .trailers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+    );
+    let mut headers = vec![(":status", "200"), ("content-type", "application/grpc")];
+    headers.extend(
+        response
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+    );
+
+    if response.messages.is_empty() {
+        // Trailers-only response
+        headers.extend(trailers);
+        http2::push_headers(frames, stream_id, &headers, true);
+        return;
+    }
+    http2::push_headers(frames, stream_id, &headers, false);
+    let mut data = Vec::new();
+    for message in &response.messages {
+        data.push(0);
+        data.extend_from_slice(
+            &u32::try_from(message.len())
+                .unwrap_or(u32::MAX)
+                .to_be_bytes(),
+        );
+        data.extend_from_slice(message);
+    }
+    http2::push_data(frames, stream_id, &data, false);
+    http2::push_headers(frames, stream_id, &trailers, true);
+}
+
+/// Percent-encode a status message, as sent in `grpc-message`
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::new();
+    for byte in message.bytes() {
+        if (b' '..=b'~').contains(&byte) && byte != b'%' {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
@@ -0,0 +1,550 @@
+//! # `http2`
+//!
+//! Server side of cleartext HTTP/2 connections started with prior knowledge (h2c), see RFC 9113,
+//! with the HPACK header compression of RFC 7541, for the protocols carried over HTTP/2.
+
+use std::collections::{HashMap, VecDeque};
+
+/// Connection preface sent by the client
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+/// Size of the header of a frame
+const FRAME_HEADER_SIZE: usize = 9;
+/// Maximum size of the payload of a frame, the default value of `SETTINGS_MAX_FRAME_SIZE`
+const MAX_FRAME_SIZE: usize = 16_384;
+
+/// Frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+/// Frame flags
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+/// Error codes sent in GOAWAY frames
+const PROTOCOL_ERROR: u32 = 0x1;
+const COMPRESSION_ERROR: u32 = 0x9;
+
+/// Default size of the HPACK dynamic table
+const DEFAULT_TABLE_SIZE: usize = 4096;
+
+/// HPACK static table, the first entry having index 1
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Lengths of the HPACK Huffman codes of every byte, the codes being canonical
+const HUFFMAN_CODE_LENGTHS: [u8; 256] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5,
+    5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, 13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, 15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6,
+    6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, 20, 22, 20, 20, 22, 22, 22, 23, 22,
+    23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, 22,
+    21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20,
+    22, 22, 22, 23, 22, 22, 23, 26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19,
+    21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, 20, 24, 20, 21, 22, 21, 21, 23, 22,
+    22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26,
+];
+
+/// Request received on a stream, complete once the client ended the stream
+pub(crate) struct Http2Request {
+    pub(crate) stream_id: u32,
+    /// Decoded header fields, pseudo-headers included
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Http2Request {
+    /// Value of the first header field with the given lowercase name
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Header block being received, split into a HEADERS frame and CONTINUATION frames
+struct HeaderBlock {
+    stream_id: u32,
+    end_stream: bool,
+    fragments: Vec<u8>,
+}
+
+/// State of a connection, answering the connection-level frames and gathering the requests
+pub(crate) struct Http2Connection {
+    /// Data received but not processed yet, as frames may be split
+    pending: Vec<u8>,
+    preface_received: bool,
+    /// Whether a connection error occurred, the following frames being ignored
+    failed: bool,
+    hpack: HpackDecoder,
+    /// Requests whose stream is still open on the client side
+    streams: HashMap<u32, Http2Request>,
+    header_block: Option<HeaderBlock>,
+}
+
+impl Http2Connection {
+    pub(crate) fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            preface_received: false,
+            failed: false,
+            hpack: HpackDecoder::new(),
+            streams: HashMap::new(),
+            header_block: None,
+        }
+    }
+
+    /// Process the given data received from the client,
+    /// returning the frames to send back and the requests completed by this data
+    pub(crate) fn receive(&mut self, data: &[u8]) -> (Vec<u8>, Vec<Http2Request>) {
+        let mut frames = Vec::new();
+        let mut requests = Vec::new();
+        self.pending.extend_from_slice(data);
+        if self.failed {
+            self.pending.clear();
+        }
+
+        if !self.preface_received {
+            if self.pending.len() < PREFACE.len() {
+                return (frames, requests);
+            }
+            if !self.pending.starts_with(PREFACE) {
+                self.fail(&mut frames, PROTOCOL_ERROR);
+                return (frames, requests);
+            }
+            self.pending.drain(..PREFACE.len());
+            self.preface_received = true;
+            // Default settings
+            push_frame(&mut frames, SETTINGS, 0, 0, &[]);
+        }
+
+        while self.pending.len() >= FRAME_HEADER_SIZE {
+            let length = usize::from(self.pending[0]) << 16
+                | usize::from(self.pending[1]) << 8
+                | usize::from(self.pending[2]);
+            if self.pending.len() < FRAME_HEADER_SIZE + length {
+                break;
+            }
+            let frame: Vec<u8> = self.pending.drain(..FRAME_HEADER_SIZE + length).collect();
+            let stream_id =
+                u32::from_be_bytes([frame[5], frame[6], frame[7], frame[8]]) & 0x7fff_ffff;
+            let payload = &frame[FRAME_HEADER_SIZE..];
+            if self
+                .frame(
+                    &mut frames,
+                    &mut requests,
+                    (frame[3], frame[4], stream_id),
+                    payload,
+                )
+                .is_none()
+            {
+                self.fail(&mut frames, PROTOCOL_ERROR);
+                break;
+            }
+        }
+        (frames, requests)
+    }
+
+    /// Process a frame, given its type, its flags and its stream ID, `None` on a connection error
+    fn frame(
+        &mut self,
+        frames: &mut Vec<u8>,
+        requests: &mut Vec<Http2Request>,
+        (frame_type, flags, stream_id): (u8, u8, u32),
+        payload: &[u8],
+    ) -> Option<()> {
+        // A header block must be continued before any other frame
+        if self.header_block.is_some() != (frame_type == CONTINUATION) {
+            return None;
+        }
+        match frame_type {
+            DATA => {
+                if !payload.is_empty() {
+                    // Give the flow control window back, the data being consumed right away
+                    let increment = u32::try_from(payload.len()).ok()?.to_be_bytes();
+                    push_frame(frames, WINDOW_UPDATE, 0, 0, &increment);
+                    push_frame(frames, WINDOW_UPDATE, 0, stream_id, &increment);
+                }
+                let data = unpadded(flags, payload)?;
+                if let Some(request) = self.streams.get_mut(&stream_id) {
+                    request.body.extend_from_slice(data);
+                }
+                if flags & END_STREAM != 0 {
+                    requests.extend(self.streams.remove(&stream_id));
+                }
+            }
+            HEADERS => {
+                let mut fragment = unpadded(flags, payload)?;
+                if flags & PRIORITY != 0 {
+                    fragment = fragment.get(5..)?;
+                }
+                self.header_block = Some(HeaderBlock {
+                    stream_id,
+                    end_stream: flags & END_STREAM != 0,
+                    fragments: fragment.to_vec(),
+                });
+            }
+            CONTINUATION => {
+                let header_block = self.header_block.as_mut()?;
+                if header_block.stream_id != stream_id {
+                    return None;
+                }
+                header_block.fragments.extend_from_slice(payload);
+            }
+            RST_STREAM => {
+                self.streams.remove(&stream_id);
+            }
+            SETTINGS if flags & ACK == 0 => push_frame(frames, SETTINGS, ACK, 0, &[]),
+            PING if flags & ACK == 0 => push_frame(frames, PING, ACK, 0, payload),
+            // PRIORITY, GOAWAY, WINDOW_UPDATE and acknowledgements, among others
+            _ => {}
+        }
+
+        if flags & END_HEADERS != 0 && matches!(frame_type, HEADERS | CONTINUATION) {
+            let header_block = self.header_block.take()?;
+            let Some(headers) = self.hpack.decode(&header_block.fragments) else {
+                self.fail(frames, COMPRESSION_ERROR);
+                return Some(());
+            };
+            // Header fields received on an open stream are trailers, and ignored
+            let request = self
+                .streams
+                .entry(header_block.stream_id)
+                .or_insert_with(|| Http2Request {
+                    stream_id: header_block.stream_id,
+                    headers,
+                    body: Vec::new(),
+                });
+            if header_block.end_stream {
+                let stream_id = request.stream_id;
+                requests.extend(self.streams.remove(&stream_id));
+            }
+        }
+        Some(())
+    }
+
+    /// Close the connection with the given error code
+    fn fail(&mut self, frames: &mut Vec<u8>, error_code: u32) {
+        if self.failed {
+            return;
+        }
+        self.failed = true;
+        self.pending.clear();
+        // No stream processed, then the error code
+        let mut payload = 0_u32.to_be_bytes().to_vec();
+        payload.extend_from_slice(&error_code.to_be_bytes());
+        push_frame(frames, GOAWAY, 0, 0, &payload);
+    }
+}
+
+/// Payload of a DATA or HEADERS frame without its padding
+fn unpadded(flags: u8, payload: &[u8]) -> Option<&[u8]> {
+    if flags & PADDED == 0 {
+        return Some(payload);
+    }
+    let (&padding, rest) = payload.split_first()?;
+    rest.get(..rest.len().checked_sub(usize::from(padding))?)
+}
+
+/// Append a frame with the given type, flags, stream ID and payload
+fn push_frame(frames: &mut Vec<u8>, frame_type: u8, flags: u8, stream_id: u32, payload: &[u8]) {
+    let length = u32::try_from(payload.len())
+        .unwrap_or(u32::MAX)
+        .to_be_bytes();
+    frames.extend_from_slice(&length[1..]);
+    frames.push(frame_type);
+    frames.push(flags);
+    frames.extend_from_slice(&stream_id.to_be_bytes());
+    frames.extend_from_slice(payload);
+}
+
+/// Append a HEADERS frame and its CONTINUATION frames carrying the given header fields
+pub(crate) fn push_headers(
+    frames: &mut Vec<u8>,
+    stream_id: u32,
+    headers: &[(&str, &str)],
+    end_stream: bool,
+) {
+    let mut block = Vec::new();
+    for (name, value) in headers {
+        // Literal header field without indexing, with a new name, the strings not being Huffman encoded
+        block.push(0);
+        for string in [name, value] {
+            push_integer(&mut block, 7, string.len());
+            block.extend_from_slice(string.as_bytes());
+        }
+    }
+    let mut chunks = block.chunks(MAX_FRAME_SIZE).peekable();
+    let mut frame_type = HEADERS;
+    let mut flags = if end_stream { END_STREAM } else { 0 };
+    while let Some(chunk) = chunks.next() {
+        if chunks.peek().is_none() {
+            flags |= END_HEADERS;
+        }
+        push_frame(frames, frame_type, flags, stream_id, chunk);
+        frame_type = CONTINUATION;
+        flags = 0;
+    }
+    if block.is_empty() {
+        push_frame(frames, HEADERS, flags | END_HEADERS, stream_id, &[]);
+    }
+}
+
+/// Append the DATA frames carrying the given data
+pub(crate) fn push_data(frames: &mut Vec<u8>, stream_id: u32, data: &[u8], end_stream: bool) {
+    let mut chunks = data.chunks(MAX_FRAME_SIZE).peekable();
+    while let Some(chunk) = chunks.next() {
+        let flags = if end_stream && chunks.peek().is_none() {
+            END_STREAM
+        } else {
+            0
+        };
+        push_frame(frames, DATA, flags, stream_id, chunk);
+    }
+    if data.is_empty() {
+        push_frame(
+            frames,
+            DATA,
+            if end_stream { END_STREAM } else { 0 },
+            stream_id,
+            &[],
+        );
+    }
+}
+
+/// Append an HPACK integer, its first byte holding the `prefix_bits` low bits of the value
+fn push_integer(block: &mut Vec<u8>, prefix_bits: u32, value: usize) {
+    let max_prefix = (1 << prefix_bits) - 1;
+    if value < max_prefix {
+        block.push(u8::try_from(value).unwrap_or(u8::MAX));
+        return;
+    }
+    block.push(u8::try_from(max_prefix).unwrap_or(u8::MAX));
+    let mut rest = value - max_prefix;
+    while rest >= 0x80 {
+        block.push(u8::try_from(rest & 0x7f).unwrap_or(u8::MAX) | 0x80);
+        rest >>= 7;
+    }
+    block.push(u8::try_from(rest).unwrap_or(u8::MAX));
+}
+
+/// HPACK decoder, holding the dynamic table shared by the header blocks of a connection
+struct HpackDecoder {
+    /// Dynamic table, the most recent entry first
+    dynamic_table: VecDeque<(String, String)>,
+    table_size: usize,
+    max_table_size: usize,
+    /// Bytes of the Huffman codes, by length and code
+    huffman_codes: HashMap<(u8, u32), u8>,
+}
+
+impl HpackDecoder {
+    fn new() -> Self {
+        let mut symbols: Vec<u8> = (0..=u8::MAX).collect();
+        symbols.sort_by_key(|&symbol| HUFFMAN_CODE_LENGTHS[usize::from(symbol)]);
+        let mut huffman_codes = HashMap::new();
+        let mut code = 0_u32;
+        let mut previous_length = HUFFMAN_CODE_LENGTHS[usize::from(symbols[0])];
+        for symbol in symbols {
+            let length = HUFFMAN_CODE_LENGTHS[usize::from(symbol)];
+            code <<= length - previous_length;
+            huffman_codes.insert((length, code), symbol);
+            code += 1;
+            previous_length = length;
+        }
+        Self {
+            dynamic_table: VecDeque::new(),
+            table_size: 0,
+            max_table_size: DEFAULT_TABLE_SIZE,
+            huffman_codes,
+        }
+    }
+
+    /// Decode a header block, `None` if it is invalid
+    fn decode(&mut self, mut block: &[u8]) -> Option<Vec<(String, String)>> {
+        let mut headers = Vec::new();
+        while let Some(&first) = block.first() {
+            if first & 0x80 != 0 {
+                // Indexed header field
+                let index = integer(&mut block, 7)?;
+                headers.push(self.entry(index)?);
+            } else if first & 0xe0 == 0x20 {
+                // Dynamic table size update
+                self.max_table_size = integer(&mut block, 5)?;
+                self.evict(0);
+            } else {
+                // Literal header field, with incremental indexing, without indexing or never indexed
+                let indexed = first & 0x40 != 0;
+                let index = integer(&mut block, if indexed { 6 } else { 4 })?;
+                let name = if index == 0 {
+                    self.string(&mut block)?
+                } else {
+                    self.entry(index)?.0
+                };
+                let value = self.string(&mut block)?;
+                if indexed {
+                    let size = name.len() + value.len() + 32;
+                    self.evict(size);
+                    if size <= self.max_table_size {
+                        self.table_size += size;
+                        self.dynamic_table.push_front((name.clone(), value.clone()));
+                    }
+                }
+                headers.push((name, value));
+            }
+        }
+        Some(headers)
+    }
+
+    /// Entry of the static or dynamic table at the given index
+    fn entry(&self, index: usize) -> Option<(String, String)> {
+        let (name, value) = match index.checked_sub(1)? {
+            index if index < STATIC_TABLE.len() => STATIC_TABLE[index],
+            index => {
+                let (name, value) = self.dynamic_table.get(index - STATIC_TABLE.len())?;
+                (name.as_str(), value.as_str())
+            }
+        };
+        Some((name.to_string(), value.to_string()))
+    }
+
+    /// Evict the oldest entries until an entry of the given size fits in the dynamic table
+    fn evict(&mut self, size: usize) {
+        while self.table_size + size > self.max_table_size {
+            let Some((name, value)) = self.dynamic_table.pop_back() else {
+                self.table_size = 0;
+                return;
+            };
+            self.table_size -= name.len() + value.len() + 32;
+        }
+    }
+
+    /// Read a string literal, Huffman encoded or not
+    fn string(&self, block: &mut &[u8]) -> Option<String> {
+        let huffman = block.first()? & 0x80 != 0;
+        let length = integer(block, 7)?;
+        let raw = block.get(..length)?;
+        *block = &block[length..];
+        let bytes = if huffman {
+            self.huffman_decode(raw)?
+        } else {
+            raw.to_vec()
+        };
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    fn huffman_decode(&self, encoded: &[u8]) -> Option<Vec<u8>> {
+        let mut decoded = Vec::new();
+        let (mut code, mut length) = (0_u32, 0_u8);
+        for bit in encoded
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+        {
+            code = code << 1 | u32::from(bit);
+            length += 1;
+            if let Some(&symbol) = self.huffman_codes.get(&(length, code)) {
+                decoded.push(symbol);
+                (code, length) = (0, 0);
+            } else if length > 30 {
+                return None;
+            }
+        }
+        // The padding is made of the most significant bits of the end-of-string code, all ones
+        (length < 8 && code == (1 << length) - 1).then_some(decoded)
+    }
+}
+
+/// Read an HPACK integer whose first byte holds the `prefix_bits` low bits of the value
+fn integer(block: &mut &[u8], prefix_bits: u32) -> Option<usize> {
+    let max_prefix = (1 << prefix_bits) - 1;
+    let (&first, mut rest) = block.split_first()?;
+    let mut value = usize::from(first) & max_prefix;
+    if value == max_prefix {
+        let mut shift = 0;
+        loop {
+            let (&byte, after) = rest.split_first()?;
+            rest = after;
+            value = value.checked_add(usize::from(byte & 0x7f).checked_shl(shift)?)?;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            if shift > 28 {
+                return None;
+            }
+        }
+    }
+    *block = rest;
+    Some(value)
+}
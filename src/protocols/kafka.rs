@@ -0,0 +1,628 @@
+//! # `kafka`
+//!
+//! Kafka broker answering `ApiVersions`, `Metadata`, `Produce` and `Fetch` requests, in their non-flexible versions.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::Instruction::{self, ReceiveMessage, Repeat, StopExchange};
+use crate::Times;
+
+// Error codes, see the Kafka protocol guide
+/// No error
+pub const NONE: i16 = 0;
+/// The requested offset is outside the range of offsets of the partition
+pub const OFFSET_OUT_OF_RANGE: i16 = 1;
+/// The topic or partition doesn't exist on this broker
+pub const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+/// The partition has no leader, e.g. during an election
+pub const LEADER_NOT_AVAILABLE: i16 = 5;
+/// This broker isn't the leader of the partition, formerly `NOT_LEADER_FOR_PARTITION`
+pub const NOT_LEADER_OR_FOLLOWER: i16 = 6;
+/// The request timed out
+pub const REQUEST_TIMED_OUT: i16 = 7;
+/// Not enough in-sync replicas to accept the records
+pub const NOT_ENOUGH_REPLICAS: i16 = 19;
+/// The client isn't authorized to access the topic
+pub const TOPIC_AUTHORIZATION_FAILED: i16 = 29;
+/// The version of the request isn't supported
+pub const UNSUPPORTED_VERSION: i16 = 35;
+
+/// API keys of the served requests
+const PRODUCE: i16 = 0;
+const FETCH: i16 = 1;
+const METADATA: i16 = 3;
+const API_VERSIONS: i16 = 18;
+/// Served API keys with their version ranges, the later versions being flexible ones
+const SUPPORTED_VERSIONS: [(i16, i16, i16); 4] = [
+    (PRODUCE, 0, 7),
+    (FETCH, 0, 7),
+    (METADATA, 0, 5),
+    (API_VERSIONS, 0, 2),
+];
+
+/// Build the instructions of a Kafka broker, until the client disconnects.
+///
+/// The broker reports the given advertised address as its own, node `0`, and leads every
+/// partition unless [`KafkaMockBuilder::partition_leader`] says otherwise. Error codes can be
+/// configured per topic for Metadata requests, and per partition for Produce and Fetch requests,
+/// to test the error handling of the client, e.g. with [`NOT_LEADER_OR_FOLLOWER`].
+///
+/// Produced records are accepted and available through [`KafkaMockBuilder::activity`], while fetched
+/// records are the raw record sets given to [`KafkaMockBuilder::fetch_records`].
+/// Requests with a version which isn't supported are left unanswered.
+///
+/// # Example
+/// ```
+/// use std::net::SocketAddr;
+/// use socket_server_mocker::protocols::kafka::{KafkaMockBuilder, NOT_LEADER_OR_FOLLOWER};
+///
+/// let advertised: SocketAddr = "127.0.0.1:9092".parse().unwrap();
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = KafkaMockBuilder::new(advertised)
+///     .broker(1, "127.0.0.1:9093".parse().unwrap())
+///     .topic("events", 3)
+///     .partition_leader("events", 2, 1)
+///     .partition_error("events", 1, NOT_LEADER_OR_FOLLOWER)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct KafkaMockBuilder {
+    brokers: Vec<(i32, SocketAddr)>,
+    topics: Vec<KafkaTopic>,
+    activity: KafkaActivity,
+}
+
+impl KafkaMockBuilder {
+    /// Broker with no topic, advertised at the given address, usually the one of the server mocker
+    pub fn new(advertised_address: SocketAddr) -> Self {
+        Self {
+            brokers: vec![(0, advertised_address)],
+            topics: Vec::new(),
+            activity: KafkaActivity::default(),
+        }
+    }
+
+    /// Report another broker of the cluster in Metadata responses
+    #[must_use]
+    pub fn broker(mut self, node_id: i32, address: SocketAddr) -> Self {
+        self.brokers.push((node_id, address));
+        self
+    }
+
+    /// Add a topic with the given count of partitions, all led by this broker
+    #[must_use]
+    pub fn topic(mut self, name: impl Into<String>, partition_count: i32) -> Self {
+        let partitions = (0..partition_count)
+            .map(|_| KafkaPartition {
+                leader: 0,
+                error_code: NONE,
+                high_watermark: 0,
+                records: Vec::new(),
+            })
+            .collect();
+        self.topics.push(KafkaTopic {
+            name: name.into(),
+            error_code: NONE,
+            partitions,
+        });
+        self
+    }
+
+    /// Report an error for the given topic in Metadata responses, adding the topic if needed
+    #[must_use]
+    pub fn topic_error(mut self, name: &str, error_code: i16) -> Self {
+        if self.find_topic(name).is_none() {
+            self = self.topic(name, 0);
+        }
+        if let Some(topic) = self.find_topic(name) {
+            topic.error_code = error_code;
+        }
+        self
+    }
+
+    /// Report another broker as the leader of the given partition
+    #[must_use]
+    pub fn partition_leader(mut self, topic: &str, partition: i32, node_id: i32) -> Self {
+        if let Some(partition) = self.find_partition(topic, partition) {
+            partition.leader = node_id;
+        }
+        self
+    }
+
+    /// Answer Produce and Fetch requests on the given partition with an error
+    #[must_use]
+    pub fn partition_error(mut self, topic: &str, partition: i32, error_code: i16) -> Self {
+        if let Some(partition) = self.find_partition(topic, partition) {
+            partition.error_code = error_code;
+        }
+        self
+    }
+
+    /// Answer Fetch requests on the given partition with the given raw record set, ending at the given offset.
+    ///
+    /// The records are only sent to clients fetching from an offset below `high_watermark`.
+    #[must_use]
+    pub fn fetch_records(
+        mut self,
+        topic: &str,
+        partition: i32,
+        high_watermark: i64,
+        records: Vec<u8>,
+    ) -> Self {
+        if let Some(partition) = self.find_partition(topic, partition) {
+            partition.high_watermark = high_watermark;
+            partition.records = records;
+        }
+        self
+    }
+
+    /// Records produced by the client, once the server mocker received them
+    pub fn activity(&self) -> KafkaActivity {
+        self.activity.clone()
+    }
+
+    /// Instructions answering the requests of the client until it disconnects, then stopping the exchange
+    pub fn build(self) -> Vec<Instruction> {
+        let mut exchange = KafkaExchange {
+            brokers: self.brokers,
+            topics: self
+                .topics
+                .into_iter()
+                .map(|topic| (topic.name.clone(), topic))
+                .collect(),
+            activity: self.activity,
+            pending: Vec::new(),
+        };
+        let responder = move |message: Option<Vec<u8>>| exchange.respond(&message?);
+        vec![
+            Repeat {
+                times: Times::at_least(0),
+                instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+            },
+            StopExchange,
+        ]
+    }
+
+    fn find_topic(&mut self, name: &str) -> Option<&mut KafkaTopic> {
+        self.topics.iter_mut().find(|topic| topic.name == name)
+    }
+
+    fn find_partition(&mut self, topic: &str, partition: i32) -> Option<&mut KafkaPartition> {
+        let partition = usize::try_from(partition).ok()?;
+        self.find_topic(topic)?.partitions.get_mut(partition)
+    }
+}
+
+/// Records sent by the client in a Produce request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KafkaProducedRecords {
+    /// Topic of the records
+    pub topic: String,
+    /// Partition of the records
+    pub partition: i32,
+    /// Raw record set: a record batch since Produce version 3, a message set before
+    pub records: Vec<u8>,
+}
+
+/// Activity of the client recorded by the Kafka server mocker, shared with the test
+#[derive(Debug, Clone, Default)]
+pub struct KafkaActivity(Arc<Mutex<Vec<KafkaProducedRecords>>>);
+
+impl KafkaActivity {
+    /// Records produced by the client so far, oldest first, including the ones rejected with an error
+    pub fn produced(&self) -> Vec<KafkaProducedRecords> {
+        self.log().clone()
+    }
+
+    fn log(&self) -> MutexGuard<'_, Vec<KafkaProducedRecords>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct KafkaTopic {
+    name: String,
+    error_code: i16,
+    partitions: Vec<KafkaPartition>,
+}
+
+#[derive(Debug, Clone)]
+struct KafkaPartition {
+    leader: i32,
+    error_code: i16,
+    /// Offset of the next produced record, then of the next fetched one
+    high_watermark: i64,
+    records: Vec<u8>,
+}
+
+/// Requests received from the client, some possibly incomplete
+struct KafkaExchange {
+    brokers: Vec<(i32, SocketAddr)>,
+    topics: HashMap<String, KafkaTopic>,
+    activity: KafkaActivity,
+    /// Start of a request whose end wasn't received yet
+    pending: Vec<u8>,
+}
+
+impl KafkaExchange {
+    /// Responses to the complete requests received so far, `None` if there's none
+    fn respond(&mut self, message: &[u8]) -> Option<Vec<u8>> {
+        self.pending.extend_from_slice(message);
+        let received = std::mem::take(&mut self.pending);
+        let mut rest = received.as_slice();
+        let mut responses = Vec::new();
+        while let Some(size) = rest.get(..4) {
+            let size = i32::from_be_bytes([size[0], size[1], size[2], size[3]]);
+            let Some(request) = usize::try_from(size)
+                .ok()
+                .and_then(|size| rest.get(4..4 + size))
+            else {
+                break;
+            };
+            if let Some(response) = self.request(request) {
+                let size = i32::try_from(response.len()).unwrap_or(i32::MAX);
+                responses.extend_from_slice(&size.to_be_bytes());
+                responses.extend_from_slice(&response);
+            }
+            rest = &rest[4 + request.len()..];
+        }
+        self.pending = rest.to_vec();
+        (!responses.is_empty()).then_some(responses)
+    }
+
+    /// Response to the given request, with its header, `None` if the request isn't answered
+    fn request(&mut self, request: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Reader(request);
+        let api_key = reader.i16()?;
+        let version = reader.i16()?;
+        let correlation_id = reader.i32()?;
+        // Client ID
+        reader.string()?;
+
+        let mut response = Encoder(correlation_id.to_be_bytes().to_vec());
+        let supported = SUPPORTED_VERSIONS
+            .iter()
+            .any(|(key, min, max)| *key == api_key && (*min..=*max).contains(&version));
+        match api_key {
+            API_VERSIONS => api_versions(&mut response, version, supported),
+            _ if !supported => return None,
+            METADATA => self.metadata(&mut response, version, &mut reader)?,
+            PRODUCE => self.produce(&mut response, version, &mut reader)?,
+            FETCH => self.fetch(&mut response, version, &mut reader)?,
+            _ => return None,
+        }
+        Some(response.0)
+    }
+
+    fn metadata(
+        &self,
+        response: &mut Encoder,
+        version: i16,
+        reader: &mut Reader<'_>,
+    ) -> Option<()> {
+        // An empty array in version 0, or a null one since version 1, requests all topics
+        let count = reader.i32()?;
+        let requested = if count < 0 || (count == 0 && version == 0) {
+            let mut names: Vec<String> = self.topics.keys().cloned().collect();
+            names.sort();
+            names
+        } else {
+            (0..count).map(|_| reader.string()).collect::<Option<_>>()?
+        };
+
+        if version >= 3 {
+            // Throttle time
+            response.i32(0);
+        }
+        response.i32(len_i32(self.brokers.len()));
+        for (node_id, address) in &self.brokers {
+            response.i32(*node_id);
+            response.string(&address.ip().to_string());
+            response.i32(i32::from(address.port()));
+            if version >= 1 {
+                // Rack
+                response.i16(-1);
+            }
+        }
+        if version >= 2 {
+            // Cluster ID
+            response.string("socket-server-mocker");
+        }
+        if version >= 1 {
+            // Controller ID
+            response.i32(self.brokers[0].0);
+        }
+        response.i32(len_i32(requested.len()));
+        for name in requested {
+            let topic = self.topics.get(&name);
+            response.i16(topic.map_or(UNKNOWN_TOPIC_OR_PARTITION, |topic| topic.error_code));
+            response.string(&name);
+            if version >= 1 {
+                // Not internal
+                response.0.push(0);
+            }
+            let partitions = topic.map_or(&[][..], |topic| &topic.partitions);
+            response.i32(len_i32(partitions.len()));
+            for (index, partition) in partitions.iter().enumerate() {
+                response.i16(NONE);
+                response.i32(len_i32(index));
+                response.i32(partition.leader);
+                // Replicas and in-sync replicas
+                for _ in 0..2 {
+                    response.i32(1);
+                    response.i32(partition.leader);
+                }
+                if version >= 5 {
+                    // No offline replica
+                    response.i32(0);
+                }
+            }
+        }
+        Some(())
+    }
+
+    fn produce(
+        &mut self,
+        response: &mut Encoder,
+        version: i16,
+        reader: &mut Reader<'_>,
+    ) -> Option<()> {
+        if version >= 3 {
+            // Transactional ID
+            reader.string()?;
+        }
+        let acks = reader.i16()?;
+        // Timeout
+        reader.i32()?;
+        let mut results = Vec::new();
+        for _ in 0..reader.i32()? {
+            let topic = reader.string()?;
+            let mut partition_results = Vec::new();
+            for _ in 0..reader.i32()? {
+                let partition = reader.i32()?;
+                let records = reader.bytes()?;
+                self.activity.log().push(KafkaProducedRecords {
+                    topic: topic.clone(),
+                    partition,
+                    records: records.to_vec(),
+                });
+                let (error_code, base_offset) = match self.partition(&topic, partition) {
+                    Some(state) if state.error_code != NONE => (state.error_code, -1),
+                    Some(state) => {
+                        let base_offset = state.high_watermark;
+                        state.high_watermark += record_count(records);
+                        (NONE, base_offset)
+                    }
+                    None => (UNKNOWN_TOPIC_OR_PARTITION, -1),
+                };
+                partition_results.push((partition, error_code, base_offset));
+            }
+            results.push((topic, partition_results));
+        }
+        // No response is expected without acknowledgement
+        if acks == 0 {
+            return None;
+        }
+
+        response.i32(len_i32(results.len()));
+        for (topic, partition_results) in results {
+            response.string(&topic);
+            response.i32(len_i32(partition_results.len()));
+            for (partition, error_code, base_offset) in partition_results {
+                response.i32(partition);
+                response.i16(error_code);
+                response.i64(base_offset);
+                if version >= 2 {
+                    // Log append time
+                    response.i64(-1);
+                }
+                if version >= 5 {
+                    // Log start offset
+                    response.i64(0);
+                }
+            }
+        }
+        if version >= 1 {
+            // Throttle time
+            response.i32(0);
+        }
+        Some(())
+    }
+
+    fn fetch(
+        &mut self,
+        response: &mut Encoder,
+        version: i16,
+        reader: &mut Reader<'_>,
+    ) -> Option<()> {
+        // Replica ID, maximum wait time and minimum bytes
+        reader.skip(12)?;
+        if version >= 3 {
+            // Maximum bytes
+            reader.i32()?;
+        }
+        if version >= 4 {
+            // Isolation level
+            reader.skip(1)?;
+        }
+        if version >= 7 {
+            // Session ID and epoch
+            reader.skip(8)?;
+        }
+
+        if version >= 1 {
+            // Throttle time
+            response.i32(0);
+        }
+        if version >= 7 {
+            // Error code and session ID
+            response.i16(NONE);
+            response.i32(0);
+        }
+        let topic_count = reader.i32()?;
+        response.i32(topic_count);
+        for _ in 0..topic_count {
+            let topic = reader.string()?;
+            response.string(&topic);
+            let partition_count = reader.i32()?;
+            response.i32(partition_count);
+            for _ in 0..partition_count {
+                let partition = reader.i32()?;
+                let fetch_offset = reader.i64()?;
+                if version >= 5 {
+                    // Log start offset
+                    reader.i64()?;
+                }
+                // Maximum bytes of the partition
+                reader.i32()?;
+
+                let (error_code, high_watermark, records) = match self.partition(&topic, partition)
+                {
+                    Some(state) if state.error_code != NONE => (state.error_code, -1, &[][..]),
+                    Some(state) if fetch_offset < state.high_watermark => {
+                        (NONE, state.high_watermark, state.records.as_slice())
+                    }
+                    Some(state) if fetch_offset == state.high_watermark => {
+                        (NONE, state.high_watermark, &[][..])
+                    }
+                    Some(state) => (OFFSET_OUT_OF_RANGE, state.high_watermark, &[][..]),
+                    None => (UNKNOWN_TOPIC_OR_PARTITION, -1, &[][..]),
+                };
+                response.i32(partition);
+                response.i16(error_code);
+                response.i64(high_watermark);
+                if version >= 4 {
+                    // Last stable offset, then no aborted transaction
+                    response.i64(high_watermark);
+                    if version >= 5 {
+                        // Log start offset
+                        response.i64(0);
+                    }
+                    response.i32(-1);
+                }
+                response.i32(len_i32(records.len()));
+                response.0.extend_from_slice(records);
+            }
+        }
+        Some(())
+    }
+
+    fn partition(&mut self, topic: &str, partition: i32) -> Option<&mut KafkaPartition> {
+        let partition = usize::try_from(partition).ok()?;
+        self.topics.get_mut(topic)?.partitions.get_mut(partition)
+    }
+}
+
+/// `ApiVersions` response, in version 0 with an error if the requested version isn't supported
+fn api_versions(response: &mut Encoder, version: i16, supported: bool) {
+    response.i16(if supported { NONE } else { UNSUPPORTED_VERSION });
+    response.i32(len_i32(SUPPORTED_VERSIONS.len()));
+    for (api_key, min_version, max_version) in SUPPORTED_VERSIONS {
+        response.i16(api_key);
+        response.i16(min_version);
+        response.i16(max_version);
+    }
+    if supported && version >= 1 {
+        // Throttle time
+        response.i32(0);
+    }
+}
+
+/// Count of records of the given record set, to compute the offsets of the next ones
+fn record_count(records: &[u8]) -> i64 {
+    let mut rest = records;
+    let mut count = 0;
+    // Record batches and message sets both start with the base offset and the length of each entry
+    while let (Some(length), Some(magic)) = (rest.get(8..12), rest.get(16)) {
+        let length = i32::from_be_bytes([length[0], length[1], length[2], length[3]]);
+        count += match (magic, rest.get(23..27)) {
+            // Last offset delta of a record batch
+            (2, Some(delta)) => {
+                i64::from(i32::from_be_bytes([delta[0], delta[1], delta[2], delta[3]])) + 1
+            }
+            _ => 1,
+        };
+        let Some(next) = usize::try_from(length)
+            .ok()
+            .and_then(|length| rest.get(12 + length..))
+        else {
+            break;
+        };
+        rest = next;
+    }
+    count
+}
+
+fn len_i32(length: usize) -> i32 {
+    i32::try_from(length).unwrap_or(i32::MAX)
+}
+
+/// Response being encoded
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn i16(&mut self, value: i16) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn i64(&mut self, value: i64) {
+        self.0.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.i16(i16::try_from(value.len()).unwrap_or(i16::MAX));
+        self.0.extend_from_slice(value.as_bytes());
+    }
+}
+
+/// Cursor over a request
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        if self.0.len() < count {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        self.take(count).map(|_| ())
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(i16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn i64(&mut self) -> Option<i64> {
+        Some(i64::from_be_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    /// String preceded by its `i16` length, empty if null
+    fn string(&mut self) -> Option<String> {
+        let Ok(length) = usize::try_from(self.i16()?) else {
+            return Some(String::new());
+        };
+        Some(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    /// Bytes preceded by their `i32` length, empty if null
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let Ok(length) = usize::try_from(self.i32()?) else {
+            return Some(&[]);
+        };
+        self.take(length)
+    }
+}
@@ -0,0 +1,254 @@
+//! # `line_session`
+//!
+//! Line-oriented session of a CLI over TCP, such as the telnet console of a router or of a lab instrument.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::Instruction::{self, ReceiveUntilDelimiter, SendMessage};
+
+// Telnet commands, see RFC 854
+/// Interpret as command, introducing every telnet command
+const IAC: u8 = 255;
+/// Start of a subnegotiation, ended by `IAC SE`
+const SB: u8 = 250;
+/// End of a subnegotiation
+const SE: u8 = 240;
+/// First of the `WILL`, `WONT`, `DO` and `DONT` negotiations, followed by an option
+const WILL: u8 = 251;
+/// Last of the negotiations followed by an option
+const DONT: u8 = 254;
+
+/// Build the instructions of a line-oriented session, scripted one line at a time instead of raw bytes.
+///
+/// Lines received from the client are compared without their line ending, and once stripped of the
+/// telnet negotiation if [`LineSessionBuilder::strip_telnet_negotiation`] is used. A line other than the
+/// expected one is recorded as a mismatch, and answered with the reply set with
+/// [`LineSessionBuilder::mismatch_reply`], if any, before the session goes on.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::line_session::LineSessionBuilder;
+///
+/// let session = LineSessionBuilder::new()
+///     .strip_telnet_negotiation()
+///     .mismatch_reply("% Invalid input detected")
+///     .send_line("User Access Verification")
+///     .prompt("Username: ")
+///     .expect_line("admin")
+///     .prompt("Password: ")
+///     .receive_line()
+///     .prompt("router> ")
+///     .expect_line("show version")
+///     .send_line("Cisco IOS Software, Version 15.2(4)M")
+///     .prompt("router> ")
+///     .expect_line("exit");
+/// let lines = session.received_lines();
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = session.build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct LineSessionBuilder {
+    steps: Vec<Step>,
+    line_ending: String,
+    strip_telnet: bool,
+    mismatch_reply: Option<String>,
+    received_lines: ReceivedLines,
+}
+
+/// Step of the session
+#[derive(Debug, Clone)]
+enum Step {
+    /// Text sent to the client, followed by the line ending if it's a line
+    Send { text: String, line: bool },
+    /// Line received from the client, compared to the expected one if any
+    Receive(Option<String>),
+}
+
+impl Default for LineSessionBuilder {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            line_ending: "\r\n".to_string(),
+            strip_telnet: false,
+            mismatch_reply: None,
+            received_lines: ReceivedLines::default(),
+        }
+    }
+}
+
+impl LineSessionBuilder {
+    /// Empty session, sending lines terminated by `\r\n`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the line ending appended to the lines sent, `\r\n` by default
+    #[must_use]
+    pub fn line_ending(mut self, line_ending: impl Into<String>) -> Self {
+        self.line_ending = line_ending.into();
+        self
+    }
+
+    /// Remove the telnet commands and option negotiations, e.g. `IAC DO ECHO`, from the lines received
+    #[must_use]
+    pub fn strip_telnet_negotiation(mut self) -> Self {
+        self.strip_telnet = true;
+        self
+    }
+
+    /// Send the given line to the client when a received line isn't the expected one
+    #[must_use]
+    pub fn mismatch_reply(mut self, line: impl Into<String>) -> Self {
+        self.mismatch_reply = Some(line.into());
+        self
+    }
+
+    /// Send the given line, followed by the line ending
+    #[must_use]
+    pub fn send_line(mut self, line: impl Into<String>) -> Self {
+        self.steps.push(Step::Send {
+            text: line.into(),
+            line: true,
+        });
+        self
+    }
+
+    /// Send the given prompt as is, without line ending, e.g. `router> `
+    #[must_use]
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.steps.push(Step::Send {
+            text: prompt.into(),
+            line: false,
+        });
+        self
+    }
+
+    /// Receive a line, expected to be the given one
+    #[must_use]
+    pub fn expect_line(mut self, line: impl Into<String>) -> Self {
+        self.steps.push(Step::Receive(Some(line.into())));
+        self
+    }
+
+    /// Receive any line, e.g. a password
+    #[must_use]
+    pub fn receive_line(mut self) -> Self {
+        self.steps.push(Step::Receive(None));
+        self
+    }
+
+    /// Handle on the lines received by the server, to be retrieved before calling [`LineSessionBuilder::build`]
+    pub fn received_lines(&self) -> ReceivedLines {
+        self.received_lines.clone()
+    }
+
+    /// Instructions of the session, ending after its last step
+    pub fn build(self) -> Vec<Instruction> {
+        let mut instructions = Vec::new();
+        for step in self.steps {
+            match step {
+                Step::Send { mut text, line } => {
+                    if line {
+                        text.push_str(&self.line_ending);
+                    }
+                    instructions.push(SendMessage(text.into_bytes()));
+                }
+                Step::Receive(expected) => {
+                    let strip_telnet = self.strip_telnet;
+                    let mismatch_reply = self
+                        .mismatch_reply
+                        .as_ref()
+                        .map(|reply| format!("{reply}{}", self.line_ending).into_bytes());
+                    let received_lines = self.received_lines.clone();
+                    let responder = move |message: Option<Vec<u8>>| {
+                        let line = received_line(&message?, strip_telnet);
+                        let mut log = received_lines.log();
+                        log.lines.push(line.clone());
+                        match &expected {
+                            Some(expected) if *expected != line => {
+                                log.mismatches.push((expected.clone(), line));
+                                mismatch_reply.clone()
+                            }
+                            _ => None,
+                        }
+                    };
+                    instructions.extend([
+                        ReceiveUntilDelimiter(b"\n".to_vec()),
+                        Instruction::send_from_closure(responder),
+                    ]);
+                }
+            }
+        }
+        instructions
+    }
+}
+
+/// Lines received by the line session, shared with the test
+#[derive(Debug, Clone, Default)]
+pub struct ReceivedLines(Arc<Mutex<ReceivedLinesLog>>);
+
+#[derive(Debug, Default)]
+struct ReceivedLinesLog {
+    lines: Vec<String>,
+    mismatches: Vec<(String, String)>,
+}
+
+impl ReceivedLines {
+    /// Lines received so far, oldest first, without their line ending
+    pub fn lines(&self) -> Vec<String> {
+        self.log().lines.clone()
+    }
+
+    /// Lines received instead of the expected ones, as expected and received lines
+    pub fn mismatches(&self) -> Vec<(String, String)> {
+        self.log().mismatches.clone()
+    }
+
+    fn log(&self) -> MutexGuard<'_, ReceivedLinesLog> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Received line, without its line ending and, if asked, its telnet commands
+fn received_line(message: &[u8], strip_telnet: bool) -> String {
+    let line = if strip_telnet {
+        strip_telnet_commands(message)
+    } else {
+        message.to_vec()
+    };
+    String::from_utf8_lossy(&line)
+        .trim_end_matches(['\r', '\n', '\0'])
+        .to_string()
+}
+
+/// Remove the telnet commands from the given data, an escaped `IAC IAC` being kept as a single 255 byte
+fn strip_telnet_commands(data: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(data.len());
+    let mut bytes = data.iter().copied();
+    while let Some(byte) = bytes.next() {
+        if byte != IAC {
+            stripped.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(IAC) => stripped.push(IAC),
+            Some(WILL..=DONT) => {
+                // Option negotiated
+                bytes.next();
+            }
+            Some(SB) => {
+                // Subnegotiation data, up to IAC SE
+                let mut previous = 0;
+                for byte in bytes.by_ref() {
+                    if previous == IAC && byte == SE {
+                        break;
+                    }
+                    // An escaped IAC doesn't start IAC SE
+                    previous = if previous == IAC { 0 } else { byte };
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
@@ -0,0 +1,409 @@
+//! # `memcached`
+//!
+//! Memcached server answering get, set and delete commands, in the text or the binary protocol.
+
+use std::collections::HashMap;
+
+use crate::Instruction::{self, ReceiveMessage, Repeat, StopExchange};
+use crate::Times;
+
+/// Size of the header of binary protocol packets
+const BINARY_HEADER_SIZE: usize = 24;
+/// Magic bytes of binary protocol requests and responses
+const REQUEST_MAGIC: u8 = 0x80;
+const RESPONSE_MAGIC: u8 = 0x81;
+
+/// Binary protocol opcodes, the quiet ones only replying on a miss or an error
+const GET: u8 = 0x00;
+const SET: u8 = 0x01;
+const DELETE: u8 = 0x04;
+const GETQ: u8 = 0x09;
+const NOOP: u8 = 0x0a;
+const VERSION: u8 = 0x0b;
+const GETK: u8 = 0x0c;
+const GETKQ: u8 = 0x0d;
+const SETQ: u8 = 0x11;
+const DELETEQ: u8 = 0x14;
+
+/// Binary protocol statuses
+const STATUS_OK: u16 = 0x0000;
+const STATUS_KEY_NOT_FOUND: u16 = 0x0001;
+const STATUS_INVALID_ARGUMENTS: u16 = 0x0004;
+const STATUS_UNKNOWN_COMMAND: u16 = 0x0081;
+const STATUS_INTERNAL_ERROR: u16 = 0x0084;
+
+/// Version reported by the `version` command
+const SERVER_VERSION: &str = "1.6.21";
+
+/// Build the instructions of a memcached server, until the client disconnects.
+///
+/// Stored items are hits, other keys are misses, and keys configured with
+/// [`MemcachedMockBuilder::error`] are answered with a server error. Items set by the client
+/// are stored, so that they're hits afterward, and deleted items become misses.
+///
+/// Both protocols are served, a request starting with the `0x80` magic byte being a binary one.
+/// Commands may be pipelined or split across several messages.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::memcached::MemcachedMockBuilder;
+///
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = MemcachedMockBuilder::new()
+///     .item("user:1", b"alice".to_vec())
+///     .item_with_flags("user:2", 42, b"bob".to_vec())
+///     .error("user:3", "out of memory storing object")
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MemcachedMockBuilder {
+    items: HashMap<Vec<u8>, Item>,
+    errors: HashMap<Vec<u8>, String>,
+}
+
+impl MemcachedMockBuilder {
+    /// Server with no item, every key being a miss
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store the given item, with no flag
+    #[must_use]
+    pub fn item(self, key: impl Into<String>, value: Vec<u8>) -> Self {
+        self.item_with_flags(key, 0, value)
+    }
+
+    /// Store the given item, with the given client flags
+    #[must_use]
+    pub fn item_with_flags(mut self, key: impl Into<String>, flags: u32, value: Vec<u8>) -> Self {
+        let cas = self.items.len() as u64 + 1;
+        self.items
+            .insert(key.into().into_bytes(), Item { flags, value, cas });
+        self
+    }
+
+    /// Answer any command on the given key with a server error
+    #[must_use]
+    pub fn error(mut self, key: impl Into<String>, message: impl Into<String>) -> Self {
+        self.errors.insert(key.into().into_bytes(), message.into());
+        self
+    }
+
+    /// Instructions answering the commands of the client until it disconnects, then stopping the exchange
+    pub fn build(self) -> Vec<Instruction> {
+        let next_cas = self.items.len() as u64 + 1;
+        let mut exchange = MemcachedExchange {
+            items: self.items,
+            errors: self.errors,
+            next_cas,
+            pending: Vec::new(),
+        };
+        let responder = move |message: Option<Vec<u8>>| exchange.respond(&message?);
+        vec![
+            Repeat {
+                times: Times::at_least(0),
+                instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+            },
+            StopExchange,
+        ]
+    }
+}
+
+/// Stored item
+#[derive(Debug, Clone)]
+struct Item {
+    flags: u32,
+    value: Vec<u8>,
+    cas: u64,
+}
+
+/// Command of either protocol, once parsed
+enum Command<'a> {
+    Get(Vec<&'a [u8]>),
+    Set {
+        key: &'a [u8],
+        flags: u32,
+        value: &'a [u8],
+    },
+    Delete(&'a [u8]),
+    Version,
+    /// Command which isn't mocked, or malformed one
+    Unknown,
+}
+
+/// Commands received from the client, some possibly incomplete
+struct MemcachedExchange {
+    items: HashMap<Vec<u8>, Item>,
+    errors: HashMap<Vec<u8>, String>,
+    next_cas: u64,
+    /// Start of a command whose end wasn't received yet
+    pending: Vec<u8>,
+}
+
+impl MemcachedExchange {
+    /// Replies to the complete commands received so far, `None` if there's none
+    fn respond(&mut self, message: &[u8]) -> Option<Vec<u8>> {
+        self.pending.extend_from_slice(message);
+        let received = std::mem::take(&mut self.pending);
+        let mut rest = received.as_slice();
+        let mut reply = Vec::new();
+        while !rest.is_empty() {
+            let consumed = if rest[0] == REQUEST_MAGIC {
+                self.binary_command(rest, &mut reply)
+            } else {
+                self.text_command(rest, &mut reply)
+            };
+            let Some(consumed) = consumed else {
+                self.pending = rest.to_vec();
+                break;
+            };
+            rest = &rest[consumed..];
+        }
+        (!reply.is_empty()).then_some(reply)
+    }
+
+    /// Answer the text command at the start of the given bytes, returning its length, `None` if it's incomplete
+    fn text_command(&mut self, bytes: &[u8], reply: &mut Vec<u8>) -> Option<usize> {
+        let line_end = bytes.windows(2).position(|window| window == b"\r\n")?;
+        let line = &bytes[..line_end];
+        let words: Vec<&[u8]> = line
+            .split(|byte| *byte == b' ')
+            .filter(|word| !word.is_empty())
+            .collect();
+        let mut consumed = line_end + 2;
+        let noreply = words.last() == Some(&&b"noreply"[..]);
+        let command = match words.as_slice() {
+            [b"get" | b"gets", keys @ ..] if !keys.is_empty() => Command::Get(keys.to_vec()),
+            [b"set", key, flags, _, length, ..] => {
+                let (Some(flags), Some(length)) = (parse_number(flags), parse_number(length))
+                else {
+                    reply.extend_from_slice(b"CLIENT_ERROR bad command line format\r\n");
+                    return Some(consumed);
+                };
+                let value = bytes.get(consumed..consumed + length)?;
+                // The data block ends with a new line too
+                bytes.get(consumed + length..consumed + length + 2)?;
+                consumed += length + 2;
+                Command::Set {
+                    key,
+                    flags: u32::try_from(flags).unwrap_or(u32::MAX),
+                    value,
+                }
+            }
+            [b"delete", key, ..] => Command::Delete(key),
+            [b"version"] => Command::Version,
+            [b"quit"] => return Some(consumed),
+            _ => Command::Unknown,
+        };
+        let with_cas = words.first() == Some(&&b"gets"[..]);
+
+        if let Some(message) = self.error(&command) {
+            reply.extend_from_slice(format!("SERVER_ERROR {message}\r\n").as_bytes());
+            return Some(consumed);
+        }
+        let text_reply = match command {
+            Command::Get(keys) => {
+                let mut text_reply = Vec::new();
+                for key in keys {
+                    if let Some(item) = self.items.get(key) {
+                        text_reply.extend_from_slice(b"VALUE ");
+                        text_reply.extend_from_slice(key);
+                        let header = if with_cas {
+                            format!(" {} {} {}", item.flags, item.value.len(), item.cas)
+                        } else {
+                            format!(" {} {}", item.flags, item.value.len())
+                        };
+                        text_reply.extend_from_slice(header.as_bytes());
+                        text_reply.extend_from_slice(b"\r\n");
+                        text_reply.extend_from_slice(&item.value);
+                        text_reply.extend_from_slice(b"\r\n");
+                    }
+                }
+                text_reply.extend_from_slice(b"END\r\n");
+                text_reply
+            }
+            Command::Set { key, flags, value } => {
+                self.set(key, flags, value);
+                b"STORED\r\n".to_vec()
+            }
+            Command::Delete(key) => {
+                if self.items.remove(key).is_some() {
+                    b"DELETED\r\n".to_vec()
+                } else {
+                    b"NOT_FOUND\r\n".to_vec()
+                }
+            }
+            Command::Version => format!("VERSION {SERVER_VERSION}\r\n").into_bytes(),
+            Command::Unknown => b"ERROR\r\n".to_vec(),
+        };
+        if !noreply {
+            reply.extend_from_slice(&text_reply);
+        }
+        Some(consumed)
+    }
+
+    /// Answer the binary request at the start of the given bytes, returning its length, `None` if it's incomplete
+    fn binary_command(&mut self, bytes: &[u8], reply: &mut Vec<u8>) -> Option<usize> {
+        let header = bytes.get(..BINARY_HEADER_SIZE)?;
+        let opcode = header[1];
+        let key_length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+        let extras_length = usize::from(header[4]);
+        let body_length = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        let body_length = usize::try_from(body_length).ok()?;
+        let opaque = [header[12], header[13], header[14], header[15]];
+        let body = bytes.get(BINARY_HEADER_SIZE..BINARY_HEADER_SIZE + body_length)?;
+        let consumed = BINARY_HEADER_SIZE + body_length;
+        let response = BinaryResponse { opcode, opaque };
+
+        let (Some(extras), Some(key)) = (
+            body.get(..extras_length),
+            body.get(extras_length..extras_length + key_length),
+        ) else {
+            response.status(reply, STATUS_INVALID_ARGUMENTS, b"Invalid arguments", 0);
+            return Some(consumed);
+        };
+        let value = &body[extras_length + key_length..];
+        let command = match opcode {
+            GET | GETQ | GETK | GETKQ => Command::Get(vec![key]),
+            SET | SETQ => match <[u8; 4]>::try_from(extras.get(..4).unwrap_or_default()) {
+                Ok(flags) => Command::Set {
+                    key,
+                    flags: u32::from_be_bytes(flags),
+                    value,
+                },
+                Err(_) => Command::Unknown,
+            },
+            DELETE | DELETEQ => Command::Delete(key),
+            VERSION => Command::Version,
+            NOOP => {
+                response.status(reply, STATUS_OK, &[], 0);
+                return Some(consumed);
+            }
+            _ => Command::Unknown,
+        };
+        match self.error(&command) {
+            Some(message) => response.status(reply, STATUS_INTERNAL_ERROR, message.as_bytes(), 0),
+            None => self.binary_reply(command, &response, reply),
+        }
+        Some(consumed)
+    }
+
+    /// Answer the given binary command, quiet ones only replying on a miss or an error
+    fn binary_reply(
+        &mut self,
+        command: Command<'_>,
+        response: &BinaryResponse,
+        reply: &mut Vec<u8>,
+    ) {
+        let quiet = matches!(response.opcode, GETQ | GETKQ | SETQ | DELETEQ);
+        match command {
+            Command::Get(keys) => match keys
+                .first()
+                .and_then(|key| Some((key, self.items.get(*key)?)))
+            {
+                Some((key, item)) => {
+                    let returned_key = if matches!(response.opcode, GETK | GETKQ) {
+                        key
+                    } else {
+                        &[][..]
+                    };
+                    let flags = item.flags.to_be_bytes();
+                    response.push(
+                        reply,
+                        STATUS_OK,
+                        &flags,
+                        returned_key,
+                        &item.value,
+                        item.cas,
+                    );
+                }
+                // Quiet gets only reply on a hit
+                None if quiet => {}
+                None => response.status(reply, STATUS_KEY_NOT_FOUND, b"Not found", 0),
+            },
+            Command::Set { key, flags, value } => {
+                let cas = self.set(key, flags, value);
+                if !quiet {
+                    response.status(reply, STATUS_OK, &[], cas);
+                }
+            }
+            Command::Delete(key) => {
+                if self.items.remove(key).is_none() {
+                    response.status(reply, STATUS_KEY_NOT_FOUND, b"Not found", 0);
+                } else if !quiet {
+                    response.status(reply, STATUS_OK, &[], 0);
+                }
+            }
+            Command::Version => response.status(reply, STATUS_OK, SERVER_VERSION.as_bytes(), 0),
+            Command::Unknown => {
+                response.status(reply, STATUS_UNKNOWN_COMMAND, b"Unknown command", 0);
+            }
+        }
+    }
+
+    /// Message of the server error configured for one of the keys of the command
+    fn error(&self, command: &Command<'_>) -> Option<&String> {
+        match command {
+            Command::Get(keys) => keys.iter().find_map(|key| self.errors.get(*key)),
+            Command::Set { key, .. } | Command::Delete(key) => self.errors.get(*key),
+            Command::Version | Command::Unknown => None,
+        }
+    }
+
+    /// Store the given item, returning its CAS value
+    fn set(&mut self, key: &[u8], flags: u32, value: &[u8]) -> u64 {
+        let cas = self.next_cas;
+        self.next_cas += 1;
+        let item = Item {
+            flags,
+            value: value.to_vec(),
+            cas,
+        };
+        self.items.insert(key.to_vec(), item);
+        cas
+    }
+}
+
+/// Response to a binary request, echoing its opcode and opaque value
+struct BinaryResponse {
+    opcode: u8,
+    opaque: [u8; 4],
+}
+
+impl BinaryResponse {
+    /// Append the response with the given status and value, without extras nor key
+    fn status(&self, reply: &mut Vec<u8>, status: u16, value: &[u8], cas: u64) {
+        self.push(reply, status, &[], &[], value, cas);
+    }
+
+    /// Append the response with the given status and body
+    fn push(
+        &self,
+        reply: &mut Vec<u8>,
+        status: u16,
+        extras: &[u8],
+        key: &[u8],
+        value: &[u8],
+        cas: u64,
+    ) {
+        let key_length = u16::try_from(key.len()).unwrap_or(u16::MAX);
+        let extras_length = u8::try_from(extras.len()).unwrap_or(u8::MAX);
+        let body_length = u32::try_from(extras.len() + key.len() + value.len()).unwrap_or(u32::MAX);
+        reply.extend_from_slice(&[RESPONSE_MAGIC, self.opcode]);
+        reply.extend_from_slice(&key_length.to_be_bytes());
+        // Raw bytes data type
+        reply.extend_from_slice(&[extras_length, 0]);
+        reply.extend_from_slice(&status.to_be_bytes());
+        reply.extend_from_slice(&body_length.to_be_bytes());
+        reply.extend_from_slice(&self.opaque);
+        reply.extend_from_slice(&cas.to_be_bytes());
+        reply.extend_from_slice(extras);
+        reply.extend_from_slice(key);
+        reply.extend_from_slice(value);
+    }
+}
+
+/// Decimal number of a text command
+fn parse_number(word: &[u8]) -> Option<usize> {
+    std::str::from_utf8(word).ok()?.parse().ok()
+}
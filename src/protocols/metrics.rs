@@ -0,0 +1,245 @@
+//! # `metrics`
+//!
+//! Parse the `StatsD` and Graphite plaintext metrics received from the client, and assert on their aggregates.
+
+use std::fmt;
+use std::ops::RangeBounds;
+
+/// Type of a metric, given by its `StatsD` type suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    /// `c`, added up
+    Counter,
+    /// `g`, the last value being the current one
+    Gauge,
+    /// `ms`
+    Timer,
+    /// `h`
+    Histogram,
+    /// `d`
+    Distribution,
+    /// `s`, counting unique values
+    Set,
+    /// Graphite plaintext metric, without type
+    Graphite,
+}
+
+/// Metric received from the client, see [`Metrics`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    /// Name of the metric, the path of a Graphite metric
+    pub name: String,
+    /// Value as sent, not a number for some sets
+    pub value: String,
+    /// Type of the metric
+    pub metric_type: MetricType,
+    /// Tags, sent in the `#` field of a `DogStatsD` line or after `;` in a Graphite path, `None` valued if sent without value
+    pub tags: Vec<(String, Option<String>)>,
+    /// Sample rate of a `StatsD` metric, 1 if not sent
+    pub sample_rate: f64,
+    /// Unix timestamp of a Graphite metric
+    pub timestamp: Option<i64>,
+}
+
+impl Metric {
+    /// Value as a number, `None` if it isn't one
+    pub fn as_f64(&self) -> Option<f64> {
+        self.value.parse().ok()
+    }
+
+    /// Value of the given tag, `Some(None)` if the tag was sent without value
+    pub fn tag(&self, key: &str) -> Option<Option<&str>> {
+        self.tags
+            .iter()
+            .find(|(tag_key, _)| tag_key == key)
+            .map(|(_, value)| value.as_deref())
+    }
+
+    /// Parse a `StatsD` line, e.g. `requests:1|c|@0.5|#route:/login`, `None` if it is invalid
+    fn parse_statsd(line: &str) -> Option<Self> {
+        let mut fields = line.split('|');
+        let (name, value) = fields.next()?.split_once(':')?;
+        let metric_type = match fields.next()? {
+            "c" => MetricType::Counter,
+            "g" => MetricType::Gauge,
+            "ms" => MetricType::Timer,
+            "h" => MetricType::Histogram,
+            "d" => MetricType::Distribution,
+            "s" => MetricType::Set,
+            _ => return None,
+        };
+        let mut metric = Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            metric_type,
+            tags: Vec::new(),
+            sample_rate: 1.0,
+            timestamp: None,
+        };
+        for field in fields {
+            if let Some(sample_rate) = field.strip_prefix('@') {
+                metric.sample_rate = sample_rate.parse().ok()?;
+            } else if let Some(tags) = field.strip_prefix('#') {
+                metric.tags = tags.split(',').map(|tag| split_tag(tag, ':')).collect();
+            }
+        }
+        Some(metric)
+    }
+
+    /// Parse a Graphite plaintext line, e.g. `servers.web01.load;dc=eu 0.42 1700000000`, `None` if it is invalid
+    fn parse_graphite(line: &str) -> Option<Self> {
+        let mut fields = line.split_ascii_whitespace();
+        let mut path = fields.next()?.split(';');
+        let name = path.next()?.to_string();
+        let value = fields.next()?.to_string();
+        let timestamp = match fields.next() {
+            // A negative timestamp asks the server to use its own time
+            Some(timestamp) => Some(timestamp.parse::<i64>().ok()?).filter(|&t| t >= 0),
+            None => None,
+        };
+        Some(Self {
+            name,
+            value,
+            metric_type: MetricType::Graphite,
+            tags: path.map(|tag| split_tag(tag, '=')).collect(),
+            sample_rate: 1.0,
+            timestamp,
+        })
+    }
+}
+
+/// Split a tag into its key and its value, if any
+fn split_tag(tag: &str, separator: char) -> (String, Option<String>) {
+    match tag.split_once(separator) {
+        Some((key, value)) => (key.to_string(), Some(value.to_string())),
+        None => (tag.to_string(), None),
+    }
+}
+
+/// Metrics received from the client, with aggregates to assert on
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::metrics::Metrics;
+///
+/// let metrics = Metrics::parse(b"requests:1|c|#route:/login\nrequests:2|c|@0.5\nlatency:12|ms\nlatency:20|ms");
+/// metrics.assert_counter("requests", 3.0..);
+/// assert_eq!(5.0, metrics.counter("requests"));
+/// assert_eq!(1.0, metrics.with_tag("route", "/login").counter("requests"));
+/// assert_eq!(vec![12.0, 20.0], metrics.values("latency"));
+///
+/// let metrics = Metrics::parse(b"servers.web01.load 0.42 1700000000\nservers.web01.load 0.5 1700000060\n");
+/// assert_eq!(Some(0.5), metrics.gauge("servers.web01.load"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics(pub Vec<Metric>);
+
+impl Metrics {
+    /// Parse `StatsD` and Graphite plaintext lines, in any order, invalid lines being ignored
+    pub fn parse(data: &[u8]) -> Self {
+        let data = String::from_utf8_lossy(data);
+        let metrics = data
+            .lines()
+            .filter_map(|line| {
+                if line.contains('|') {
+                    Metric::parse_statsd(line)
+                } else {
+                    Metric::parse_graphite(line)
+                }
+            })
+            .collect();
+        Self(metrics)
+    }
+
+    /// Parse the lines of each of the given messages, e.g. all the messages received so far
+    /// with [`ServerMocker::received_messages`](crate::ServerMocker::received_messages).
+    ///
+    /// Over TCP, set [`Framing::Line`](crate::Framing::Line) so that a line isn't split across messages.
+    ///
+    /// # Example
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use socket_server_mocker::protocols::metrics::Metrics;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::udp().unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage, ReceiveMessage]).unwrap();
+    /// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// client.send_to(b"requests:1|c\nqueue.size:12|g", server.socket_address()).unwrap();
+    /// client.send_to(b"requests:2|c", server.socket_address()).unwrap();
+    ///
+    /// let metrics = Metrics::parse_messages(&server.received_messages());
+    /// metrics.assert_counter("requests", 3.0..);
+    /// metrics.assert_gauge("queue.size", 10.0..=20.0);
+    /// ```
+    pub fn parse_messages(messages: &[Vec<u8>]) -> Self {
+        let metrics = messages
+            .iter()
+            .flat_map(|message| Self::parse(message).0)
+            .collect();
+        Self(metrics)
+    }
+
+    /// Metrics with the given name, in order
+    pub fn named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Metric> {
+        self.0.iter().filter(move |metric| metric.name == name)
+    }
+
+    /// Metrics having the given tag
+    #[must_use]
+    pub fn with_tag(&self, key: &str, value: &str) -> Self {
+        let metrics = self
+            .0
+            .iter()
+            .filter(|metric| metric.tag(key) == Some(Some(value)));
+        Self(metrics.cloned().collect())
+    }
+
+    /// Total of the counters with the given name, each value being scaled up by its sample rate
+    pub fn counter(&self, name: &str) -> f64 {
+        self.named(name)
+            .filter(|metric| metric.metric_type == MetricType::Counter)
+            .filter_map(|metric| Some(metric.as_f64()? / metric.sample_rate))
+            .sum()
+    }
+
+    /// Last value of the gauge or Graphite metric with the given name
+    pub fn gauge(&self, name: &str) -> Option<f64> {
+        self.named(name)
+            .filter(|metric| matches!(metric.metric_type, MetricType::Gauge | MetricType::Graphite))
+            .filter_map(Metric::as_f64)
+            .last()
+    }
+
+    /// Values of the metrics with the given name, e.g. the samples of a timer
+    pub fn values(&self, name: &str) -> Vec<f64> {
+        self.named(name).filter_map(Metric::as_f64).collect()
+    }
+
+    /// Assert that the total of the counters with the given name is in the given range, e.g. `3.0..` for at least 3
+    ///
+    /// # Panics
+    /// If the total is out of the range
+    #[track_caller]
+    pub fn assert_counter(&self, name: &str, expected: impl RangeBounds<f64> + fmt::Debug) {
+        let total = self.counter(name);
+        assert!(
+            expected.contains(&total),
+            "Counter {name} is {total}, expected in {expected:?}"
+        );
+    }
+
+    /// Assert that the last value of the gauge with the given name is in the given range
+    ///
+    /// # Panics
+    /// If the gauge wasn't received, or if its value is out of the range
+    #[track_caller]
+    pub fn assert_gauge(&self, name: &str, expected: impl RangeBounds<f64> + fmt::Debug) {
+        let value = self.gauge(name);
+        assert!(
+            value.is_some_and(|value| expected.contains(&value)),
+            "Gauge {name} is {value:?}, expected in {expected:?}"
+        );
+    }
+}
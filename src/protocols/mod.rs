@@ -0,0 +1,22 @@
+//! # `protocols`
+//!
+//! Builders generating the instructions of common protocol conversations from high-level settings,
+//! instead of hand-written byte scripts.
+
+pub mod amqp;
+pub mod dhcp;
+pub mod grpc;
+mod http2;
+pub mod kafka;
+pub mod line_session;
+pub mod memcached;
+pub mod metrics;
+pub mod mysql;
+pub mod ntp;
+pub mod postgres;
+pub mod sip;
+pub mod smtp;
+pub mod ssh;
+pub mod syslog;
+pub mod websocket;
+pub mod whois;
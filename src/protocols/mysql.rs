@@ -0,0 +1,576 @@
+//! # `mysql`
+//!
+//! `MySQL` client/server protocol: handshake and authentication of a client, then text queries.
+
+use sha1::{Digest, Sha1};
+
+use crate::Instruction::{self, ReceiveMessage, Repeat, SendMessage};
+use crate::Times;
+
+// Column types, see `enum_field_types` in MySQL sources
+/// Column type of `TINYINT`
+pub const TYPE_TINY: u8 = 1;
+/// Column type of `SMALLINT`
+pub const TYPE_SHORT: u8 = 2;
+/// Column type of `INT`
+pub const TYPE_LONG: u8 = 3;
+/// Column type of `DOUBLE`
+pub const TYPE_DOUBLE: u8 = 5;
+/// Column type of `NULL`
+pub const TYPE_NULL: u8 = 6;
+/// Column type of `BIGINT`
+pub const TYPE_LONGLONG: u8 = 8;
+/// Column type of `DATETIME`
+pub const TYPE_DATETIME: u8 = 12;
+/// Column type of `BLOB` and `TEXT`
+pub const TYPE_BLOB: u8 = 252;
+/// Column type of `VARCHAR`
+pub const TYPE_VAR_STRING: u8 = 253;
+
+/// Capability flags, see `CLIENT_*` in `MySQL` sources
+const CLIENT_LONG_PASSWORD: u32 = 0x0000_0001;
+const CLIENT_FOUND_ROWS: u32 = 0x0000_0002;
+const CLIENT_LONG_FLAG: u32 = 0x0000_0004;
+const CLIENT_CONNECT_WITH_DB: u32 = 0x0000_0008;
+const CLIENT_PROTOCOL_41: u32 = 0x0000_0200;
+const CLIENT_TRANSACTIONS: u32 = 0x0000_2000;
+const CLIENT_SECURE_CONNECTION: u32 = 0x0000_8000;
+const CLIENT_MULTI_STATEMENTS: u32 = 0x0001_0000;
+const CLIENT_MULTI_RESULTS: u32 = 0x0002_0000;
+const CLIENT_PS_MULTI_RESULTS: u32 = 0x0004_0000;
+const CLIENT_PLUGIN_AUTH: u32 = 0x0008_0000;
+const CLIENT_CONNECT_ATTRS: u32 = 0x0010_0000;
+const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 0x0020_0000;
+/// Capabilities of the mocked server. `CLIENT_DEPRECATE_EOF` isn't one of them,
+/// so that resultsets always end with EOF packets whatever the client.
+const SERVER_CAPABILITIES: u32 = CLIENT_LONG_PASSWORD
+    | CLIENT_FOUND_ROWS
+    | CLIENT_LONG_FLAG
+    | CLIENT_CONNECT_WITH_DB
+    | CLIENT_PROTOCOL_41
+    | CLIENT_TRANSACTIONS
+    | CLIENT_SECURE_CONNECTION
+    | CLIENT_MULTI_STATEMENTS
+    | CLIENT_MULTI_RESULTS
+    | CLIENT_PS_MULTI_RESULTS
+    | CLIENT_PLUGIN_AUTH
+    | CLIENT_CONNECT_ATTRS
+    | CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA;
+
+/// `SERVER_STATUS_AUTOCOMMIT`, sent in OK and EOF packets
+const STATUS_AUTOCOMMIT: u16 = 0x0002;
+/// `utf8mb4_general_ci`, and `binary` for numeric columns
+const UTF8MB4_CHARSET: u8 = 45;
+const BINARY_CHARSET: u16 = 63;
+/// Scramble sent with the authentication requests.
+/// It's supposed to be random, but a mocked server has nothing to protect.
+const SCRAMBLE: &[u8; 20] = b"socket-server-mocker";
+const NATIVE_PASSWORD_PLUGIN: &str = "mysql_native_password";
+/// Plugin advertised in the initial handshake before switching to `mysql_native_password`,
+/// the default of `MySQL` 8
+const CACHING_SHA2_PASSWORD_PLUGIN: &str = "caching_sha2_password";
+
+/// Packets, appended one after the other with consecutive sequence IDs to be sent at once.
+///
+/// The sequence ID is reset by the client at each command, so a reply to a command starts at 1.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::mysql::{MysqlColumn, MysqlPackets, TYPE_VAR_STRING};
+/// use socket_server_mocker::Instruction::SendMessage;
+///
+/// let reply = MysqlPackets::new(1)
+///     .column_count(1)
+///     .column_definition(&MysqlColumn::new("name", TYPE_VAR_STRING))
+///     .eof()
+///     .text_row(&[Some("alice")])
+///     .eof()
+///     .build();
+/// // Length of the payload, then sequence ID
+/// assert_eq!([1, 0, 0, 1, 1], reply[..5]);
+/// let instruction = SendMessage(reply);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MysqlPackets {
+    bytes: Vec<u8>,
+    sequence_id: u8,
+}
+
+impl MysqlPackets {
+    /// No packet yet, the first one having the given sequence ID
+    pub fn new(sequence_id: u8) -> Self {
+        Self {
+            bytes: Vec::new(),
+            sequence_id,
+        }
+    }
+
+    /// Initial handshake (protocol version 10), asking for the authentication with the given plugin
+    #[must_use]
+    pub fn handshake(self, server_version: &str, connection_id: u32, auth_plugin: &str) -> Self {
+        let mut payload = vec![10];
+        push_null_terminated(&mut payload, server_version.as_bytes());
+        payload.extend_from_slice(&connection_id.to_le_bytes());
+        payload.extend_from_slice(&SCRAMBLE[..8]);
+        payload.push(0);
+        payload.extend_from_slice(&SERVER_CAPABILITIES.to_le_bytes()[..2]);
+        payload.push(UTF8MB4_CHARSET);
+        payload.extend_from_slice(&STATUS_AUTOCOMMIT.to_le_bytes());
+        payload.extend_from_slice(&SERVER_CAPABILITIES.to_le_bytes()[2..]);
+        // Length of the scramble with its terminating null byte
+        payload.push(21);
+        payload.extend_from_slice(&[0; 10]);
+        push_null_terminated(&mut payload, &SCRAMBLE[8..]);
+        push_null_terminated(&mut payload, auth_plugin.as_bytes());
+        self.packet(&payload)
+    }
+
+    /// Auth switch request, asking the client to authenticate again with the given plugin
+    #[must_use]
+    pub fn auth_switch_request(self, auth_plugin: &str) -> Self {
+        let mut payload = vec![0xfe];
+        push_null_terminated(&mut payload, auth_plugin.as_bytes());
+        push_null_terminated(&mut payload, SCRAMBLE);
+        self.packet(&payload)
+    }
+
+    /// OK packet, with the number of affected rows and the last inserted ID
+    #[must_use]
+    pub fn ok(self, affected_rows: u64, last_insert_id: u64) -> Self {
+        let mut payload = vec![0];
+        push_length_encoded_int(&mut payload, affected_rows);
+        push_length_encoded_int(&mut payload, last_insert_id);
+        payload.extend_from_slice(&STATUS_AUTOCOMMIT.to_le_bytes());
+        // No warning
+        payload.extend_from_slice(&[0; 2]);
+        self.packet(&payload)
+    }
+
+    /// ERR packet, with the given error code and SQL state, e.g. `1146` and `42S02` for an unknown table
+    #[must_use]
+    pub fn err(self, code: u16, sql_state: &str, message: &str) -> Self {
+        let mut payload = vec![0xff];
+        payload.extend_from_slice(&code.to_le_bytes());
+        payload.push(b'#');
+        payload.extend_from_slice(sql_state.as_bytes());
+        payload.extend_from_slice(message.as_bytes());
+        self.packet(&payload)
+    }
+
+    /// EOF packet, ending the column definitions and the rows of a resultset
+    #[must_use]
+    pub fn eof(self) -> Self {
+        let mut payload = vec![0xfe, 0, 0];
+        payload.extend_from_slice(&STATUS_AUTOCOMMIT.to_le_bytes());
+        self.packet(&payload)
+    }
+
+    /// Number of columns, starting a resultset
+    #[must_use]
+    pub fn column_count(self, count: u64) -> Self {
+        let mut payload = Vec::new();
+        push_length_encoded_int(&mut payload, count);
+        self.packet(&payload)
+    }
+
+    /// Definition of a column of a resultset
+    #[must_use]
+    pub fn column_definition(self, column: &MysqlColumn) -> Self {
+        let mut payload = Vec::new();
+        // Catalog, schema, table and original table
+        for field in ["def", "", "", ""] {
+            push_length_encoded_string(&mut payload, field.as_bytes());
+        }
+        // Name and original name
+        push_length_encoded_string(&mut payload, column.name.as_bytes());
+        push_length_encoded_string(&mut payload, column.name.as_bytes());
+        // Length of the fixed fields
+        payload.push(0x0c);
+        let charset = match column.column_type {
+            TYPE_TINY | TYPE_SHORT | TYPE_LONG | TYPE_DOUBLE | TYPE_LONGLONG => BINARY_CHARSET,
+            _ => u16::from(UTF8MB4_CHARSET),
+        };
+        payload.extend_from_slice(&charset.to_le_bytes());
+        // Maximum length, unknown
+        payload.extend_from_slice(&u32::MAX.to_le_bytes());
+        payload.push(column.column_type);
+        // No flag, no decimal and filler
+        payload.extend_from_slice(&[0; 5]);
+        self.packet(&payload)
+    }
+
+    /// Row of a resultset, with the text of each value, `None` being `NULL`
+    #[must_use]
+    pub fn text_row<V: AsRef<[u8]>>(self, values: &[Option<V>]) -> Self {
+        let mut payload = Vec::new();
+        for value in values {
+            match value {
+                Some(value) => push_length_encoded_string(&mut payload, value.as_ref()),
+                None => payload.push(0xfb),
+            }
+        }
+        self.packet(&payload)
+    }
+
+    /// Packet with the given raw payload
+    #[must_use]
+    pub fn packet(mut self, payload: &[u8]) -> Self {
+        let length = u32::try_from(payload.len()).unwrap_or(u32::MAX);
+        self.bytes.extend_from_slice(&length.to_le_bytes()[..3]);
+        self.bytes.push(self.sequence_id);
+        self.bytes.extend_from_slice(payload);
+        self.sequence_id = self.sequence_id.wrapping_add(1);
+        self
+    }
+
+    /// Raw packets, to be sent with [`Instruction::SendMessage`]
+    pub fn build(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Column of a resultset, see [`MysqlPackets::column_definition`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MysqlColumn {
+    /// Name of the column
+    pub name: String,
+    /// Type of the column, such as [`TYPE_LONG`]
+    pub column_type: u8,
+}
+
+impl MysqlColumn {
+    /// Column with the given name and type
+    pub fn new(name: impl Into<String>, column_type: u8) -> Self {
+        Self {
+            name: name.into(),
+            column_type,
+        }
+    }
+}
+
+/// Build the instructions of a `MySQL` server accepting the connection of a client.
+///
+/// Any credentials are accepted, unless [`MysqlMockBuilder::expect_credentials`] is used:
+/// other ones are then rejected with an ERR packet, so that the client fails to connect.
+/// Queries can then be mocked with [`MysqlQueryBuilder`].
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::mysql::MysqlMockBuilder;
+///
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = MysqlMockBuilder::new()
+///     .server_version("5.7.44")
+///     .auth_switch()
+///     .expect_credentials("root", "password")
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct MysqlMockBuilder {
+    server_version: String,
+    connection_id: u32,
+    auth_switch: bool,
+    credentials: Option<(String, String)>,
+}
+
+impl Default for MysqlMockBuilder {
+    fn default() -> Self {
+        Self {
+            server_version: "8.0.36".to_string(),
+            connection_id: 1,
+            auth_switch: false,
+            credentials: None,
+        }
+    }
+}
+
+impl MysqlMockBuilder {
+    /// Server reporting `MySQL` 8.0.36, authenticating clients with `mysql_native_password`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the version reported in the handshake
+    #[must_use]
+    pub fn server_version(mut self, server_version: impl Into<String>) -> Self {
+        self.server_version = server_version.into();
+        self
+    }
+
+    /// Set the connection ID reported in the handshake
+    #[must_use]
+    pub fn connection_id(mut self, connection_id: u32) -> Self {
+        self.connection_id = connection_id;
+        self
+    }
+
+    /// Advertise `caching_sha2_password` in the handshake, then switch to `mysql_native_password`,
+    /// as `MySQL` 8 does for users created with the latter
+    #[must_use]
+    pub fn auth_switch(mut self) -> Self {
+        self.auth_switch = true;
+        self
+    }
+
+    /// Only accept the given user and password, replying ERR `1045` to the others
+    #[must_use]
+    pub fn expect_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Instructions of the connection phase, ending once the client is authenticated
+    pub fn build(self) -> Vec<Instruction> {
+        let auth_plugin = if self.auth_switch {
+            CACHING_SHA2_PASSWORD_PLUGIN
+        } else {
+            NATIVE_PASSWORD_PLUGIN
+        };
+        let handshake = MysqlPackets::new(0)
+            .handshake(&self.server_version, self.connection_id, auth_plugin)
+            .build();
+        let rounds = if self.auth_switch { 2 } else { 1 };
+        let mut exchange = HandshakeExchange {
+            credentials: self.credentials,
+            auth_switch: self.auth_switch,
+            username: None,
+        };
+        let responder = move |message: Option<Vec<u8>>| Some(exchange.respond(&message?));
+        vec![
+            SendMessage(handshake),
+            Repeat {
+                times: Times::exactly(rounds),
+                instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+            },
+        ]
+    }
+}
+
+/// Build the instructions of a text query (`COM_QUERY`), answered with a resultset or an OK packet.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::mysql::{MysqlQueryBuilder, TYPE_LONG, TYPE_VAR_STRING};
+///
+/// // Given to ServerMocker::add_mock_instructions, once connected
+/// let select = MysqlQueryBuilder::new()
+///     .column("id", TYPE_LONG)
+///     .column("name", TYPE_VAR_STRING)
+///     .row(&[Some("1"), Some("alice")])
+///     .row(&[Some("2"), None])
+///     .build();
+/// let insert = MysqlQueryBuilder::new()
+///     .affected_rows(1)
+///     .last_insert_id(3)
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MysqlQueryBuilder {
+    columns: Vec<MysqlColumn>,
+    rows: Vec<Vec<Option<Vec<u8>>>>,
+    affected_rows: u64,
+    last_insert_id: u64,
+}
+
+impl MysqlQueryBuilder {
+    /// Query answered with an OK packet, until columns are added
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a column of the given type to the resultset
+    #[must_use]
+    pub fn column(mut self, name: impl Into<String>, column_type: u8) -> Self {
+        self.columns.push(MysqlColumn::new(name, column_type));
+        self
+    }
+
+    /// Add a row with the text of each value to the resultset, `None` being `NULL`
+    #[must_use]
+    pub fn row<V: AsRef<[u8]>>(mut self, values: &[Option<V>]) -> Self {
+        self.rows.push(
+            values
+                .iter()
+                .map(|value| value.as_ref().map(|value| value.as_ref().to_vec()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Set the number of affected rows of the OK packet
+    #[must_use]
+    pub fn affected_rows(mut self, affected_rows: u64) -> Self {
+        self.affected_rows = affected_rows;
+        self
+    }
+
+    /// Set the last inserted ID of the OK packet
+    #[must_use]
+    pub fn last_insert_id(mut self, last_insert_id: u64) -> Self {
+        self.last_insert_id = last_insert_id;
+        self
+    }
+
+    /// Instructions receiving the query and sending the reply
+    pub fn build(self) -> Vec<Instruction> {
+        let reply = if self.columns.is_empty() {
+            MysqlPackets::new(1).ok(self.affected_rows, self.last_insert_id)
+        } else {
+            let mut reply = MysqlPackets::new(1).column_count(self.columns.len() as u64);
+            for column in &self.columns {
+                reply = reply.column_definition(column);
+            }
+            reply = reply.eof();
+            for row in &self.rows {
+                reply = reply.text_row(row);
+            }
+            reply.eof()
+        };
+        vec![ReceiveMessage, SendMessage(reply.build())]
+    }
+}
+
+/// Authentication of the client, one packet at a time
+struct HandshakeExchange {
+    credentials: Option<(String, String)>,
+    auth_switch: bool,
+    /// User sent in the handshake response, once received
+    username: Option<String>,
+}
+
+impl HandshakeExchange {
+    /// Reply to the given packet of the client
+    fn respond(&mut self, message: &[u8]) -> Vec<u8> {
+        let Some((&sequence_id, payload)) = message.get(3..).and_then(<[u8]>::split_first) else {
+            return handshake_error(0);
+        };
+        let reply = MysqlPackets::new(sequence_id.wrapping_add(1));
+        if self.username.is_none() {
+            let Some((username, auth_response)) = parse_handshake_response(payload) else {
+                return handshake_error(sequence_id.wrapping_add(1));
+            };
+            self.username = Some(username);
+            if self.auth_switch {
+                return reply.auth_switch_request(NATIVE_PASSWORD_PLUGIN).build();
+            }
+            return self.verdict(reply, &auth_response);
+        }
+        // Response to the auth switch request
+        self.verdict(reply, payload)
+    }
+
+    /// Check the credentials, and reply whether they are accepted
+    fn verdict(&self, reply: MysqlPackets, auth_response: &[u8]) -> Vec<u8> {
+        let username = self.username.as_deref().unwrap_or_default();
+        let accepted =
+            self.credentials
+                .as_ref()
+                .map_or(true, |(expected_username, expected_password)| {
+                    expected_username == username
+                        && native_password_token(expected_password) == auth_response
+                });
+        if accepted {
+            reply.ok(0, 0).build()
+        } else {
+            let using_password = if auth_response.is_empty() {
+                "NO"
+            } else {
+                "YES"
+            };
+            let message = format!(
+                "Access denied for user '{username}'@'localhost' (using password: {using_password})"
+            );
+            reply.err(1045, "28000", &message).build()
+        }
+    }
+}
+
+/// ERR packet replying to a malformed handshake response
+fn handshake_error(sequence_id: u8) -> Vec<u8> {
+    MysqlPackets::new(sequence_id)
+        .err(1043, "08S01", "Bad handshake")
+        .build()
+}
+
+/// User and authentication response of the given `HandshakeResponse41` payload
+fn parse_handshake_response(payload: &[u8]) -> Option<(String, Vec<u8>)> {
+    let capabilities = u32::from_le_bytes(payload.get(..4)?.try_into().ok()?);
+    // Capabilities, maximum packet size, charset and filler
+    let rest = payload.get(32..)?;
+    let username_end = rest.iter().position(|byte| *byte == 0)?;
+    let username = String::from_utf8_lossy(&rest[..username_end]).into_owned();
+    let rest = &rest[username_end + 1..];
+    let (length, rest) = if capabilities & CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA != 0 {
+        read_length_encoded_int(rest)?
+    } else {
+        let (&length, rest) = rest.split_first()?;
+        (u64::from(length), rest)
+    };
+    let auth_response = rest.get(..usize::try_from(length).ok()?)?;
+    Some((username, auth_response.to_vec()))
+}
+
+/// `mysql_native_password` token: `SHA1(password) XOR SHA1(scramble + SHA1(SHA1(password)))`
+fn native_password_token(password: &str) -> Vec<u8> {
+    if password.is_empty() {
+        return Vec::new();
+    }
+    let hashed = Sha1::digest(password.as_bytes());
+    let double_hashed = Sha1::digest(hashed);
+    let mut salted = Sha1::new();
+    salted.update(SCRAMBLE);
+    salted.update(double_hashed);
+    hashed
+        .iter()
+        .zip(salted.finalize())
+        .map(|(hashed, salted)| hashed ^ salted)
+        .collect()
+}
+
+fn push_null_terminated(payload: &mut Vec<u8>, value: &[u8]) {
+    payload.extend_from_slice(value);
+    payload.push(0);
+}
+
+/// Append a length-encoded integer, see the `MySQL` protocol basic data types
+fn push_length_encoded_int(payload: &mut Vec<u8>, value: u64) {
+    let bytes = value.to_le_bytes();
+    match value {
+        0..=250 => payload.push(bytes[0]),
+        251..=0xffff => {
+            payload.push(0xfc);
+            payload.extend_from_slice(&bytes[..2]);
+        }
+        0x1_0000..=0xff_ffff => {
+            payload.push(0xfd);
+            payload.extend_from_slice(&bytes[..3]);
+        }
+        _ => {
+            payload.push(0xfe);
+            payload.extend_from_slice(&bytes);
+        }
+    }
+}
+
+fn push_length_encoded_string(payload: &mut Vec<u8>, value: &[u8]) {
+    push_length_encoded_int(payload, value.len() as u64);
+    payload.extend_from_slice(value);
+}
+
+/// Length-encoded integer at the start of the given bytes, and the bytes after it
+fn read_length_encoded_int(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let (&first, rest) = bytes.split_first()?;
+    let size = match first {
+        0xfc => 2,
+        0xfd => 3,
+        0xfe => 8,
+        _ => return Some((u64::from(first), rest)),
+    };
+    let mut value = [0; 8];
+    value[..size].copy_from_slice(rest.get(..size)?);
+    Some((u64::from_le_bytes(value), &rest[size..]))
+}
@@ -0,0 +1,214 @@
+//! # `ntp`
+//!
+//! Canned NTP server answering the requests of an SNTP or NTP client, with a configurable clock.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::Instruction::{self, ReceiveMessage, Repeat, StopExchange};
+use crate::Times;
+
+/// Size of an NTP packet without extension fields nor authenticator
+const PACKET_SIZE: usize = 48;
+/// Seconds between the NTP epoch, 1900-01-01, and the Unix epoch
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// Association modes, see RFC 5905
+const CLIENT_MODE: u8 = 3;
+const SERVER_MODE: u8 = 4;
+
+/// Build the instructions of a canned NTP server, answering the requests of the client until it stops sending.
+///
+/// Client requests are answered with the clock of the server, that is the local clock shifted by [`NtpMockBuilder::offset`].
+/// Responses echo the version and the poll interval of the request, and its transmit timestamp as originate timestamp,
+/// so the client can compute the offset and the round-trip delay.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use socket_server_mocker::protocols::ntp::NtpMockBuilder;
+///
+/// let builder = NtpMockBuilder::new()
+///     .stratum(2)
+///     .reference_id(*b"GPS\0")
+///     .behind_by(Duration::from_millis(1500))
+///     .root_delay(Duration::from_millis(20));
+/// assert!(builder.response(b"not an NTP request").is_none());
+///
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = builder.build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct NtpMockBuilder {
+    /// Leap indicator, 0 for no warning and 3 for an unsynchronized clock, 0 by default
+    pub leap_indicator: u8,
+    /// Stratum of the server, 1 by default, 0 for a Kiss-o'-Death packet
+    pub stratum: u8,
+    /// Precision of the clock, as a power of two exponent in seconds, -20 by default
+    pub precision: i8,
+    /// Round-trip delay to the reference clock, zero by default
+    pub root_delay: Duration,
+    /// Dispersion to the reference clock, zero by default
+    pub root_dispersion: Duration,
+    /// Reference ID, or the kiss code of a Kiss-o'-Death packet, `LOCL` by default
+    pub reference_id: [u8; 4],
+    /// Offset of the clock of the server from the local clock, zero by default
+    pub offset: Duration,
+    /// Whether the clock of the server is behind the local clock, rather than ahead of it
+    pub behind: bool,
+    /// Time between the receive and the transmit timestamps, zero by default
+    pub processing_delay: Duration,
+}
+
+impl Default for NtpMockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NtpMockBuilder {
+    /// Synchronized stratum 1 server, its clock being the local one
+    pub fn new() -> Self {
+        Self {
+            leap_indicator: 0,
+            stratum: 1,
+            precision: -20,
+            root_delay: Duration::ZERO,
+            root_dispersion: Duration::ZERO,
+            reference_id: *b"LOCL",
+            offset: Duration::ZERO,
+            behind: false,
+            processing_delay: Duration::ZERO,
+        }
+    }
+
+    /// Set the leap indicator, only its 2 lowest bits being sent
+    #[must_use]
+    pub fn leap_indicator(mut self, leap_indicator: u8) -> Self {
+        self.leap_indicator = leap_indicator;
+        self
+    }
+
+    /// Set the stratum of the server
+    #[must_use]
+    pub fn stratum(mut self, stratum: u8) -> Self {
+        self.stratum = stratum;
+        self
+    }
+
+    /// Set the precision of the clock
+    #[must_use]
+    pub fn precision(mut self, precision: i8) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Set the round-trip delay to the reference clock
+    #[must_use]
+    pub fn root_delay(mut self, root_delay: Duration) -> Self {
+        self.root_delay = root_delay;
+        self
+    }
+
+    /// Set the dispersion to the reference clock
+    #[must_use]
+    pub fn root_dispersion(mut self, root_dispersion: Duration) -> Self {
+        self.root_dispersion = root_dispersion;
+        self
+    }
+
+    /// Set the reference ID, e.g. `*b"RATE"` along with stratum 0 to ask the client to reduce its rate
+    #[must_use]
+    pub fn reference_id(mut self, reference_id: [u8; 4]) -> Self {
+        self.reference_id = reference_id;
+        self
+    }
+
+    /// Put the clock of the server ahead of the local clock by the given offset
+    #[must_use]
+    pub fn ahead_by(mut self, offset: Duration) -> Self {
+        self.offset = offset;
+        self.behind = false;
+        self
+    }
+
+    /// Put the clock of the server behind the local clock by the given offset
+    #[must_use]
+    pub fn behind_by(mut self, offset: Duration) -> Self {
+        self.offset = offset;
+        self.behind = true;
+        self
+    }
+
+    /// Set the time between the receive and the transmit timestamps, the response not being delayed
+    #[must_use]
+    pub fn processing_delay(mut self, processing_delay: Duration) -> Self {
+        self.processing_delay = processing_delay;
+        self
+    }
+
+    /// Raw response to the given NTP packet, `None` if it isn't a client request
+    pub fn response(&self, request: &[u8]) -> Option<Vec<u8>> {
+        if request.len() < PACKET_SIZE || request[0] & 0b111 != CLIENT_MODE {
+            return None;
+        }
+        let version = (request[0] >> 3) & 0b111;
+
+        let now = SystemTime::now();
+        let receive_time = if self.behind {
+            now.checked_sub(self.offset)
+        } else {
+            now.checked_add(self.offset)
+        }?;
+        let transmit_time = receive_time.checked_add(self.processing_delay)?;
+
+        // Leap indicator, version and mode, stratum, then poll interval echoed from the request
+        let mut response = vec![
+            (self.leap_indicator & 0b11) << 6 | version << 3 | SERVER_MODE,
+            self.stratum,
+            request[2],
+        ];
+        response.extend_from_slice(&self.precision.to_be_bytes());
+        response.extend_from_slice(&short_format(self.root_delay));
+        response.extend_from_slice(&short_format(self.root_dispersion));
+        response.extend_from_slice(&self.reference_id);
+        // Reference timestamp, the clock being last set when receiving the request
+        response.extend_from_slice(&timestamp(receive_time));
+        // Originate timestamp, the transmit timestamp of the request
+        response.extend_from_slice(&request[40..48]);
+        response.extend_from_slice(&timestamp(receive_time));
+        response.extend_from_slice(&timestamp(transmit_time));
+        Some(response)
+    }
+
+    /// Instructions answering the NTP requests of the client until it stops sending, then stopping the exchange.
+    ///
+    /// The received requests can still be retrieved with [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message).
+    pub fn build(self) -> Vec<Instruction> {
+        let responder = move |request: Option<Vec<u8>>| self.response(&request?);
+        vec![
+            Repeat {
+                times: Times::at_least(0),
+                instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+            },
+            StopExchange,
+        ]
+    }
+}
+
+/// NTP short format of the given duration, 16 bits of seconds and 16 bits of fraction
+fn short_format(duration: Duration) -> [u8; 4] {
+    let fixed_point = (duration.as_nanos() << 16) / 1_000_000_000;
+    u32::try_from(fixed_point).unwrap_or(u32::MAX).to_be_bytes()
+}
+
+/// NTP timestamp format of the given time, 32 bits of seconds since 1900 and 32 bits of fraction
+fn timestamp(time: SystemTime) -> [u8; 8] {
+    let since_unix_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    // Wraps around in 2036, as NTP era 0 ends
+    let seconds = (since_unix_epoch.as_secs() + NTP_UNIX_OFFSET_SECS) & u64::from(u32::MAX);
+    let fraction = (u64::from(since_unix_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+    let mut timestamp = [0; 8];
+    timestamp[..4].copy_from_slice(&u32::try_from(seconds).unwrap_or_default().to_be_bytes());
+    timestamp[4..].copy_from_slice(&u32::try_from(fraction).unwrap_or(u32::MAX).to_be_bytes());
+    timestamp
+}
@@ -0,0 +1,643 @@
+//! # `postgres`
+//!
+//! `PostgreSQL` wire protocol: startup and authentication of a client, then queries using the extended query protocol.
+
+use std::fmt::Write;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+use crate::Instruction::{self, ReceiveMessage, Repeat, SendMessage};
+use crate::Times;
+
+// Object IDs of common types, see `pg_type.dat` in PostgreSQL sources
+/// Object ID of `BOOL`
+pub const BOOL_OID: u32 = 16;
+/// Object ID of `BYTEA`
+pub const BYTEA_OID: u32 = 17;
+/// Object ID of `INT8`, or `BIGINT`
+pub const INT8_OID: u32 = 20;
+/// Object ID of `INT2`, or `SMALLINT`
+pub const INT2_OID: u32 = 21;
+/// Object ID of `INT4`, or `INTEGER`
+pub const INT4_OID: u32 = 23;
+/// Object ID of `TEXT`
+pub const TEXT_OID: u32 = 25;
+/// Object ID of `FLOAT4`, or `REAL`
+pub const FLOAT4_OID: u32 = 700;
+/// Object ID of `FLOAT8`, or `DOUBLE PRECISION`
+pub const FLOAT8_OID: u32 = 701;
+/// Object ID of `VARCHAR`
+pub const VARCHAR_OID: u32 = 1043;
+
+/// Protocol version 3.0, sent in the startup message
+const PROTOCOL_VERSION: u32 = 196_608;
+/// Salt sent with the MD5 authentication request
+const MD5_SALT: [u8; 4] = [0x1c, 0x53, 0xa5, 0xf3];
+/// Salt, iteration count and nonce of the SCRAM-SHA-256 authentication.
+/// They're supposed to be random, but a mocked server has nothing to protect.
+const SCRAM_SALT: &[u8] = b"socket-server-mocker";
+const SCRAM_ITERATIONS: u32 = 4096;
+const SCRAM_SERVER_NONCE: &str = "c29ja2V0LXNlcnZlci1tb2NrZXI";
+
+/// Backend messages, appended one after the other to be sent at once.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::postgres::{BackendMessages, PostgresColumn, TEXT_OID};
+/// use socket_server_mocker::Instruction::SendMessage;
+///
+/// let reply = BackendMessages::new()
+///     .row_description(&[PostgresColumn::new("name", TEXT_OID)])
+///     .data_row(&[Some("alice")])
+///     .command_complete("SELECT 1")
+///     .ready_for_query()
+///     .build();
+/// assert_eq!(b'T', reply[0]);
+/// let instruction = SendMessage(reply);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackendMessages(Vec<u8>);
+
+impl BackendMessages {
+    /// No message yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `AuthenticationOk`, the client is authenticated
+    #[must_use]
+    pub fn authentication_ok(self) -> Self {
+        self.message(b'R', &0_u32.to_be_bytes())
+    }
+
+    /// `AuthenticationMD5Password`, asking for the password hashed with the given salt
+    #[must_use]
+    pub fn authentication_md5_password(self, salt: [u8; 4]) -> Self {
+        let mut body = 5_u32.to_be_bytes().to_vec();
+        body.extend_from_slice(&salt);
+        self.message(b'R', &body)
+    }
+
+    /// `ParameterStatus`, the current value of a run-time parameter
+    #[must_use]
+    pub fn parameter_status(self, name: &str, value: &str) -> Self {
+        let mut body = Vec::new();
+        push_string(&mut body, name);
+        push_string(&mut body, value);
+        self.message(b'S', &body)
+    }
+
+    /// `BackendKeyData`, used by the client to cancel its queries
+    #[must_use]
+    pub fn backend_key_data(self, process_id: u32, secret_key: u32) -> Self {
+        let mut body = process_id.to_be_bytes().to_vec();
+        body.extend_from_slice(&secret_key.to_be_bytes());
+        self.message(b'K', &body)
+    }
+
+    /// `ReadyForQuery`, outside of any transaction
+    #[must_use]
+    pub fn ready_for_query(self) -> Self {
+        self.message(b'Z', b"I")
+    }
+
+    /// `ParseComplete`
+    #[must_use]
+    pub fn parse_complete(self) -> Self {
+        self.message(b'1', &[])
+    }
+
+    /// `BindComplete`
+    #[must_use]
+    pub fn bind_complete(self) -> Self {
+        self.message(b'2', &[])
+    }
+
+    /// `ParameterDescription`, the types of the parameters of a prepared statement
+    #[must_use]
+    pub fn parameter_description(self, type_oids: &[u32]) -> Self {
+        let mut body = count_bytes(type_oids.len()).to_vec();
+        for type_oid in type_oids {
+            body.extend_from_slice(&type_oid.to_be_bytes());
+        }
+        self.message(b't', &body)
+    }
+
+    /// `RowDescription`, the columns of the returned rows
+    #[must_use]
+    pub fn row_description(self, columns: &[PostgresColumn]) -> Self {
+        let mut body = count_bytes(columns.len()).to_vec();
+        for column in columns {
+            push_string(&mut body, &column.name);
+            // Table OID and column attribute number, unknown
+            body.extend_from_slice(&[0; 6]);
+            body.extend_from_slice(&column.type_oid.to_be_bytes());
+            // Variable type size, no type modifier and text format
+            body.extend_from_slice(&(-1_i16).to_be_bytes());
+            body.extend_from_slice(&(-1_i32).to_be_bytes());
+            body.extend_from_slice(&[0; 2]);
+        }
+        self.message(b'T', &body)
+    }
+
+    /// `NoData`, the statement doesn't return rows
+    #[must_use]
+    pub fn no_data(self) -> Self {
+        self.message(b'n', &[])
+    }
+
+    /// `DataRow`, with the raw value of each column, `None` being `NULL`
+    #[must_use]
+    pub fn data_row<V: AsRef<[u8]>>(self, values: &[Option<V>]) -> Self {
+        let mut body = count_bytes(values.len()).to_vec();
+        for value in values {
+            match value {
+                Some(value) => {
+                    let value = value.as_ref();
+                    let length = u32::try_from(value.len()).unwrap_or(u32::MAX);
+                    body.extend_from_slice(&length.to_be_bytes());
+                    body.extend_from_slice(value);
+                }
+                None => body.extend_from_slice(&(-1_i32).to_be_bytes()),
+            }
+        }
+        self.message(b'D', &body)
+    }
+
+    /// `CommandComplete`, with the command tag such as `INSERT 0 1` or `SELECT 2`
+    #[must_use]
+    pub fn command_complete(self, tag: &str) -> Self {
+        let mut body = Vec::new();
+        push_string(&mut body, tag);
+        self.message(b'C', &body)
+    }
+
+    /// `ErrorResponse`, with the given SQLSTATE code, e.g. `42P01` for an undefined table
+    #[must_use]
+    pub fn error_response(self, code: &str, message: &str) -> Self {
+        let mut body = Vec::new();
+        for (field, value) in [
+            (b'S', "ERROR"),
+            (b'V', "ERROR"),
+            (b'C', code),
+            (b'M', message),
+        ] {
+            body.push(field);
+            push_string(&mut body, value);
+        }
+        body.push(0);
+        self.message(b'E', &body)
+    }
+
+    /// Raw messages, to be sent with [`Instruction::SendMessage`]
+    pub fn build(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Append a message of the given type
+    fn message(mut self, message_type: u8, body: &[u8]) -> Self {
+        self.0.push(message_type);
+        let length = u32::try_from(body.len() + 4).unwrap_or(u32::MAX);
+        self.0.extend_from_slice(&length.to_be_bytes());
+        self.0.extend_from_slice(body);
+        self
+    }
+}
+
+/// Column of the rows returned by a query, see [`BackendMessages::row_description`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostgresColumn {
+    /// Name of the column
+    pub name: String,
+    /// Object ID of the type of the column, such as [`INT4_OID`]
+    pub type_oid: u32,
+}
+
+impl PostgresColumn {
+    /// Column with the given name and type
+    pub fn new(name: impl Into<String>, type_oid: u32) -> Self {
+        Self {
+            name: name.into(),
+            type_oid,
+        }
+    }
+}
+
+/// Build the instructions of a `PostgreSQL` server accepting the connection of a client.
+///
+/// Clients are trusted by default. With [`PostgresMockBuilder::md5_auth`] or
+/// [`PostgresMockBuilder::scram_sha256_auth`], the password sent by the client is checked,
+/// and a wrong one is rejected with an `ErrorResponse` so that the client fails to connect.
+/// Queries can then be mocked with [`PostgresQueryBuilder`].
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::postgres::PostgresMockBuilder;
+///
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = PostgresMockBuilder::new()
+///     .scram_sha256_auth("password")
+///     .parameter("TimeZone", "Europe/Paris")
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct PostgresMockBuilder {
+    auth: PostgresAuth,
+    parameters: Vec<(String, String)>,
+}
+
+impl Default for PostgresMockBuilder {
+    fn default() -> Self {
+        let parameters = [
+            ("server_version", "16.0"),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, MDY"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+        ];
+        Self {
+            auth: PostgresAuth::Trust,
+            parameters: parameters
+                .iter()
+                .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl PostgresMockBuilder {
+    /// Server trusting any client, reporting `PostgreSQL` 16.0 with UTF-8 encoding
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the client for its password hashed with MD5, and check it against the given one
+    #[must_use]
+    pub fn md5_auth(mut self, password: impl Into<String>) -> Self {
+        self.auth = PostgresAuth::Md5(password.into());
+        self
+    }
+
+    /// Authenticate the client with SCRAM-SHA-256, the default method of modern `PostgreSQL`.
+    ///
+    /// The server proves it knows the given password too, which the client checks.
+    /// Both sides derive their keys with 4096 rounds of PBKDF2, which may exceed the default
+    /// [`TcpMocker::net_timeout`](crate::TcpMocker::net_timeout) in debug builds.
+    #[must_use]
+    pub fn scram_sha256_auth(mut self, password: impl Into<String>) -> Self {
+        self.auth = PostgresAuth::ScramSha256(password.into());
+        self
+    }
+
+    /// Report the given run-time parameter once the client is authenticated, replacing its default value
+    #[must_use]
+    pub fn parameter(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        let value = value.into();
+        match self.parameters.iter_mut().find(|(known, _)| *known == name) {
+            Some((_, known_value)) => *known_value = value,
+            None => self.parameters.push((name, value)),
+        }
+        self
+    }
+
+    /// Instructions of the startup, ending once the server is ready for the first query
+    pub fn build(self) -> Vec<Instruction> {
+        let mut ready = BackendMessages::new().authentication_ok();
+        for (name, value) in &self.parameters {
+            ready = ready.parameter_status(name, value);
+        }
+        let ready = ready
+            .backend_key_data(1, 0x5eed_5eed)
+            .ready_for_query()
+            .build();
+
+        let rounds = match self.auth {
+            PostgresAuth::Trust => 1,
+            PostgresAuth::Md5(_) => 2,
+            PostgresAuth::ScramSha256(_) => 3,
+        };
+        let mut exchange = StartupExchange {
+            auth: self.auth,
+            ready,
+            user: String::new(),
+            scram_messages: None,
+        };
+        let responder = move |message: Option<Vec<u8>>| Some(exchange.respond(&message?));
+        vec![Repeat {
+            times: Times::exactly(rounds),
+            instructions: vec![ReceiveMessage, Instruction::send_from_closure(responder)],
+        }]
+    }
+}
+
+/// Build the instructions of a query sent with the extended query protocol, as done by the `postgres` crate.
+///
+/// The client first prepares the statement, getting the types of its parameters and the columns of its rows,
+/// then executes it, getting the rows and the command tag.
+///
+/// Row values are sent as is: the `postgres` crate asks for them in binary format,
+/// e.g. `1_i32.to_be_bytes()` for an `INT4` column, while text is the same in both formats.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::postgres::{PostgresQueryBuilder, INT4_OID, VARCHAR_OID};
+///
+/// // Given to ServerMocker::add_mock_instructions, once connected
+/// let instructions = PostgresQueryBuilder::new("SELECT 2")
+///     .parameter(INT4_OID)
+///     .column("id", INT4_OID)
+///     .column("name", VARCHAR_OID)
+///     .row(vec![Some(1_i32.to_be_bytes().to_vec()), Some(b"alice".to_vec())])
+///     .row(vec![Some(2_i32.to_be_bytes().to_vec()), None])
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct PostgresQueryBuilder {
+    command_tag: String,
+    parameter_types: Vec<u32>,
+    columns: Vec<PostgresColumn>,
+    rows: Vec<Vec<Option<Vec<u8>>>>,
+}
+
+impl PostgresQueryBuilder {
+    /// Query completing with the given command tag, such as `INSERT 0 1` or `SELECT 2`
+    pub fn new(command_tag: impl Into<String>) -> Self {
+        Self {
+            command_tag: command_tag.into(),
+            parameter_types: Vec::new(),
+            columns: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a parameter of the given type to the statement, such as [`VARCHAR_OID`]
+    #[must_use]
+    pub fn parameter(mut self, type_oid: u32) -> Self {
+        self.parameter_types.push(type_oid);
+        self
+    }
+
+    /// Add a column of the given type to the returned rows
+    #[must_use]
+    pub fn column(mut self, name: impl Into<String>, type_oid: u32) -> Self {
+        self.columns.push(PostgresColumn::new(name, type_oid));
+        self
+    }
+
+    /// Return a row with the given raw values, `None` being `NULL`
+    #[must_use]
+    pub fn row(mut self, values: Vec<Option<Vec<u8>>>) -> Self {
+        self.rows.push(values);
+        self
+    }
+
+    /// Instructions answering the Parse, Describe and Sync messages, then the Bind, Execute and Sync messages
+    pub fn build(self) -> Vec<Instruction> {
+        let prepared = BackendMessages::new()
+            .parse_complete()
+            .parameter_description(&self.parameter_types);
+        let prepared = if self.columns.is_empty() {
+            prepared.no_data()
+        } else {
+            prepared.row_description(&self.columns)
+        };
+
+        let mut executed = BackendMessages::new().bind_complete();
+        for row in &self.rows {
+            executed = executed.data_row(row);
+        }
+        vec![
+            ReceiveMessage,
+            SendMessage(prepared.ready_for_query().build()),
+            ReceiveMessage,
+            SendMessage(
+                executed
+                    .command_complete(&self.command_tag)
+                    .ready_for_query()
+                    .build(),
+            ),
+        ]
+    }
+}
+
+/// Authentication method, see [`PostgresMockBuilder`]
+#[derive(Debug, Clone)]
+enum PostgresAuth {
+    Trust,
+    Md5(String),
+    ScramSha256(String),
+}
+
+/// Startup exchange with the client, one message at a time
+struct StartupExchange {
+    auth: PostgresAuth,
+    /// Reply once the client is authenticated
+    ready: Vec<u8>,
+    /// User sent in the startup message
+    user: String,
+    /// Client first message without its header, and server first message of SCRAM-SHA-256
+    scram_messages: Option<(String, String)>,
+}
+
+impl StartupExchange {
+    /// Reply to the given message of the client
+    fn respond(&mut self, message: &[u8]) -> Vec<u8> {
+        if let Some(user) = startup_user(message) {
+            self.user = user;
+            return match self.auth {
+                PostgresAuth::Trust => self.ready.clone(),
+                PostgresAuth::Md5(_) => BackendMessages::new()
+                    .authentication_md5_password(MD5_SALT)
+                    .build(),
+                PostgresAuth::ScramSha256(_) => {
+                    let mut body = 10_u32.to_be_bytes().to_vec();
+                    push_string(&mut body, "SCRAM-SHA-256");
+                    body.push(0);
+                    BackendMessages::new().message(b'R', &body).build()
+                }
+            };
+        }
+        let Some(body) = password_message_body(message) else {
+            return protocol_violation();
+        };
+        match &self.auth {
+            PostgresAuth::Md5(password) => {
+                let inner = md5_hex(format!("{password}{}", self.user).as_bytes());
+                let mut salted = inner.into_bytes();
+                salted.extend_from_slice(&MD5_SALT);
+                let expected = format!("md5{}", md5_hex(&salted));
+                if body.strip_suffix(&[0]) == Some(expected.as_bytes()) {
+                    self.ready.clone()
+                } else {
+                    self.authentication_failed()
+                }
+            }
+            PostgresAuth::ScramSha256(password) => match self.scram_messages.take() {
+                None => self.scram_first(body),
+                Some((client_first_bare, server_first)) => {
+                    let password = password.clone();
+                    self.scram_final(body, &password, &client_first_bare, &server_first)
+                }
+            },
+            PostgresAuth::Trust => protocol_violation(),
+        }
+    }
+
+    /// Reply to the `SASLInitialResponse` of the client with the server first message
+    fn scram_first(&mut self, body: &[u8]) -> Vec<u8> {
+        let Some(client_first) = body
+            .strip_prefix(b"SCRAM-SHA-256\0")
+            .and_then(|rest| rest.get(4..))
+            .and_then(|rest| std::str::from_utf8(rest).ok())
+        else {
+            return protocol_violation();
+        };
+        // Skip the GS2 header, e.g. "n,,"
+        let Some(client_first_bare) = client_first.splitn(3, ',').nth(2) else {
+            return protocol_violation();
+        };
+        let Some(client_nonce) = scram_attribute(client_first_bare, 'r') else {
+            return protocol_violation();
+        };
+        let server_first = format!(
+            "r={client_nonce}{SCRAM_SERVER_NONCE},s={},i={SCRAM_ITERATIONS}",
+            STANDARD.encode(SCRAM_SALT)
+        );
+        let mut reply = 11_u32.to_be_bytes().to_vec();
+        reply.extend_from_slice(server_first.as_bytes());
+        self.scram_messages = Some((client_first_bare.to_string(), server_first));
+        BackendMessages::new().message(b'R', &reply).build()
+    }
+
+    /// Check the proof sent in the `SASLResponse` of the client, and reply with the server signature
+    fn scram_final(
+        &self,
+        body: &[u8],
+        password: &str,
+        client_first_bare: &str,
+        server_first: &str,
+    ) -> Vec<u8> {
+        let Ok(client_final) = std::str::from_utf8(body) else {
+            return protocol_violation();
+        };
+        let Some((client_final_without_proof, proof)) = client_final.rsplit_once(",p=") else {
+            return protocol_violation();
+        };
+        let auth_message =
+            format!("{client_first_bare},{server_first},{client_final_without_proof}");
+
+        let salted_password = pbkdf2_sha256(password.as_bytes(), SCRAM_SALT, SCRAM_ITERATIONS);
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(client_key);
+        let client_signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+        let expected_proof: Vec<u8> = client_key
+            .iter()
+            .zip(client_signature)
+            .map(|(key, signature)| key ^ signature)
+            .collect();
+        if STANDARD.decode(proof).ok() != Some(expected_proof) {
+            return self.authentication_failed();
+        }
+
+        let server_key = hmac_sha256(&salted_password, b"Server Key");
+        let server_signature = hmac_sha256(&server_key, auth_message.as_bytes());
+        let mut reply = 12_u32.to_be_bytes().to_vec();
+        reply.extend_from_slice(format!("v={}", STANDARD.encode(server_signature)).as_bytes());
+        let mut reply = BackendMessages::new().message(b'R', &reply).build();
+        reply.extend_from_slice(&self.ready);
+        reply
+    }
+
+    fn authentication_failed(&self) -> Vec<u8> {
+        let message = format!("password authentication failed for user \"{}\"", self.user);
+        BackendMessages::new()
+            .error_response("28P01", &message)
+            .build()
+    }
+}
+
+/// User of the given startup message, `None` if it isn't one
+fn startup_user(message: &[u8]) -> Option<String> {
+    let version = message.get(4..8)?;
+    if version != PROTOCOL_VERSION.to_be_bytes() {
+        return None;
+    }
+    let mut parameters = message[8..].split(|byte| *byte == 0);
+    while let (Some(name), Some(value)) = (parameters.next(), parameters.next()) {
+        if name == b"user" {
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+    }
+    Some(String::new())
+}
+
+/// Body of the given password message, also used for SASL responses
+fn password_message_body(message: &[u8]) -> Option<&[u8]> {
+    let (&b'p', rest) = message.split_first()? else {
+        return None;
+    };
+    rest.get(4..)
+}
+
+/// Value of the given attribute of a SCRAM message
+fn scram_attribute(message: &str, attribute: char) -> Option<&str> {
+    message
+        .split(',')
+        .find_map(|field| field.strip_prefix(attribute)?.strip_prefix('='))
+}
+
+/// Reply to an unexpected message
+fn protocol_violation() -> Vec<u8> {
+    BackendMessages::new()
+        .error_response("08P01", "unexpected message during startup")
+        .build()
+}
+
+/// Append a null-terminated string
+fn push_string(body: &mut Vec<u8>, value: &str) {
+    body.extend_from_slice(value.as_bytes());
+    body.push(0);
+}
+
+/// Count of fields or columns, as sent in messages
+fn count_bytes(count: usize) -> [u8; 2] {
+    u16::try_from(count).unwrap_or(u16::MAX).to_be_bytes()
+}
+
+/// Lowercase hex MD5 digest
+fn md5_hex(data: &[u8]) -> String {
+    Md5::digest(data)
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// HMAC-SHA-256 of the data keyed by the given key
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// PBKDF2 with HMAC-SHA-256, deriving a single block, see RFC 8018
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&1_u32.to_be_bytes());
+    let mut u = hmac_sha256(password, &block);
+    let mut derived = u;
+    for _ in 1..iterations {
+        u = hmac_sha256(password, &u);
+        for (derived, u) in derived.iter_mut().zip(u) {
+            *derived ^= u;
+        }
+    }
+    derived
+}
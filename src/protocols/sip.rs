@@ -0,0 +1,384 @@
+//! # `sip`
+//!
+//! SIP user agent server answering the requests of a client with scripted provisional and final responses.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::Instruction::{self, ReceiveMessage, Repeat, StopExchange};
+use crate::Times;
+
+/// Tag added to the To header of the responses, identifying the dialog on the server side
+const TO_TAG: &str = "socket-server-mocker";
+
+/// Compact forms of the header names, see RFC 3261 section 7.3.3
+const COMPACT_FORMS: [(&str, &str); 7] = [
+    ("v", "via"),
+    ("f", "from"),
+    ("t", "to"),
+    ("i", "call-id"),
+    ("m", "contact"),
+    ("l", "content-length"),
+    ("c", "content-type"),
+];
+
+/// Response sent to a SIP request, the transaction headers being copied from the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SipResponse {
+    status_code: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl SipResponse {
+    /// Response with the given status code and reason phrase
+    pub fn new(status_code: u16, reason: impl Into<String>) -> Self {
+        Self {
+            status_code,
+            reason: reason.into(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// `100 Trying`
+    pub fn trying() -> Self {
+        Self::new(100, "Trying")
+    }
+
+    /// `180 Ringing`
+    pub fn ringing() -> Self {
+        Self::new(180, "Ringing")
+    }
+
+    /// `200 OK`
+    pub fn ok() -> Self {
+        Self::new(200, "OK")
+    }
+
+    /// Add the given header, e.g. `WWW-Authenticate` to challenge a REGISTER
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Send the given body, e.g. an SDP answer with the `application/sdp` content type
+    #[must_use]
+    pub fn body(self, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        let mut response = self.header("Content-Type", content_type);
+        response.body = body.into();
+        response
+    }
+}
+
+/// Request received from the client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SipRequest {
+    /// Method, e.g. `INVITE`
+    pub method: String,
+    /// Request URI, e.g. `sip:bob@example.com`
+    pub uri: String,
+    /// Headers, in order, as sent
+    pub headers: Vec<(String, String)>,
+    /// Body, e.g. an SDP offer
+    pub body: Vec<u8>,
+}
+
+impl SipRequest {
+    /// Value of the first header with the given name, case-insensitive, compact forms included
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.header_values(name).first().copied()
+    }
+
+    /// Values of every header with the given name, e.g. the Via headers
+    fn header_values(&self, name: &str) -> Vec<&str> {
+        let name = full_header_name(name);
+        self.headers
+            .iter()
+            .filter(|(header_name, _)| full_header_name(header_name).eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+}
+
+/// Full name of a header given in its compact form
+fn full_header_name(name: &str) -> &str {
+    COMPACT_FORMS
+        .iter()
+        .find(|(compact, _)| compact.eq_ignore_ascii_case(name))
+        .map_or(name, |(_, full)| full)
+}
+
+/// Requests received by the SIP server mocker, shared with the test
+#[derive(Debug, Clone, Default)]
+pub struct SipRequests(Arc<Mutex<Vec<SipRequest>>>);
+
+impl SipRequests {
+    /// Requests received so far, oldest first, ACKs included
+    pub fn requests(&self) -> Vec<SipRequest> {
+        self.log().clone()
+    }
+
+    fn log(&self) -> MutexGuard<'_, Vec<SipRequest>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Build the instructions of a SIP user agent server, over UDP or TCP, until the client stops sending.
+///
+/// Each request is answered with the responses scripted for its method, every response being sent
+/// in its own datagram over UDP. By default, INVITE requests are answered with `100 Trying`,
+/// `180 Ringing` then `200 OK`, ACK requests aren't answered, and other requests are answered with `200 OK`.
+///
+/// Responses copy the `Via`, `From`, `Call-ID` and `CSeq` headers of the request, and its `To` header with a tag added
+/// except in `100 Trying`. Successful responses to INVITE requests carry the Contact of the server,
+/// and the ones to REGISTER requests the Contact and Expires headers of the request.
+///
+/// # Example
+/// ```
+/// use std::net::SocketAddr;
+/// use socket_server_mocker::protocols::sip::{SipMockBuilder, SipResponse};
+///
+/// let address: SocketAddr = "127.0.0.1:5060".parse().unwrap();
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = SipMockBuilder::new(address)
+///     // Challenge the first REGISTER, then accept the next ones
+///     .respond(
+///         "REGISTER",
+///         vec![SipResponse::new(401, "Unauthorized")
+///             .header("WWW-Authenticate", r#"Digest realm="mock", nonce="42""#)],
+///     )
+///     .respond("REGISTER", vec![SipResponse::ok()])
+///     .respond("INVITE", vec![SipResponse::trying(), SipResponse::new(486, "Busy Here")])
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SipMockBuilder {
+    contact: String,
+    scripts: HashMap<String, VecDeque<Vec<SipResponse>>>,
+    requests: SipRequests,
+}
+
+impl SipMockBuilder {
+    /// Server whose Contact is at the given address, usually the one of the server mocker
+    pub fn new(address: SocketAddr) -> Self {
+        Self {
+            contact: format!("<sip:mock@{address}>"),
+            scripts: HashMap::new(),
+            requests: SipRequests::default(),
+        }
+    }
+
+    /// Set the Contact header sent in successful responses to INVITE requests, e.g. with a `transport=tcp` parameter
+    #[must_use]
+    pub fn contact(mut self, contact: impl Into<String>) -> Self {
+        self.contact = contact.into();
+        self
+    }
+
+    /// Answer the next request with the given method with the given responses, in order.
+    ///
+    /// Responses given for the same method are used for the successive requests, the last ones being repeated.
+    #[must_use]
+    pub fn respond(mut self, method: &str, responses: Vec<SipResponse>) -> Self {
+        self.scripts
+            .entry(method.to_ascii_uppercase())
+            .or_default()
+            .push_back(responses);
+        self
+    }
+
+    /// Handle on the requests received by the server, to be retrieved before calling [`SipMockBuilder::build`]
+    pub fn requests(&self) -> SipRequests {
+        self.requests.clone()
+    }
+
+    /// Instructions answering the requests of the client until it stops sending, then stopping the exchange
+    pub fn build(self) -> Vec<Instruction> {
+        // At least the 3 responses of the default INVITE script
+        let max_responses = self
+            .scripts
+            .values()
+            .flatten()
+            .map(Vec::len)
+            .chain([3])
+            .max()
+            .unwrap_or_default();
+        let exchange = Arc::new(Mutex::new(SipExchange {
+            contact: self.contact,
+            scripts: self.scripts,
+            requests: self.requests,
+            pending: Vec::new(),
+            responses: VecDeque::new(),
+        }));
+
+        let mut instructions = vec![ReceiveMessage];
+        let receiving_exchange = Arc::clone(&exchange);
+        instructions.push(Instruction::send_from_closure(move |message| {
+            let mut exchange = receiving_exchange
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            exchange.receive(&message?);
+            exchange.responses.pop_front()
+        }));
+        // Following responses to the same request, each one sent as a separate message
+        for _ in 1..max_responses {
+            let exchange = Arc::clone(&exchange);
+            instructions.push(Instruction::send_from_closure(move |_| {
+                let mut exchange = exchange.lock().unwrap_or_else(PoisonError::into_inner);
+                exchange.responses.pop_front()
+            }));
+        }
+        vec![
+            Repeat {
+                times: Times::at_least(0),
+                instructions,
+            },
+            StopExchange,
+        ]
+    }
+}
+
+/// State of the conversation with the client
+struct SipExchange {
+    contact: String,
+    scripts: HashMap<String, VecDeque<Vec<SipResponse>>>,
+    requests: SipRequests,
+    /// Start of a request whose end wasn't received yet, over TCP
+    pending: Vec<u8>,
+    /// Responses not sent yet
+    responses: VecDeque<Vec<u8>>,
+}
+
+impl SipExchange {
+    /// Queue the responses to the complete requests received so far
+    fn receive(&mut self, message: &[u8]) {
+        self.pending.extend_from_slice(message);
+        while let Some(request) = self.next_request() {
+            let responses = self.script(&request.method);
+            for response in responses {
+                let encoded = self.encode(&request, &response);
+                self.responses.push_back(encoded);
+            }
+            self.requests.log().push(request);
+        }
+    }
+
+    /// Parse the next complete request, skipping the responses and the keep-alive line breaks
+    fn next_request(&mut self) -> Option<SipRequest> {
+        loop {
+            let start = self
+                .pending
+                .iter()
+                .position(|byte| !byte.is_ascii_whitespace())?;
+            self.pending.drain(..start);
+            let header_end = self.pending.windows(4).position(|w| w == b"\r\n\r\n")?;
+            let head = String::from_utf8_lossy(&self.pending[..header_end]).into_owned();
+            let mut lines = head.split("\r\n");
+            let mut start_line = lines.next()?.split(' ');
+            let (method, uri) = (
+                start_line.next()?.to_string(),
+                start_line.next()?.to_string(),
+            );
+
+            let mut headers: Vec<(String, String)> = Vec::new();
+            for line in lines {
+                match (line.strip_prefix([' ', '\t']), headers.last_mut()) {
+                    // Folded header value
+                    (Some(continuation), Some((_, value))) => {
+                        value.push(' ');
+                        value.push_str(continuation.trim());
+                    }
+                    _ => {
+                        if let Some((name, value)) = line.split_once(':') {
+                            headers.push((name.trim().to_string(), value.trim().to_string()));
+                        }
+                    }
+                }
+            }
+            let mut request = SipRequest {
+                method,
+                uri,
+                headers,
+                body: Vec::new(),
+            };
+            let body_start = header_end + 4;
+            // Without Content-Length, as allowed over UDP, the body is the rest of the datagram
+            let body_end = match request.header("content-length") {
+                Some(length) => body_start + length.parse::<usize>().unwrap_or_default(),
+                None => self.pending.len(),
+            };
+            if self.pending.len() < body_end {
+                return None;
+            }
+            request.body = self.pending[body_start..body_end].to_vec();
+            self.pending.drain(..body_end);
+            if request.method != "SIP/2.0" {
+                return Some(request);
+            }
+        }
+    }
+
+    /// Responses scripted for the next request with the given method
+    fn script(&mut self, method: &str) -> Vec<SipResponse> {
+        match self.scripts.get_mut(method) {
+            Some(scripts) if scripts.len() > 1 => scripts.pop_front().unwrap_or_default(),
+            Some(scripts) => scripts.front().cloned().unwrap_or_default(),
+            None if method == "INVITE" => {
+                vec![
+                    SipResponse::trying(),
+                    SipResponse::ringing(),
+                    SipResponse::ok(),
+                ]
+            }
+            None if method == "ACK" => Vec::new(),
+            None => vec![SipResponse::ok()],
+        }
+    }
+
+    /// Encode a response to the given request
+    fn encode(&self, request: &SipRequest, response: &SipResponse) -> Vec<u8> {
+        let mut encoded = format!("SIP/2.0 {} {}\r\n", response.status_code, response.reason);
+        for via in request.header_values("via") {
+            let _ = write!(encoded, "Via: {via}\r\n");
+        }
+        if let Some(from) = request.header("from") {
+            let _ = write!(encoded, "From: {from}\r\n");
+        }
+        if let Some(to) = request.header("to") {
+            let has_tag = to.to_ascii_lowercase().contains(";tag=");
+            if has_tag || response.status_code == 100 {
+                let _ = write!(encoded, "To: {to}\r\n");
+            } else {
+                let _ = write!(encoded, "To: {to};tag={TO_TAG}\r\n");
+            }
+        }
+        for name in ["Call-ID", "CSeq"] {
+            if let Some(value) = request.header(name) {
+                let _ = write!(encoded, "{name}: {value}\r\n");
+            }
+        }
+        if (200..300).contains(&response.status_code) {
+            if request.method == "INVITE" {
+                let _ = write!(encoded, "Contact: {}\r\n", self.contact);
+            } else if request.method == "REGISTER" {
+                for name in ["Contact", "Expires"] {
+                    for value in request.header_values(name) {
+                        let _ = write!(encoded, "{name}: {value}\r\n");
+                    }
+                }
+            }
+        }
+        for (name, value) in &response.headers {
+            let _ = write!(encoded, "{name}: {value}\r\n");
+        }
+        let _ = write!(encoded, "Content-Length: {}\r\n\r\n", response.body.len());
+        let mut encoded = encoded.into_bytes();
+        encoded.extend_from_slice(&response.body);
+        encoded
+    }
+}
@@ -0,0 +1,468 @@
+//! # `smtp`
+//!
+//! SMTP conversation of a client sending a single email.
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use md5::Md5;
+
+use crate::Instruction::{
+    self, ReceiveMessage, ReceiveUntilDelimiter, Repeat, SendMessage, StopExchange,
+};
+use crate::Times;
+
+/// Reply to a command other than the one expected at this point of the conversation
+const BAD_SEQUENCE_REPLY: &[u8] = b"503 5.5.1 Bad sequence of commands\r\n";
+/// Replies to the last step of the authentication
+const AUTH_SUCCESSFUL_REPLY: &[u8] = b"235 2.7.0 Authentication successful\r\n";
+const AUTH_INVALID_REPLY: &[u8] = b"535 5.7.8 Authentication credentials invalid\r\n";
+/// Reply to an authentication response which isn't valid base64
+const AUTH_UNDECODABLE_REPLY: &[u8] = b"501 5.5.2 Cannot decode response\r\n";
+
+/// Build the instructions of an SMTP server accepting a single email: banner, EHLO, MAIL, RCPT and DATA.
+///
+/// The envelope is checked as it's received: a sender or a recipient other than the expected ones
+/// is rejected with a `550` reply, so that the client under test fails to send the email.
+/// The received commands and the email content can still be retrieved with
+/// [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message).
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::smtp::SmtpMockBuilder;
+///
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = SmtpMockBuilder::new()
+///     .hostname("mail.example.com")
+///     .capability("SIZE 10240000")
+///     .expect_sender("alice@example.com")
+///     .expect_recipient("bob@example.com")
+///     .queue_id("4F2A1B3C")
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SmtpMockBuilder {
+    hostname: String,
+    capabilities: Vec<String>,
+    sender: Option<String>,
+    recipients: Vec<String>,
+    queue_id: String,
+    auth: Option<SmtpAuth>,
+    captured_credentials: CapturedCredentials,
+}
+
+impl Default for SmtpMockBuilder {
+    fn default() -> Self {
+        Self {
+            hostname: "smtp.localhost.mock".to_string(),
+            capabilities: vec!["ENHANCEDSTATUSCODES".to_string(), "8BITMIME".to_string()],
+            sender: None,
+            recipients: Vec::new(),
+            queue_id: "1C1A1B1C1D".to_string(),
+            auth: None,
+            captured_credentials: CapturedCredentials::default(),
+        }
+    }
+}
+
+impl SmtpMockBuilder {
+    /// Builder accepting any sender and a single recipient, with the default hostname and capabilities
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the hostname sent in the banner and in the EHLO reply, `smtp.localhost.mock` by default
+    #[must_use]
+    pub fn hostname(mut self, hostname: impl Into<String>) -> Self {
+        self.hostname = hostname.into();
+        self
+    }
+
+    /// Advertise the given capability in the EHLO reply, such as `SIZE 10240000`,
+    /// after `ENHANCEDSTATUSCODES` and `8BITMIME`
+    #[must_use]
+    pub fn capability(mut self, capability: impl Into<String>) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    /// Only accept the given sender address in `MAIL FROM`
+    #[must_use]
+    pub fn expect_sender(mut self, sender: impl Into<String>) -> Self {
+        self.sender = Some(sender.into());
+        self
+    }
+
+    /// Expect the given recipient address in `RCPT TO`, in any order.
+    ///
+    /// As many `RCPT TO` commands as expected recipients are received, a single one accepting any address
+    /// if no recipient is expected.
+    #[must_use]
+    pub fn expect_recipient(mut self, recipient: impl Into<String>) -> Self {
+        self.recipients.push(recipient.into());
+        self
+    }
+
+    /// Set the queue ID sent once the email is accepted
+    #[must_use]
+    pub fn queue_id(mut self, queue_id: impl Into<String>) -> Self {
+        self.queue_id = queue_id.into();
+        self
+    }
+
+    /// Advertise the given authentication mechanism, and expect the client to authenticate before sending the email.
+    ///
+    /// Any credentials are accepted, unless [`SmtpMockBuilder::expect_credentials`] or
+    /// [`SmtpMockBuilder::reject_auth`] is used. The decoded credentials are available through
+    /// [`SmtpMockBuilder::captured_credentials`].
+    #[must_use]
+    pub fn auth(mut self, mechanism: SmtpAuthMechanism) -> Self {
+        self.auth.get_or_insert_with(SmtpAuth::default).mechanism = mechanism;
+        self
+    }
+
+    /// Only accept the given credentials, replying `535` to the others.
+    ///
+    /// With CRAM-MD5, the digest sent by the client is checked against the given password.
+    #[must_use]
+    pub fn expect_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.auth.get_or_insert_with(SmtpAuth::default).expected =
+            Some((username.into(), password.into()));
+        self
+    }
+
+    /// Reply `535` to any credentials, to test the handling of authentication failures
+    #[must_use]
+    pub fn reject_auth(mut self) -> Self {
+        self.auth.get_or_insert_with(SmtpAuth::default).reject = true;
+        self
+    }
+
+    /// Credentials sent by the client, available once the server mocker received them
+    pub fn captured_credentials(&self) -> CapturedCredentials {
+        self.captured_credentials.clone()
+    }
+
+    /// Instructions of the conversation, ending once the email is accepted
+    pub fn build(mut self) -> Vec<Instruction> {
+        if let Some(auth) = &self.auth {
+            self.capabilities
+                .push(format!("AUTH {}", auth.mechanism.name()));
+        }
+        let mut ehlo_reply = format!("250-{}\r\n", self.hostname);
+        for capability in &self.capabilities {
+            let _ = write!(ehlo_reply, "250-{capability}\r\n");
+        }
+        ehlo_reply.push_str("250 HELP\r\n");
+
+        let sender = self.sender;
+        let sender_responder = move |command: Option<Vec<u8>>| {
+            let Some(address) = envelope_address(&command?, "MAIL FROM:") else {
+                return Some(BAD_SEQUENCE_REPLY.to_vec());
+            };
+            Some(match &sender {
+                Some(sender) if !address.eq_ignore_ascii_case(sender) => {
+                    format!("550 5.1.0 Unexpected sender <{address}>\r\n").into_bytes()
+                }
+                _ => b"250 2.1.0 Ok\r\n".to_vec(),
+            })
+        };
+        let recipient_count = self.recipients.len().max(1);
+        let any_recipient = self.recipients.is_empty();
+        let mut recipients = self.recipients;
+        let recipient_responder = move |command: Option<Vec<u8>>| {
+            let Some(address) = envelope_address(&command?, "RCPT TO:") else {
+                return Some(BAD_SEQUENCE_REPLY.to_vec());
+            };
+            let expected = recipients
+                .iter()
+                .position(|recipient| address.eq_ignore_ascii_case(recipient));
+            Some(match expected {
+                Some(index) => {
+                    // Each recipient is expected once
+                    recipients.remove(index);
+                    b"250 2.1.5 Ok\r\n".to_vec()
+                }
+                None if any_recipient => b"250 2.1.5 Ok\r\n".to_vec(),
+                None => format!("550 5.1.1 Unexpected recipient <{address}>\r\n").into_bytes(),
+            })
+        };
+
+        let mut instructions = vec![
+            SendMessage(format!("220 {} ESMTP Mocker\r\n", self.hostname).into_bytes()),
+            ReceiveMessage,
+            SendMessage(ehlo_reply.into_bytes()),
+        ];
+        if let Some(auth) = self.auth {
+            let rounds = auth.mechanism.rounds();
+            let mut exchange = AuthExchange {
+                auth,
+                challenge: format!("<1896.697170952@{}>", self.hostname),
+                username: None,
+                captured_credentials: self.captured_credentials,
+            };
+            let auth_responder = move |message: Option<Vec<u8>>| Some(exchange.respond(&message?));
+            instructions.push(Repeat {
+                times: Times::exactly(rounds),
+                instructions: vec![
+                    ReceiveMessage,
+                    Instruction::send_from_closure(auth_responder),
+                ],
+            });
+        }
+        instructions.extend([
+            ReceiveMessage,
+            Instruction::send_from_closure(sender_responder),
+            Repeat {
+                times: Times::exactly(recipient_count),
+                instructions: vec![
+                    ReceiveMessage,
+                    Instruction::send_from_closure(recipient_responder),
+                ],
+            },
+            ReceiveMessage,
+            SendMessage(b"354 End data with <CR><LF>.<CR><LF>\r\n".to_vec()),
+            ReceiveUntilDelimiter(b"\r\n.\r\n".to_vec()),
+            SendMessage(format!("250 2.0.0 Ok: queued as {}\r\n", self.queue_id).into_bytes()),
+            StopExchange,
+        ]);
+        instructions
+    }
+}
+
+/// SASL mechanism of the SMTP authentication, see [`SmtpMockBuilder::auth`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SmtpAuthMechanism {
+    /// `AUTH PLAIN`, the credentials being sent along with the command
+    #[default]
+    Plain,
+    /// `AUTH LOGIN`, the username and the password being sent in reply to two challenges
+    Login,
+    /// `AUTH CRAM-MD5`, only an HMAC-MD5 digest of a challenge keyed by the password being sent
+    CramMd5,
+}
+
+impl SmtpAuthMechanism {
+    /// Name of the mechanism in the `AUTH` command
+    fn name(self) -> &'static str {
+        match self {
+            SmtpAuthMechanism::Plain => "PLAIN",
+            SmtpAuthMechanism::Login => "LOGIN",
+            SmtpAuthMechanism::CramMd5 => "CRAM-MD5",
+        }
+    }
+
+    /// Number of messages sent by the client to authenticate
+    fn rounds(self) -> usize {
+        match self {
+            SmtpAuthMechanism::Plain => 1,
+            SmtpAuthMechanism::Login => 3,
+            SmtpAuthMechanism::CramMd5 => 2,
+        }
+    }
+}
+
+/// Credentials sent by the client, see [`SmtpMockBuilder::captured_credentials`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmtpCredentials {
+    /// Mechanism used to authenticate
+    pub mechanism: SmtpAuthMechanism,
+    /// Username, the authentication identity with PLAIN
+    pub username: String,
+    /// Password, `None` with CRAM-MD5 which only sends a digest of it
+    pub password: Option<String>,
+}
+
+/// Credentials captured by the SMTP server mocker, shared with the test.
+///
+/// # Example
+/// ```
+/// use std::io::{BufRead, BufReader, Write};
+/// use std::net::TcpStream;
+/// use socket_server_mocker::protocols::smtp::{SmtpAuthMechanism, SmtpMockBuilder};
+/// use socket_server_mocker::ServerMocker;
+///
+/// let smtp_mock = SmtpMockBuilder::new().auth(SmtpAuthMechanism::Plain);
+/// let credentials = smtp_mock.captured_credentials();
+/// let mut server = ServerMocker::tcp().unwrap();
+/// server.add_mock_instructions(smtp_mock.build()).unwrap();
+///
+/// let mut client = BufReader::new(TcpStream::connect(server.socket_address()).unwrap());
+/// let mut line = String::new();
+/// // Banner and EHLO reply
+/// client.read_line(&mut line).unwrap();
+/// client.get_mut().write_all(b"EHLO localhost\r\n").unwrap();
+/// while !line.starts_with("250 ") {
+///     line.clear();
+///     client.read_line(&mut line).unwrap();
+/// }
+/// // "\0alice\0secret" in base64
+/// client.get_mut().write_all(b"AUTH PLAIN AGFsaWNlAHNlY3JldA==\r\n").unwrap();
+/// line.clear();
+/// client.read_line(&mut line).unwrap();
+/// assert!(line.starts_with("235 "));
+///
+/// let credentials = credentials.get().unwrap();
+/// assert_eq!("alice", credentials.username);
+/// assert_eq!(Some("secret".to_string()), credentials.password);
+/// # server.reset().unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CapturedCredentials(Arc<Mutex<Option<SmtpCredentials>>>);
+
+impl CapturedCredentials {
+    /// Last credentials sent by the client, `None` if it hasn't authenticated yet
+    pub fn get(&self) -> Option<SmtpCredentials> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn set(&self, credentials: SmtpCredentials) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(credentials);
+    }
+}
+
+/// Expected authentication, see [`SmtpMockBuilder::auth`]
+#[derive(Debug, Clone, Default)]
+struct SmtpAuth {
+    mechanism: SmtpAuthMechanism,
+    expected: Option<(String, String)>,
+    reject: bool,
+}
+
+/// Authentication exchange with the client, one message at a time
+struct AuthExchange {
+    auth: SmtpAuth,
+    /// Challenge sent with CRAM-MD5
+    challenge: String,
+    /// Username received with LOGIN, before the password
+    username: Option<String>,
+    captured_credentials: CapturedCredentials,
+}
+
+impl AuthExchange {
+    /// Reply to the given message of the client
+    fn respond(&mut self, message: &[u8]) -> Vec<u8> {
+        let message = String::from_utf8_lossy(message);
+        let message = message.trim_end();
+        let command = format!("AUTH {}", self.auth.mechanism.name());
+        if let Some(arguments) = strip_prefix_ignore_case(message, &command) {
+            return match self.auth.mechanism {
+                SmtpAuthMechanism::Plain => match decode(arguments.trim_start()) {
+                    Some(response) => self.plain(&response),
+                    None => AUTH_UNDECODABLE_REPLY.to_vec(),
+                },
+                SmtpAuthMechanism::Login => {
+                    self.username = None;
+                    // "Username:"
+                    b"334 VXNlcm5hbWU6\r\n".to_vec()
+                }
+                SmtpAuthMechanism::CramMd5 => {
+                    format!("334 {}\r\n", STANDARD.encode(&self.challenge)).into_bytes()
+                }
+            };
+        }
+        let Some(response) = decode(message) else {
+            return AUTH_UNDECODABLE_REPLY.to_vec();
+        };
+        match (self.auth.mechanism, self.username.take()) {
+            (SmtpAuthMechanism::Login, None) => {
+                self.username = Some(response);
+                // "Password:"
+                b"334 UGFzc3dvcmQ6\r\n".to_vec()
+            }
+            (SmtpAuthMechanism::Login, Some(username)) => {
+                self.verdict(username, Some(response), None)
+            }
+            (SmtpAuthMechanism::CramMd5, _) => match response.rsplit_once(' ') {
+                Some((username, digest)) => {
+                    let digest = digest.to_ascii_lowercase();
+                    self.verdict(username.to_string(), None, Some(&digest))
+                }
+                None => AUTH_INVALID_REPLY.to_vec(),
+            },
+            (SmtpAuthMechanism::Plain, _) => BAD_SEQUENCE_REPLY.to_vec(),
+        }
+    }
+
+    /// Reply to the `authzid\0authcid\0password` response of PLAIN
+    fn plain(&self, response: &str) -> Vec<u8> {
+        match response.split('\0').collect::<Vec<_>>().as_slice() {
+            [_, username, password] => {
+                self.verdict((*username).to_string(), Some((*password).to_string()), None)
+            }
+            _ => AUTH_INVALID_REPLY.to_vec(),
+        }
+    }
+
+    /// Capture the credentials, and reply whether they are accepted
+    fn verdict(&self, username: String, password: Option<String>, digest: Option<&str>) -> Vec<u8> {
+        let accepted = !self.auth.reject
+            && self.auth.expected.as_ref().map_or(
+                true,
+                |(expected_username, expected_password)| {
+                    *expected_username == username
+                        && match digest {
+                            Some(digest) => {
+                                cram_md5_digest(&self.challenge, expected_password) == digest
+                            }
+                            None => password.as_ref() == Some(expected_password),
+                        }
+                },
+            );
+        self.captured_credentials.set(SmtpCredentials {
+            mechanism: self.auth.mechanism,
+            username,
+            password,
+        });
+        if accepted {
+            AUTH_SUCCESSFUL_REPLY.to_vec()
+        } else {
+            AUTH_INVALID_REPLY.to_vec()
+        }
+    }
+}
+
+/// Text of the given base64 response, `None` if it can't be decoded
+fn decode(response: &str) -> Option<String> {
+    let decoded = STANDARD.decode(response).ok()?;
+    String::from_utf8(decoded).ok()
+}
+
+/// Lowercase hex HMAC-MD5 digest of the challenge keyed by the password, see RFC 2195
+fn cram_md5_digest(challenge: &str, password: &str) -> String {
+    let mut mac =
+        Hmac::<Md5>::new_from_slice(password.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(challenge.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Rest of the given text after the prefix, compared case insensitively
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &text[prefix.len()..])
+}
+
+/// Address of the given `MAIL FROM` or `RCPT TO` command, between angle brackets, `None` for another command
+fn envelope_address(command: &[u8], prefix: &str) -> Option<String> {
+    let command = std::str::from_utf8(command).ok()?;
+    let arguments = strip_prefix_ignore_case(command, prefix)?.trim_start();
+    let address = arguments.strip_prefix('<')?.split('>').next()?;
+    Some(address.to_string())
+}
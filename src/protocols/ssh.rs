@@ -0,0 +1,167 @@
+//! # `ssh`
+//!
+//! SSH server performing the identification string exchange, then misbehaving instead of negotiating the keys.
+
+use std::time::Duration;
+
+use crate::Instruction::{self, ReceiveUntilDelimiter, SendMessage, Silence, StopExchange};
+
+/// Message number of `SSH_MSG_KEXINIT`
+const SSH_MSG_KEXINIT: u8 = 20;
+/// Number of algorithm name-lists in a `SSH_MSG_KEXINIT`, from the key exchange algorithms to the languages
+const KEXINIT_NAME_LISTS: usize = 10;
+/// Algorithm supported by no client, offered in every name-list of an unsupported `SSH_MSG_KEXINIT`
+const UNSUPPORTED_ALGORITHM: &str = "mock@socket-server-mocker";
+/// Time left to the client to close the connection after a malformed packet, its data being discarded
+const LINGER: Duration = Duration::from_secs(1);
+
+/// Behavior of the SSH server once the identification strings are exchanged, see [`SshMockBuilder::then`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SshFailure {
+    /// Close the connection, as a server rejecting the client
+    Close,
+    /// Keep the connection open without sending anything for the given duration, discarding the client data,
+    /// then close it
+    Stall(Duration),
+    /// Send a `SSH_MSG_KEXINIT` offering only unknown algorithms, so that the client finds no matching key exchange method
+    UnsupportedAlgorithms,
+    /// Send a `SSH_MSG_KEXINIT` whose first name-list is longer than the packet
+    MalformedKexinit,
+    /// Send a packet header announcing a packet bigger than any client accepts
+    OversizedPacket,
+    /// Send the given bytes, e.g. a hand-crafted packet
+    Raw(Vec<u8>),
+}
+
+/// Build the instructions of an SSH server exchanging the identification strings, then failing in the given way,
+/// to test how a client handles unreachable or misbehaving SSH servers without implementing SSH.
+///
+/// The server sends its banner lines and its identification string as soon as the client connects,
+/// then receives the identification string of the client, which can be retrieved with
+/// [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message).
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use socket_server_mocker::protocols::ssh::{SshFailure, SshMockBuilder};
+///
+/// // Given to ServerMocker::add_mock_instructions
+/// let instructions = SshMockBuilder::new()
+///     .banner("Authorized access only")
+///     .identification("SSH-2.0-OpenSSH_9.6")
+///     .then(SshFailure::UnsupportedAlgorithms)
+///     .build();
+/// let instructions = SshMockBuilder::new()
+///     .then(SshFailure::Stall(Duration::from_secs(5)))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SshMockBuilder {
+    banner: Vec<String>,
+    identification: String,
+    failure: SshFailure,
+}
+
+impl Default for SshMockBuilder {
+    fn default() -> Self {
+        Self {
+            banner: Vec::new(),
+            identification: "SSH-2.0-OpenSSH_9.6".to_string(),
+            failure: SshFailure::Close,
+        }
+    }
+}
+
+impl SshMockBuilder {
+    /// Server identifying as `SSH-2.0-OpenSSH_9.6`, then closing the connection
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send the given line before the identification string, as allowed by RFC 4253
+    #[must_use]
+    pub fn banner(mut self, line: impl Into<String>) -> Self {
+        self.banner.push(line.into());
+        self
+    }
+
+    /// Set the identification string, without line ending, e.g. `SSH-1.5-Legacy` to test a protocol version mismatch
+    #[must_use]
+    pub fn identification(mut self, identification: impl Into<String>) -> Self {
+        self.identification = identification.into();
+        self
+    }
+
+    /// Set the behavior of the server once the identification strings are exchanged, [`SshFailure::Close`] by default
+    #[must_use]
+    pub fn then(mut self, failure: SshFailure) -> Self {
+        self.failure = failure;
+        self
+    }
+
+    /// Instructions of the exchange, ending with the connection being closed
+    pub fn build(self) -> Vec<Instruction> {
+        let mut greeting = String::new();
+        for line in self.banner.iter().chain([&self.identification]) {
+            greeting.push_str(line);
+            greeting.push_str("\r\n");
+        }
+        let mut instructions = vec![
+            SendMessage(greeting.into_bytes()),
+            ReceiveUntilDelimiter(b"\n".to_vec()),
+        ];
+        let packet = match self.failure {
+            SshFailure::Close => None,
+            SshFailure::Stall(duration) => {
+                instructions.push(Silence(duration));
+                None
+            }
+            SshFailure::UnsupportedAlgorithms => Some(packet(&kexinit(UNSUPPORTED_ALGORITHM))),
+            SshFailure::MalformedKexinit => {
+                let mut payload = kexinit("");
+                // The first name-list claims more bytes than the payload holds
+                payload[17..21].copy_from_slice(&0xFFFF_u32.to_be_bytes());
+                Some(packet(&payload))
+            }
+            // Only the packet length and the padding length, OpenSSH rejecting packets over 256 KiB
+            SshFailure::OversizedPacket => Some(vec![0x7F, 0xFF, 0xFF, 0xF0, 4]),
+            SshFailure::Raw(data) => Some(data),
+        };
+        if let Some(packet) = packet {
+            instructions.extend([SendMessage(packet), Silence(LINGER)]);
+        }
+        instructions.push(StopExchange);
+        instructions
+    }
+}
+
+/// Payload of a `SSH_MSG_KEXINIT` offering the given algorithm in every name-list
+fn kexinit(algorithm: &str) -> Vec<u8> {
+    let mut payload = vec![SSH_MSG_KEXINIT];
+    // Cookie
+    payload.extend_from_slice(&[0x42; 16]);
+    for _ in 0..KEXINIT_NAME_LISTS {
+        let algorithm_len = u32::try_from(algorithm.len()).unwrap_or(u32::MAX);
+        payload.extend_from_slice(&algorithm_len.to_be_bytes());
+        payload.extend_from_slice(algorithm.as_bytes());
+    }
+    // first_kex_packet_follows, then the reserved field
+    payload.push(0);
+    payload.extend_from_slice(&[0; 4]);
+    payload
+}
+
+/// Binary packet holding the given payload, unencrypted and without MAC, as before the first key exchange
+fn packet(payload: &[u8]) -> Vec<u8> {
+    // The packet length, the padding length, the payload and at least 4 bytes of padding make a multiple of 8
+    let mut padding_len = 8 - (payload.len() + 5) % 8;
+    if padding_len < 4 {
+        padding_len += 8;
+    }
+    let packet_len = u32::try_from(1 + payload.len() + padding_len).unwrap_or(u32::MAX);
+    let mut packet = packet_len.to_be_bytes().to_vec();
+    packet.push(u8::try_from(padding_len).unwrap_or(u8::MAX));
+    packet.extend_from_slice(payload);
+    packet.resize(packet.len() + padding_len, 0);
+    packet
+}
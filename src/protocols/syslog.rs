@@ -0,0 +1,231 @@
+//! # `syslog`
+//!
+//! Parse the syslog messages received from the client, in the RFC 5424 or in the legacy RFC 3164 format.
+
+use crate::ServerMockerError::{self, InvalidSyslogMessage};
+
+/// Syslog message received from the client, see [`parse`].
+///
+/// Fields missing from the message, or sent as the `-` nil value, are `None`.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::syslog;
+///
+/// let message = syslog::parse(
+///     br#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3"] An application event"#,
+/// )
+/// .unwrap();
+/// assert_eq!(20, message.facility);
+/// assert_eq!(5, message.severity);
+/// assert_eq!(Some("evntslog"), message.app_name.as_deref());
+/// assert_eq!(Some("3"), message.param("exampleSDID@32473", "iut"));
+/// assert_eq!("An application event", message.message);
+///
+/// let message = syslog::parse(b"<34>Oct 11 22:14:15 mymachine su[42]: 'su root' failed").unwrap();
+/// assert_eq!(None, message.version);
+/// assert_eq!(Some("42"), message.proc_id.as_deref());
+/// assert_eq!("'su root' failed", message.message);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogMessage {
+    /// Facility, e.g. 4 for security messages
+    pub facility: u8,
+    /// Severity, from 0 for emergency to 7 for debug
+    pub severity: u8,
+    /// Version of the format, `None` for an RFC 3164 message
+    pub version: Option<u8>,
+    /// Timestamp, as sent
+    pub timestamp: Option<String>,
+    /// Host name of the sender
+    pub hostname: Option<String>,
+    /// Application name, the tag of an RFC 3164 message
+    pub app_name: Option<String>,
+    /// Process ID, between brackets after the tag of an RFC 3164 message
+    pub proc_id: Option<String>,
+    /// Message type ID
+    pub msg_id: Option<String>,
+    /// Structured data elements, as IDs and parameters
+    pub structured_data: Vec<(String, Vec<(String, String)>)>,
+    /// Free-form message, without its byte order mark
+    pub message: String,
+}
+
+impl SyslogMessage {
+    /// Parse the given raw syslog message, starting with a valid priority
+    fn parse(message: &[u8]) -> Option<Self> {
+        let message = String::from_utf8_lossy(message);
+        let message = message.trim_end_matches(['\r', '\n', '\0']);
+        let (priority, rest) = message.strip_prefix('<')?.split_once('>')?;
+        if priority.is_empty() || priority.len() > 3 {
+            return None;
+        }
+        let priority: u8 = priority.parse().ok().filter(|&priority| priority < 192)?;
+        let mut parsed = Self {
+            facility: priority >> 3,
+            severity: priority & 0b111,
+            version: None,
+            timestamp: None,
+            hostname: None,
+            app_name: None,
+            proc_id: None,
+            msg_id: None,
+            structured_data: Vec::new(),
+            message: String::new(),
+        };
+        let version = rest
+            .split_once(' ')
+            .and_then(|(version, rest)| Some((version.parse().ok()?, rest)));
+        match version {
+            Some((version, rest)) if version > 0 => {
+                parsed.version = Some(version);
+                parsed.parse_rfc5424(rest)?;
+            }
+            _ => parsed.parse_rfc3164(rest),
+        }
+        Some(parsed)
+    }
+
+    /// Value of the given parameter of the given structured data element
+    pub fn param(&self, id: &str, name: &str) -> Option<&str> {
+        self.structured_data
+            .iter()
+            .filter(|(element_id, _)| element_id == id)
+            .flat_map(|(_, params)| params)
+            .find(|(param_name, _)| param_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parse the header fields, the structured data and the message following the version
+    fn parse_rfc5424(&mut self, rest: &str) -> Option<()> {
+        let mut fields = rest.splitn(6, ' ');
+        let mut next_field = || {
+            let field = fields.next()?;
+            Some((field != "-").then(|| field.to_string()))
+        };
+        self.timestamp = next_field()?;
+        self.hostname = next_field()?;
+        self.app_name = next_field()?;
+        self.proc_id = next_field()?;
+        self.msg_id = next_field()?;
+        let mut rest = fields.next()?;
+
+        if let Some(after_nil) = rest.strip_prefix('-') {
+            rest = after_nil;
+        } else {
+            while let Some(element) = rest.strip_prefix('[') {
+                let (id_and_params, after_element) = structured_element(element)?;
+                self.structured_data.push(id_and_params);
+                rest = after_element;
+            }
+        }
+        if !rest.is_empty() {
+            rest = rest.strip_prefix(' ')?;
+        }
+        self.message = rest.trim_start_matches('\u{feff}').to_string();
+        Some(())
+    }
+
+    /// Parse the timestamp, the hostname and the tag preceding the message, if any
+    fn parse_rfc3164(&mut self, rest: &str) {
+        const MONTHS: [&str; 12] = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        // e.g. "Oct 11 22:14:15 ", the day being padded with a space
+        let timestamp = rest.get(..15).filter(|timestamp| {
+            timestamp
+                .get(..3)
+                .is_some_and(|month| MONTHS.contains(&month))
+                && rest.as_bytes().get(15) == Some(&b' ')
+        });
+        let Some(timestamp) = timestamp else {
+            self.message = rest.to_string();
+            return;
+        };
+        self.timestamp = Some(timestamp.to_string());
+        let (hostname, rest) = rest[16..].split_once(' ').unwrap_or((&rest[16..], ""));
+        self.hostname = Some(hostname.to_string());
+
+        // The tag ends at the first character that isn't alphanumeric, usually "[" or ":"
+        let tag_end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && !"-_./".contains(c))
+            .unwrap_or(rest.len());
+        let (tag, mut rest) = rest.split_at(tag_end);
+        if !tag.is_empty() {
+            self.app_name = Some(tag.to_string());
+        }
+        if let Some((proc_id, after_proc_id)) = rest
+            .strip_prefix('[')
+            .and_then(|after_tag| after_tag.split_once(']'))
+        {
+            self.proc_id = Some(proc_id.to_string());
+            rest = after_proc_id;
+        }
+        rest = rest.strip_prefix(':').unwrap_or(rest);
+        self.message = rest.strip_prefix(' ').unwrap_or(rest).to_string();
+    }
+}
+
+/// Parse a message received from the client, e.g. popped with
+/// [`ServerMocker::pop_received_message`](crate::ServerMocker::pop_received_message).
+///
+/// Over TCP, set [`Framing::Syslog`](crate::Framing::Syslog) so that each received message is a single syslog message.
+/// A trailing line ending is ignored.
+///
+/// # Errors
+/// [`ServerMockerError::InvalidSyslogMessage`] if the message doesn't start with a valid priority.
+///
+/// # Example
+/// ```
+/// use std::net::UdpSocket;
+/// use socket_server_mocker::protocols::syslog;
+/// use socket_server_mocker::Instruction::ReceiveMessage;
+/// use socket_server_mocker::ServerMocker;
+///
+/// let server = ServerMocker::udp().unwrap();
+/// server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+/// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+/// client
+///     .send_to(b"<13>1 - - backup - - - Backup completed", server.socket_address())
+///     .unwrap();
+///
+/// let message = syslog::parse(&server.pop_received_message().unwrap()).unwrap();
+/// assert_eq!(Some("backup"), message.app_name.as_deref());
+/// assert_eq!("Backup completed", message.message);
+/// ```
+pub fn parse(message: &[u8]) -> Result<SyslogMessage, ServerMockerError> {
+    SyslogMessage::parse(message).ok_or_else(|| InvalidSyslogMessage(message.to_vec()))
+}
+
+/// ID and parameters of a structured data element
+type StructuredElement = (String, Vec<(String, String)>);
+
+/// Parse a structured data element after its opening bracket, returning it and what follows it
+fn structured_element(element: &str) -> Option<(StructuredElement, &str)> {
+    let id_end = element.find([' ', ']'])?;
+    let (id, mut rest) = element.split_at(id_end);
+    let mut params = Vec::new();
+    while let Some(param) = rest.strip_prefix(' ') {
+        let (name, value) = param.split_once("=\"")?;
+        // The value ends at the first quote not escaped by a backslash
+        let mut unescaped = String::new();
+        let mut chars = value.char_indices();
+        let value_end = loop {
+            match chars.next()? {
+                (_, '\\') => {
+                    let (_, escaped) = chars.next()?;
+                    if !matches!(escaped, '"' | '\\' | ']') {
+                        unescaped.push('\\');
+                    }
+                    unescaped.push(escaped);
+                }
+                (index, '"') => break index,
+                (_, c) => unescaped.push(c),
+            }
+        };
+        params.push((name.to_string(), unescaped));
+        rest = &value[value_end + 1..];
+    }
+    let rest = rest.strip_prefix(']')?;
+    Some(((id.to_string(), params), rest))
+}
@@ -0,0 +1,264 @@
+//! # `websocket`
+//!
+//! WebSocket frames and opening handshake, as described in RFC 6455, to be sent and checked in raw instruction scripts.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+
+// Opcodes
+/// Continuation of a fragmented message
+pub const CONTINUATION: u8 = 0x0;
+/// Text message, UTF-8 encoded
+pub const TEXT: u8 = 0x1;
+/// Binary message
+pub const BINARY: u8 = 0x2;
+/// Close, with an optional status code and reason
+pub const CLOSE: u8 = 0x8;
+/// Ping, to be answered with a pong carrying the same payload
+pub const PING: u8 = 0x9;
+/// Pong
+pub const PONG: u8 = 0xA;
+
+/// GUID appended to the key of the client to compute `Sec-WebSocket-Accept`
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Payload lengths announcing a 16-bit and a 64-bit extended payload length
+const EXTENDED_LEN_16: u8 = 126;
+const EXTENDED_LEN_64: u8 = 127;
+
+/// WebSocket frame, built to be sent to the client or decoded from the data sent by the client.
+///
+/// Frames are built valid, as sent by a server: final and unmasked. The other methods break the rules on purpose,
+/// e.g. to fragment a message, to set reserved bits, to send an invalid close code or a control frame
+/// with a payload longer than 125 bytes, so that the error handling of the client can be tested.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::websocket::WebSocketFrame;
+/// use socket_server_mocker::Instruction::SendMessage;
+///
+/// // "Hello world" fragmented in two frames
+/// let first = WebSocketFrame::text("Hello ").fin(false);
+/// let last = WebSocketFrame::continuation(b"world".to_vec());
+/// let instructions = vec![SendMessage(first.encode()), SendMessage(last.encode())];
+///
+/// // Close code reserved for internal use, never sent on the wire
+/// let invalid_close = WebSocketFrame::close(1005, "");
+/// assert_eq!(b"\x88\x02\x03\xed", invalid_close.encode().as_slice());
+///
+/// // Frames sent by the client are masked
+/// let (frame, size) = WebSocketFrame::decode(b"\x81\x82\x01\x02\x03\x04\x69\x6b").unwrap();
+/// assert_eq!(8, size);
+/// assert_eq!(b"hi", frame.payload.as_slice());
+/// assert_eq!(Some([1, 2, 3, 4]), frame.mask);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebSocketFrame {
+    /// Whether the frame is the last one of its message
+    pub fin: bool,
+    /// Reserved bits RSV1, RSV2 and RSV3, from the most significant one, only allowed by extensions
+    pub rsv: u8,
+    /// Opcode, e.g. [`TEXT`], on 4 bits
+    pub opcode: u8,
+    /// Masking key, mandatory in the frames sent by the client and forbidden in the ones sent by the server
+    pub mask: Option<[u8; 4]>,
+    /// Unmasked payload
+    pub payload: Vec<u8>,
+}
+
+impl WebSocketFrame {
+    /// Final and unmasked frame with the given opcode, which may be a reserved one
+    pub fn new(opcode: u8, payload: Vec<u8>) -> Self {
+        Self {
+            fin: true,
+            rsv: 0,
+            opcode,
+            mask: None,
+            payload,
+        }
+    }
+
+    /// Text frame, holding the given text or a fragment of it
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::new(TEXT, text.into().into_bytes())
+    }
+
+    /// Binary frame
+    pub fn binary(data: Vec<u8>) -> Self {
+        Self::new(BINARY, data)
+    }
+
+    /// Continuation frame of a fragmented message, the first fragment having been sent with `fin(false)`
+    pub fn continuation(data: Vec<u8>) -> Self {
+        Self::new(CONTINUATION, data)
+    }
+
+    /// Ping frame
+    pub fn ping(data: Vec<u8>) -> Self {
+        Self::new(PING, data)
+    }
+
+    /// Pong frame
+    pub fn pong(data: Vec<u8>) -> Self {
+        Self::new(PONG, data)
+    }
+
+    /// Close frame with the given status code, which may be invalid, e.g. 1005 or 999, and the given reason
+    pub fn close(code: u16, reason: &str) -> Self {
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        Self::new(CLOSE, payload)
+    }
+
+    /// Close frame without status code
+    pub fn close_empty() -> Self {
+        Self::new(CLOSE, Vec::new())
+    }
+
+    /// Set whether the frame is the last one of its message, `false` to fragment a message
+    #[must_use]
+    pub fn fin(mut self, fin: bool) -> Self {
+        self.fin = fin;
+        self
+    }
+
+    /// Set the reserved bits, e.g. `0b100` for RSV1, rejected by a client which negotiated no extension
+    #[must_use]
+    pub fn rsv(mut self, rsv: u8) -> Self {
+        self.rsv = rsv;
+        self
+    }
+
+    /// Mask the payload with the given key, as a client does, which a client must reject from a server
+    #[must_use]
+    pub fn masked(mut self, key: [u8; 4]) -> Self {
+        self.mask = Some(key);
+        self
+    }
+
+    /// Encode the frame, with the shortest payload length encoding
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame =
+            vec![(u8::from(self.fin) << 7) | ((self.rsv & 0b111) << 4) | (self.opcode & 0xF)];
+        let mask_bit = if self.mask.is_some() { 0x80 } else { 0 };
+        let payload_len = self.payload.len();
+        if let Ok(len @ 0..=125) = u8::try_from(payload_len) {
+            frame.push(mask_bit | len);
+        } else if let Ok(len) = u16::try_from(payload_len) {
+            frame.push(mask_bit | EXTENDED_LEN_16);
+            frame.extend_from_slice(&len.to_be_bytes());
+        } else {
+            frame.push(mask_bit | EXTENDED_LEN_64);
+            frame.extend_from_slice(&u64::try_from(payload_len).unwrap_or(u64::MAX).to_be_bytes());
+        }
+        match self.mask {
+            Some(key) => {
+                frame.extend_from_slice(&key);
+                frame.extend(apply_mask(&self.payload, key));
+            }
+            None => frame.extend_from_slice(&self.payload),
+        }
+        frame
+    }
+
+    /// Decode the frame at the beginning of the given data, returning it with its size,
+    /// `None` if the data doesn't hold a whole frame.
+    ///
+    /// The payload is unmasked, the masking key being kept in [`WebSocketFrame::mask`].
+    pub fn decode(data: &[u8]) -> Option<(Self, usize)> {
+        let (&first, rest) = data.split_first()?;
+        let (&second, mut rest) = rest.split_first()?;
+        let payload_len = match second & 0x7F {
+            EXTENDED_LEN_16 => {
+                let (len, after_len) = split_at_checked(rest, 2)?;
+                rest = after_len;
+                u64::from(u16::from_be_bytes([len[0], len[1]]))
+            }
+            EXTENDED_LEN_64 => {
+                let (len, after_len) = split_at_checked(rest, 8)?;
+                rest = after_len;
+                u64::from_be_bytes(len.try_into().ok()?)
+            }
+            len => u64::from(len),
+        };
+        let mask = if second & 0x80 == 0 {
+            None
+        } else {
+            let (key, after_key) = split_at_checked(rest, 4)?;
+            rest = after_key;
+            Some([key[0], key[1], key[2], key[3]])
+        };
+        let (payload, _) = split_at_checked(rest, usize::try_from(payload_len).ok()?)?;
+        let frame = Self {
+            fin: first & 0x80 != 0,
+            rsv: (first >> 4) & 0b111,
+            opcode: first & 0xF,
+            mask,
+            payload: match mask {
+                Some(key) => apply_mask(payload, key).collect(),
+                None => payload.to_vec(),
+            },
+        };
+        Some((frame, data.len() - rest.len() + payload.len()))
+    }
+
+    /// Status code of a close frame, `None` if it has none
+    pub fn close_code(&self) -> Option<u16> {
+        match self.payload.as_slice() {
+            [high, low, ..] if self.opcode == CLOSE => Some(u16::from_be_bytes([*high, *low])),
+            _ => None,
+        }
+    }
+}
+
+/// Mask or unmask the given payload with the given key
+fn apply_mask(payload: &[u8], key: [u8; 4]) -> impl Iterator<Item = u8> + '_ {
+    payload
+        .iter()
+        .enumerate()
+        .map(move |(index, byte)| byte ^ key[index % 4])
+}
+
+/// Split the given data at the given index, `None` if it's too short
+fn split_at_checked(data: &[u8], mid: usize) -> Option<(&[u8], &[u8])> {
+    (mid <= data.len()).then(|| data.split_at(mid))
+}
+
+/// `Sec-WebSocket-Accept` value answering the given `Sec-WebSocket-Key` of the client
+pub fn accept_key(key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    STANDARD.encode(hasher.finalize())
+}
+
+/// `101 Switching Protocols` response accepting the opening handshake request of the client,
+/// `None` if the request has no `Sec-WebSocket-Key` header.
+///
+/// Its signature allows it to be used in [`Instruction::SendMessageDependingOnLastReceivedMessage`](crate::Instruction::SendMessageDependingOnLastReceivedMessage).
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::websocket::{handshake_response, WebSocketFrame};
+/// use socket_server_mocker::Instruction::{ReceiveUntilDelimiter, SendMessage, SendMessageDependingOnLastReceivedMessage};
+///
+/// let instructions = vec![
+///     ReceiveUntilDelimiter(b"\r\n\r\n".to_vec()),
+///     SendMessageDependingOnLastReceivedMessage(handshake_response),
+///     SendMessage(WebSocketFrame::text("Welcome").encode()),
+/// ];
+/// ```
+pub fn handshake_response(request: Option<Vec<u8>>) -> Option<Vec<u8>> {
+    let request = String::from_utf8(request?).ok()?;
+    let key = request.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("Sec-WebSocket-Key")
+            .then_some(value)
+    })?;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(key)
+    );
+    Some(response.into_bytes())
+}
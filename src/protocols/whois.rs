@@ -0,0 +1,99 @@
+//! # `whois`
+//!
+//! WHOIS server answering a single query with a canned record, as described in RFC 3912.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::Instruction::{self, ReceiveUntilDelimiter, StopExchange};
+
+/// Build the instructions of a WHOIS server: receive the query line, send the record of the query, then close the connection.
+///
+/// Queries are matched case-insensitively, without their line ending, queries without record being answered
+/// with a `No match` record. Since a WHOIS client opens a connection per query, batched lookups are served by
+/// several server mockers, e.g. each one given the instructions of a clone of the same builder, whose clones
+/// share the [`WhoisQueries`] handle.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::protocols::whois::WhoisMockBuilder;
+///
+/// let whois_mock = WhoisMockBuilder::new()
+///     .record("example.com", "Domain Name: EXAMPLE.COM\r\nRegistrar: RESERVED-Internet Assigned Numbers Authority\r\n")
+///     .record("example.net", "Domain Name: EXAMPLE.NET\r\n")
+///     .not_found("% No entries found for the selected source(s).\r\n");
+/// let queries = whois_mock.queries();
+/// // Given to ServerMocker::add_mock_instructions, one server mocker per lookup
+/// let instructions = whois_mock.clone().build();
+/// let instructions = whois_mock.build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WhoisMockBuilder {
+    records: Vec<(String, String)>,
+    not_found: Option<String>,
+    queries: WhoisQueries,
+}
+
+impl WhoisMockBuilder {
+    /// Server without record, answering `No match` to every query
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the given query with the given record, sent as is
+    #[must_use]
+    pub fn record(mut self, query: impl Into<String>, record: impl Into<String>) -> Self {
+        self.records.push((query.into(), record.into()));
+        self
+    }
+
+    /// Answer the queries without record with the given one, instead of `No match for "<query>".`
+    #[must_use]
+    pub fn not_found(mut self, record: impl Into<String>) -> Self {
+        self.not_found = Some(record.into());
+        self
+    }
+
+    /// Handle on the queries received by the server, to be retrieved before calling [`WhoisMockBuilder::build`]
+    pub fn queries(&self) -> WhoisQueries {
+        self.queries.clone()
+    }
+
+    /// Instructions answering a single query, then closing the connection
+    pub fn build(self) -> Vec<Instruction> {
+        let responder = move |message: Option<Vec<u8>>| {
+            let message = message?;
+            let query = String::from_utf8_lossy(&message)
+                .trim_end_matches(['\r', '\n'])
+                .to_string();
+            let record = self
+                .records
+                .iter()
+                .find(|(record_query, _)| record_query.eq_ignore_ascii_case(&query))
+                .map(|(_, record)| record.clone())
+                .or_else(|| self.not_found.clone())
+                .unwrap_or_else(|| format!("No match for \"{query}\".\r\n"));
+            self.queries.log().push(query);
+            Some(record.into_bytes())
+        };
+        vec![
+            ReceiveUntilDelimiter(b"\n".to_vec()),
+            Instruction::send_from_closure(responder),
+            StopExchange,
+        ]
+    }
+}
+
+/// Queries received by the WHOIS server mockers, shared with the test
+#[derive(Debug, Clone, Default)]
+pub struct WhoisQueries(Arc<Mutex<Vec<String>>>);
+
+impl WhoisQueries {
+    /// Queries received so far, oldest first, without their line ending
+    pub fn queries(&self) -> Vec<String> {
+        self.log().clone()
+    }
+
+    fn log(&self) -> MutexGuard<'_, Vec<String>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
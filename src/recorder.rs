@@ -0,0 +1,209 @@
+//! # `recorder`
+//!
+//! Transparent TCP proxy to a real server, recording the exchange as server mocker instructions.
+
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::Instruction::{self, ReceiveExactBytes, SendMessage, StopExchange};
+use crate::ServerMockerError::{
+    self, UnableToAcceptConnection, UnableToBindListener, UnableToConnectToUpstream,
+    UnableToGetLocalAddress, UnableToReadTcpStream, UnableToWriteTcpStream,
+};
+
+/// Timeout to retrieve the errors raised by the proxy thread
+const ERROR_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Data exchanged through the proxy, in order, consecutive data in the same direction being merged
+type Exchange = Arc<Mutex<Vec<(Direction, Vec<u8>)>>>;
+
+/// Direction of the data going through the proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    FromClient,
+    FromServer,
+}
+
+/// Transparent TCP proxy forwarding the traffic of a client to a real server, while recording both directions.
+///
+/// Created with [`ServerMocker::record`](crate::ServerMocker::record). Once the exchange is over,
+/// the recorded instructions can be given to a [`ServerMocker`](crate::ServerMocker) to replay the real server,
+/// or printed with [`Recorder::rust_code`] to be pasted in a test.
+///
+/// # Example
+/// ```no_run
+/// use std::net::{SocketAddr, TcpStream};
+/// use socket_server_mocker::ServerMocker;
+///
+/// let recorder = ServerMocker::record(SocketAddr::from(([127, 0, 0, 1], 5432))).unwrap();
+/// let client = TcpStream::connect(recorder.socket_address()).unwrap();
+/// // ... exchange with the real server through the recorder, then close the connection
+/// println!("{}", recorder.rust_code());
+/// ```
+pub struct Recorder {
+    socket_addr: SocketAddr,
+    exchange: Exchange,
+    error_rx: Receiver<ServerMockerError>,
+}
+
+impl Recorder {
+    /// Start a proxy listening on a random free port, forwarding the first client connection to `upstream_addr`
+    pub(crate) fn start(upstream_addr: SocketAddr) -> Result<Self, ServerMockerError> {
+        let listen_addr = SocketAddr::from(([127, 0, 0, 1], 0));
+        let listener =
+            TcpListener::bind(listen_addr).map_err(|e| UnableToBindListener(listen_addr, e))?;
+        let socket_addr = listener.local_addr().map_err(UnableToGetLocalAddress)?;
+        let exchange = Exchange::default();
+        let (error_tx, error_rx) = mpsc::channel();
+
+        let proxy_exchange = Arc::clone(&exchange);
+        thread::spawn(move || {
+            let result = listener
+                .accept()
+                .map_err(|e| UnableToAcceptConnection(socket_addr, e))
+                .and_then(|(client, _)| {
+                    let upstream = TcpStream::connect(upstream_addr)
+                        .map_err(|e| UnableToConnectToUpstream(upstream_addr, e))?;
+                    proxy(client, upstream, &proxy_exchange, &error_tx)
+                });
+            if let Err(e) = result {
+                // The recorder may have been dropped meanwhile
+                let _ = error_tx.send(e);
+            }
+        });
+
+        Ok(Self {
+            socket_addr,
+            exchange,
+            error_rx,
+        })
+    }
+
+    /// Get the socket address on which the proxy is listening
+    pub fn socket_address(&self) -> SocketAddr {
+        self.socket_addr
+    }
+
+    /// Get the port on which the proxy is listening
+    pub fn port(&self) -> u16 {
+        self.socket_addr.port()
+    }
+
+    /// Instructions replaying the real server for the exchange recorded so far
+    ///
+    /// # Panics
+    /// It is assumed that the proxy threads don't panic while recording.
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions: Vec<Instruction> = self
+            .exchange
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(direction, data)| match direction {
+                Direction::FromClient => ReceiveExactBytes(data.len()),
+                Direction::FromServer => SendMessage(data.clone()),
+            })
+            .collect();
+        instructions.push(StopExchange);
+        instructions
+    }
+
+    /// Rust source code of [`Recorder::instructions`], ready to be pasted in a test
+    ///
+    /// # Panics
+    /// It is assumed that the proxy threads don't panic while recording.
+    pub fn rust_code(&self) -> String {
+        let mut code = String::from("vec![\n");
+        for (direction, data) in self.exchange.lock().unwrap().iter() {
+            let _ = match direction {
+                Direction::FromClient => {
+                    writeln!(
+                        code,
+                        "    // b\"{}\"\n    ReceiveExactBytes({}),",
+                        data.escape_ascii(),
+                        data.len()
+                    )
+                }
+                Direction::FromServer => {
+                    writeln!(
+                        code,
+                        "    SendMessage(b\"{}\".to_vec()),",
+                        data.escape_ascii()
+                    )
+                }
+            };
+        }
+        code.push_str("    StopExchange,\n]");
+        code
+    }
+
+    /// Pop the last error raised while forwarding the traffic
+    pub fn pop_server_error(&self) -> Option<ServerMockerError> {
+        self.error_rx.recv_timeout(ERROR_TIMEOUT).ok()
+    }
+}
+
+/// Forward the traffic in both directions until both the client and the server closed their side
+fn proxy(
+    client: TcpStream,
+    upstream: TcpStream,
+    exchange: &Exchange,
+    error_tx: &Sender<ServerMockerError>,
+) -> Result<(), ServerMockerError> {
+    let client_reader = client.try_clone().map_err(UnableToReadTcpStream)?;
+    let upstream_reader = upstream.try_clone().map_err(UnableToReadTcpStream)?;
+
+    let server_exchange = Arc::clone(exchange);
+    let server_error_tx = error_tx.clone();
+    let server_to_client = thread::spawn(move || {
+        let result = forward(
+            upstream_reader,
+            client,
+            Direction::FromServer,
+            &server_exchange,
+        );
+        if let Err(e) = result {
+            let _ = server_error_tx.send(e);
+        }
+    });
+    let result = forward(client_reader, upstream, Direction::FromClient, exchange);
+    let _ = server_to_client.join();
+    result
+}
+
+/// Copy the data from `source` to `destination` until `source` is closed, recording it on the way
+fn forward(
+    mut source: TcpStream,
+    mut destination: TcpStream,
+    direction: Direction,
+    exchange: &Exchange,
+) -> Result<(), ServerMockerError> {
+    let mut buffer = [0; 4096];
+    loop {
+        let bytes_read = source.read(&mut buffer).map_err(UnableToReadTcpStream)?;
+        if bytes_read == 0 {
+            // Propagate the end of stream, the other side may already be closed
+            let _ = destination.shutdown(Shutdown::Write);
+            return Ok(());
+        }
+        // Recorded before being forwarded, so that the answer can't be recorded first
+        let data = &buffer[..bytes_read];
+        {
+            let mut exchange = exchange.lock().unwrap();
+            match exchange.last_mut() {
+                Some((last_direction, last_data)) if *last_direction == direction => {
+                    last_data.extend_from_slice(data);
+                }
+                _ => exchange.push((direction, data.to_vec())),
+            }
+        }
+        destination
+            .write_all(data)
+            .map_err(UnableToWriteTcpStream)?;
+    }
+}
@@ -0,0 +1,46 @@
+//! # `rng`
+//!
+//! Small pseudo-random number generator used for fault injection, reproducible from its seed.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// `SplitMix64` pseudo-random number generator
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(Cell<u64>);
+
+impl Rng {
+    /// Create a generator from the given seed
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(Cell::new(seed))
+    }
+
+    /// Create a generator from a random seed
+    pub(crate) fn from_random_seed() -> Self {
+        Self::new(RandomState::new().build_hasher().finish())
+    }
+
+    /// Next pseudo-random 64 bits
+    pub(crate) fn next_u64(&self) -> u64 {
+        let state = self.0.get().wrapping_add(0x9E37_79B9_7F4A_7C15);
+        self.0.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Random number between 0 and `bound` excluded, `bound` being greater than 0
+    pub(crate) fn below(&self, bound: usize) -> usize {
+        let bound = u64::try_from(bound).unwrap_or(u64::MAX);
+        usize::try_from(self.next_u64() % bound).unwrap_or(0)
+    }
+
+    /// Return true with the given probability, between 0 and 1
+    pub(crate) fn chance(&self, probability: f32) -> bool {
+        let random = u32::try_from(self.next_u64() >> 32).unwrap_or(u32::MAX);
+        // Never true with 0, always true with 1
+        f64::from(random) + 1.0 <= f64::from(probability) * 4_294_967_296.0
+    }
+}
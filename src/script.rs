@@ -0,0 +1,157 @@
+//! # `script`
+//!
+//! Instruction scripts stored in JSON or YAML fixture files, available with the `serde` feature.
+
+use std::fs;
+use std::path::Path;
+
+use crate::Instruction;
+use crate::ServerMockerError::{self, InvalidScript, UnableToReadScript};
+
+/// Read the instructions of the given JSON or YAML script, the format being chosen from the file extension
+pub(crate) fn load_script(path: &Path) -> Result<Vec<Instruction>, ServerMockerError> {
+    let content = fs::read_to_string(path).map_err(|e| UnableToReadScript(path.to_owned(), e))?;
+    let is_yaml = path
+        .extension()
+        .is_some_and(|extension| extension == "yaml" || extension == "yml");
+    let instructions = if is_yaml {
+        // Same layout as JSON, instead of YAML tags
+        serde_yaml::with::singleton_map_recursive::deserialize(serde_yaml::Deserializer::from_str(
+            &content,
+        ))
+        .map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+    };
+    instructions.map_err(|e| InvalidScript(path.to_owned(), e))
+}
+
+/// Binary payloads, written as `{"text": "..."}`, `{"hex": "..."}` or `{"base64": "..."}`.
+///
+/// Payloads are serialized as text if they are printable UTF-8, as hex otherwise.
+pub(crate) mod payload {
+    use std::fmt::Write;
+
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    /// A payload in one of the supported encodings
+    #[derive(Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct EncodedPayload {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hex: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        base64: Option<String>,
+    }
+
+    pub(crate) fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let text = std::str::from_utf8(data)
+            .ok()
+            .filter(|text| !text.chars().any(|c| c.is_control() && !c.is_whitespace()));
+        let encoded = match text {
+            Some(text) => EncodedPayload {
+                text: Some(text.to_owned()),
+                hex: None,
+                base64: None,
+            },
+            None => EncodedPayload {
+                text: None,
+                hex: Some(data.iter().fold(String::new(), |mut hex, byte| {
+                    let _ = write!(hex, "{byte:02x}");
+                    hex
+                })),
+                base64: None,
+            },
+        };
+        encoded.serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        match EncodedPayload::deserialize(deserializer)? {
+            EncodedPayload {
+                text: Some(text),
+                hex: None,
+                base64: None,
+            } => Ok(text.into_bytes()),
+            EncodedPayload {
+                text: None,
+                hex: Some(hex),
+                base64: None,
+            } => decode_hex(&hex).map_err(D::Error::custom),
+            EncodedPayload {
+                text: None,
+                hex: None,
+                base64: Some(base64),
+            } => STANDARD.decode(base64).map_err(D::Error::custom),
+            _ => Err(D::Error::custom(
+                "expected exactly one of `text`, `hex` or `base64`",
+            )),
+        }
+    }
+
+    /// Decode hex digits, ignoring whitespaces so that long payloads can be split over several lines
+    fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+        let digits = hex
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| {
+                c.to_digit(16)
+                    .ok_or_else(|| format!("invalid hex digit {c:?}"))
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+        if digits.len() % 2 != 0 {
+            return Err("odd number of hex digits".to_string());
+        }
+        Ok(digits
+            .chunks(2)
+            .map(|pair| u8::try_from(pair[0] << 4 | pair[1]).unwrap_or_default())
+            .collect())
+    }
+}
+
+/// Regular expressions, written as their pattern
+pub(crate) mod regex {
+    use regex::bytes::Regex;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(
+        regex: &Regex,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Regex, D::Error> {
+        Regex::new(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// Durations, written as a number of milliseconds
+pub(crate) mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
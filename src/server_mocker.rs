@@ -2,15 +2,123 @@
 //!
 //! Mock an IP server for testing application that connect to external server.
 
-use std::net::SocketAddr;
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io;
+use std::io::BufWriter;
+use std::iter;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
 use std::sync::mpsc;
+#[cfg(feature = "futures")]
+use std::sync::mpsc::TryRecvError;
 use std::sync::mpsc::{Receiver, Sender};
-use std::time::Duration;
+use std::sync::{Arc, Mutex, MutexGuard};
+#[cfg(feature = "futures")]
+use std::task::{Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use socket2::Socket;
+
+#[cfg(feature = "prost")]
+use crate::decode_prost;
+#[cfg(feature = "serde")]
+use crate::har;
+use crate::hex::hex_dump;
+use crate::hooks::Hooks;
+use crate::http;
+use crate::instructions::PendingInstructions;
+use crate::pcap;
+#[cfg(feature = "serde")]
+use crate::script;
+#[cfg(feature = "futures")]
+use crate::stream::{ErrorStream, MessageStream};
+use crate::stream::{EventSender, Wakers};
 use crate::tcp_server::TcpMocker;
+use crate::timeline::TimelineEntry;
+#[cfg(feature = "tls")]
+use crate::tls_server::TlsMocker;
+use crate::traffic::{ReceivedMessage, ServerStats, Traffic, Transport, WireEvent};
+use crate::transcript::Transcript;
 use crate::udp_server::UdpMocker;
-use crate::ServerMockerError::UnableToSendInstructions;
-use crate::{Instruction, ServerMockerError};
+#[cfg(feature = "serde")]
+use crate::wiremock;
+#[cfg(feature = "serde")]
+use crate::ServerMockerError::InvalidJsonMessage;
+use crate::ServerMockerError::{
+    InvalidPcap, MessageSequenceMismatch, NoMessageReceived, ServerStopped, ServerThreadPanicked,
+    UnableToDecodeMessage, UnableToReadFile, UnableToResolveAddress, UnableToSendInstructions,
+    UnableToSpawnThread, UnableToWriteFile, UnexpectedRepeatCount,
+};
+use crate::{
+    matcher, Codec, ErrorReport, HttpMock, Instruction, Matcher, RawCodec, Recorder,
+    ServerMockerError,
+};
+
+/// Interval at which client data is polled while the server is waiting for new instructions
+pub(crate) const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Pause between the writes of a fragmented message
+pub(crate) const FRAGMENT_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Time needed to send `bytes` bytes at `bytes_per_sec` bytes per second
+pub(crate) fn transmission_time(bytes: usize, bytes_per_sec: u64) -> Duration {
+    let nanos = bytes as u128 * 1_000_000_000 / u128::from(bytes_per_sec.max(1));
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+/// Loopback address of the other IP family than `socket_addr`, on the same port, to listen on both loopbacks
+pub(crate) fn other_loopback(socket_addr: SocketAddr) -> SocketAddr {
+    let ip: IpAddr = match socket_addr {
+        SocketAddr::V4(_) => Ipv6Addr::LOCALHOST.into(),
+        SocketAddr::V6(_) => Ipv4Addr::LOCALHOST.into(),
+    };
+    SocketAddr::new(ip, socket_addr.port())
+}
+
+/// First socket address the given address resolves to
+fn resolve_socket_address(addr: impl ToSocketAddrs) -> Result<SocketAddr, ServerMockerError> {
+    addr.to_socket_addrs()
+        .map_err(UnableToResolveAddress)?
+        .next()
+        .ok_or_else(|| {
+            UnableToResolveAddress(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no address to listen on",
+            ))
+        })
+}
+
+/// Spawn a server thread named after its socket address, reporting a panic (e.g. in an instruction closure or a hook) as a fatal error
+/// instead of silently losing the thread
+pub(crate) fn spawn_server_thread(
+    transport: Transport,
+    socket_addr: SocketAddr,
+    traffic: Traffic,
+    error_tx: EventSender<ErrorReport>,
+    server: impl FnOnce() + Send + 'static,
+) -> Result<JoinHandle<()>, ServerMockerError> {
+    let name = format!("ssm-{}-{socket_addr}", transport.name().to_lowercase());
+    thread::Builder::new()
+        .name(name)
+        .spawn(move || {
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(server)) {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|message| (*message).to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                let report = traffic.error_raised(ServerThreadPanicked(message), None, None);
+                let _ = error_tx.send(report);
+            }
+        })
+        .map_err(UnableToSpawnThread)
+}
 
 /// Options for the mocker, implemented by the specific TCP/UDP backends
 pub trait MockerOptions: Clone {
@@ -20,13 +128,54 @@ pub trait MockerOptions: Clone {
     /// Timeout for the server to wait for a message from the client.
     fn net_timeout(&self) -> Duration;
 
+    /// Seed of the random faults injected by the server, if any, to replay a failing exchange
+    fn chaos_seed(&self) -> Option<u64> {
+        None
+    }
+
+    /// Transcript files on which every byte exchanged with the clients is teed, if any
+    fn transcript(&self) -> Option<&Transcript> {
+        None
+    }
+
+    /// Whether every message exchanged with the clients is logged as a hex dump
+    fn wire_dump(&self) -> bool {
+        false
+    }
+
+    /// Whether every packet exchanged with the clients is kept to be exported as a pcap capture
+    fn capture(&self) -> bool {
+        false
+    }
+
+    /// Callbacks invoked by the server thread on the events of the exchange
+    fn hooks(&self) -> Hooks {
+        Hooks::default()
+    }
+
+    /// Set the options of the listening socket before it's bound, see [`Hooks::on_socket`]
+    fn configure_socket(&self, socket: &Socket) -> io::Result<()> {
+        self.hooks().socket_created(socket)
+    }
+
+    /// Listen on the given socket address instead, to restart the server mocker on the same port.
+    ///
+    /// Does nothing by default: the server mocker then restarts on [`MockerOptions::socket_address`].
+    fn set_socket_address(&mut self, _socket_addr: SocketAddr) {}
+
+    /// Wake up the server thread listening on `socket_addr` if it is blocked waiting for a client
+    fn unblock(&self, _socket_addr: SocketAddr) {}
+
     /// Run the server mocker with the given instructions
     fn run(
         self,
         instruction_rx: Receiver<Vec<Instruction>>,
-        message_tx: Sender<Vec<u8>>,
-        error_tx: Sender<ServerMockerError>,
-    ) -> Result<SocketAddr, ServerMockerError>;
+        pending_instructions: PendingInstructions,
+        message_tx: EventSender<ReceivedMessage>,
+        chunk_tx: Sender<Vec<u8>>,
+        traffic: Traffic,
+        error_tx: EventSender<ErrorReport>,
+    ) -> Result<(SocketAddr, JoinHandle<()>), ServerMockerError>;
 }
 
 /// A socket server mocker, able to mock a TCP or UDP server to help test socket connections in a user app.
@@ -77,12 +226,27 @@ pub trait MockerOptions: Clone {
 /// assert_eq!(Some(vec![1, 2, 3]), server.pop_received_message());
 /// assert!(server.pop_server_error().is_none());
 /// ```
-pub struct ServerMocker<T> {
+///
+/// # Panics
+///
+/// When dropped, the server mocker waits for the server thread to execute the pending instructions,
+/// and panics with a summary of the instructions that never ran (e.g. because the client never connected).
+/// The server thread is then stopped, and joined if it stops within twice [`MockerOptions::net_timeout`].
+pub struct ServerMocker<T: MockerOptions, C: Codec = RawCodec> {
     options: T,
+    codec: C,
     socket_addr: SocketAddr,
     instruction_tx: Sender<Vec<Instruction>>,
-    message_rx: Receiver<Vec<u8>>,
-    error_rx: Receiver<ServerMockerError>,
+    pending_instructions: PendingInstructions,
+    message_rx: Receiver<ReceivedMessage>,
+    /// Message received by [`ServerMocker::peek_received_message`], returned by the next pop
+    peeked_message: Mutex<Option<ReceivedMessage>>,
+    chunk_rx: Receiver<Vec<u8>>,
+    traffic: Traffic,
+    error_rx: Receiver<ErrorReport>,
+    /// Async tasks waiting for a message or an error
+    wakers: Wakers,
+    server_thread: Option<JoinHandle<()>>,
 }
 
 impl ServerMocker<TcpMocker> {
@@ -99,6 +263,112 @@ impl ServerMocker<TcpMocker> {
         opts.socket_addr.set_port(port);
         Self::new_with_opts(opts)
     }
+
+    /// Create a new instance of the TCP server mocker on a random free port of the IPv6 loopback `[::1]`,
+    /// for clients configured with IPv6 literals or resolving `localhost` to `::1`
+    pub fn tcp_v6() -> Result<Self, ServerMockerError> {
+        Self::tcp_at((Ipv6Addr::LOCALHOST, 0))
+    }
+
+    /// Create a new instance of the TCP server mocker listening on the given address, e.g. `"0.0.0.0:0"`
+    /// to be reachable from another container or network namespace.
+    /// The first address it resolves to is used.
+    pub fn tcp_at(addr: impl ToSocketAddrs) -> Result<Self, ServerMockerError> {
+        Self::new_with_opts(TcpMocker {
+            socket_addr: resolve_socket_address(addr)?,
+            ..TcpMocker::default()
+        })
+    }
+
+    /// Start a [`Recorder`] proxy forwarding the traffic of a client to the real server at `upstream_addr`,
+    /// to capture the exchange as instructions for a TCP server mocker.
+    pub fn record(upstream_addr: SocketAddr) -> Result<Recorder, ServerMockerError> {
+        Recorder::start(upstream_addr)
+    }
+
+    /// Serve the given canned HTTP responses, depending on the method, the path, the headers and the body
+    /// of the requests, until the client stops sending requests; then close the connection.
+    ///
+    /// Each request is answered by the first matching mock which hasn't reached its [`HttpMock::max_uses`],
+    /// or with `404 Not Found` if there is none.
+    /// The received requests can still be retrieved with [`ServerMocker::pop_received_message`].
+    ///
+    /// # Example
+    /// ```
+    /// use socket_server_mocker::{HttpMock, ServerMocker};
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// server.add_http_mocks(vec![
+    ///     HttpMock::new("GET", "/health").body("OK"),
+    ///     HttpMock::new("POST", "/users").status(201),
+    /// ]).unwrap();
+    ///
+    /// let response = reqwest::blocking::get(format!("http://localhost:{}/health", server.port())).unwrap();
+    /// assert_eq!("OK", response.text().unwrap());
+    /// ```
+    pub fn add_http_mocks(&self, mocks: Vec<HttpMock>) -> Result<(), ServerMockerError> {
+        self.add_mock_instructions(http::http_instructions(mocks))
+    }
+
+    /// Serve one HTTP mock per entry of a HAR (HTTP Archive) file exported by a browser, see [`ServerMocker::add_http_mocks`].
+    ///
+    /// Each entry is matched on its method and its path, and replays its response status, headers and body.
+    /// Successive entries with the same method and path are served in turn.
+    #[cfg(feature = "serde")]
+    pub fn load_har(&self, path: impl AsRef<Path>) -> Result<(), ServerMockerError> {
+        self.add_http_mocks(har::load_har(path.as_ref())?)
+    }
+
+    /// Serve the HTTP mocks of wiremock JSON stub mappings, see [`ServerMocker::add_http_mocks`].
+    ///
+    /// `path` is either a single mapping file, holding one stub or a `{"mappings": [...]}` array of stubs,
+    /// or a wiremock `mappings` directory whose JSON files are all loaded. Stubs are tried by `priority`,
+    /// and `bodyFileName` is read from the `__files` directory next to the mappings directory.
+    ///
+    /// Supported request matchers are `method`, `url`, `urlPath`, `equalTo` headers,
+    /// and `equalTo` or `contains` body patterns. Supported response fields are `status`, `headers`,
+    /// `body`, `jsonBody`, `base64Body`, `bodyFileName` and `fixedDelayMilliseconds`.
+    /// Other fields are reported as [`ServerMockerError::InvalidStubMapping`], rather than silently ignored.
+    #[cfg(feature = "serde")]
+    pub fn load_stub_mappings(&self, path: impl AsRef<Path>) -> Result<(), ServerMockerError> {
+        self.add_http_mocks(wiremock::load_stub_mappings(path.as_ref())?)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl ServerMocker<TlsMocker> {
+    /// Create a new instance of the TLS server mocker on a random free port, presenting newly generated certificates.
+    /// The client must trust [`ServerMocker::ca_cert_pem`].
+    ///
+    /// # Example
+    /// ```
+    /// use socket_server_mocker::Instruction::{ReceiveUntilDelimiter, SendMessage, StopExchange};
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::tls().unwrap();
+    /// server.add_mock_instructions(vec![
+    ///     ReceiveUntilDelimiter(b"\r\n\r\n".to_vec()),
+    ///     SendMessage(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nHello".to_vec()),
+    ///     StopExchange,
+    /// ]).unwrap();
+    ///
+    /// let ca_cert = reqwest::Certificate::from_pem(server.ca_cert_pem().as_bytes()).unwrap();
+    /// let client = reqwest::blocking::Client::builder()
+    ///     .add_root_certificate(ca_cert)
+    ///     .build()
+    ///     .unwrap();
+    /// let response = client.get(format!("https://127.0.0.1:{}/", server.port())).send().unwrap();
+    /// assert_eq!("Hello", response.text().unwrap());
+    /// ```
+    pub fn tls() -> Result<Self, ServerMockerError> {
+        Self::new_with_opts(TlsMocker::new(TcpMocker::default())?)
+    }
+
+    /// Certificate of the authority which signed the certificate of the server, in PEM,
+    /// to be added to the trust store of the client
+    pub fn ca_cert_pem(&self) -> &str {
+        self.options.certificates.ca_cert_pem()
+    }
 }
 
 impl ServerMocker<UdpMocker> {
@@ -115,9 +385,35 @@ impl ServerMocker<UdpMocker> {
         opts.socket_addr.set_port(port);
         Self::new_with_opts(opts)
     }
+
+    /// Create a new instance of the UDP server mocker on a random free port of the IPv6 loopback `[::1]`,
+    /// for clients configured with IPv6 literals or resolving `localhost` to `::1`
+    pub fn udp_v6() -> Result<Self, ServerMockerError> {
+        Self::udp_at((Ipv6Addr::LOCALHOST, 0))
+    }
+
+    /// Create a new instance of the UDP server mocker listening on the given address, e.g. `"0.0.0.0:0"`
+    /// to be reachable from another container or network namespace.
+    /// The first address it resolves to is used.
+    pub fn udp_at(addr: impl ToSocketAddrs) -> Result<Self, ServerMockerError> {
+        Self::new_with_opts(UdpMocker {
+            socket_addr: resolve_socket_address(addr)?,
+            ..UdpMocker::default()
+        })
+    }
 }
 
 impl<T: MockerOptions> ServerMocker<T> {
+    /// Create a new instance of the TCP server mocker with the given options.
+    ///
+    /// # Panics
+    /// It is assumed that threads can use messages channels without panicking.
+    pub fn new_with_opts(options: T) -> Result<Self, ServerMockerError> {
+        Self::new_with_codec(options, RawCodec)
+    }
+}
+
+impl<T: MockerOptions, C: Codec> ServerMocker<T, C> {
     /// Get the options used to create the server mocker
     pub fn options(&self) -> &T {
         &self.options
@@ -133,44 +429,908 @@ impl<T: MockerOptions> ServerMocker<T> {
         self.socket_addr.port()
     }
 
+    /// Whether the server thread is still running, i.e. waiting for a client, for instructions or executing them.
+    ///
+    /// The thread stops once the exchange is over, or after a fatal error retrieved with
+    /// [`ServerMocker::pop_server_error`].
+    pub fn is_running(&self) -> bool {
+        self.server_thread
+            .as_ref()
+            .is_some_and(|server_thread| !server_thread.is_finished())
+    }
+
     /// Add instructions to the server mocker
+    ///
+    /// Fails with [`ServerMockerError::ServerStopped`] if the server thread stopped because of a fatal error,
+    /// so that a server which never worked can't go unnoticed, or if it finished its script with [`Finish::CloseGracefully`](crate::Finish::CloseGracefully).
     pub fn add_mock_instructions(
         &self,
         instructions: Vec<Instruction>,
     ) -> Result<(), ServerMockerError> {
-        self.instruction_tx
-            .send(instructions)
-            .map_err(UnableToSendInstructions)
+        self.check_running()?;
+        // Register instructions before sending them, as the server thread may execute them right away
+        let count = instructions.len();
+        if !self.pending_instructions.push(&instructions) {
+            return Err(ServerStopped(
+                "all instructions were executed, see Finish::CloseGracefully".to_string(),
+            ));
+        }
+        self.instruction_tx.send(instructions).map_err(|e| {
+            self.pending_instructions.cancel(count);
+            UnableToSendInstructions(e)
+        })
+    }
+
+    /// Answer the data received from the client outside of a receive instruction with the given message,
+    /// once the instructions added before are executed, e.g. to answer keep-alives without scripting them.
+    ///
+    /// Replaces [`TcpMocker::default_response`] or [`UdpMocker::default_response`], see [`Instruction::SetDefaultResponse`].
+    pub fn default_response(&self, message: impl Into<Vec<u8>>) -> Result<(), ServerMockerError> {
+        let message = message.into();
+        self.default_response_from_closure(move |_| Some(message.clone()))
+    }
+
+    /// Same as [`ServerMocker::default_response`], but the message is computed by the closure from the
+    /// unmatched data, nothing being sent if it returns `None`
+    ///
+    /// # Example
+    /// ```
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::udp().unwrap();
+    /// // Answer PING with PONG, and ignore anything else
+    /// server
+    ///     .default_response_from_closure(|data| (data? == b"PING\r\n").then(|| b"PONG\r\n".to_vec()))
+    ///     .unwrap();
+    /// ```
+    pub fn default_response_from_closure(
+        &self,
+        responder: impl FnMut(Option<Vec<u8>>) -> Option<Vec<u8>> + Send + 'static,
+    ) -> Result<(), ServerMockerError> {
+        self.add_mock_instructions(vec![Instruction::SetDefaultResponse(Arc::new(Mutex::new(
+            responder,
+        )))])
+    }
+
+    /// Add the instructions of a JSON or YAML script to the server mocker,
+    /// the format being chosen from the file extension (`.yaml` or `.yml` for YAML, JSON otherwise).
+    ///
+    /// Payloads are written as `{"text": "..."}`, `{"hex": "..."}` or `{"base64": "..."}`, durations in milliseconds.
+    ///
+    /// # Example
+    /// ```yaml
+    /// - ReceiveMessage
+    /// - SendMessage: {text: "+OK POP3 ready\r\n"}
+    /// - Repeat:
+    ///     times: {min: 1, max: null}
+    ///     instructions:
+    ///       - ReceiveMessage
+    ///       - SendMessage: {hex: "2b4f4b0d0a"}
+    /// - Pause: 100
+    /// - StopExchange
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn load_script(&self, path: impl AsRef<Path>) -> Result<(), ServerMockerError> {
+        self.add_mock_instructions(script::load_script(path.as_ref())?)
+    }
+
+    /// Add instructions replaying the server listening on `server_port` in a pcap capture of a real exchange,
+    /// such as one taken with `tcpdump -i lo -w capture.pcap port 5432`.
+    ///
+    /// Only the first TCP connection or UDP client of the server is replayed:
+    /// - the data sent by the client becomes [`Instruction::ReceiveExactBytes`] instructions for TCP,
+    ///   and [`Instruction::ReceiveMessage`] instructions for UDP,
+    /// - the data sent by the server becomes [`Instruction::SendMessage`] instructions,
+    /// - a final [`Instruction::StopExchange`] closes the exchange.
+    ///
+    /// Raw IP, Ethernet, loopback and Linux cooked captures are supported. pcapng captures must first be converted,
+    /// for example with `editcap -F pcap capture.pcapng capture.pcap`.
+    pub fn load_pcap(
+        &self,
+        path: impl AsRef<Path>,
+        server_port: u16,
+    ) -> Result<(), ServerMockerError> {
+        let path = path.as_ref();
+        let capture = fs::read(path).map_err(|e| UnableToReadFile(path.to_owned(), e))?;
+        let instructions = pcap::read_instructions(&capture, server_port)
+            .map_err(|e| InvalidPcap(path.to_owned(), e))?;
+        self.add_mock_instructions(instructions)
     }
 
     /// Pop the last received message from the server mocker
     pub fn pop_received_message(&self) -> Option<Vec<u8>> {
-        self.message_rx
-            .recv_timeout(self.options.net_timeout())
-            .ok()
+        self.pop_received_message_timeout(self.options.net_timeout())
+    }
+
+    /// Pop the last received message from the server mocker, waiting up to `timeout` for it
+    /// instead of [`MockerOptions::net_timeout`], e.g. for a slow client
+    pub fn pop_received_message_timeout(&self, timeout: Duration) -> Option<Vec<u8>> {
+        self.next_received_message(timeout)
+            .map(|message| message.data)
+    }
+
+    /// Pop the last received message from the server mocker, decoded as UTF-8.
+    ///
+    /// Returns `None` if no message was received, or if the message is not valid UTF-8,
+    /// see [`ServerMocker::pop_received_string_lossy`] for binary data.
+    pub fn pop_received_string(&self) -> Option<String> {
+        self.pop_received_message()
+            .and_then(|message| String::from_utf8(message).ok())
+    }
+
+    /// Pop the last received message from the server mocker, decoded as UTF-8,
+    /// invalid sequences being replaced with `U+FFFD REPLACEMENT CHARACTER`
+    pub fn pop_received_string_lossy(&self) -> Option<String> {
+        self.pop_received_message()
+            .map(|message| String::from_utf8_lossy(&message).into_owned())
+    }
+
+    /// Pop the last received message from the server mocker, split into lines without their `\n` or `\r\n` ending
+    ///
+    /// Returns `None` if no message was received, or if the message is not valid UTF-8.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use std::net::TcpStream;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    /// client.write_all(b"Subject: Hello\r\n\r\nHello world!\r\n").unwrap();
+    ///
+    /// assert_eq!(
+    ///     Some(vec!["Subject: Hello".to_string(), String::new(), "Hello world!".to_string()]),
+    ///     server.pop_received_lines()
+    /// );
+    /// ```
+    pub fn pop_received_lines(&self) -> Option<Vec<String>> {
+        self.pop_received_string()
+            .map(|message| message.lines().map(str::to_owned).collect())
+    }
+
+    /// Pop the last received message from the server mocker, deserialized from JSON.
+    ///
+    /// If the message is an HTTP request, its body is deserialized.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use std::net::TcpStream;
+    /// use serde::Deserialize;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Login {
+    ///     user: String,
+    /// }
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    /// client.write_all(br#"{"user": "alice"}"#).unwrap();
+    ///
+    /// let login: Login = server.pop_received_json().unwrap();
+    /// assert_eq!("alice", login.user);
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn pop_received_json<M: DeserializeOwned>(&self) -> Result<M, ServerMockerError> {
+        self.check_running()?;
+        let message = self.pop_received_message().ok_or(NoMessageReceived)?;
+        serde_json::from_slice(http::message_body(&message))
+            .map_err(|e| InvalidJsonMessage(e.to_string(), message))
+    }
+
+    /// Pop the last received message from the server mocker, decoded as a protobuf message,
+    /// possibly preceded by its length as a varint, see [`decode_prost`](crate::decode_prost).
+    ///
+    /// Over TCP, a [`Framer`](crate::Framer) may be needed so that each received message is a single protobuf message.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use std::net::TcpStream;
+    /// use prost::Message;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// #[derive(Clone, PartialEq, Message)]
+    /// struct Login {
+    ///     #[prost(string, tag = "1")]
+    ///     user: String,
+    /// }
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    /// let login = Login { user: "alice".to_string() };
+    /// client.write_all(&login.encode_length_delimited_to_vec()).unwrap();
+    ///
+    /// let login: Login = server.pop_received_proto().unwrap();
+    /// assert_eq!("alice", login.user);
+    /// ```
+    #[cfg(feature = "prost")]
+    pub fn pop_received_proto<M: prost::Message + Default>(&self) -> Result<M, ServerMockerError> {
+        self.check_running()?;
+        let message = self.pop_received_message().ok_or(NoMessageReceived)?;
+        decode_prost(&message).map_err(|e| UnableToDecodeMessage(e, message))
+    }
+
+    /// Pop the last received message from the server mocker, decoded by the codec
+    pub fn pop_received_typed(&self) -> Result<C::Request, ServerMockerError> {
+        self.check_running()?;
+        let message = self.pop_received_message().ok_or(NoMessageReceived)?;
+        self.codec
+            .decode(&message)
+            .map_err(|e| UnableToDecodeMessage(e, message))
+    }
+
+    /// Instruction sending the given response, encoded by the codec
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use std::net::TcpStream;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::{Codec, ServerMocker, TcpMocker};
+    ///
+    /// /// Numbers written as a single byte
+    /// struct ByteCodec;
+    ///
+    /// impl Codec for ByteCodec {
+    ///     type Request = u8;
+    ///     type Response = u8;
+    ///
+    ///     fn encode(&self, response: &u8) -> Vec<u8> {
+    ///         vec![*response]
+    ///     }
+    ///
+    ///     fn decode(&self, data: &[u8]) -> Result<u8, String> {
+    ///         match data {
+    ///             [number] => Ok(*number),
+    ///             _ => Err(format!("expected 1 byte, got {}", data.len())),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let server = ServerMocker::new_with_codec(TcpMocker::default(), ByteCodec).unwrap();
+    /// let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage, server.send_typed(&43)]).unwrap();
+    ///
+    /// client.write_all(&[42]).unwrap();
+    /// let mut response = [0];
+    /// client.read_exact(&mut response).unwrap();
+    /// assert_eq!([43], response);
+    /// assert_eq!(42, server.pop_received_typed().unwrap());
+    /// ```
+    pub fn send_typed(&self, response: &C::Response) -> Instruction {
+        Instruction::SendMessage(self.codec.encode(response))
+    }
+
+    /// Pop the last received message from the server mocker if one has already arrived, without waiting
+    pub fn try_pop_received_message(&self) -> Option<Vec<u8>> {
+        self.peeked_message()
+            .take()
+            .or_else(|| self.message_rx.try_recv().ok())
+            .map(|message| message.data)
+    }
+
+    /// Iterate over the received messages, until none arrives within `timeout_per_item`
+    ///
+    /// # Example
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use std::time::Duration;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::udp().unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage, ReceiveMessage]).unwrap();
+    ///
+    /// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// client.send_to(b"Hello, ", server.socket_address()).unwrap();
+    /// client.send_to(b"world!", server.socket_address()).unwrap();
+    ///
+    /// let payload: Vec<u8> = server.iter_received(Duration::from_millis(100)).flatten().collect();
+    /// assert_eq!(b"Hello, world!", payload.as_slice());
+    /// ```
+    pub fn iter_received(&self, timeout_per_item: Duration) -> impl Iterator<Item = Vec<u8>> + '_ {
+        iter::from_fn(move || self.pop_received_message_timeout(timeout_per_item))
+    }
+
+    /// Pop the last received message from the server mocker, with the address of its sender
+    /// and the time at which it was received, to check timings such as client retries
+    ///
+    /// # Example
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use std::time::Duration;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::udp().unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage, ReceiveMessage]).unwrap();
+    ///
+    /// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// client.send_to(b"request", server.socket_address()).unwrap();
+    /// client.send_to(b"request", server.socket_address()).unwrap();
+    ///
+    /// let first = server.pop_received_message_with_meta().unwrap();
+    /// let retry = server.pop_received_message_with_meta().unwrap();
+    /// assert_eq!(Some(client.local_addr().unwrap()), retry.client_addr);
+    /// assert!(retry.received_at - first.received_at < Duration::from_secs(2));
+    /// ```
+    pub fn pop_received_message_with_meta(&self) -> Option<ReceivedMessage> {
+        self.next_received_message(self.options.net_timeout())
+    }
+
+    /// Pop the peeked message if any, otherwise wait up to `timeout` for the next one
+    fn next_received_message(&self, timeout: Duration) -> Option<ReceivedMessage> {
+        // Nothing more can be received once the server thread stopped
+        let timeout = if self.check_running().is_ok() {
+            timeout
+        } else {
+            Duration::ZERO
+        };
+        self.peeked_message()
+            .take()
+            .or_else(|| self.message_rx.recv_timeout(timeout).ok())
+    }
+
+    /// Stream of the messages received by the server mocker from now on, for async tests to wait for them
+    /// alongside the client futures, e.g. with `tokio::select!`, instead of blocking on
+    /// [`ServerMocker::pop_received_message`].
+    ///
+    /// Messages are consumed by whichever of the stream and the pop methods gets them first.
+    /// The stream ends once the server thread stopped and every message has been received.
+    ///
+    /// # Example
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use futures_lite::{future, StreamExt};
+    /// use socket_server_mocker::Instruction::{ReceiveMessage, StopExchange};
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::udp().unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage, StopExchange]).unwrap();
+    /// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// client.send_to(b"hello", server.socket_address()).unwrap();
+    ///
+    /// let messages = future::block_on(server.message_stream().collect::<Vec<_>>());
+    /// assert_eq!(vec![b"hello".to_vec()], messages);
+    /// ```
+    #[cfg(feature = "futures")]
+    pub fn message_stream(&self) -> MessageStream<'_, T, C> {
+        MessageStream { server: self }
+    }
+
+    /// Stream of the errors raised by the server mocker from now on, see [`ServerMocker::message_stream`].
+    ///
+    /// Errors are consumed by whichever of the stream and the pop methods gets them first.
+    #[cfg(feature = "futures")]
+    pub fn error_stream(&self) -> ErrorStream<'_, T, C> {
+        ErrorStream { server: self }
+    }
+
+    /// Next received message if any, waking the given task once one is received otherwise
+    #[cfg(feature = "futures")]
+    pub(crate) fn poll_received_message(&self, waker: &Waker) -> Poll<Option<ReceivedMessage>> {
+        if let Some(message) = self.peeked_message().take() {
+            return Poll::Ready(Some(message));
+        }
+        // Registered before receiving, so that a message sent meanwhile wakes the task
+        self.wakers.register(waker);
+        match self.message_rx.try_recv() {
+            Ok(message) => Poll::Ready(Some(message)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+
+    /// Next server error if any, waking the given task once one is raised otherwise
+    #[cfg(feature = "futures")]
+    pub(crate) fn poll_server_error(&self, waker: &Waker) -> Poll<Option<ErrorReport>> {
+        self.wakers.register(waker);
+        match self.error_rx.try_recv() {
+            Ok(report) => Poll::Ready(Some(report)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+
+    /// Fail with [`ServerMockerError::ServerStopped`] if the server thread stopped because of a fatal error
+    fn check_running(&self) -> Result<(), ServerMockerError> {
+        match self.traffic.fatal_error() {
+            Some(error) => Err(ServerStopped(error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the next received message without consuming it, it will be returned again by the next pop
+    ///
+    /// # Example
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::udp().unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    ///
+    /// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// client.send_to(b"PING", server.socket_address()).unwrap();
+    ///
+    /// assert_eq!(Some(b"PING".to_vec()), server.peek_received_message());
+    /// assert_eq!(Some(b"PING".to_vec()), server.pop_received_message());
+    /// ```
+    pub fn peek_received_message(&self) -> Option<Vec<u8>> {
+        let mut peeked_message = self.peeked_message();
+        if peeked_message.is_none() {
+            *peeked_message = self
+                .message_rx
+                .recv_timeout(self.options.net_timeout())
+                .ok();
+        }
+        peeked_message.as_ref().map(|message| message.data.clone())
+    }
+
+    /// Pop the next chunk of data received by a [`Instruction::ReceiveChunksUntilClose`] instruction
+    ///
+    /// Chunks are delivered as soon as they are read, and are not kept in the server mocker history.
+    pub fn pop_received_chunk(&self) -> Option<Vec<u8>> {
+        self.chunk_rx.recv_timeout(self.options.net_timeout()).ok()
+    }
+
+    /// All the messages received so far, in order, whether they have already been popped or not.
+    ///
+    /// The pending instructions are given some time to run first, so that the whole conversation
+    /// can be checked at the end of a test.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use std::net::TcpStream;
+    /// use socket_server_mocker::Instruction::ReceiveMessage;
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    /// client.write_all(b"hello").unwrap();
+    ///
+    /// assert_eq!(vec![b"hello".to_vec()], server.received_messages());
+    /// // Still available
+    /// assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+    /// ```
+    pub fn received_messages(&self) -> Vec<Vec<u8>> {
+        self.pending_instructions
+            .wait_for_execution(2 * self.options.net_timeout());
+        self.traffic.received_messages()
+    }
+
+    /// Whether the TCP client closed the connection, as detected by the reads of the server so far:
+    /// `Some(true)` if it closed it gracefully, `Some(false)` if it reset it, `None` if it's still open
+    /// or never connected.
+    ///
+    /// The pending instructions are given some time to run first, so that the disconnection can be
+    /// checked at a given point of the exchange. It's also logged in the [`ServerMocker::timeline`].
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use std::net::TcpStream;
+    /// use socket_server_mocker::Instruction::{ReceiveMessage, ReceiveUntilClose};
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    /// client.write_all(b"hello").unwrap();
+    /// assert_eq!(None, server.client_disconnected());
+    ///
+    /// server.add_mock_instructions(vec![ReceiveUntilClose]).unwrap();
+    /// drop(client);
+    /// assert_eq!(Some(true), server.client_disconnected());
+    /// ```
+    pub fn client_disconnected(&self) -> Option<bool> {
+        self.pending_instructions
+            .wait_for_execution(2 * self.options.net_timeout());
+        self.traffic.client_disconnection()
+    }
+
+    /// Verify that all the messages received so far match the given matchers, in order.
+    ///
+    /// Messages are verified whether they have already been popped or not.
+    /// The pending instructions are given some time to run before the verification.
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use std::net::TcpStream;
+    /// use socket_server_mocker::{Matcher, ServerMocker};
+    /// use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage, SendMessage(b"250 OK\r\n".to_vec()), StopExchange]).unwrap();
+    /// client.write_all(b"EHLO localhost\r\n").unwrap();
+    ///
+    /// server.verify_sequence(&[Matcher::StartsWith(b"EHLO ".to_vec())]).unwrap();
+    /// ```
+    pub fn verify_sequence(&self, matchers: &[Matcher]) -> Result<(), ServerMockerError> {
+        self.verify_sequence_impl(matchers, false)
+    }
+
+    /// Same as [`ServerMocker::verify_sequence`], but received messages that don't match
+    /// the next matcher are ignored instead of failing the verification.
+    pub fn verify_sequence_lenient(&self, matchers: &[Matcher]) -> Result<(), ServerMockerError> {
+        self.verify_sequence_impl(matchers, true)
+    }
+
+    fn verify_sequence_impl(
+        &self,
+        matchers: &[Matcher],
+        lenient: bool,
+    ) -> Result<(), ServerMockerError> {
+        self.pending_instructions
+            .wait_for_execution(2 * self.options.net_timeout());
+        matcher::verify_sequence(&self.traffic.received_messages(), matchers, lenient)
+            .map_err(|mismatch| MessageSequenceMismatch(mismatch + &self.chaos_seed_note()))
+    }
+
+    /// Line to append to failure reports if no client ever connected, e.g. because it used another address family,
+    /// empty otherwise
+    fn no_client_note(&self) -> String {
+        if self.traffic.stats().connections_accepted == 0 {
+            format!("\nNo client connected to {}", self.socket_addr)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Line giving the chaos seed to append to failure reports, empty if no fault is injected
+    fn chaos_seed_note(&self) -> String {
+        self.options
+            .chaos_seed()
+            .map(|seed| format!("\nChaos seed: {seed}"))
+            .unwrap_or_default()
+    }
+
+    fn peeked_message(&self) -> MutexGuard<'_, Option<ReceivedMessage>> {
+        self.peeked_message.lock().unwrap()
+    }
+
+    /// Statistics on the traffic handled so far: bytes and messages exchanged, connections accepted,
+    /// instructions executed and errors raised.
+    ///
+    /// Useful to detect extra round trips that the received messages alone wouldn't reveal.
+    pub fn stats(&self) -> ServerStats {
+        self.traffic.stats()
+    }
+
+    /// Timestamped account of everything the server did so far: connections accepted, bytes received and sent,
+    /// timeouts, closed connections and errors, in chronological order.
+    ///
+    /// Each entry is displayed as a readable line, to print the whole session when a test fails:
+    /// ```
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// for entry in server.timeline() {
+    ///     println!("{entry}");
+    /// }
+    /// ```
+    pub fn timeline(&self) -> Vec<TimelineEntry> {
+        self.traffic.timeline()
+    }
+
+    /// Stream every payload read from or written to the socket from now on, with its direction and timestamp.
+    ///
+    /// Unlike [`ServerMocker::pop_received_message`], the wiretap doesn't consume the received messages:
+    /// it can log the exchange or check it live while the instructions keep running.
+    /// Several wiretaps can be opened, each receiving every event.
+    ///
+    /// # Example
+    /// ```
+    /// use std::net::UdpSocket;
+    /// use socket_server_mocker::Instruction::{ReceiveMessage, StopExchange};
+    /// use socket_server_mocker::{Direction, ServerMocker};
+    ///
+    /// let server = ServerMocker::udp().unwrap();
+    /// let wiretap = server.wiretap();
+    /// server.add_mock_instructions(vec![ReceiveMessage, StopExchange]).unwrap();
+    ///
+    /// let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    /// client.send_to(b"hello", server.socket_address()).unwrap();
+    ///
+    /// let event = wiretap.recv().unwrap();
+    /// assert_eq!(Direction::Inbound, event.direction);
+    /// assert_eq!(b"hello", event.data.as_slice());
+    /// // Still available
+    /// assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+    /// ```
+    pub fn wiretap(&self) -> Receiver<WireEvent> {
+        self.traffic.wiretap()
+    }
+
+    /// Write all the data exchanged with the clients so far to a pcap file, with synthesized IP/TCP/UDP headers,
+    /// to inspect the exchange in Wireshark with its protocol dissectors.
+    ///
+    /// Packets are only kept if [`TcpMocker::capture`](crate::TcpMocker::capture) or
+    /// [`UdpMocker::capture`](crate::UdpMocker::capture) is set, the capture is empty otherwise.
+    pub fn export_pcap(&self, path: impl AsRef<Path>) -> Result<(), ServerMockerError> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| UnableToWriteFile(path.to_owned(), e))?;
+        pcap::write_pcap(
+            BufWriter::new(file),
+            self.socket_addr,
+            &self.traffic.packets(),
+        )
+        .map_err(|e| UnableToWriteFile(path.to_owned(), e))
     }
 
     /// Pop the last server error from the server mocker
     pub fn pop_server_error(&self) -> Option<ServerMockerError> {
+        self.pop_server_error_timeout(self.options.net_timeout())
+    }
+
+    /// Pop the last server error from the server mocker, waiting up to `timeout` for it
+    /// instead of [`MockerOptions::net_timeout`]
+    pub fn pop_server_error_timeout(&self, timeout: Duration) -> Option<ServerMockerError> {
+        self.error_rx
+            .recv_timeout(timeout)
+            .ok()
+            .map(|report| report.error)
+    }
+
+    /// Pop the last server error from the server mocker, with the instruction being executed,
+    /// the client and the amount of data exchanged when it was raised,
+    /// to pinpoint the failing step of a long script
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::Write;
+    /// use std::net::TcpStream;
+    /// use socket_server_mocker::Instruction::{ReceiveExactBytes, ReceiveMessage};
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let server = ServerMocker::tcp().unwrap();
+    /// let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    /// server.add_mock_instructions(vec![ReceiveMessage, ReceiveExactBytes(8)]).unwrap();
+    ///
+    /// client.write_all(b"hello").unwrap();
+    /// server.pop_received_message().unwrap();
+    /// client.write_all(b"abc").unwrap();
+    /// drop(client);
+    ///
+    /// let report = server.pop_server_error_with_context().unwrap();
+    /// assert_eq!(Some(1), report.instruction_index);
+    /// assert_eq!(Some("ReceiveExactBytes(8)"), report.instruction.as_deref());
+    /// assert_eq!(8, report.bytes_received);
+    /// ```
+    pub fn pop_server_error_with_context(&self) -> Option<ErrorReport> {
         self.error_rx.recv_timeout(self.options.net_timeout()).ok()
     }
 
-    /// Create a new instance of the TCP server mocker with the given options.
+    /// Take all the server errors raised so far, oldest first.
+    ///
+    /// The pending instructions are given some time to run first, so that no error is missed
+    /// at the end of a test, unlike with a single [`ServerMocker::pop_server_error`].
+    pub fn take_errors(&self) -> Vec<ServerMockerError> {
+        self.pending_instructions
+            .wait_for_execution(2 * self.options.net_timeout());
+        // An error is reported right after its instruction is marked as executed
+        iter::from_fn(|| self.error_rx.recv_timeout(IDLE_POLL_INTERVAL).ok())
+            .map(|report| report.error)
+            .collect()
+    }
+
+    /// Assert that nothing else happened: no received message is left unconsumed,
+    /// no error is pending, and the client doesn't send anything during a grace period
+    /// of [`MockerOptions::net_timeout`]. The client may have closed the connection cleanly.
+    ///
+    /// # Panics
+    /// Panics with a hex dump of the leftover data if any message or error remains.
+    pub fn assert_no_more_messages(&self) {
+        let grace_period = self.options.net_timeout();
+        // Ask the server to watch the socket meanwhile, unless the exchange is already over
+        if self
+            .add_mock_instructions(vec![Instruction::ExpectNoMessage(grace_period)])
+            .is_ok()
+        {
+            thread::sleep(grace_period);
+        }
+
+        let mut leftovers = String::new();
+        let peeked_message = self.peeked_message().take();
+        let unconsumed_messages = peeked_message.into_iter().chain(self.message_rx.try_iter());
+        for ReceivedMessage { data: message, .. } in unconsumed_messages {
+            let _ = write!(
+                leftovers,
+                "Unconsumed message of {} bytes:\n{}",
+                message.len(),
+                hex_dump(&message)
+            );
+        }
+        while let Some(error) = self.pop_server_error() {
+            // The client closing its socket cleanly at the end of the test is expected
+            if matches!(
+                error,
+                ServerMockerError::ClientDisconnected { graceful: true }
+            ) {
+                continue;
+            }
+            let _ = writeln!(leftovers, "{error}");
+            if let ServerMockerError::UnexpectedData(data) = &error {
+                leftovers.push_str(&hex_dump(data));
+            }
+        }
+        assert!(
+            leftovers.is_empty(),
+            "Mocked server has leftover messages:\n{leftovers}{}",
+            self.chaos_seed_note()
+        );
+    }
+
+    /// Restart the server mocker on the same socket address, to reuse it across test cases.
+    ///
+    /// The pending instructions are dropped, the current client is disconnected, and the received messages,
+    /// errors and traffic history are cleared: the server mocker is back to the state it was in when created,
+    /// waiting for a client and for new instructions.
+    ///
+    /// The server thread finishes the instruction it is executing before stopping,
+    /// e.g. a receive instruction waits for up to [`MockerOptions::net_timeout`].
+    ///
+    /// # Example
+    /// ```
+    /// use std::io::{Read, Write};
+    /// use std::net::TcpStream;
+    /// use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage};
+    /// use socket_server_mocker::ServerMocker;
+    ///
+    /// let mut server = ServerMocker::tcp().unwrap();
+    /// for name in ["alice", "bob"] {
+    ///     server.reset().unwrap();
+    ///     let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    ///     server.add_mock_instructions(vec![ReceiveMessage, SendMessage(b"OK".to_vec())]).unwrap();
+    ///
+    ///     client.write_all(name.as_bytes()).unwrap();
+    ///     let mut response = [0; 2];
+    ///     client.read_exact(&mut response).unwrap();
+    ///     assert_eq!(Some(name.as_bytes().to_vec()), server.pop_received_message());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// If the socket address can't be bound again, e.g. because the server thread didn't stop in time,
+    /// the server mocker is left stopped.
+    pub fn reset(&mut self) -> Result<(), ServerMockerError> {
+        self.stop();
+
+        let mut options = self.options.clone();
+        options.set_socket_address(self.socket_addr);
+        let mut restarted = ServerMocker::new_with_opts(options)?;
+        // The previous server is dropped along with `restarted`, with no pending instruction left
+        mem::swap(&mut self.instruction_tx, &mut restarted.instruction_tx);
+        mem::swap(
+            &mut self.pending_instructions,
+            &mut restarted.pending_instructions,
+        );
+        mem::swap(&mut self.message_rx, &mut restarted.message_rx);
+        mem::swap(&mut self.peeked_message, &mut restarted.peeked_message);
+        mem::swap(&mut self.chunk_rx, &mut restarted.chunk_rx);
+        mem::swap(&mut self.traffic, &mut restarted.traffic);
+        mem::swap(&mut self.error_rx, &mut restarted.error_rx);
+        mem::swap(&mut self.wakers, &mut restarted.wakers);
+        mem::swap(&mut self.server_thread, &mut restarted.server_thread);
+        Ok(())
+    }
+
+    /// Stop the server thread, dropping the pending instructions, and join it if it stops in time
+    fn stop(&mut self) {
+        let Some(server_thread) = self.server_thread.take() else {
+            return;
+        };
+        self.pending_instructions.abort();
+        // Let the server thread stop waiting for instructions
+        self.instruction_tx = mpsc::channel().0;
+        self.options.unblock(self.socket_addr);
+        // A receive instruction may block the server thread for up to net_timeout
+        let deadline = Instant::now() + 2 * self.options.net_timeout();
+        while !server_thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(IDLE_POLL_INTERVAL);
+        }
+        if server_thread.is_finished() {
+            let _ = server_thread.join();
+        }
+    }
+
+    /// Create a new instance of the server mocker with the given options,
+    /// exchanging typed messages encoded and decoded by the given codec.
     ///
     /// # Panics
     /// It is assumed that threads can use messages channels without panicking.
-    pub fn new_with_opts(options: T) -> Result<Self, ServerMockerError> {
+    pub fn new_with_codec(options: T, codec: C) -> Result<Self, ServerMockerError> {
         let (instruction_tx, instruction_rx) = mpsc::channel();
         let (message_tx, message_rx) = mpsc::channel();
+        let (chunk_tx, chunk_rx) = mpsc::channel();
         let (error_tx, error_rx) = mpsc::channel();
-        let socket_addr = options.clone().run(instruction_rx, message_tx, error_tx)?;
+        let pending_instructions = PendingInstructions::default();
+        if let Some(transcript) = options.transcript() {
+            fs::create_dir_all(&transcript.directory)
+                .map_err(|e| UnableToWriteFile(transcript.directory.clone(), e))?;
+        }
+        let traffic = Traffic::new(
+            options.transcript().cloned(),
+            options.wire_dump(),
+            options.capture(),
+            options.hooks(),
+            options.chaos_seed(),
+        );
+        if let Some(seed) = options.chaos_seed() {
+            log::info!("Injecting random faults with chaos seed {seed}");
+        }
+        let wakers = Wakers::default();
+        let (socket_addr, server_thread) = options.clone().run(
+            instruction_rx,
+            pending_instructions.clone(),
+            EventSender::new(message_tx, wakers.clone()),
+            chunk_tx,
+            traffic.clone(),
+            EventSender::new(error_tx, wakers.clone()),
+        )?;
 
         Ok(Self {
             options,
+            codec,
             socket_addr,
             instruction_tx,
+            pending_instructions,
             message_rx,
+            peeked_message: Mutex::default(),
+            chunk_rx,
+            traffic,
             error_rx,
+            wakers,
+            server_thread: Some(server_thread),
         })
     }
 }
+
+impl<T: MockerOptions, C: Codec> Drop for ServerMocker<T, C> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.stop();
+            return;
+        }
+        // A receive instruction may block the server thread for up to net_timeout
+        let never_executed = self
+            .pending_instructions
+            .wait_for_execution(2 * self.options.net_timeout());
+        // Repeated instructions which ran an unexpected number of times fail the test, even if the error wasn't popped
+        let repeat_count_errors: Vec<String> =
+            iter::from_fn(|| self.error_rx.recv_timeout(IDLE_POLL_INTERVAL).ok())
+                .filter(|report| matches!(report.error, UnexpectedRepeatCount(..)))
+                .map(|report| report.error.to_string())
+                .collect();
+        // Don't leave the thread behind, e.g. waiting for a client which will never connect
+        self.stop();
+        assert!(
+            never_executed.is_empty(),
+            "Mocked server dropped before executing {} instructions:\n{}{}{}",
+            never_executed.len(),
+            never_executed.join("\n"),
+            self.no_client_note(),
+            self.chaos_seed_note()
+        );
+        assert!(
+            repeat_count_errors.is_empty(),
+            "Mocked server dropped with unmet repetition counts:\n{}{}",
+            repeat_count_errors.join("\n"),
+            self.chaos_seed_note()
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! # `stream`
+//!
+//! Channels from the server thread to the testing code, waking the async tasks waiting on them.
+
+use std::mem;
+use std::sync::mpsc::{SendError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::Waker;
+
+#[cfg(feature = "futures")]
+use std::pin::Pin;
+#[cfg(feature = "futures")]
+use std::task::{Context, Poll};
+
+#[cfg(feature = "futures")]
+use futures_core::Stream;
+
+#[cfg(feature = "futures")]
+use crate::server_mocker::MockerOptions;
+#[cfg(feature = "futures")]
+use crate::{Codec, ServerMocker, ServerMockerError};
+
+/// Async tasks waiting for the server thread, shared between the [`ServerMocker`](crate::ServerMocker)
+/// and its server thread
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Wakers(Arc<Mutex<Vec<Waker>>>);
+
+impl Wakers {
+    /// Wake the given task on the next message or error sent by the server thread
+    #[cfg(feature = "futures")]
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+
+    /// Wake every waiting task
+    fn wake(&self) {
+        let wakers = mem::take(&mut *self.0.lock().unwrap_or_else(PoisonError::into_inner));
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Sender of the server thread, waking the async tasks once a value is sent
+#[derive(Debug)]
+pub struct EventSender<T> {
+    tx: Sender<T>,
+    wakers: Wakers,
+}
+
+impl<T> EventSender<T> {
+    /// Send values through the given sender, waking the given tasks
+    pub(crate) fn new(tx: Sender<T>, wakers: Wakers) -> Self {
+        Self { tx, wakers }
+    }
+
+    /// Send a value, then wake the waiting tasks so that they receive it
+    pub(crate) fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let result = self.tx.send(value);
+        self.wakers.wake();
+        result
+    }
+}
+
+// Not derived, as T doesn't need to be Clone
+impl<T> Clone for EventSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            wakers: self.wakers.clone(),
+        }
+    }
+}
+
+impl<T> Drop for EventSender<T> {
+    // The stream ends once every sender of the server thread is dropped
+    fn drop(&mut self) {
+        self.wakers.wake();
+    }
+}
+
+/// Stream of the messages received by the server mocker, see [`ServerMocker::message_stream`]
+#[cfg(feature = "futures")]
+pub struct MessageStream<'a, T: MockerOptions, C: Codec> {
+    pub(crate) server: &'a ServerMocker<T, C>,
+}
+
+#[cfg(feature = "futures")]
+impl<T: MockerOptions, C: Codec> Stream for MessageStream<'_, T, C> {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.server
+            .poll_received_message(cx.waker())
+            .map(|message| message.map(|message| message.data))
+    }
+}
+
+/// Stream of the errors raised by the server mocker, see [`ServerMocker::error_stream`]
+#[cfg(feature = "futures")]
+pub struct ErrorStream<'a, T: MockerOptions, C: Codec> {
+    pub(crate) server: &'a ServerMocker<T, C>,
+}
+
+#[cfg(feature = "futures")]
+impl<T: MockerOptions, C: Codec> Stream for ErrorStream<'_, T, C> {
+    type Item = ServerMockerError;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.server
+            .poll_server_error(cx.waker())
+            .map(|report| report.map(|report| report.error))
+    }
+}
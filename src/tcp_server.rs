@@ -1,42 +1,228 @@
-use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream};
-use std::sync::mpsc::{Receiver, Sender};
-use std::thread;
-use std::time::Duration;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::server_mocker::MockerOptions;
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+
+use crate::chaos::ChaosConfig;
+use crate::framing::{DelimiterFramer, ExactBytesFramer, Framer, Framing, UntilCloseFramer};
+use crate::hooks::Hooks;
+use crate::instructions::{
+    constant_responder, expand_template, Finish, IdlePolicy, MessageResponder, PendingInstructions,
+    Times,
+};
+use crate::rng::Rng;
+use crate::server_mocker::{
+    other_loopback, spawn_server_thread, transmission_time, MockerOptions, FRAGMENT_INTERVAL,
+    IDLE_POLL_INTERVAL,
+};
+use crate::stream::EventSender;
+use crate::traffic::{Direction, ReceivedMessage, Traffic, Transport};
+use crate::transcript::{Transcript, TranscriptFormat};
+use crate::ErrorReport;
 use crate::Instruction::{
-    self, ReceiveMessageWithMaxSize, SendMessage, SendMessageDependingOnLastReceivedMessage,
+    self, Branch, ExpectNoMessage, Pause, ReceiveExactBytes, ReceiveFor, ReceiveMessageWithMaxSize,
+    ReceiveMessages, ReceiveUntilDelimiter, Repeat, RouteByPeer, SendFile, SendFileChunked,
+    SendMessage, SendMessageChunked, SendMessageDependingOnLastReceivedMessage,
+    SendMessageFromClosure, SendMessageTo, SendPartialThenClose, SendTemplate, SetDefaultResponse,
+    Silence, SlowDrip,
 };
+use crate::ReadInterruption::{ConnectionClosed, Timeout};
 use crate::ServerMockerError::{
-    self, UnableToAcceptConnection, UnableToBindListener, UnableToGetLocalAddress,
-    UnableToReadTcpStream, UnableToSetReadTimeout, UnableToWriteTcpStream,
+    self, ClientDisconnected, ReadInterrupted, UnableToAcceptConnection, UnableToBindListener,
+    UnableToGetLocalAddress, UnableToReadFile, UnableToReadTcpStream, UnableToSetReadTimeout,
+    UnableToSetSocketOption, UnableToSpawnThread, UnableToWriteTcpStream, UnexpectedData,
+    UnexpectedRepeatCount, UnsupportedInstruction,
 };
 
 /// Options for the TCP server mocker
 #[derive(Debug, Clone)]
+// Independent options rather than a state machine
+#[allow(clippy::struct_excessive_bools)]
 pub struct TcpMocker {
     /// Socket address on which the server will listen. Will be set to `127.0.0.1:0` by default.
     pub socket_addr: SocketAddr,
+    /// Also listen on the loopback of the other IP family, on the same port, e.g. on `[::1]` as well as `127.0.0.1`,
+    /// so that the client connects whichever family its resolver prefers. The first connection on either is accepted.
+    pub dual_stack: bool,
+    /// Size of the queue of connections waiting to be accepted, 128 if `None`
+    pub backlog: Option<i32>,
+    /// What to do with the connections made while the server mocker serves its client, see [`ExcessConnections`]
+    pub excess_connections: ExcessConnections,
     /// Timeout for the server to wait for a message from the client.
     pub net_timeout: Duration,
     /// Timeout if no more instruction is available and [`Instruction::StopExchange`] hasn't been sent
     pub rx_timeout: Duration,
+    /// What to do once `rx_timeout` elapsed without new instructions
+    pub idle_policy: IdlePolicy,
+    /// What to do once all the queued instructions are executed
+    pub finish_behavior: Finish,
+    /// Strict mode: data received from the client outside of a receive instruction
+    /// is reported as [`ServerMockerError::UnexpectedData`] instead of being silently ignored
+    pub strict: bool,
+    /// Message sent back to the client whenever data is received outside of a receive instruction,
+    /// including while the server is waiting for new instructions
+    pub default_response: Option<Vec<u8>>,
+    /// Split the data received from the client into messages, instead of delivering whatever was read at once
+    pub framer: Option<Arc<Mutex<dyn Framer>>>,
+    /// Delay applied before every write to the client, to simulate a slow server
+    pub latency: Option<Duration>,
+    /// Maximum outbound bandwidth, in bytes per second: messages are split into paced writes to simulate a slow link
+    pub throttle_bytes_per_sec: Option<u64>,
+    /// Random faults applied to the messages sent to the client: only [`ChaosConfig::fragment_max_size`] applies to TCP
+    pub chaos: ChaosConfig,
+    /// Disable Nagle's algorithm on the accepted connection, so that the small scripted writes are sent immediately
+    /// instead of being delayed until the previous ones are acknowledged
+    pub nodelay: bool,
+    /// `SO_LINGER` of the accepted connection, if set: how long closing it waits for the unsent data to be flushed.
+    /// `Some(Duration::ZERO)` resets the connection on close instead of closing it gracefully.
+    pub linger: Option<Duration>,
+    /// TCP keepalive of the accepted connection, disabled if `None`
+    pub keepalive: Option<Keepalive>,
+    /// Tee every byte sent and received into a transcript file per connection, see [`Transcript`]
+    pub transcript: Option<Transcript>,
+    /// Log every message sent and received as an `xxd`-style hex dump, at the `debug` level of the `log` crate,
+    /// to debug binary protocols
+    pub wire_dump: bool,
+    /// Keep every packet sent and received in memory, to be exported with
+    /// [`ServerMocker::export_pcap`](crate::ServerMocker::export_pcap)
+    pub capture: bool,
+    /// Callbacks invoked by the server thread on the events of the exchange, see [`Hooks`]
+    pub hooks: Hooks,
     /// Buffer size for TCP socket
     pub reader_buffer_size: usize,
 }
 
+/// TCP keepalive settings of the accepted connection, see [`TcpMocker::keepalive`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// Idle time after which the first keepalive probe is sent
+    pub time: Duration,
+    /// Time between two keepalive probes, the system default if `None`.
+    /// Ignored on the systems where it can't be set, e.g. OpenBSD.
+    pub interval: Option<Duration>,
+    /// Unanswered probes after which the connection is dropped, the system default if `None`.
+    /// Ignored on the systems where it can't be set, e.g. OpenBSD.
+    pub probes: Option<u32>,
+}
+
+impl Keepalive {
+    /// Parameters given to the socket
+    fn params(self) -> TcpKeepalive {
+        let params = TcpKeepalive::new().with_time(self.time);
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "netbsd",
+            target_os = "windows",
+        ))]
+        let params = {
+            let params = match self.interval {
+                Some(interval) => params.with_interval(interval),
+                None => params,
+            };
+            match self.probes {
+                Some(probes) => params.with_retries(probes),
+                None => params,
+            }
+        };
+        params
+    }
+}
+
+/// What to do with the connections made while the TCP server mocker serves its client,
+/// since it serves a single connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExcessConnections {
+    /// Leave them in the queue of the listener, up to [`TcpMocker::backlog`]: they are never accepted
+    #[default]
+    Queue,
+    /// Accept and reset them right away, as a server at its connection limit would
+    Refuse,
+}
+
 impl Default for TcpMocker {
     fn default() -> Self {
         Self {
             socket_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            dual_stack: false,
+            backlog: None,
+            excess_connections: ExcessConnections::default(),
             net_timeout: Duration::from_millis(100),
             rx_timeout: Duration::from_millis(100),
+            idle_policy: IdlePolicy::default(),
+            finish_behavior: Finish::default(),
+            strict: false,
+            default_response: None,
+            framer: None,
+            latency: None,
+            throttle_bytes_per_sec: None,
+            chaos: ChaosConfig::default(),
+            nodelay: false,
+            linger: None,
+            keepalive: None,
+            transcript: None,
+            wire_dump: false,
+            capture: false,
+            hooks: Hooks::default(),
             reader_buffer_size: 1024,
         }
     }
 }
 
+impl TcpMocker {
+    /// Split the data received from the client with the given built-in framing,
+    /// see [`TcpMocker::framer`] for custom framings
+    #[must_use]
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framer = Some(framing.framer());
+        self
+    }
+
+    /// Split every message sent to the client into writes of random sizes, between 1 and `max_size` bytes
+    #[must_use]
+    pub fn fragment(mut self, max_size: usize) -> Self {
+        self.chaos.fragment_max_size = Some(max_size);
+        self
+    }
+
+    /// Inject the given random faults, see [`ChaosConfig`]
+    #[must_use]
+    pub fn chaos(mut self, chaos: ChaosConfig) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Invoke the given callbacks on the events of the exchange, see [`Hooks`]
+    #[must_use]
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Tee every byte sent and received into a transcript file per connection in `directory`, see [`Transcript`]
+    #[must_use]
+    pub fn transcript(mut self, directory: impl Into<PathBuf>, format: TranscriptFormat) -> Self {
+        self.transcript = Some(Transcript {
+            directory: directory.into(),
+            format,
+        });
+        self
+    }
+}
+
 impl MockerOptions for TcpMocker {
     fn socket_address(&self) -> SocketAddr {
         self.socket_addr
@@ -46,127 +232,955 @@ impl MockerOptions for TcpMocker {
         self.net_timeout
     }
 
+    fn chaos_seed(&self) -> Option<u64> {
+        self.chaos.replay_seed()
+    }
+
+    fn transcript(&self) -> Option<&Transcript> {
+        self.transcript.as_ref()
+    }
+
+    fn wire_dump(&self) -> bool {
+        self.wire_dump
+    }
+
+    fn capture(&self) -> bool {
+        self.capture
+    }
+
+    fn hooks(&self) -> Hooks {
+        self.hooks.clone()
+    }
+
+    fn set_socket_address(&mut self, socket_addr: SocketAddr) {
+        self.socket_addr = socket_addr;
+    }
+
+    fn unblock(&self, socket_addr: SocketAddr) {
+        // The server thread accepts this connection, and then notices that it must stop
+        connect_to_self(socket_addr);
+    }
+
     fn run(
         self,
         instruction_rx: Receiver<Vec<Instruction>>,
-        message_tx: Sender<Vec<u8>>,
-        error_tx: Sender<ServerMockerError>,
-    ) -> Result<SocketAddr, ServerMockerError> {
-        let listener = TcpListener::bind(self.socket_addr)
+        pending_instructions: PendingInstructions,
+        message_tx: EventSender<ReceivedMessage>,
+        chunk_tx: Sender<Vec<u8>>,
+        traffic: Traffic,
+        error_tx: EventSender<ErrorReport>,
+    ) -> Result<(SocketAddr, JoinHandle<()>), ServerMockerError> {
+        self.serve(
+            |stream| Ok(ClientStream::Plain(stream)),
+            instruction_rx,
+            pending_instructions,
+            message_tx,
+            chunk_tx,
+            traffic,
+            error_tx,
+        )
+    }
+}
+
+impl TcpMocker {
+    /// Run the server mocker with the given instructions, the accepted connection being wrapped by `wrap_stream`
+    // The arguments of MockerOptions::run, and the wrapper
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn serve(
+        self,
+        wrap_stream: impl FnOnce(TcpStream) -> Result<ClientStream, ServerMockerError> + Send + 'static,
+        instruction_rx: Receiver<Vec<Instruction>>,
+        pending_instructions: PendingInstructions,
+        message_tx: EventSender<ReceivedMessage>,
+        chunk_tx: Sender<Vec<u8>>,
+        traffic: Traffic,
+        error_tx: EventSender<ErrorReport>,
+    ) -> Result<(SocketAddr, JoinHandle<()>), ServerMockerError> {
+        let listener = bind_listener(&self, self.socket_addr)
             .map_err(|e| UnableToBindListener(self.socket_addr, e))?;
         let socket_addr = listener.local_addr().map_err(UnableToGetLocalAddress)?;
+        let dual_listener = if self.dual_stack {
+            let dual_addr = other_loopback(socket_addr);
+            let dual_listener =
+                bind_listener(&self, dual_addr).map_err(|e| UnableToBindListener(dual_addr, e))?;
+            Some(dual_listener)
+        } else {
+            None
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%socket_addr, "TCP server mocker listening");
+        let server_thread = spawn_server_thread(
+            Transport::Tcp,
+            socket_addr,
+            traffic.clone(),
+            error_tx.clone(),
+            move || match accept(&listener, dual_listener.as_ref()) {
+                Ok(_) if pending_instructions.is_aborted() => {}
+                Ok((stream, client_addr)) => {
+                    #[cfg(feature = "tracing")]
+                    let _span =
+                        tracing::info_span!("tcp_server", %socket_addr, %client_addr).entered();
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("connection accepted");
+                    traffic.connection_accepted(client_addr);
+                    let refuser = match self.excess_connections {
+                        ExcessConnections::Queue => None,
+                        ExcessConnections::Refuse => {
+                            ConnectionRefuser::spawn(listener, dual_listener, socket_addr)
+                                .map_err(|e| {
+                                    let error =
+                                        traffic.error_raised(UnableToSpawnThread(e), None, None);
+                                    let _ = error_tx.send(error);
+                                })
+                                .ok()
+                        }
+                    };
+                    let stream = match wrap_stream(stream) {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            let _ = error_tx.send(traffic.error_raised(e, None, Some(client_addr)));
+                            return;
+                        }
+                    };
+                    let rng = self.chaos.rng();
+                    let default_response = self.default_response.clone().map(constant_responder);
+                    TcpServerImpl {
+                        options: self,
+                        stream,
+                        client_addr,
+                        instruction_rx,
+                        pending_instructions,
+                        message_tx,
+                        chunk_tx,
+                        traffic: traffic.clone(),
+                        error_tx,
+                        last_received_message: None,
+                        unframed_data: Vec::new(),
+                        rng,
+                        default_response,
+                        drip_interval: None,
+                        script_started: false,
+                    }
+                    .run();
+                    if let Some(refuser) = refuser {
+                        refuser.stop();
+                    }
+                    traffic.connection_closed(client_addr);
+                }
+                Err(err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::error!(%socket_addr, error = %err, "unable to accept a connection");
+                    let error = UnableToAcceptConnection(socket_addr, err);
+                    let _ = error_tx.send(traffic.error_raised(error, None, None));
+                }
+            },
+        )?;
+
+        Ok((socket_addr, server_thread))
+    }
+}
+
+/// Bind a listener with the options of the server mocker
+fn bind_listener(options: &TcpMocker, socket_addr: SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(socket_addr),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    // As done by TcpListener, so that the port can be reused right after the server mocker is dropped
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    options.configure_socket(&socket)?;
+    socket.bind(&socket_addr.into())?;
+    socket.listen(options.backlog.unwrap_or(128))?;
+    Ok(socket.into())
+}
+
+/// Connect to the listener on `socket_addr`, to wake up a thread blocked accepting connections
+fn connect_to_self(mut socket_addr: SocketAddr) {
+    // A wildcard address can't be connected to on every system
+    if socket_addr.ip().is_unspecified() {
+        socket_addr.set_ip(match socket_addr {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+    let _ = TcpStream::connect(socket_addr);
+}
+
+/// Thread resetting the connections made while the server mocker serves its client, see [`ExcessConnections::Refuse`]
+struct ConnectionRefuser {
+    socket_addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
 
-        thread::spawn(move || match listener.accept() {
-            Ok((stream, _addr)) => {
-                TcpServerImpl {
-                    options: self,
-                    stream,
-                    instruction_rx,
-                    message_tx,
-                    error_tx,
+impl ConnectionRefuser {
+    fn spawn(
+        listener: TcpListener,
+        dual_listener: Option<TcpListener>,
+        socket_addr: SocketAddr,
+    ) -> io::Result<Self> {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = Arc::clone(&stopped);
+        let thread = thread::Builder::new()
+            .name(format!("ssm-tcp-{socket_addr}-refuser"))
+            .spawn(move || {
+                while let Ok((stream, _)) = accept(&listener, dual_listener.as_ref()) {
+                    if thread_stopped.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    // Closing with a zero linger resets the connection
+                    let _ = SockRef::from(&stream).set_linger(Some(Duration::ZERO));
                 }
-                .run();
+            })?;
+        Ok(Self {
+            socket_addr,
+            stopped,
+            thread,
+        })
+    }
+
+    fn stop(self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        connect_to_self(self.socket_addr);
+        let _ = self.thread.join();
+    }
+}
+
+/// Accept a connection on the listener, or on the first of both listeners to get one when listening on both loopbacks
+fn accept(
+    listener: &TcpListener,
+    dual_listener: Option<&TcpListener>,
+) -> io::Result<(TcpStream, SocketAddr)> {
+    let Some(dual_listener) = dual_listener else {
+        return listener.accept();
+    };
+    listener.set_nonblocking(true)?;
+    dual_listener.set_nonblocking(true)?;
+    loop {
+        for listener in [listener, dual_listener] {
+            match listener.accept() {
+                Ok((stream, client_addr)) => {
+                    // The accepted stream inherits the non-blocking mode on some systems
+                    stream.set_nonblocking(false)?;
+                    return Ok((stream, client_addr));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
             }
-            Err(err) => {
-                error_tx
-                    .send(UnableToAcceptConnection(socket_addr, err))
-                    .unwrap();
+        }
+        thread::sleep(IDLE_POLL_INTERVAL);
+    }
+}
+
+/// Connection accepted by the TCP server mocker, in plain text or wrapped in TLS
+pub(crate) enum ClientStream {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
+}
+
+impl ClientStream {
+    /// Underlying TCP socket, to set its options
+    fn socket(&self) -> &TcpStream {
+        match self {
+            Self::Plain(stream) => stream,
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => &stream.sock,
+        }
+    }
+
+    /// Close the connection for writing, after notifying a TLS client
+    fn shutdown_write(&mut self) -> io::Result<()> {
+        #[cfg(feature = "tls")]
+        if let Self::Tls(stream) = self {
+            stream.conn.send_close_notify();
+            stream.flush()?;
+        }
+        self.socket().shutdown(Shutdown::Write)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Drop for ClientStream {
+    // Without close_notify, a TLS client can't tell the end of the exchange from a truncation attack
+    fn drop(&mut self) {
+        if let Self::Tls(stream) = self {
+            if !stream.conn.is_handshaking() {
+                stream.conn.send_close_notify();
+                let _ = stream.flush();
             }
-        });
+        }
+    }
+}
 
-        Ok(socket_addr)
+impl Read for ClientStream {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.read(buffer),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.read(buffer),
+        }
+    }
+}
+
+impl Write for ClientStream {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.write(data),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.flush(),
+        }
     }
 }
 
 /// TCP server mocker thread implementation
 pub(crate) struct TcpServerImpl {
     options: TcpMocker,
-    stream: TcpStream,
+    stream: ClientStream,
+    client_addr: SocketAddr,
     instruction_rx: Receiver<Vec<Instruction>>,
-    message_tx: Sender<Vec<u8>>,
-    error_tx: Sender<ServerMockerError>,
+    pending_instructions: PendingInstructions,
+    message_tx: EventSender<ReceivedMessage>,
+    chunk_tx: Sender<Vec<u8>>,
+    traffic: Traffic,
+    error_tx: EventSender<ErrorReport>,
+    last_received_message: Option<Vec<u8>>,
+    /// Data received from the client but not split into a message by the framer yet
+    unframed_data: Vec<u8>,
+    /// Random generator used to fragment messages
+    rng: Rng,
+    /// Answer to the data received outside of a receive instruction, see [`Instruction::SetDefaultResponse`]
+    default_response: Option<MessageResponder>,
+    /// Delay between two bytes sent to the client, while executing [`Instruction::SlowDrip`]
+    drip_interval: Option<Duration>,
+    /// Whether instructions have been received, so that the script can be finished once they are executed
+    script_started: bool,
 }
 
 /// TCP server mocker thread implementation
 impl TcpServerImpl {
     fn run(mut self) {
         let timeout = Some(self.options.net_timeout);
-        if let Err(e) = self.stream.set_read_timeout(timeout) {
-            self.error_tx.send(UnableToSetReadTimeout(e)).unwrap();
+        if let Err(e) = self.stream.socket().set_read_timeout(timeout) {
+            self.report_error(UnableToSetReadTimeout(e));
             return;
         }
-        let mut last_received_message: Option<Vec<u8>> = None;
+        // Send each fragment as soon as it's written
+        if self.options.nodelay || self.options.chaos.fragment_max_size.is_some() {
+            if let Err(e) = self.stream.socket().set_nodelay(true) {
+                self.report_error(UnableToSetSocketOption("TCP_NODELAY", e));
+            }
+        }
+        if let Some(linger) = self.options.linger {
+            if let Err(e) = SockRef::from(self.stream.socket()).set_linger(Some(linger)) {
+                self.report_error(UnableToSetSocketOption("SO_LINGER", e));
+            }
+        }
+        if let Some(keepalive) = self.options.keepalive {
+            let result = SockRef::from(self.stream.socket()).set_tcp_keepalive(&keepalive.params());
+            if let Err(e) = result {
+                self.report_error(UnableToSetSocketOption("SO_KEEPALIVE", e));
+            }
+        }
 
         // Timeout: if no more instruction is available and StopExchange hasn't been sent
         // Stop server if no more instruction is available and StopExchange hasn't been sent
-        while let Ok(instructions) = self.instruction_rx.recv_timeout(self.options.rx_timeout) {
-            for instruction in instructions {
-                match instruction {
-                    SendMessage(binary_message) => {
-                        if let Err(e) = self.send_packet(&binary_message) {
-                            self.error_tx.send(e).unwrap();
-                        }
-                    }
-                    SendMessageDependingOnLastReceivedMessage(sent_message_calculator) => {
-                        // Call the closure to get the message to send
-                        let message_to_send =
-                            sent_message_calculator(last_received_message.clone());
-                        // Send the message or skip if the closure returned None
-                        if let Some(message_to_send) = message_to_send {
-                            if let Err(e) = self.send_packet(&message_to_send) {
-                                self.error_tx.send(e).unwrap();
-                            }
-                        }
-                    }
-                    Instruction::ReceiveMessage => {
-                        match self.read_packet() {
-                            Ok(whole_received_packet) => {
-                                last_received_message = Some(whole_received_packet.clone());
-                                self.message_tx.send(whole_received_packet).unwrap();
-                            }
-                            Err(e) => self.error_tx.send(e).unwrap(),
-                        };
+        while let Some(mut instructions) = self.next_instructions() {
+            for instruction in &mut instructions {
+                if self.pending_instructions.is_aborted() {
+                    return;
+                }
+                let executing = self.pending_instructions.executing();
+                let result = self.execute(instruction);
+                self.pending_instructions.pop_executed();
+                match result {
+                    Ok(ControlFlow::Continue(())) => {}
+                    Ok(ControlFlow::Break(())) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!("exchange stopped");
+                        return;
                     }
-                    ReceiveMessageWithMaxSize(max_message_size) => {
-                        match self.read_packet() {
-                            Ok(mut whole_received_packet) => {
-                                whole_received_packet.truncate(max_message_size);
-                                last_received_message = Some(whole_received_packet.clone());
-                                self.message_tx.send(whole_received_packet).unwrap();
-                            }
-                            Err(e) => self.error_tx.send(e).unwrap(),
-                        };
+                    Err(e) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(error = %e, "instruction failed");
+                        self.report_instruction_error(e, executing);
                     }
-                    Instruction::StopExchange => {
-                        return;
+                }
+            }
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(rx_timeout = ?self.options.rx_timeout, "no more instructions, exchange stopped");
+        self.handle_unexpected_data();
+        if self.options.idle_policy == IdlePolicy::CloseConnectionGracefully
+            || self.options.finish_behavior == Finish::CloseGracefully
+        {
+            self.close_gracefully();
+        }
+    }
+
+    /// Close the connection for writing, and wait for the client to close its side
+    /// so that data it sends meanwhile doesn't reset the connection
+    fn close_gracefully(&mut self) {
+        if self.stream.shutdown_write().is_err() {
+            return;
+        }
+        let mut buffer = vec![0; self.options.reader_buffer_size];
+        // Until the end of stream, or a timeout
+        while matches!(self.read_stream(&mut buffer, false), Ok(bytes_read) if bytes_read > 0) {}
+    }
+
+    /// Wait for the next instructions, answering unexpected client data meanwhile
+    fn next_instructions(&mut self) -> Option<Vec<Instruction>> {
+        if self.options.finish_behavior == Finish::CloseGracefully && self.script_started {
+            // Everything queued so far has been executed, unless more instructions are being sent
+            if self.pending_instructions.finish() {
+                return None;
+            }
+            return self.instruction_rx.recv().ok();
+        }
+        let instructions = self.wait_for_instructions();
+        self.script_started = instructions.is_some();
+        instructions
+    }
+
+    /// Wait for the next instructions according to the idle policy
+    fn wait_for_instructions(&mut self) -> Option<Vec<Instruction>> {
+        let keep_waiting = self.options.idle_policy == IdlePolicy::KeepConnectionOpen;
+        if self.default_response.is_none() {
+            loop {
+                match self.instruction_rx.recv_timeout(self.options.rx_timeout) {
+                    Err(RecvTimeoutError::Timeout) if keep_waiting => {}
+                    result => return result.ok(),
+                }
+            }
+        }
+        let mut deadline = Instant::now() + self.options.rx_timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self
+                .instruction_rx
+                .recv_timeout(remaining.min(IDLE_POLL_INTERVAL))
+            {
+                Ok(instructions) => return Some(instructions),
+                Err(RecvTimeoutError::Disconnected) => return None,
+                Err(RecvTimeoutError::Timeout) if remaining.is_zero() => {
+                    if !keep_waiting {
+                        return None;
                     }
+                    deadline = Instant::now() + self.options.rx_timeout;
                 }
+                Err(RecvTimeoutError::Timeout) => self.handle_unexpected_data(),
             }
         }
     }
 
-    /// Read a TCP packet from the client, using temporary buffer
-    fn read_packet(&mut self) -> Result<Vec<u8>, ServerMockerError> {
-        let mut whole_received_packet: Vec<u8> = Vec::new();
-        // FIXME: not much point in reading into a buffer and copying, perhaps need to consolidate
+    /// Execute a single instruction, returning [`ControlFlow::Break`] if the exchange must stop
+    fn execute(
+        &mut self,
+        instruction: &mut Instruction,
+    ) -> Result<ControlFlow<()>, ServerMockerError> {
+        #[cfg(feature = "tracing")]
+        let _span = instruction.enter_span();
+        self.traffic.count_instruction();
+        if !instruction.is_receive() {
+            self.handle_unexpected_data();
+        }
+        match instruction {
+            SendMessage(binary_message) => self.send_packet(binary_message)?,
+            // A single client is connected, there is no other destination
+            SendMessageTo(..) => return Err(UnsupportedInstruction("SendMessageTo")),
+            RouteByPeer(_) => return Err(UnsupportedInstruction("RouteByPeer")),
+            SendMessageDependingOnLastReceivedMessage(sent_message_calculator) => {
+                // Call the closure to get the message to send
+                let message_to_send = sent_message_calculator(self.last_received_message.clone());
+                // Send the message or skip if the closure returned None
+                if let Some(message_to_send) = message_to_send {
+                    self.send_packet(&message_to_send)?;
+                }
+            }
+            SendMessageFromClosure(sent_message_calculator) => {
+                let mut sent_message_calculator = sent_message_calculator
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                if let Some(message_to_send) =
+                    sent_message_calculator(self.last_received_message.clone())
+                {
+                    self.send_packet(&message_to_send)?;
+                }
+            }
+            SendTemplate { pattern, template } => {
+                let message =
+                    expand_template(pattern, template, self.last_received_message.as_deref())?;
+                self.send_packet(&message)?;
+            }
+            SendMessageChunked {
+                message,
+                chunk_size,
+                interval,
+            } => {
+                self.send_chunked(message, *chunk_size, *interval)?;
+            }
+            SendFile(path) => {
+                let content = fs::read(&path).map_err(|e| UnableToReadFile(path.clone(), e))?;
+                self.send_packet(&content)?;
+            }
+            SendFileChunked {
+                path,
+                chunk_size,
+                interval,
+            } => {
+                let content = fs::read(&path).map_err(|e| UnableToReadFile(path.clone(), e))?;
+                self.send_chunked(&content, *chunk_size, *interval)?;
+            }
+            SendPartialThenClose {
+                message,
+                bytes_to_send,
+            } => {
+                self.send_packet(&message[..(*bytes_to_send).min(message.len())])?;
+                return Ok(ControlFlow::Break(()));
+            }
+            Repeat {
+                times,
+                instructions,
+            } => return self.repeat(*times, instructions),
+            Branch {
+                predicate,
+                if_true,
+                if_false,
+            } => {
+                let branch = match self.last_received_message {
+                    Some(ref last_received_message) if predicate(last_received_message) => if_true,
+                    _ => if_false,
+                };
+                return self.execute_all(branch);
+            }
+            SlowDrip {
+                byte_interval,
+                instructions,
+            } => {
+                // Send each byte as soon as it's written
+                self.stream
+                    .socket()
+                    .set_nodelay(true)
+                    .map_err(|e| UnableToSetSocketOption("TCP_NODELAY", e))?;
+                let previous_interval = self.drip_interval.replace(*byte_interval);
+                let result = self.execute_all(instructions);
+                self.drip_interval = previous_interval;
+                let restored = self.stream.socket().set_nodelay(
+                    self.options.nodelay || self.options.chaos.fragment_max_size.is_some(),
+                );
+                // An error of the instructions prevails over a failure to restore the option
+                let control_flow = result?;
+                restored.map_err(|e| UnableToSetSocketOption("TCP_NODELAY", e))?;
+                return Ok(control_flow);
+            }
+            Pause(duration) => thread::sleep(*duration),
+            SetDefaultResponse(responder) => self.default_response = Some(responder.clone()),
+            Instruction::StopExchange => return Ok(ControlFlow::Break(())),
+            _ => self.execute_receive(instruction)?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Execute an instruction waiting for data from the client
+    fn execute_receive(&mut self, instruction: &Instruction) -> Result<(), ServerMockerError> {
+        match instruction {
+            Instruction::ReceiveMessage => {
+                let whole_received_packet = self.read_message()?;
+                self.push_received_message(whole_received_packet);
+            }
+            ReceiveMessageWithMaxSize(max_message_size) => {
+                let message = self.read_at_most(*max_message_size)?;
+                self.push_received_message(message);
+            }
+            ReceiveMessages(count) => {
+                for _ in 0..*count {
+                    let message = self.read_message()?;
+                    self.push_received_message(message);
+                }
+            }
+            ReceiveExactBytes(size) => self.receive_framed(&mut ExactBytesFramer(*size))?,
+            ReceiveUntilDelimiter(delimiter) => {
+                self.receive_framed(&mut DelimiterFramer(delimiter.clone()))?;
+            }
+            Instruction::ReceiveUntilClose => self.receive_framed(&mut UntilCloseFramer)?,
+            Instruction::ReceiveChunksUntilClose => self.receive_chunks_until_close()?,
+            ReceiveFor(duration) => {
+                let mut message = mem::take(&mut self.unframed_data);
+                message.extend_from_slice(&self.read_for(*duration)?);
+                self.push_received_message(message);
+            }
+            Silence(duration) => {
+                self.read_for(*duration)?;
+            }
+            ExpectNoMessage(duration) => {
+                let unexpected_data = self.read_for(*duration)?;
+                if !unexpected_data.is_empty() {
+                    return Err(UnexpectedData(unexpected_data));
+                }
+            }
+            _ => unreachable!("{} doesn't receive data", instruction.summary()),
+        }
+        Ok(())
+    }
+
+    /// Execute the instructions repeatedly, as many times as allowed by `times`
+    fn repeat(
+        &mut self,
+        times: Times,
+        instructions: &mut [Instruction],
+    ) -> Result<ControlFlow<()>, ServerMockerError> {
+        let mut count = 0;
+        while !times.is_exhausted(count) {
+            match self.execute_all(instructions) {
+                Ok(ControlFlow::Continue(())) => count += 1,
+                Ok(ControlFlow::Break(())) => return Ok(ControlFlow::Break(())),
+                // The client stopped repeating between two messages, check the count below
+                Err(ReadInterrupted(_, data)) if data.is_empty() => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if !times.contains(count) {
+            return Err(UnexpectedRepeatCount(times, count));
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Execute a list of instructions, stopping at the first error
+    fn execute_all(
+        &mut self,
+        instructions: &mut [Instruction],
+    ) -> Result<ControlFlow<()>, ServerMockerError> {
+        for instruction in instructions {
+            if self.execute(instruction)?.is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Receive a message split by the given framer and forward it to the testing code
+    fn receive_framed(&mut self, framer: &mut dyn Framer) -> Result<(), ServerMockerError> {
+        let message = self.read_framed(framer)?;
+        self.push_received_message(message);
+        Ok(())
+    }
+
+    /// Forward everything the client sends to the testing code as soon as it's read, until it closes the connection
+    fn receive_chunks_until_close(&mut self) -> Result<(), ServerMockerError> {
+        // Data left over by a previous receive instruction
+        if !self.unframed_data.is_empty() {
+            let _ = self.chunk_tx.send(mem::take(&mut self.unframed_data));
+        }
+        let mut buffer = vec![0; self.options.reader_buffer_size];
+        loop {
+            match self.read_stream(&mut buffer, false) {
+                Ok(0) => return Ok(()),
+                Ok(bytes_read) => {
+                    let _ = self.chunk_tx.send(buffer[..bytes_read].to_vec());
+                }
+                Err(e) => return Err(self.read_error(e)),
+            }
+        }
+    }
+
+    /// Remember the received message and forward it to the testing code
+    fn push_received_message(&mut self, message: Vec<u8>) {
+        self.last_received_message = Some(message.clone());
+        self.traffic.push_received_message(message.clone());
+        // The server mocker may have been dropped meanwhile
+        let _ = self
+            .message_tx
+            .send(ReceivedMessage::new(message, Some(self.client_addr)));
+    }
+
+    /// Answer data sent by the client that no receive instruction expected with the default response,
+    /// or report it in strict mode
+    fn handle_unexpected_data(&mut self) {
+        if !self.options.strict && self.default_response.is_none() {
+            return;
+        }
+        let result = match self.read_available(true) {
+            Ok(data) if data.is_empty() => Ok(()),
+            Ok(data) => match self.default_response.clone() {
+                Some(responder) => {
+                    let response =
+                        responder.lock().unwrap_or_else(PoisonError::into_inner)(Some(data));
+                    response.map_or(Ok(()), |response| self.send_packet(&response))
+                }
+                None => Err(UnexpectedData(data)),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            self.report_error(e);
+        }
+    }
+
+    /// Read all the data immediately available from the client, without blocking
+    fn read_available(&mut self, report_disconnection: bool) -> Result<Vec<u8>, ServerMockerError> {
+        self.stream
+            .socket()
+            .set_nonblocking(true)
+            .map_err(UnableToReadTcpStream)?;
+        let mut available_data = Vec::new();
         let mut buffer = vec![0; self.options.reader_buffer_size];
+        let result = loop {
+            match self.read_stream(&mut buffer, report_disconnection) {
+                Ok(0) => break Ok(()),
+                Ok(bytes_read) => available_data.extend_from_slice(&buffer[..bytes_read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(UnableToReadTcpStream(e)),
+            }
+        };
+        self.stream
+            .socket()
+            .set_nonblocking(false)
+            .map_err(UnableToReadTcpStream)?;
+        result.map(|()| available_data)
+    }
 
+    /// Read the next message from the client, split by the framer if any
+    fn read_message(&mut self) -> Result<Vec<u8>, ServerMockerError> {
+        match self.options.framer.clone() {
+            Some(framer) => {
+                self.read_framed(&mut *framer.lock().unwrap_or_else(PoisonError::into_inner))
+            }
+            // Data left over by a previous receive instruction
+            None if !self.unframed_data.is_empty() => Ok(mem::take(&mut self.unframed_data)),
+            None => self.read_packet(),
+        }
+    }
+
+    /// Read at most `max_size` bytes, keeping the remaining data for the next receive instruction
+    fn read_at_most(&mut self, max_size: usize) -> Result<Vec<u8>, ServerMockerError> {
+        if self.unframed_data.is_empty() {
+            self.unframed_data = self.read_packet()?;
+        }
+        let message_size = max_size.min(self.unframed_data.len());
+        Ok(self.unframed_data.drain(..message_size).collect())
+    }
+
+    /// Read from the client until the given framer can split a whole message.
+    ///
+    /// If the client closes the connection or stops sending before the message is complete,
+    /// the incomplete message is reported as [`ServerMockerError::ReadInterrupted`].
+    fn read_framed(&mut self, framer: &mut dyn Framer) -> Result<Vec<u8>, ServerMockerError> {
+        let mut buffer = vec![0; self.options.reader_buffer_size];
         loop {
-            let bytes_read = self
-                .stream
-                .read(&mut buffer)
-                .map_err(UnableToReadTcpStream)?;
-            whole_received_packet.extend_from_slice(&buffer[..bytes_read]);
-            if bytes_read < self.options.reader_buffer_size {
-                break;
+            if let Some(message) = framer.split(&mut self.unframed_data) {
+                return Ok(message);
             }
+            match self.read_stream(&mut buffer, false) {
+                Ok(0) => {
+                    return framer.finish(&mut self.unframed_data).ok_or_else(|| {
+                        ReadInterrupted(ConnectionClosed, mem::take(&mut self.unframed_data))
+                    });
+                }
+                Ok(bytes_read) => self.unframed_data.extend_from_slice(&buffer[..bytes_read]),
+                Err(e) => return Err(self.read_error(e)),
+            }
+        }
+    }
+
+    /// Read whatever the client sent at once: wait for some data, then take everything immediately available
+    fn read_packet(&mut self) -> Result<Vec<u8>, ServerMockerError> {
+        let mut buffer = vec![0; self.options.reader_buffer_size];
+        let bytes_read = match self.read_stream(&mut buffer, false) {
+            Ok(0) => return Err(ReadInterrupted(ConnectionClosed, Vec::new())),
+            Ok(bytes_read) => bytes_read,
+            Err(e) => return Err(self.read_error(e)),
+        };
+        let mut whole_received_packet = buffer[..bytes_read].to_vec();
+        // The message may be bigger than the buffer
+        if bytes_read == buffer.len() {
+            whole_received_packet.extend_from_slice(&self.read_available(false)?);
         }
         Ok(whole_received_packet)
     }
 
+    /// Convert a read error, reporting a timeout with the data received so far
+    fn read_error(&mut self, error: io::Error) -> ServerMockerError {
+        if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                net_timeout = ?self.options.net_timeout,
+                received = self.unframed_data.len(),
+                "timed out waiting for the client"
+            );
+            self.traffic.timed_out(Some(self.client_addr));
+            ReadInterrupted(Timeout, mem::take(&mut self.unframed_data))
+        } else {
+            UnableToReadTcpStream(error)
+        }
+    }
+
+    /// Read everything the client sends during the given duration, or until it closes the connection
+    fn read_for(&mut self, duration: Duration) -> Result<Vec<u8>, ServerMockerError> {
+        let deadline = Instant::now() + duration;
+        let mut received_data = Vec::new();
+        let mut buffer = vec![0; self.options.reader_buffer_size];
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Ok(());
+            }
+            if let Err(e) = self.stream.socket().set_read_timeout(Some(remaining)) {
+                break Err(UnableToSetReadTimeout(e));
+            }
+            // The client closing the connection ends the duration early, without being an error
+            match self.read_stream(&mut buffer, false) {
+                Ok(0) => break Ok(()),
+                Ok(bytes_read) => received_data.extend_from_slice(&buffer[..bytes_read]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    break Ok(())
+                }
+                Err(e) => break Err(UnableToReadTcpStream(e)),
+            }
+        };
+        self.stream
+            .socket()
+            .set_read_timeout(Some(self.options.net_timeout))
+            .map_err(UnableToSetReadTimeout)?;
+        result.map(|()| received_data)
+    }
+
+    /// Count the error and forward it to the testing code
+    fn report_error(&self, error: ServerMockerError) {
+        self.report_instruction_error(error, None);
+    }
+
+    /// Count the error raised while executing the given instruction, and forward it to the testing code
+    fn report_instruction_error(
+        &self,
+        error: ServerMockerError,
+        instruction: Option<(usize, String)>,
+    ) {
+        let client_addr = Some(self.client_addr);
+        let report = self.traffic.error_raised(error, instruction, client_addr);
+        // The server mocker may have been dropped meanwhile
+        let _ = self.error_tx.send(report);
+    }
+
+    /// Send the message in chunks of `chunk_size` bytes, waiting `interval` between each chunk
+    fn send_chunked(
+        &mut self,
+        message: &[u8],
+        chunk_size: usize,
+        interval: Duration,
+    ) -> Result<(), ServerMockerError> {
+        for (index, chunk) in message.chunks(chunk_size.max(1)).enumerate() {
+            if index > 0 {
+                thread::sleep(interval);
+            }
+            self.send_packet(chunk)?;
+        }
+        Ok(())
+    }
+
     fn send_packet(&mut self, packet: &[u8]) -> Result<(), ServerMockerError> {
+        self.traffic.message_sent(packet);
+        if let Some(latency) = self.options.latency {
+            thread::sleep(latency);
+        }
+        if let Some(drip_interval) = self.drip_interval {
+            for (index, byte) in packet.iter().enumerate() {
+                if index > 0 {
+                    thread::sleep(drip_interval);
+                }
+                self.write_paced(slice::from_ref(byte))?;
+            }
+            return Ok(());
+        }
+        let Some(fragment_max_size) = self.options.chaos.fragment_max_size else {
+            return self.write_paced(packet);
+        };
+        let mut remaining = packet;
+        while !remaining.is_empty() {
+            if remaining.len() < packet.len() {
+                thread::sleep(FRAGMENT_INTERVAL);
+            }
+            let fragment_size = 1 + self.rng.below(fragment_max_size.max(1));
+            let (fragment, rest) = remaining.split_at(fragment_size.min(remaining.len()));
+            self.write_paced(fragment)?;
+            remaining = rest;
+        }
+        Ok(())
+    }
+
+    /// Read from the client, logging the received data.
+    ///
+    /// The client closing the connection is reported as [`ServerMockerError::ClientDisconnected`]
+    /// if `report_disconnection` is set, i.e. if no receive instruction reports it otherwise.
+    fn read_stream(&mut self, buffer: &mut [u8], report_disconnection: bool) -> io::Result<usize> {
+        let result = self.stream.read(buffer);
+        match &result {
+            Ok(0) => self.client_disconnected(true, report_disconnection),
+            Ok(bytes_read) => self.traffic.record(
+                Transport::Tcp,
+                Direction::Inbound,
+                self.client_addr,
+                &buffer[..*bytes_read],
+            ),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+                ) =>
+            {
+                self.client_disconnected(false, report_disconnection);
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// Log the client closing the connection the first time it's detected, and report it if asked to
+    fn client_disconnected(&self, graceful: bool, report: bool) {
+        if self.traffic.client_disconnected(self.client_addr, graceful) && report {
+            self.report_error(ClientDisconnected { graceful });
+        }
+    }
+
+    /// Write to the client, logging the sent data
+    fn write_stream(&mut self, data: &[u8]) -> Result<(), ServerMockerError> {
+        // Logged first, so that a client can't observe a response missing from the log
+        self.traffic
+            .record(Transport::Tcp, Direction::Outbound, self.client_addr, data);
         self.stream
-            .write_all(packet)
+            .write_all(data)
+            .and_then(|()| self.stream.flush())
             .map_err(UnableToWriteTcpStream)
     }
+
+    /// Write the data to the client, at the throttled bandwidth if any
+    fn write_paced(&mut self, packet: &[u8]) -> Result<(), ServerMockerError> {
+        let Some(bytes_per_sec) = self.options.throttle_bytes_per_sec else {
+            return self.write_stream(packet);
+        };
+        // About 20 writes per second
+        let chunk_size = usize::try_from(bytes_per_sec / 20)
+            .unwrap_or(usize::MAX)
+            .max(1);
+        let start = Instant::now();
+        let mut bytes_sent = 0;
+        for chunk in packet.chunks(chunk_size) {
+            self.write_stream(chunk)?;
+            bytes_sent += chunk.len();
+            let elapsed = start.elapsed();
+            thread::sleep(transmission_time(bytes_sent, bytes_per_sec).saturating_sub(elapsed));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,72 @@
+//! # `timeline`
+//!
+//! Chronological account of everything the server mocker did, to be printed when a test fails.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Event of the [`ServerMocker::timeline`](crate::ServerMocker::timeline)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimelineEvent {
+    /// A TCP connection was accepted, or a datagram was received from a new UDP client
+    Accepted,
+    /// The given number of bytes was read from the socket
+    Received(usize),
+    /// The given number of bytes was written to the socket
+    Sent(usize),
+    /// The server stopped waiting for data from the client
+    TimedOut,
+    /// The TCP client closed the connection, gracefully or by resetting it, as detected by a read of the server
+    ClientDisconnected {
+        /// Whether the client closed the connection normally rather than resetting it
+        graceful: bool,
+    },
+    /// The server is done with the TCP connection
+    Closed,
+    /// The server raised the given error
+    Error(String),
+}
+
+/// Timestamped event of the [`ServerMocker::timeline`](crate::ServerMocker::timeline).
+///
+/// Displayed as a line of a readable account, e.g. `+0.012s 127.0.0.1:41234 received 18 bytes after 0.002s`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEntry {
+    /// Time elapsed since the server mocker was created
+    pub elapsed: Duration,
+    /// Address of the client concerned by the event, if known
+    pub client_addr: Option<SocketAddr>,
+    /// What happened
+    pub event: TimelineEvent,
+    /// For the first data received or sent after data in the other direction,
+    /// time elapsed since that data: the latency of the client or of the server
+    pub latency: Option<Duration>,
+}
+
+impl fmt::Display for TimelineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{:.3}s ", self.elapsed.as_secs_f64())?;
+        if let Some(client_addr) = self.client_addr {
+            write!(f, "{client_addr} ")?;
+        }
+        match &self.event {
+            TimelineEvent::Accepted => write!(f, "accepted"),
+            TimelineEvent::Received(size) => write!(f, "received {size} bytes"),
+            TimelineEvent::Sent(size) => write!(f, "sent {size} bytes"),
+            TimelineEvent::TimedOut => write!(f, "timed out"),
+            TimelineEvent::ClientDisconnected { graceful: true } => {
+                write!(f, "client closed the connection")
+            }
+            TimelineEvent::ClientDisconnected { graceful: false } => {
+                write!(f, "client reset the connection")
+            }
+            TimelineEvent::Closed => write!(f, "closed"),
+            TimelineEvent::Error(error) => write!(f, "error: {error}"),
+        }?;
+        if let Some(latency) = self.latency {
+            write!(f, " after {:.3}s", latency.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
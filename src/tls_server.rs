@@ -0,0 +1,140 @@
+use std::net::{SocketAddr, TcpStream};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+use crate::hooks::Hooks;
+use crate::instructions::PendingInstructions;
+use crate::server_mocker::MockerOptions;
+use crate::stream::EventSender;
+use crate::tcp_server::ClientStream;
+use crate::traffic::{ReceivedMessage, Traffic};
+use crate::transcript::Transcript;
+use crate::ServerMockerError::{self, UnableToConfigureTls};
+use crate::{ErrorReport, Instruction, TcpMocker, TlsCertificates};
+
+/// Options for the TLS server mocker: a TCP server mocker whose connection is wrapped in TLS.
+///
+/// The instructions send and receive the plain text, the handshake and the encryption being done by the server mocker.
+/// The client trusts [`ServerMocker::ca_cert_pem`](crate::ServerMocker::ca_cert_pem) to connect to
+/// `localhost`, `127.0.0.1` or `::1`.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use socket_server_mocker::{ServerMocker, TcpMocker, TlsMocker};
+///
+/// let options = TlsMocker::new(TcpMocker {
+///     net_timeout: Duration::from_secs(1),
+///     ..TcpMocker::default()
+/// })
+/// .unwrap();
+/// let server = ServerMocker::new_with_opts(options).unwrap();
+/// assert!(server.ca_cert_pem().starts_with("-----BEGIN CERTIFICATE-----"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TlsMocker {
+    /// Options of the underlying TCP server mocker
+    pub tcp: TcpMocker,
+    /// Certificates presented to the client, generated by default
+    pub certificates: TlsCertificates,
+}
+
+impl TlsMocker {
+    /// TCP server mocker with the given options, presenting newly generated certificates
+    pub fn new(tcp: TcpMocker) -> Result<Self, ServerMockerError> {
+        Ok(Self {
+            tcp,
+            certificates: TlsCertificates::generate()?,
+        })
+    }
+
+    /// Configuration of the TLS connections, presenting the certificate of the server
+    fn server_config(&self) -> Result<Arc<ServerConfig>, ServerMockerError> {
+        let error = |e: rustls::Error| UnableToConfigureTls(e.to_string());
+        let config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(error)?
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(self.certificates.cert_der().to_vec())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+                    self.certificates.key_der().to_vec(),
+                )),
+            )
+            .map_err(error)?;
+        Ok(Arc::new(config))
+    }
+}
+
+impl MockerOptions for TlsMocker {
+    fn socket_address(&self) -> SocketAddr {
+        self.tcp.socket_address()
+    }
+
+    fn net_timeout(&self) -> Duration {
+        self.tcp.net_timeout()
+    }
+
+    fn chaos_seed(&self) -> Option<u64> {
+        self.tcp.chaos_seed()
+    }
+
+    fn transcript(&self) -> Option<&Transcript> {
+        MockerOptions::transcript(&self.tcp)
+    }
+
+    fn wire_dump(&self) -> bool {
+        self.tcp.wire_dump()
+    }
+
+    fn capture(&self) -> bool {
+        self.tcp.capture()
+    }
+
+    fn hooks(&self) -> Hooks {
+        MockerOptions::hooks(&self.tcp)
+    }
+
+    fn set_socket_address(&mut self, socket_addr: SocketAddr) {
+        self.tcp.set_socket_address(socket_addr);
+    }
+
+    fn unblock(&self, socket_addr: SocketAddr) {
+        self.tcp.unblock(socket_addr);
+    }
+
+    fn run(
+        self,
+        instruction_rx: Receiver<Vec<Instruction>>,
+        pending_instructions: PendingInstructions,
+        message_tx: EventSender<ReceivedMessage>,
+        chunk_tx: Sender<Vec<u8>>,
+        traffic: Traffic,
+        error_tx: EventSender<ErrorReport>,
+    ) -> Result<(SocketAddr, JoinHandle<()>), ServerMockerError> {
+        let config = self.server_config()?;
+        // The handshake is done by the first read or write of the instructions
+        let wrap_stream = move |stream: TcpStream| {
+            let connection =
+                ServerConnection::new(config).map_err(|e| UnableToConfigureTls(e.to_string()))?;
+            Ok(ClientStream::Tls(Box::new(StreamOwned::new(
+                connection, stream,
+            ))))
+        };
+        self.tcp.serve(
+            wrap_stream,
+            instruction_rx,
+            pending_instructions,
+            message_tx,
+            chunk_tx,
+            traffic,
+            error_tx,
+        )
+    }
+}
@@ -0,0 +1,434 @@
+//! # `traffic`
+//!
+//! Log of the data exchanged between the server mocker and its clients.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::hex::hex_dump;
+use crate::hooks::Hooks;
+use crate::timeline::{TimelineEntry, TimelineEvent};
+use crate::transcript::{Transcript, TranscriptWriter};
+use crate::{ErrorReport, ServerMockerError};
+
+/// Transport protocol of the server mocker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl Transport {
+    /// Name of the protocol, as written in the logs
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Transport::Tcp => "TCP",
+            Transport::Udp => "UDP",
+        }
+    }
+}
+
+/// Direction of the data exchanged with a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the client to the server mocker
+    Inbound,
+    /// Sent by the server mocker to the client
+    Outbound,
+}
+
+impl Direction {
+    /// Direction of the data between the client and the server mocker, as written in the logs
+    pub(crate) fn arrow(self) -> &'static str {
+        match self {
+            Direction::Inbound => "client -> server",
+            Direction::Outbound => "server -> client",
+        }
+    }
+}
+
+/// Data read from or written to the socket at once
+#[derive(Debug, Clone)]
+pub(crate) struct Packet {
+    pub(crate) transport: Transport,
+    pub(crate) direction: Direction,
+    pub(crate) client_addr: SocketAddr,
+    pub(crate) timestamp: SystemTime,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Data read from or written to the socket, streamed by [`ServerMocker::wiretap`](crate::ServerMocker::wiretap)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WireEvent {
+    /// Whether the data was received from or sent to the client
+    pub direction: Direction,
+    /// Address of the client
+    pub client_addr: SocketAddr,
+    /// Time at which the data was read or written
+    pub timestamp: SystemTime,
+    /// Data read or written at once
+    pub data: Vec<u8>,
+}
+
+/// Message received by a receive instruction, with its metadata,
+/// see [`ServerMocker::pop_received_message_with_meta`](crate::ServerMocker::pop_received_message_with_meta)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedMessage {
+    /// Content of the message
+    pub data: Vec<u8>,
+    /// Address of the client which sent the message, `None` if nothing was received from any UDP client
+    pub client_addr: Option<SocketAddr>,
+    /// Time at which the message was completely received
+    pub received_at: Instant,
+}
+
+impl ReceivedMessage {
+    /// Message completely received just now
+    pub(crate) fn new(data: Vec<u8>, client_addr: Option<SocketAddr>) -> Self {
+        Self {
+            data,
+            client_addr,
+            received_at: Instant::now(),
+        }
+    }
+}
+
+/// Statistics on the traffic handled by a server mocker, see [`ServerMocker::stats`](crate::ServerMocker::stats)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Bytes received from the clients, whether a receive instruction expected them or not
+    pub bytes_received: usize,
+    /// Bytes sent to the clients
+    pub bytes_sent: usize,
+    /// Messages received by the receive instructions
+    pub messages_received: usize,
+    /// Messages sent to the clients: one per send instruction or chunk, or per datagram for UDP
+    pub messages_sent: usize,
+    /// TCP connections accepted, or distinct UDP clients
+    pub connections_accepted: usize,
+    /// Instructions executed, including the ones nested in other instructions
+    pub instructions_executed: usize,
+    /// Errors raised by the server thread
+    pub errors_raised: usize,
+    /// Longest time between data sent to a client and the next data received, if any.
+    /// An unexpected extra round trip or a Nagle's algorithm delay shows up here.
+    pub max_client_latency: Option<Duration>,
+    /// Longest time between data received from a client and the next data sent, if any
+    pub max_server_latency: Option<Duration>,
+}
+
+/// Data exchanged with the clients, shared between the [`ServerMocker`](crate::ServerMocker) and its server thread.
+#[derive(Debug, Clone)]
+pub struct Traffic {
+    state: Arc<Mutex<TrafficState>>,
+    /// Origin of the timeline
+    start: Instant,
+    /// Callbacks invoked on the events of the exchange, outside of the lock
+    hooks: Hooks,
+    /// Seed of the random faults injected by the server, if any, written in the error reports
+    chaos_seed: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct TrafficState {
+    /// Messages received by the receive instructions, in order
+    received_messages: Vec<Vec<u8>>,
+    /// Raw packets exchanged in both directions, in order, if `capture`
+    packets: Vec<Packet>,
+    /// Keep the packets, to export them as a pcap capture
+    capture: bool,
+    /// Files on which the packets are teed, if any
+    transcript: Option<TranscriptWriter>,
+    /// Log every packet as a hex dump
+    wire_dump: bool,
+    stats: ServerStats,
+    /// Clients from which a datagram has been received
+    udp_clients: HashSet<SocketAddr>,
+    /// Subscribers to every packet, see [`Traffic::wiretap`]
+    wiretaps: Vec<Sender<WireEvent>>,
+    timeline: Vec<TimelineEntry>,
+    /// Direction and time of the last packet, to measure the latencies
+    last_packet: Option<(Direction, Instant)>,
+    /// First fatal error raised by the server thread, which stopped it
+    fatal_error: Option<String>,
+    /// Whether the TCP client closed the connection gracefully, once it's detected
+    client_disconnection: Option<bool>,
+}
+
+impl Traffic {
+    /// Log the traffic, teeing the packets to the given transcript if any, and to the `log` crate if `wire_dump`,
+    /// keeping them if `capture`, and invoke the given hooks
+    pub(crate) fn new(
+        transcript: Option<Transcript>,
+        wire_dump: bool,
+        capture: bool,
+        hooks: Hooks,
+        chaos_seed: Option<u64>,
+    ) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(TrafficState {
+                transcript: transcript.map(TranscriptWriter::new),
+                wire_dump,
+                capture,
+                ..TrafficState::default()
+            })),
+            start: Instant::now(),
+            hooks,
+            chaos_seed,
+        }
+    }
+
+    /// Log a message received by a receive instruction
+    pub(crate) fn push_received_message(&self, message: Vec<u8>) {
+        self.hooks.received(&message);
+        let mut state = self.state();
+        state.stats.messages_received += 1;
+        state.received_messages.push(message);
+    }
+
+    /// Log a TCP connection accepted
+    pub(crate) fn connection_accepted(&self, client_addr: SocketAddr) {
+        {
+            let mut state = self.state();
+            state.stats.connections_accepted += 1;
+            self.add_to_timeline(&mut state, Some(client_addr), TimelineEvent::Accepted);
+        }
+        self.hooks.connected(client_addr);
+    }
+
+    /// Log the end of a TCP connection
+    pub(crate) fn connection_closed(&self, client_addr: SocketAddr) {
+        self.add_to_timeline(&mut self.state(), Some(client_addr), TimelineEvent::Closed);
+        self.hooks.disconnected(client_addr);
+    }
+
+    /// Log that the TCP client closed the connection, returning whether it's the first time it's detected
+    pub(crate) fn client_disconnected(&self, client_addr: SocketAddr, graceful: bool) -> bool {
+        let mut state = self.state();
+        if state.client_disconnection.is_some() {
+            return false;
+        }
+        state.client_disconnection = Some(graceful);
+        self.add_to_timeline(
+            &mut state,
+            Some(client_addr),
+            TimelineEvent::ClientDisconnected { graceful },
+        );
+        true
+    }
+
+    /// Log that the server stopped waiting for data from the client
+    pub(crate) fn timed_out(&self, client_addr: Option<SocketAddr>) {
+        self.add_to_timeline(&mut self.state(), client_addr, TimelineEvent::TimedOut);
+    }
+
+    /// Log a message about to be sent to a client
+    pub(crate) fn message_sent(&self, message: &[u8]) {
+        self.state().stats.messages_sent += 1;
+        self.hooks.sent(message);
+    }
+
+    /// Count an instruction executed
+    pub(crate) fn count_instruction(&self) {
+        self.state().stats.instructions_executed += 1;
+    }
+
+    /// Log an error raised by the server thread while executing the given instruction if any,
+    /// and report it with its context to be forwarded to the testing code
+    pub(crate) fn error_raised(
+        &self,
+        error: ServerMockerError,
+        instruction: Option<(usize, String)>,
+        client_addr: Option<SocketAddr>,
+    ) -> ErrorReport {
+        let stats = {
+            let mut state = self.state();
+            state.stats.errors_raised += 1;
+            if error.is_fatal() && state.fatal_error.is_none() {
+                state.fatal_error = Some(error.to_string());
+            }
+            self.add_to_timeline(
+                &mut state,
+                client_addr,
+                TimelineEvent::Error(error.to_string()),
+            );
+            state.stats
+        };
+        self.hooks.raised(&error);
+        let (instruction_index, instruction) = instruction.unzip();
+        ErrorReport {
+            error,
+            instruction_index,
+            instruction,
+            client_addr,
+            chaos_seed: self.chaos_seed,
+            bytes_received: stats.bytes_received,
+            bytes_sent: stats.bytes_sent,
+        }
+    }
+
+    /// Log data read from or written to the socket
+    pub(crate) fn record(
+        &self,
+        transport: Transport,
+        direction: Direction,
+        client_addr: SocketAddr,
+        data: &[u8],
+    ) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            ?transport,
+            ?direction,
+            %client_addr,
+            size = data.len(),
+            data = %data.escape_ascii(),
+            "data exchanged"
+        );
+        let packet = Packet {
+            transport,
+            direction,
+            client_addr,
+            timestamp: SystemTime::now(),
+            data: data.to_vec(),
+        };
+        let mut state = self.state();
+        let new_client =
+            self.count_packet(&mut state, transport, direction, client_addr, data.len());
+        if state.wire_dump {
+            log::debug!(
+                "{} {} {}, {} bytes\n{}",
+                transport.name(),
+                client_addr,
+                direction.arrow(),
+                data.len(),
+                hex_dump(data).trim_end()
+            );
+        }
+        if let Some(transcript) = &mut state.transcript {
+            transcript.write(&packet);
+        }
+        if !state.wiretaps.is_empty() {
+            let event = WireEvent {
+                direction,
+                client_addr,
+                timestamp: packet.timestamp,
+                data: packet.data.clone(),
+            };
+            // Forget the subscribers which dropped their receiver
+            state
+                .wiretaps
+                .retain(|wiretap| wiretap.send(event.clone()).is_ok());
+        }
+        if state.capture {
+            state.packets.push(packet);
+        }
+        drop(state);
+        if new_client {
+            self.hooks.connected(client_addr);
+        }
+    }
+
+    /// Messages received by the receive instructions so far
+    pub(crate) fn received_messages(&self) -> Vec<Vec<u8>> {
+        self.state().received_messages.clone()
+    }
+
+    /// Raw packets exchanged so far
+    pub(crate) fn packets(&self) -> Vec<Packet> {
+        self.state().packets.clone()
+    }
+
+    /// First fatal error raised by the server thread, if it stopped because of one
+    pub(crate) fn fatal_error(&self) -> Option<String> {
+        self.state().fatal_error.clone()
+    }
+
+    /// Whether the TCP client closed the connection gracefully, if it was detected
+    pub(crate) fn client_disconnection(&self) -> Option<bool> {
+        self.state().client_disconnection
+    }
+
+    /// Statistics on the traffic so far
+    pub(crate) fn stats(&self) -> ServerStats {
+        self.state().stats
+    }
+
+    /// Everything that happened so far, in chronological order
+    pub(crate) fn timeline(&self) -> Vec<TimelineEntry> {
+        self.state().timeline.clone()
+    }
+
+    /// Subscribe to every packet exchanged from now on
+    pub(crate) fn wiretap(&self) -> Receiver<WireEvent> {
+        let (wiretap_tx, wiretap_rx) = mpsc::channel();
+        self.state().wiretaps.push(wiretap_tx);
+        wiretap_rx
+    }
+
+    /// Update the statistics and the timeline with a packet, returning whether it comes from a new UDP client
+    fn count_packet(
+        &self,
+        state: &mut TrafficState,
+        transport: Transport,
+        direction: Direction,
+        client_addr: SocketAddr,
+        size: usize,
+    ) -> bool {
+        let now = Instant::now();
+        // Only the first packet after a change of direction answers the previous ones
+        let latency = match state.last_packet {
+            Some((last_direction, last_time)) if last_direction != direction => {
+                Some(now - last_time)
+            }
+            _ => None,
+        };
+        state.last_packet = Some((direction, now));
+        let mut new_client = false;
+        let event = match direction {
+            Direction::Inbound => {
+                state.stats.bytes_received += size;
+                state.stats.max_client_latency = state.stats.max_client_latency.max(latency);
+                if transport == Transport::Udp && state.udp_clients.insert(client_addr) {
+                    state.stats.connections_accepted += 1;
+                    self.add_to_timeline(state, Some(client_addr), TimelineEvent::Accepted);
+                    new_client = true;
+                }
+                TimelineEvent::Received(size)
+            }
+            Direction::Outbound => {
+                state.stats.bytes_sent += size;
+                state.stats.max_server_latency = state.stats.max_server_latency.max(latency);
+                TimelineEvent::Sent(size)
+            }
+        };
+        state.timeline.push(TimelineEntry {
+            elapsed: now - self.start,
+            client_addr: Some(client_addr),
+            event,
+            latency,
+        });
+        new_client
+    }
+
+    fn add_to_timeline(
+        &self,
+        state: &mut TrafficState,
+        client_addr: Option<SocketAddr>,
+        event: TimelineEvent,
+    ) {
+        state.timeline.push(TimelineEntry {
+            elapsed: self.start.elapsed(),
+            client_addr,
+            event,
+            latency: None,
+        });
+    }
+
+    fn state(&self) -> MutexGuard<'_, TrafficState> {
+        // A hook may have panicked in the server thread, the state is still consistent
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,146 @@
+//! # `transcript`
+//!
+//! Raw transcripts of the data exchanged with each client, written to files as the exchange goes.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use crate::hex::hex_dump;
+use crate::traffic::{Direction, Packet, Transport};
+
+/// Format of the transcript files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    /// Sequence of records: a direction byte (`0` received from the client, `1` sent to the client),
+    /// the timestamp in microseconds since the Unix epoch as a big endian `u64`,
+    /// the data length as a big endian `u32`, then the data
+    Binary,
+    /// Text file with a header line per read or write, followed by an `xxd`-style hex dump of the data
+    AnnotatedHex,
+}
+
+/// Transcript of every byte sent and received by the server mocker, set in
+/// [`TcpMocker::transcript`](crate::TcpMocker::transcript) or [`UdpMocker::transcript`](crate::UdpMocker::transcript).
+///
+/// One file is written per connection in `directory`, named after the transport and the client address,
+/// such as `tcp-127.0.0.1_54321.txt`. The files are written as the exchange goes, independently of the
+/// assertions, so that they are left behind as an artifact when a test fails.
+///
+/// # Example
+/// ```
+/// use socket_server_mocker::{ServerMocker, TcpMocker, TranscriptFormat};
+///
+/// let directory = std::env::temp_dir().join("transcripts");
+/// let server = ServerMocker::new_with_opts(
+///     TcpMocker::default().transcript(&directory, TranscriptFormat::AnnotatedHex),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript {
+    /// Directory of the transcript files, created if it doesn't exist
+    pub directory: PathBuf,
+    /// Format of the transcript files
+    pub format: TranscriptFormat,
+}
+
+/// Transcript files being written, one per connection
+#[derive(Debug)]
+pub(crate) struct TranscriptWriter {
+    transcript: Transcript,
+    files: HashMap<(Transport, SocketAddr), File>,
+}
+
+impl TranscriptWriter {
+    pub(crate) fn new(transcript: Transcript) -> Self {
+        Self {
+            transcript,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Append the packet to the transcript of its connection.
+    ///
+    /// Transcripts are a best effort debugging aid: failing to write them doesn't fail the exchange.
+    pub(crate) fn write(&mut self, packet: &Packet) {
+        let file = match self.files.entry((packet.transport, packet.client_addr)) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = transcript_path(&self.transcript, packet.transport, packet.client_addr);
+                let Ok(file) = OpenOptions::new().create(true).append(true).open(path) else {
+                    return;
+                };
+                entry.insert(file)
+            }
+        };
+        let record = match self.transcript.format {
+            TranscriptFormat::Binary => binary_record(packet),
+            TranscriptFormat::AnnotatedHex => annotated_record(packet).into_bytes(),
+        };
+        // A single write per record, so that a record is never split by a crash
+        let _ = file.write_all(&record);
+    }
+}
+
+/// Path of the transcript file of the given connection
+fn transcript_path(
+    transcript: &Transcript,
+    transport: Transport,
+    client_addr: SocketAddr,
+) -> PathBuf {
+    let transport = match transport {
+        Transport::Tcp => "tcp",
+        Transport::Udp => "udp",
+    };
+    let extension = match transcript.format {
+        TranscriptFormat::Binary => "bin",
+        TranscriptFormat::AnnotatedHex => "txt",
+    };
+    // Colons and brackets of the address aren't allowed in file names on every platform
+    let client = client_addr.to_string().replace([':', '[', ']'], "_");
+    transcript
+        .directory
+        .join(format!("{transport}-{client}.{extension}"))
+}
+
+fn binary_record(packet: &Packet) -> Vec<u8> {
+    let timestamp = packet
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros();
+    let length = u32::try_from(packet.data.len()).unwrap_or(u32::MAX);
+    let mut record = Vec::with_capacity(13 + packet.data.len());
+    record.push(match packet.direction {
+        Direction::Inbound => 0,
+        Direction::Outbound => 1,
+    });
+    record.extend_from_slice(&u64::try_from(timestamp).unwrap_or(u64::MAX).to_be_bytes());
+    record.extend_from_slice(&length.to_be_bytes());
+    record.extend_from_slice(&packet.data);
+    record
+}
+
+fn annotated_record(packet: &Packet) -> String {
+    let timestamp = packet
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let direction = packet.direction.arrow();
+    let mut record = String::new();
+    let _ = writeln!(
+        record,
+        "# {}.{:06} {direction} {} bytes",
+        timestamp.as_secs(),
+        timestamp.subsec_micros(),
+        packet.data.len()
+    );
+    record.push_str(&hex_dump(&packet.data));
+    record
+}
@@ -1,41 +1,180 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::mem;
 use std::net::{SocketAddr, UdpSocket};
-use std::sync::mpsc::{Receiver, Sender};
-use std::thread;
-use std::time::Duration;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::slice;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::server_mocker::MockerOptions;
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::chaos::ChaosConfig;
+use crate::framing::{DelimiterFramer, ExactBytesFramer, Framer, Framing, UntilCloseFramer};
+use crate::hooks::Hooks;
+use crate::instructions::{
+    constant_responder, expand_template, Finish, IdlePolicy, MessageResponder, PeerRoute,
+    PendingInstructions, Times,
+};
+use crate::rng::Rng;
+use crate::server_mocker::{
+    other_loopback, spawn_server_thread, transmission_time, MockerOptions, IDLE_POLL_INTERVAL,
+};
+use crate::stream::EventSender;
+use crate::traffic::{Direction, ReceivedMessage, Traffic, Transport};
+use crate::transcript::{Transcript, TranscriptFormat};
+use crate::ErrorReport;
 use crate::Instruction::{
-    self, ReceiveMessageWithMaxSize, SendMessage, SendMessageDependingOnLastReceivedMessage,
+    self, Branch, ExpectNoMessage, Pause, ReceiveExactBytes, ReceiveFor, ReceiveMessageWithMaxSize,
+    ReceiveMessages, ReceiveUntilDelimiter, Repeat, RouteByPeer, SendFile, SendFileChunked,
+    SendMessage, SendMessageChunked, SendMessageDependingOnLastReceivedMessage,
+    SendMessageFromClosure, SendMessageTo, SendPartialThenClose, SendTemplate, SetDefaultResponse,
+    Silence, SlowDrip,
 };
 use crate::ServerMockerError::{
     self, FailedToSendUdpMessage, GotSendMessageBeforeReceiveMessage, UnableToBindListener,
-    UnableToGetLocalAddress, UnableToReadUdpStream, UnableToSetReadTimeout,
+    UnableToGetLocalAddress, UnableToReadFile, UnableToReadUdpStream, UnableToSetReadTimeout,
+    UnexpectedData, UnexpectedRepeatCount,
 };
 
 /// Options for the UDP server mocker
 #[derive(Debug, Clone)]
+// Independent options rather than a state machine
+#[allow(clippy::struct_excessive_bools)]
 pub struct UdpMocker {
     /// Socket address on which the server will listen. Will be set to `127.0.0.1:0` by default.
     pub socket_addr: SocketAddr,
+    /// Also listen on the loopback of the other IP family, on the same port, e.g. on `[::1]` as well as `127.0.0.1`,
+    /// so that the client sends to whichever family its resolver prefers. Datagrams received on either are handled alike.
+    pub dual_stack: bool,
     /// Timeout for the server to wait for a message from the client.
     pub net_timeout: Duration,
     /// Timeout if no more instruction is available and [`Instruction::StopExchange`] hasn't been sent
     pub rx_timeout: Duration,
+    /// What to do once `rx_timeout` elapsed without new instructions
+    pub idle_policy: IdlePolicy,
+    /// What to do once all the queued instructions are executed
+    pub finish_behavior: Finish,
+    /// Strict mode: data received from the client outside of a receive instruction
+    /// is reported as [`ServerMockerError::UnexpectedData`] instead of being silently ignored
+    pub strict: bool,
+    /// Message sent back to the client whenever data is received outside of a receive instruction,
+    /// including while the server is waiting for new instructions
+    pub default_response: Option<Vec<u8>>,
+    /// Split the data received from the client into messages, instead of delivering whatever was read at once
+    pub framer: Option<Arc<Mutex<dyn Framer>>>,
+    /// Delay applied before every write to the client, to simulate a slow server
+    pub latency: Option<Duration>,
+    /// Maximum outbound bandwidth, in bytes per second: each datagram is delayed by its transmission time on a slow link
+    pub throttle_bytes_per_sec: Option<u64>,
+    /// Random faults (drop, duplication, reordering, corruption, delay) applied to the datagrams
+    pub chaos: ChaosConfig,
+    /// Tee every byte sent and received into a transcript file per client, see [`Transcript`]
+    pub transcript: Option<Transcript>,
+    /// Log every message sent and received as an `xxd`-style hex dump, at the `debug` level of the `log` crate,
+    /// to debug binary protocols
+    pub wire_dump: bool,
+    /// Keep every packet sent and received in memory, to be exported with
+    /// [`ServerMocker::export_pcap`](crate::ServerMocker::export_pcap)
+    pub capture: bool,
+    /// Callbacks invoked by the server thread on the events of the exchange, see [`Hooks`]
+    pub hooks: Hooks,
     /// Maximum size of a UDP packet in bytes, specified in RFC 768
     pub max_packet_size: usize,
+    /// Time to live, or hop limit for IPv6, of the datagrams sent to the clients, the system default if `None`
+    pub ttl: Option<u32>,
+    /// Type of service byte (DSCP and ECN) of the datagrams sent to the clients, the system default if `None`.
+    /// Only applied to IPv4 sockets.
+    pub tos: Option<u8>,
+    /// Send the datagrams from a secondary socket bound to another port of the same address, instead of the
+    /// one the client sent to, to check that the client rejects responses from an unexpected source (DNS spoofing)
+    pub reply_from_other_port: bool,
 }
 
 impl Default for UdpMocker {
     fn default() -> Self {
         Self {
             socket_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            dual_stack: false,
             net_timeout: Duration::from_millis(100),
             rx_timeout: Duration::from_millis(100),
+            idle_policy: IdlePolicy::default(),
+            finish_behavior: Finish::default(),
+            strict: false,
+            default_response: None,
+            framer: None,
+            latency: None,
+            throttle_bytes_per_sec: None,
+            chaos: ChaosConfig::default(),
+            transcript: None,
+            wire_dump: false,
+            capture: false,
+            hooks: Hooks::default(),
             max_packet_size: 65507,
+            ttl: None,
+            tos: None,
+            reply_from_other_port: false,
         }
     }
 }
 
+impl UdpMocker {
+    /// Split the data received from the client with the given built-in framing,
+    /// see [`UdpMocker::framer`] for custom framings
+    #[must_use]
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framer = Some(framing.framer());
+        self
+    }
+
+    /// Silently drop the given fraction of the datagrams sent to the client,
+    /// to test client retransmissions. See [`ChaosConfig::drop_inbound`] to drop received datagrams as well.
+    #[must_use]
+    pub fn drop_probability(mut self, probability: f32) -> Self {
+        self.chaos.drop_probability = probability;
+        self
+    }
+
+    /// Hold back the given fraction of the datagrams sent to the client until `window` next datagrams are sent,
+    /// so that they are received out of order
+    #[must_use]
+    pub fn reorder(mut self, probability: f32, window: usize) -> Self {
+        self.chaos.reorder_probability = probability;
+        self.chaos.reorder_window = window;
+        self
+    }
+
+    /// Inject the given random faults, see [`ChaosConfig`]
+    #[must_use]
+    pub fn chaos(mut self, chaos: ChaosConfig) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Invoke the given callbacks on the events of the exchange, see [`Hooks`]
+    #[must_use]
+    pub fn hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Tee every byte sent and received into a transcript file per client in `directory`, see [`Transcript`]
+    #[must_use]
+    pub fn transcript(mut self, directory: impl Into<PathBuf>, format: TranscriptFormat) -> Self {
+        self.transcript = Some(Transcript {
+            directory: directory.into(),
+            format,
+        });
+        self
+    }
+}
+
 impl MockerOptions for UdpMocker {
     fn socket_address(&self) -> SocketAddr {
         self.socket_addr
@@ -45,148 +184,1021 @@ impl MockerOptions for UdpMocker {
         self.net_timeout
     }
 
+    fn chaos_seed(&self) -> Option<u64> {
+        self.chaos.replay_seed()
+    }
+
+    fn transcript(&self) -> Option<&Transcript> {
+        self.transcript.as_ref()
+    }
+
+    fn wire_dump(&self) -> bool {
+        self.wire_dump
+    }
+
+    fn capture(&self) -> bool {
+        self.capture
+    }
+
+    fn hooks(&self) -> Hooks {
+        self.hooks.clone()
+    }
+
+    fn set_socket_address(&mut self, socket_addr: SocketAddr) {
+        self.socket_addr = socket_addr;
+    }
+
     fn run(
         self,
         instruction_rx: Receiver<Vec<Instruction>>,
-        message_tx: Sender<Vec<u8>>,
-        error_tx: Sender<ServerMockerError>,
-    ) -> Result<SocketAddr, ServerMockerError> {
-        let connection = UdpSocket::bind(self.socket_addr)
+        pending_instructions: PendingInstructions,
+        message_tx: EventSender<ReceivedMessage>,
+        chunk_tx: Sender<Vec<u8>>,
+        traffic: Traffic,
+        error_tx: EventSender<ErrorReport>,
+    ) -> Result<(SocketAddr, JoinHandle<()>), ServerMockerError> {
+        let connection = bind_socket(&self, self.socket_addr)
             .map_err(|e| UnableToBindListener(self.socket_addr, e))?;
         let socket_addr = connection.local_addr().map_err(UnableToGetLocalAddress)?;
-
-        thread::spawn(move || {
-            UdpServerImpl {
-                options: self,
-                connection,
-                instruction_rx,
-                message_tx,
-                error_tx,
+        let mut connection = if self.dual_stack {
+            let dual_addr = other_loopback(socket_addr);
+            let dual_socket =
+                bind_socket(&self, dual_addr).map_err(|e| UnableToBindListener(dual_addr, e))?;
+            UdpConnection::dual(connection, dual_socket)
+                .map_err(|e| UnableToBindListener(dual_addr, e))?
+        } else {
+            UdpConnection::single(connection)
+        };
+        if self.reply_from_other_port {
+            let mut listen_addrs = vec![socket_addr];
+            if self.dual_stack {
+                listen_addrs.push(other_loopback(socket_addr));
             }
-            .run();
-        });
+            for listen_addr in listen_addrs {
+                // Any other free port of the same address
+                let reply_addr = SocketAddr::new(listen_addr.ip(), 0);
+                let reply_socket = bind_socket(&self, reply_addr)
+                    .map_err(|e| UnableToBindListener(reply_addr, e))?;
+                connection.reply_sockets.push(reply_socket);
+            }
+        }
+
+        let rng = self.chaos.rng();
+        let default_response = self.default_response.clone().map(constant_responder);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%socket_addr, "UDP server mocker listening");
+        let server_thread = spawn_server_thread(
+            Transport::Udp,
+            socket_addr,
+            traffic.clone(),
+            error_tx.clone(),
+            move || {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::info_span!("udp_server", %socket_addr).entered();
+                UdpServerImpl {
+                    options: self,
+                    connection,
+                    instruction_rx,
+                    pending_instructions,
+                    message_tx,
+                    chunk_tx,
+                    traffic,
+                    error_tx,
+                    last_received_messages: HashMap::new(),
+                    last_sender_addr: None,
+                    unframed_data: Vec::new(),
+                    routed_peer: None,
+                    rng,
+                    default_response,
+                    held_back_datagrams: RefCell::new(Vec::new()),
+                    drip_interval: None,
+                    script_started: false,
+                }
+                .run();
+            },
+        )?;
 
-        Ok(socket_addr)
+        Ok((socket_addr, server_thread))
     }
 }
 
-/// TCP server mocker thread implementation
+/// UDP server mocker thread implementation
 struct UdpServerImpl {
     options: UdpMocker,
-    connection: UdpSocket,
+    connection: UdpConnection,
     instruction_rx: Receiver<Vec<Instruction>>,
-    message_tx: Sender<Vec<u8>>,
-    error_tx: Sender<ServerMockerError>,
+    pending_instructions: PendingInstructions,
+    message_tx: EventSender<ReceivedMessage>,
+    chunk_tx: Sender<Vec<u8>>,
+    traffic: Traffic,
+    error_tx: EventSender<ErrorReport>,
+    /// Last message received from each client, so that each one is answered according to its own request
+    last_received_messages: HashMap<SocketAddr, Vec<u8>>,
+    /// Client which sent the last received message, answered unless a route serves another one
+    last_sender_addr: Option<SocketAddr>,
+    /// Data received from each client but not split into a message by the framer yet, oldest client first,
+    /// so that interleaved messages of several clients are neither mixed nor lost
+    unframed_data: Vec<(SocketAddr, Vec<u8>)>,
+    /// Peer served by the route being executed, see [`Instruction::RouteByPeer`]:
+    /// datagrams of other peers are kept in `unframed_data` for later
+    routed_peer: Option<SocketAddr>,
+    /// Random generator taking the chaos decisions
+    rng: Rng,
+    /// Answer to the datagrams received outside of a receive instruction, see [`Instruction::SetDefaultResponse`]
+    default_response: Option<MessageResponder>,
+    /// Datagrams held back to be reordered, with the number of datagrams to send before them
+    held_back_datagrams: RefCell<Vec<(usize, SocketAddr, Vec<u8>)>>,
+    /// Delay between two bytes sent to the client, while executing [`Instruction::SlowDrip`]
+    drip_interval: Option<Duration>,
+    /// Whether instructions have been received, so that the script can be finished once they are executed
+    script_started: bool,
 }
 
 /// Specific implementation methods and constants for UDP server mocker
 impl UdpServerImpl {
-    fn run(&self) {
+    fn run(mut self) {
         let timeout = Some(self.options.net_timeout);
         if let Err(e) = self.connection.set_read_timeout(timeout) {
-            self.error_tx.send(UnableToSetReadTimeout(e)).unwrap();
+            self.report_error(UnableToSetReadTimeout(e));
             return;
         }
 
-        // Last message received with the address of the client, used to send the response
-        let mut last_received_packed_with_addr: Option<(SocketAddr, Vec<u8>)> = None;
-
         // Timeout: if no more instruction is available and StopExchange hasn't been sent
         // Stop server if no more instruction is available and StopExchange hasn't been sent
-        while let Ok(instructions) = self.instruction_rx.recv_timeout(self.options.rx_timeout) {
-            for instruction in instructions {
-                match instruction {
-                    SendMessage(binary_message) => {
-                        if let Err(e) = self.send_packet_to_last_client(
-                            &binary_message,
-                            &last_received_packed_with_addr,
-                        ) {
-                            self.error_tx.send(e).unwrap();
-                        }
+        let stopped = 'exchange: {
+            while let Some(mut instructions) = self.next_instructions() {
+                for instruction in &mut instructions {
+                    if self.pending_instructions.is_aborted() {
+                        return;
                     }
-                    SendMessageDependingOnLastReceivedMessage(sent_message_calculator) => {
-                        // Pass None if no message has been received yet
-                        let message_to_send =
-                            sent_message_calculator(match last_received_packed_with_addr {
-                                Some((_, ref message)) => Some(message.clone()),
-                                None => None,
-                            });
-                        if let Some(message_to_send) = message_to_send {
-                            if let Err(e) = self.send_packet_to_last_client(
-                                &message_to_send,
-                                &last_received_packed_with_addr,
-                            ) {
-                                self.error_tx.send(e).unwrap();
-                            }
+                    let executing = self.pending_instructions.executing();
+                    let result = self.execute(instruction);
+                    self.pending_instructions.pop_executed();
+                    match result {
+                        Ok(ControlFlow::Continue(())) => {}
+                        Ok(ControlFlow::Break(())) => break 'exchange true,
+                        Err(e) => {
+                            #[cfg(feature = "tracing")]
+                            tracing::warn!(error = %e, "instruction failed");
+                            self.report_instruction_error(e, executing);
                         }
                     }
-                    Instruction::ReceiveMessage => {
-                        let received_packet_with_addr =
-                            match self.receive_packet(self.options.max_packet_size) {
-                                Ok(received) => received,
-                                Err(e) => {
-                                    self.error_tx.send(e).unwrap();
-                                    continue;
-                                }
-                            };
-
-                        last_received_packed_with_addr = Some((
-                            received_packet_with_addr.0,
-                            received_packet_with_addr.1.clone(),
-                        ));
-                        self.message_tx.send(received_packet_with_addr.1).unwrap();
-                    }
-                    ReceiveMessageWithMaxSize(max_message_size) => {
-                        match self.receive_packet(max_message_size) {
-                            Ok(received) => {
-                                last_received_packed_with_addr =
-                                    Some((received.0, received.1.clone()));
-                                self.message_tx.send(received.1).unwrap();
-                            }
-                            Err(e) => self.error_tx.send(e).unwrap(),
-                        };
-                    }
-                    Instruction::StopExchange => {
-                        return;
+                }
+            }
+            #[cfg(feature = "tracing")]
+            tracing::debug!(rx_timeout = ?self.options.rx_timeout, "no more instructions");
+            false
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!("exchange stopped");
+        // Held back datagrams are never lost
+        if let Err(e) = self.release_held_back_datagrams(true) {
+            self.report_error(e);
+        }
+        if !stopped {
+            self.handle_unexpected_data();
+        }
+    }
+
+    /// Wait for the next instructions, answering unexpected client data meanwhile
+    fn next_instructions(&mut self) -> Option<Vec<Instruction>> {
+        if self.options.finish_behavior == Finish::CloseGracefully && self.script_started {
+            // Everything queued so far has been executed, unless more instructions are being sent
+            if self.pending_instructions.finish() {
+                return None;
+            }
+            return self.instruction_rx.recv().ok();
+        }
+        let instructions = self.wait_for_instructions();
+        self.script_started = instructions.is_some();
+        instructions
+    }
+
+    /// Wait for the next instructions according to the idle policy
+    fn wait_for_instructions(&mut self) -> Option<Vec<Instruction>> {
+        let keep_waiting = self.options.idle_policy == IdlePolicy::KeepConnectionOpen;
+        if self.default_response.is_none() {
+            loop {
+                match self.instruction_rx.recv_timeout(self.options.rx_timeout) {
+                    Err(RecvTimeoutError::Timeout) if keep_waiting => {}
+                    result => return result.ok(),
+                }
+            }
+        }
+        let mut deadline = Instant::now() + self.options.rx_timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self
+                .instruction_rx
+                .recv_timeout(remaining.min(IDLE_POLL_INTERVAL))
+            {
+                Ok(instructions) => return Some(instructions),
+                Err(RecvTimeoutError::Disconnected) => return None,
+                Err(RecvTimeoutError::Timeout) if remaining.is_zero() => {
+                    if !keep_waiting {
+                        return None;
                     }
+                    deadline = Instant::now() + self.options.rx_timeout;
                 }
+                Err(RecvTimeoutError::Timeout) => self.handle_unexpected_data(),
             }
         }
     }
 
-    fn receive_packet(
+    /// Execute a single instruction, returning [`ControlFlow::Break`] if the exchange must stop
+    fn execute(
+        &mut self,
+        instruction: &mut Instruction,
+    ) -> Result<ControlFlow<()>, ServerMockerError> {
+        #[cfg(feature = "tracing")]
+        let _span = instruction.enter_span();
+        self.traffic.count_instruction();
+        if instruction.is_receive() {
+            // The client may wait for the held back datagrams before sending anything
+            self.release_held_back_datagrams(true)?;
+        } else {
+            self.handle_unexpected_data();
+        }
+        match instruction {
+            SendMessage(binary_message) => self.send_packet_to_last_client(binary_message)?,
+            SendMessageTo(client_addr, binary_message) => {
+                self.send_packet_to_client(binary_message, *client_addr)?;
+            }
+            SendMessageDependingOnLastReceivedMessage(sent_message_calculator) => {
+                // Pass None if no message has been received yet
+                let message_to_send =
+                    sent_message_calculator(self.last_received_message().map(<[u8]>::to_vec));
+                if let Some(message_to_send) = message_to_send {
+                    self.send_packet_to_last_client(&message_to_send)?;
+                }
+            }
+            SendMessageFromClosure(sent_message_calculator) => {
+                let mut sent_message_calculator = sent_message_calculator
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let message_to_send =
+                    sent_message_calculator(self.last_received_message().map(<[u8]>::to_vec));
+                if let Some(message_to_send) = message_to_send {
+                    self.send_packet_to_last_client(&message_to_send)?;
+                }
+            }
+            SendTemplate { pattern, template } => {
+                let message = expand_template(pattern, template, self.last_received_message())?;
+                self.send_packet_to_last_client(&message)?;
+            }
+            SendMessageChunked {
+                message,
+                chunk_size,
+                interval,
+            } => {
+                self.send_chunked(message, *chunk_size, *interval)?;
+            }
+            SendFile(path) => {
+                let content = fs::read(&path).map_err(|e| UnableToReadFile(path.clone(), e))?;
+                self.send_packet_to_last_client(&content)?;
+            }
+            SendFileChunked {
+                path,
+                chunk_size,
+                interval,
+            } => {
+                let content = fs::read(&path).map_err(|e| UnableToReadFile(path.clone(), e))?;
+                self.send_chunked(&content, *chunk_size, *interval)?;
+            }
+            SendPartialThenClose {
+                message,
+                bytes_to_send,
+            } => {
+                self.send_packet_to_last_client(&message[..(*bytes_to_send).min(message.len())])?;
+                return Ok(ControlFlow::Break(()));
+            }
+            Repeat {
+                times,
+                instructions,
+            } => return self.repeat(*times, instructions),
+            Branch {
+                predicate,
+                if_true,
+                if_false,
+            } => {
+                let branch = match self.last_received_message() {
+                    Some(last_received_message) if predicate(last_received_message) => if_true,
+                    _ => if_false,
+                };
+                return self.execute_all(branch);
+            }
+            SlowDrip {
+                byte_interval,
+                instructions,
+            } => {
+                let previous_interval = self.drip_interval.replace(*byte_interval);
+                let result = self.execute_all(instructions);
+                self.drip_interval = previous_interval;
+                return result;
+            }
+            RouteByPeer(routes) => return self.route_by_peer(routes),
+            Pause(duration) => thread::sleep(*duration),
+            SetDefaultResponse(responder) => self.default_response = Some(responder.clone()),
+            Instruction::StopExchange => return Ok(ControlFlow::Break(())),
+            _ => self.execute_receive(instruction)?,
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Serve each route to the first peer it selects, one peer at a time
+    fn route_by_peer(
+        &mut self,
+        routes: &mut [PeerRoute],
+    ) -> Result<ControlFlow<()>, ServerMockerError> {
+        let mut remaining_routes: Vec<&mut PeerRoute> = routes.iter_mut().collect();
+        let mut served_peers = Vec::new();
+        while !remaining_routes.is_empty() {
+            let (peer_addr, first_datagram) = self.receive_unserved_datagram(&served_peers)?;
+            let Some(index) = remaining_routes
+                .iter()
+                .position(|route| route.peer.selects(peer_addr, &first_datagram))
+            else {
+                if self.options.strict {
+                    self.report_error(UnexpectedData(first_datagram));
+                }
+                continue;
+            };
+            let route = remaining_routes.remove(index);
+            served_peers.push(peer_addr);
+            // The first datagram is received by the route itself
+            self.unframed_data.insert(0, (peer_addr, first_datagram));
+            self.routed_peer = Some(peer_addr);
+            let result = self.execute_all(&mut route.instructions);
+            self.routed_peer = None;
+            if result?.is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Take the first data received from a peer not served yet, waiting for it if needed
+    fn receive_unserved_datagram(
+        &mut self,
+        served_peers: &[SocketAddr],
+    ) -> Result<(SocketAddr, Vec<u8>), ServerMockerError> {
+        if let Some(index) = self
+            .unframed_data
+            .iter()
+            .position(|(addr, _)| !served_peers.contains(addr))
+        {
+            return Ok(self.unframed_data.remove(index));
+        }
+        loop {
+            let (sender_addr, datagram) = self.receive_datagram(self.options.max_packet_size)?;
+            if !served_peers.contains(&sender_addr) {
+                return Ok((sender_addr, datagram));
+            }
+            // Kept for the instructions following the routes
+            self.keep_unframed_datagram(sender_addr, datagram);
+        }
+    }
+
+    /// Execute an instruction waiting for data from the client
+    fn execute_receive(&mut self, instruction: &Instruction) -> Result<(), ServerMockerError> {
+        match instruction {
+            Instruction::ReceiveMessage => {
+                self.receive_packet(self.options.max_packet_size)?;
+            }
+            ReceiveMessageWithMaxSize(max_message_size) => {
+                self.receive_packet(*max_message_size)?;
+            }
+            ReceiveMessages(count) => {
+                for _ in 0..*count {
+                    self.receive_packet(self.options.max_packet_size)?;
+                }
+            }
+            ReceiveExactBytes(size) => self.receive_framed(&mut ExactBytesFramer(*size))?,
+            ReceiveUntilDelimiter(delimiter) => {
+                self.receive_framed(&mut DelimiterFramer(delimiter.clone()))?;
+            }
+            Instruction::ReceiveUntilClose => self.receive_framed(&mut UntilCloseFramer)?,
+            Instruction::ReceiveChunksUntilClose => self.receive_chunks_until_close()?,
+            ReceiveFor(duration) => self.receive_for(*duration)?,
+            Silence(duration) => {
+                self.read_for(*duration)?;
+            }
+            ExpectNoMessage(duration) => {
+                let (_, unexpected_data) = self.read_for(*duration)?;
+                if !unexpected_data.is_empty() {
+                    return Err(UnexpectedData(unexpected_data));
+                }
+            }
+            _ => unreachable!("{} doesn't receive data", instruction.summary()),
+        }
+        Ok(())
+    }
+
+    /// Execute the instructions repeatedly, as many times as allowed by `times`
+    fn repeat(
+        &mut self,
+        times: Times,
+        instructions: &mut [Instruction],
+    ) -> Result<ControlFlow<()>, ServerMockerError> {
+        let mut count = 0;
+        while !times.is_exhausted(count) {
+            let bytes_received = self.traffic.stats().bytes_received;
+            match self.execute_all(instructions) {
+                Ok(ControlFlow::Continue(())) => count += 1,
+                Ok(ControlFlow::Break(())) => return Ok(ControlFlow::Break(())),
+                // The client stopped repeating before the first receive, check the count below
+                Err(UnableToReadUdpStream(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+                        && self.traffic.stats().bytes_received == bytes_received
+                        && self.unframed_data.is_empty() =>
+                {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if !times.contains(count) {
+            return Err(UnexpectedRepeatCount(times, count));
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Execute a list of instructions, stopping at the first error
+    fn execute_all(
+        &mut self,
+        instructions: &mut [Instruction],
+    ) -> Result<ControlFlow<()>, ServerMockerError> {
+        for instruction in instructions {
+            if self.execute(instruction)?.is_break() {
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Receive a packet, remember its sender and forward it to the testing code
+    fn receive_packet(&mut self, max_packet_size: usize) -> Result<(), ServerMockerError> {
+        let (packet_sender_addr, mut whole_received_packet) = match self.options.framer.clone() {
+            Some(framer) => self.receive_framed_message(
+                &mut *framer.lock().unwrap_or_else(PoisonError::into_inner),
+            )?,
+            None => match self
+                .unframed_data
+                .iter()
+                .position(|(addr, _)| self.accepts(*addr))
+            {
+                // Data left over by a previous receive instruction
+                Some(index) => self.unframed_data.remove(index),
+                None => self.receive_accepted_datagram(max_packet_size)?,
+            },
+        };
+        whole_received_packet.truncate(max_packet_size);
+        self.push_received_message(packet_sender_addr, whole_received_packet);
+        Ok(())
+    }
+
+    /// Receive a message split by the given framer and forward it to the testing code
+    fn receive_framed(&mut self, framer: &mut dyn Framer) -> Result<(), ServerMockerError> {
+        let (sender_addr, message) = self.receive_framed_message(framer)?;
+        self.push_received_message(sender_addr, message);
+        Ok(())
+    }
+
+    /// Receive everything sent during the given duration as a single message
+    fn receive_for(&mut self, duration: Duration) -> Result<(), ServerMockerError> {
+        // Data left over by a previous receive instruction comes first
+        let mut unframed_data_addr = None;
+        let mut message = Vec::new();
+        for (sender_addr, unframed_data) in mem::take(&mut self.unframed_data) {
+            unframed_data_addr = Some(sender_addr);
+            message.extend_from_slice(&unframed_data);
+        }
+        let (last_sender_addr, received_data) = self.read_for(duration)?;
+        message.extend_from_slice(&received_data);
+        if let Some(sender_addr) = last_sender_addr.or(unframed_data_addr) {
+            self.push_received_message(sender_addr, message);
+        } else {
+            // Nothing received from any client
+            self.traffic.push_received_message(message.clone());
+            let _ = self.message_tx.send(ReceivedMessage::new(message, None));
+        }
+        Ok(())
+    }
+
+    /// Forward each datagram to the testing code as soon as it's received, until an empty datagram
+    fn receive_chunks_until_close(&mut self) -> Result<(), ServerMockerError> {
+        // Data left over by a previous receive instruction
+        for (_, unframed_data) in mem::take(&mut self.unframed_data) {
+            let _ = self.chunk_tx.send(unframed_data);
+        }
+        loop {
+            let (sender_addr, datagram) = self.receive_datagram(self.options.max_packet_size)?;
+            if datagram.is_empty() {
+                return Ok(());
+            }
+            self.last_sender_addr = Some(sender_addr);
+            self.last_received_messages
+                .insert(sender_addr, datagram.clone());
+            let _ = self.chunk_tx.send(datagram);
+        }
+    }
+
+    /// Remember the received message with its sender and forward it to the testing code
+    fn push_received_message(&mut self, sender_addr: SocketAddr, message: Vec<u8>) {
+        self.last_sender_addr = Some(sender_addr);
+        self.last_received_messages
+            .insert(sender_addr, message.clone());
+        self.traffic.push_received_message(message.clone());
+        // The server mocker may have been dropped meanwhile
+        let _ = self
+            .message_tx
+            .send(ReceivedMessage::new(message, Some(sender_addr)));
+    }
+
+    /// Receive a single datagram of at most `max_packet_size` bytes, with the address of its sender
+    fn receive_datagram(
         &self,
         max_packet_size: usize,
     ) -> Result<(SocketAddr, Vec<u8>), ServerMockerError> {
         let mut whole_received_packet: Vec<u8> = vec![0; max_packet_size];
 
-        let (bytes_read, packet_sender_addr) = self
-            .connection
-            .recv_from(&mut whole_received_packet)
-            .map_err(UnableToReadUdpStream)?;
+        let (bytes_read, packet_sender_addr) = loop {
+            let received = self
+                .connection
+                .recv_from(&mut whole_received_packet)
+                .map_err(|e| {
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+                        #[cfg(feature = "tracing")]
+                        tracing::debug!(
+                            net_timeout = ?self.options.net_timeout,
+                            "timed out waiting for a datagram"
+                        );
+                        self.traffic.timed_out(None);
+                    }
+                    UnableToReadUdpStream(e)
+                })?;
+            let chaos = &self.options.chaos;
+            if !(chaos.drop_inbound && self.rng.chance(chaos.drop_probability)) {
+                break received;
+            }
+            #[cfg(feature = "tracing")]
+            tracing::trace!(client_addr = %received.1, "inbound datagram dropped");
+        };
 
         // Remove the extra bytes
         whole_received_packet.truncate(bytes_read);
-
+        self.traffic.record(
+            Transport::Udp,
+            Direction::Inbound,
+            packet_sender_addr,
+            &whole_received_packet,
+        );
         Ok((packet_sender_addr, whole_received_packet))
     }
 
-    fn send_packet_to_last_client(
+    /// Receive a datagram from the routed peer if any, keeping the datagrams of the other peers for later
+    fn receive_accepted_datagram(
+        &mut self,
+        max_packet_size: usize,
+    ) -> Result<(SocketAddr, Vec<u8>), ServerMockerError> {
+        loop {
+            let (sender_addr, datagram) = self.receive_datagram(max_packet_size)?;
+            if self.accepts(sender_addr) {
+                return Ok((sender_addr, datagram));
+            }
+            self.keep_unframed_datagram(sender_addr, datagram);
+        }
+    }
+
+    /// Check if data from the given client can be received, see [`UdpServerImpl::routed_peer`]
+    fn accepts(&self, sender_addr: SocketAddr) -> bool {
+        self.routed_peer.map_or(true, |peer| peer == sender_addr)
+    }
+
+    /// Keep a datagram for a later receive instruction, appended to the data of the same client
+    /// when a framer splits it into messages, as is otherwise
+    fn keep_unframed_datagram(&mut self, sender_addr: SocketAddr, datagram: Vec<u8>) {
+        let client_data = self
+            .unframed_data
+            .iter_mut()
+            .find(|(addr, _)| *addr == sender_addr)
+            .filter(|_| self.options.framer.is_some());
+        match client_data {
+            Some((_, unframed_data)) => unframed_data.extend_from_slice(&datagram),
+            None => self.unframed_data.push((sender_addr, datagram)),
+        }
+    }
+
+    /// Receive datagrams until the framer can split a whole message, with the address of its sender
+    fn receive_framed_message(
+        &mut self,
+        framer: &mut dyn Framer,
+    ) -> Result<(SocketAddr, Vec<u8>), ServerMockerError> {
+        loop {
+            for index in 0..self.unframed_data.len() {
+                let (sender_addr, unframed_data) = &mut self.unframed_data[index];
+                if self.routed_peer.is_some_and(|peer| peer != *sender_addr) {
+                    continue;
+                }
+                if let Some(message) = framer.split(unframed_data) {
+                    let sender_addr = *sender_addr;
+                    if unframed_data.is_empty() {
+                        self.unframed_data.remove(index);
+                    }
+                    return Ok((sender_addr, message));
+                }
+            }
+            let (sender_addr, datagram) =
+                self.receive_accepted_datagram(self.options.max_packet_size)?;
+            // Only data from the same client can complete a message
+            let client_data = self
+                .unframed_data
+                .iter()
+                .position(|(addr, _)| *addr == sender_addr);
+            if datagram.is_empty() {
+                // An empty datagram ends the incomplete message, as a closed TCP connection would
+                let unframed_data = client_data
+                    .map(|index| self.unframed_data.remove(index).1)
+                    .unwrap_or_default();
+                return Ok((sender_addr, unframed_data));
+            }
+            match client_data {
+                Some(index) => self.unframed_data[index].1.extend_from_slice(&datagram),
+                None => self.unframed_data.push((sender_addr, datagram)),
+            }
+        }
+    }
+
+    /// Read all the datagrams received during the given duration, from any client,
+    /// with the address of the last client
+    fn read_for(
         &self,
-        message_to_send: &[u8],
-        last_received_packed_with_addr: &Option<(SocketAddr, Vec<u8>)>,
+        duration: Duration,
+    ) -> Result<(Option<SocketAddr>, Vec<u8>), ServerMockerError> {
+        let deadline = Instant::now() + duration;
+        let mut last_sender_addr = None;
+        let mut received_data = Vec::new();
+        let mut buffer = vec![0; self.options.max_packet_size];
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break Ok(());
+            }
+            if let Err(e) = self.connection.set_read_timeout(Some(remaining)) {
+                break Err(UnableToSetReadTimeout(e));
+            }
+            match self.connection.recv_from(&mut buffer) {
+                Ok((bytes_read, sender_addr)) => {
+                    self.traffic.record(
+                        Transport::Udp,
+                        Direction::Inbound,
+                        sender_addr,
+                        &buffer[..bytes_read],
+                    );
+                    last_sender_addr = Some(sender_addr);
+                    received_data.extend_from_slice(&buffer[..bytes_read]);
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::WouldBlock
+                            | ErrorKind::TimedOut
+                            | ErrorKind::ConnectionRefused
+                            | ErrorKind::ConnectionReset
+                    ) => {}
+                Err(e) => break Err(UnableToReadUdpStream(e)),
+            }
+        };
+        self.connection
+            .set_read_timeout(Some(self.options.net_timeout))
+            .map_err(UnableToSetReadTimeout)?;
+        result.map(|()| (last_sender_addr, received_data))
+    }
+
+    /// Answer datagrams sent by the client that no receive instruction expected with the default response,
+    /// or report them in strict mode
+    fn handle_unexpected_data(&self) {
+        if !self.options.strict && self.default_response.is_none() {
+            return;
+        }
+        if let Err(e) = self.connection.set_nonblocking(true) {
+            self.report_error(UnableToReadUdpStream(e));
+            return;
+        }
+        let mut buffer = vec![0; self.options.max_packet_size];
+        loop {
+            match self.connection.recv_from(&mut buffer) {
+                Ok((bytes_read, packet_sender_addr)) => {
+                    self.traffic.record(
+                        Transport::Udp,
+                        Direction::Inbound,
+                        packet_sender_addr,
+                        &buffer[..bytes_read],
+                    );
+                    let data = buffer[..bytes_read].to_vec();
+                    let result = match &self.default_response {
+                        Some(responder) => {
+                            let response = responder.lock().unwrap_or_else(PoisonError::into_inner)(
+                                Some(data),
+                            );
+                            response.map_or(Ok(()), |response| {
+                                self.send_packet_to(&response, packet_sender_addr)
+                            })
+                        }
+                        None => Err(UnexpectedData(data)),
+                    };
+                    if let Err(e) = result {
+                        self.report_error(e);
+                    }
+                }
+                // ICMP errors caused by previously sent datagrams are not unexpected data
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        ErrorKind::WouldBlock
+                            | ErrorKind::ConnectionRefused
+                            | ErrorKind::ConnectionReset
+                    ) =>
+                {
+                    break
+                }
+                Err(e) => {
+                    self.report_error(UnableToReadUdpStream(e));
+                    break;
+                }
+            }
+        }
+        if let Err(e) = self.connection.set_nonblocking(false) {
+            self.report_error(UnableToReadUdpStream(e));
+        }
+    }
+
+    /// Client whose request is being answered: the routed peer if any, otherwise the client
+    /// which sent the last received message
+    fn answered_client(&self) -> Option<SocketAddr> {
+        self.routed_peer.or(self.last_sender_addr)
+    }
+
+    /// Last message received from the answered client, if any
+    fn last_received_message(&self) -> Option<&[u8]> {
+        self.answered_client()
+            .and_then(|client_addr| self.last_received_messages.get(&client_addr))
+            .map(Vec::as_slice)
+    }
+
+    /// Count the error and forward it to the testing code
+    fn report_error(&self, error: ServerMockerError) {
+        self.report_instruction_error(error, None);
+    }
+
+    /// Count the error raised while executing the given instruction, and forward it to the testing code
+    fn report_instruction_error(
+        &self,
+        error: ServerMockerError,
+        instruction: Option<(usize, String)>,
+    ) {
+        let report = self
+            .traffic
+            .error_raised(error, instruction, self.answered_client());
+        // The server mocker may have been dropped meanwhile
+        let _ = self.error_tx.send(report);
+    }
+
+    /// Send the message in chunks of `chunk_size` bytes, waiting `interval` between each chunk
+    fn send_chunked(
+        &self,
+        message: &[u8],
+        chunk_size: usize,
+        interval: Duration,
     ) -> Result<(), ServerMockerError> {
-        // Last message received with the address of the client, used to send the response
-        last_received_packed_with_addr
-            .as_ref()
+        for (index, chunk) in message.chunks(chunk_size.max(1)).enumerate() {
+            if index > 0 {
+                thread::sleep(interval);
+            }
+            self.send_packet_to_last_client(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn send_packet_to_last_client(&self, message_to_send: &[u8]) -> Result<(), ServerMockerError> {
+        let client_addr = self
+            .answered_client()
             .ok_or(GotSendMessageBeforeReceiveMessage)?;
+        self.send_packet_to_client(message_to_send, client_addr)
+    }
+
+    /// Send the message to the given client, one byte per datagram if slowly dripped
+    fn send_packet_to_client(
+        &self,
+        message_to_send: &[u8],
+        client_addr: SocketAddr,
+    ) -> Result<(), ServerMockerError> {
+        let Some(drip_interval) = self.drip_interval else {
+            return self.send_packet_to(message_to_send, client_addr);
+        };
+        // One byte per datagram
+        for (index, byte) in message_to_send.iter().enumerate() {
+            if index > 0 {
+                thread::sleep(drip_interval);
+            }
+            self.send_packet_to(slice::from_ref(byte), client_addr)?;
+        }
+        Ok(())
+    }
+
+    /// Send a datagram to the given client
+    fn send_packet_to(&self, packet: &[u8], addr: SocketAddr) -> Result<(), ServerMockerError> {
+        self.traffic.message_sent(packet);
+        if let Some(latency) = self.options.latency {
+            thread::sleep(latency);
+        }
+        if let Some(bytes_per_sec) = self.options.throttle_bytes_per_sec {
+            thread::sleep(transmission_time(packet.len(), bytes_per_sec));
+        }
+        let chaos = &self.options.chaos;
+        if !chaos.max_delay.is_zero() {
+            let max_delay_nanos = u64::try_from(chaos.max_delay.as_nanos()).unwrap_or(u64::MAX);
+            let delay_nanos = self.rng.next_u64() % max_delay_nanos.saturating_add(1);
+            thread::sleep(Duration::from_nanos(delay_nanos));
+        }
+        if self.rng.chance(chaos.drop_probability) {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(client_addr = %addr, "outbound datagram dropped");
+            return Ok(());
+        }
+        let mut packet = Cow::Borrowed(packet);
+        if !packet.is_empty() && self.rng.chance(chaos.corrupt_probability) {
+            let index = self.rng.below(packet.len());
+            packet.to_mut()[index] ^= 1 << self.rng.below(8);
+        }
+        self.send_datagram(&packet, addr)?;
+        if self.rng.chance(chaos.duplicate_probability) {
+            self.send_datagram(&packet, addr)?;
+        }
+        Ok(())
+    }
+
+    /// Send a datagram to the given client, unless it's held back to be reordered
+    fn send_datagram(&self, packet: &[u8], addr: SocketAddr) -> Result<(), ServerMockerError> {
+        let chaos = &self.options.chaos;
+        if chaos.reorder_window > 0 && self.rng.chance(chaos.reorder_probability) {
+            self.held_back_datagrams.borrow_mut().push((
+                chaos.reorder_window,
+                addr,
+                packet.to_vec(),
+            ));
+            return Ok(());
+        }
+        self.send_to(packet, addr)?;
+        self.release_held_back_datagrams(false)
+    }
 
+    /// Send the held back datagrams after which enough datagrams have been sent, or all of them if `all`
+    fn release_held_back_datagrams(&self, all: bool) -> Result<(), ServerMockerError> {
+        let mut released = Vec::new();
+        self.held_back_datagrams
+            .borrow_mut()
+            .retain_mut(|(remaining_datagrams, addr, packet)| {
+                *remaining_datagrams = remaining_datagrams.saturating_sub(1);
+                if all || *remaining_datagrams == 0 {
+                    released.push((*addr, mem::take(packet)));
+                    return false;
+                }
+                true
+            });
+        for (addr, packet) in released {
+            self.send_to(&packet, addr)?;
+        }
+        Ok(())
+    }
+
+    /// Send a datagram to the given client right away, logging it
+    fn send_to(&self, packet: &[u8], addr: SocketAddr) -> Result<(), ServerMockerError> {
+        // Logged first, so that a client can't observe a response missing from the log
+        self.traffic
+            .record(Transport::Udp, Direction::Outbound, addr, packet);
         self.connection
-            .send_to(
-                message_to_send,
-                last_received_packed_with_addr.as_ref().unwrap().0,
-            )
+            .send_to(packet, addr)
             .map_err(FailedToSendUdpMessage)?;
         Ok(())
     }
 }
+
+/// Bind a socket with the options of the server mocker
+fn bind_socket(options: &UdpMocker, socket_addr: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(socket_addr),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    if let Some(ttl) = options.ttl {
+        match socket_addr {
+            SocketAddr::V4(_) => socket.set_ttl_v4(ttl)?,
+            SocketAddr::V6(_) => socket.set_unicast_hops_v6(ttl)?,
+        }
+    }
+    #[cfg(not(any(
+        target_os = "fuchsia",
+        target_os = "redox",
+        target_os = "solaris",
+        target_os = "haiku",
+        target_os = "wasi",
+    )))]
+    if let (Some(tos), SocketAddr::V4(_)) = (options.tos, socket_addr) {
+        socket.set_tos_v4(u32::from(tos))?;
+    }
+    options.configure_socket(&socket)?;
+    socket.bind(&socket_addr.into())?;
+    Ok(socket.into())
+}
+
+/// Socket of the UDP server mocker, or both sockets when listening on both loopbacks,
+/// offering the subset of the [`UdpSocket`] API used by the server
+struct UdpConnection {
+    socket: UdpSocket,
+    /// Socket on the loopback of the other IP family, polled along with `socket`
+    dual_socket: Option<UdpSocket>,
+    /// Sockets bound to other ports, one per listening address, sending the datagrams instead of the listening sockets
+    reply_sockets: Vec<UdpSocket>,
+    /// Read timeout and non-blocking mode emulated for the polled sockets
+    read_timeout: Cell<Option<Duration>>,
+    nonblocking: Cell<bool>,
+}
+
+impl UdpConnection {
+    fn single(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            dual_socket: None,
+            reply_sockets: Vec::new(),
+            read_timeout: Cell::new(None),
+            nonblocking: Cell::new(false),
+        }
+    }
+
+    fn dual(socket: UdpSocket, dual_socket: UdpSocket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        dual_socket.set_nonblocking(true)?;
+        Ok(Self {
+            dual_socket: Some(dual_socket),
+            ..Self::single(socket)
+        })
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        // Rejects the same timeouts as a single socket would
+        self.socket.set_read_timeout(timeout)?;
+        self.read_timeout.set(timeout);
+        Ok(())
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        if self.dual_socket.is_none() {
+            self.socket.set_nonblocking(nonblocking)?;
+        }
+        self.nonblocking.set(nonblocking);
+        Ok(())
+    }
+
+    fn recv_from(&self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let Some(dual_socket) = &self.dual_socket else {
+            return self.socket.recv_from(buffer);
+        };
+        let deadline = self
+            .read_timeout
+            .get()
+            .filter(|_| !self.nonblocking.get())
+            .map(|timeout| Instant::now() + timeout);
+        loop {
+            for socket in [&self.socket, dual_socket] {
+                match socket.recv_from(buffer) {
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    result => return result,
+                }
+            }
+            if self.nonblocking.get() || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            thread::sleep(IDLE_POLL_INTERVAL);
+        }
+    }
+
+    /// Send the datagram from the socket of the client address family
+    fn send_to(&self, packet: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        if let [reply_socket, dual_reply_socket] = self.reply_sockets.as_slice() {
+            if addr.is_ipv4() != reply_socket.local_addr()?.is_ipv4() {
+                return dual_reply_socket.send_to(packet, addr);
+            }
+        }
+        if let Some(reply_socket) = self.reply_sockets.first() {
+            return reply_socket.send_to(packet, addr);
+        }
+        match &self.dual_socket {
+            Some(dual_socket) if addr.is_ipv4() != self.socket.local_addr()?.is_ipv4() => {
+                dual_socket.send_to(packet, addr)
+            }
+            _ => self.socket.send_to(packet, addr),
+        }
+    }
+}
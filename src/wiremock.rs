@@ -0,0 +1,228 @@
+//! # `wiremock`
+//!
+//! HTTP mocks loaded from wiremock JSON stub mappings, available with the `serde` feature.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{Map, Value};
+
+use crate::ServerMockerError::{self, InvalidStubMapping, UnableToReadFile};
+use crate::{HttpMock, Matcher};
+
+/// Priority of the stubs without explicit priority, as in wiremock
+const DEFAULT_PRIORITY: u64 = 5;
+
+/// Read the mocks of the given stub mapping file, or of all the JSON files of the given mappings directory.
+///
+/// Mocks are sorted by priority, the highest priority (lowest number) first.
+pub(crate) fn load_stub_mappings(path: &Path) -> Result<Vec<HttpMock>, ServerMockerError> {
+    let (mapping_files, mappings_directory) = if path.is_dir() {
+        let mut files = fs::read_dir(path)
+            .map_err(|e| UnableToReadFile(path.to_owned(), e))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, _>>()
+            .map_err(|e| UnableToReadFile(path.to_owned(), e))?;
+        files.retain(|file| {
+            file.extension()
+                .is_some_and(|extension| extension == "json")
+        });
+        files.sort();
+        (files, path)
+    } else {
+        (vec![path.to_owned()], path.parent().unwrap_or(path))
+    };
+    // Response bodies are stored next to the mappings directory, as in wiremock
+    let files_directory = mappings_directory
+        .parent()
+        .unwrap_or(mappings_directory)
+        .join("__files");
+
+    let mut stubs = Vec::new();
+    for file in mapping_files {
+        let content = fs::read_to_string(&file).map_err(|e| UnableToReadFile(file.clone(), e))?;
+        let json: Value = serde_json::from_str(&content)
+            .map_err(|e| InvalidStubMapping(file.clone(), e.to_string()))?;
+        let mappings = match json.get("mappings") {
+            Some(Value::Array(mappings)) => mappings.clone(),
+            Some(_) => {
+                return Err(InvalidStubMapping(
+                    file,
+                    "`mappings` must be an array".to_string(),
+                ))
+            }
+            None => vec![json],
+        };
+        for mapping in &mappings {
+            let stub = parse_mapping(mapping, &files_directory)
+                .map_err(|e| InvalidStubMapping(file.clone(), e))?;
+            stubs.push(stub);
+        }
+    }
+    // Stable sort, the stubs with the same priority are kept in file order
+    stubs.sort_by_key(|(priority, _)| *priority);
+    Ok(stubs.into_iter().map(|(_, mock)| mock).collect())
+}
+
+/// Priority and mock of a stub mapping
+fn parse_mapping(mapping: &Value, files_directory: &Path) -> Result<(u64, HttpMock), String> {
+    let mapping = as_object(mapping, "stub mapping")?;
+    for key in mapping.keys() {
+        if ![
+            "request",
+            "response",
+            "priority",
+            "id",
+            "uuid",
+            "name",
+            "persistent",
+            "metadata",
+        ]
+        .contains(&key.as_str())
+        {
+            return Err(format!("unsupported stub mapping field `{key}`"));
+        }
+    }
+    let priority = match mapping.get("priority") {
+        Some(priority) => priority.as_u64().ok_or("`priority` must be a number")?,
+        None => DEFAULT_PRIORITY,
+    };
+    let request = as_object(
+        mapping.get("request").ok_or("missing `request`")?,
+        "`request`",
+    )?;
+    let mut mock = parse_request(request)?;
+    if let Some(response) = mapping.get("response") {
+        parse_response(
+            &mut mock,
+            as_object(response, "`response`")?,
+            files_directory,
+        )?;
+    }
+    Ok((priority, mock))
+}
+
+fn parse_request(request: &Map<String, Value>) -> Result<HttpMock, String> {
+    let method = match request.get("method") {
+        Some(method) => as_str(method, "`method`")?,
+        None => "ANY",
+    };
+    let mut path = None;
+    for (key, value) in request {
+        match key.as_str() {
+            // The query string of the requests is ignored if the path has none, even for `url`
+            "url" | "urlPath" => path = Some(as_str(value, key)?),
+            "method" | "headers" | "bodyPatterns" => {}
+            _ => return Err(format!("unsupported request matcher `{key}`")),
+        }
+    }
+    let mut mock = HttpMock::new(method, path.ok_or("missing `url` or `urlPath`")?);
+
+    if let Some(headers) = request.get("headers") {
+        for (name, pattern) in as_object(headers, "`headers`")? {
+            let pattern = as_object(pattern, name)?;
+            match pattern.get("equalTo") {
+                Some(value) if pattern.len() == 1 => {
+                    mock = mock.request_header(name, as_str(value, name)?);
+                }
+                _ => {
+                    return Err(format!(
+                        "only `equalTo` header matchers are supported, for `{name}`"
+                    ))
+                }
+            }
+        }
+    }
+    if let Some(patterns) = request.get("bodyPatterns") {
+        let patterns = patterns
+            .as_array()
+            .ok_or("`bodyPatterns` must be an array")?;
+        for pattern in patterns {
+            let pattern = as_object(pattern, "body pattern")?;
+            let matcher = match pattern.iter().next() {
+                Some((operator, value)) if pattern.len() == 1 => {
+                    let value = as_str(value, operator)?.as_bytes().to_vec();
+                    match operator.as_str() {
+                        "equalTo" => Matcher::Exact(value),
+                        "contains" => Matcher::Contains(value),
+                        _ => return Err(format!("unsupported body pattern `{operator}`")),
+                    }
+                }
+                _ => return Err("body patterns must have a single operator".to_string()),
+            };
+            mock = mock.request_body(matcher);
+        }
+    }
+    Ok(mock)
+}
+
+fn parse_response(
+    mock: &mut HttpMock,
+    response: &Map<String, Value>,
+    files_directory: &Path,
+) -> Result<(), String> {
+    for (key, value) in response {
+        match key.as_str() {
+            "status" => {
+                mock.status = value
+                    .as_u64()
+                    .and_then(|status| u16::try_from(status).ok())
+                    .ok_or("`status` must be a status code")?;
+            }
+            // The reason phrase is deduced from the status code
+            "statusMessage" => {}
+            "headers" => {
+                for (name, values) in as_object(value, "`headers`")? {
+                    // A header can have several values
+                    match values {
+                        Value::Array(values) => {
+                            for value in values {
+                                mock.headers
+                                    .push((name.clone(), as_str(value, name)?.to_owned()));
+                            }
+                        }
+                        value => mock
+                            .headers
+                            .push((name.clone(), as_str(value, name)?.to_owned())),
+                    }
+                }
+            }
+            "body" => mock.body = as_str(value, key)?.as_bytes().to_vec(),
+            "jsonBody" => mock.body = value.to_string().into_bytes(),
+            "base64Body" => {
+                mock.body = STANDARD
+                    .decode(as_str(value, key)?)
+                    .map_err(|e| format!("invalid `base64Body`: {e}"))?;
+            }
+            "bodyFileName" => {
+                let body_file = files_directory.join(as_str(value, key)?);
+                mock.body = fs::read(&body_file).map_err(|e| {
+                    format!("unable to read body file {}: {e}", body_file.display())
+                })?;
+            }
+            "fixedDelayMilliseconds" => {
+                let delay = value
+                    .as_u64()
+                    .ok_or("`fixedDelayMilliseconds` must be a number")?;
+                mock.delay = Some(Duration::from_millis(delay));
+            }
+            _ => return Err(format!("unsupported response field `{key}`")),
+        }
+    }
+    Ok(())
+}
+
+fn as_object<'a>(value: &'a Value, name: &str) -> Result<&'a Map<String, Value>, String> {
+    value
+        .as_object()
+        .ok_or_else(|| format!("{name} must be an object"))
+}
+
+fn as_str<'a>(value: &'a Value, name: &str) -> Result<&'a str, String> {
+    value
+        .as_str()
+        .ok_or_else(|| format!("{name} must be a string"))
+}
@@ -0,0 +1,101 @@
+//! Final check that nothing else happened during the exchange
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage};
+use socket_server_mocker::{ServerMocker, TcpMocker};
+
+#[test]
+fn test_no_more_messages() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"world".to_vec())])
+        .unwrap();
+
+    client.write_all(b"hello").unwrap();
+    let mut buffer = [0; 16];
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"world", &buffer[..received_size]);
+    assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+
+    server.assert_no_more_messages();
+}
+
+#[test]
+fn test_no_more_messages_after_client_closed() {
+    // The default response makes the server watch the socket while idle, and notice the client closing
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        default_response: Some(b"?".to_vec()),
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"world".to_vec())])
+        .unwrap();
+
+    client.write_all(b"hello").unwrap();
+    let mut buffer = [0; 5];
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+
+    drop(client);
+    thread::sleep(Duration::from_millis(50));
+    server.assert_no_more_messages();
+}
+
+#[test]
+#[should_panic(
+    expected = "Received 5 bytes of unexpected data from client: b\"extra\"\n00000000: 6578 7472 61"
+)]
+fn test_no_more_messages_with_extra_data() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"world".to_vec())])
+        .unwrap();
+
+    client.write_all(b"hello").unwrap();
+    let mut buffer = [0; 5];
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+
+    // The client sends something the test didn't script
+    client.write_all(b"extra").unwrap();
+
+    server.assert_no_more_messages();
+}
+
+#[test]
+#[should_panic(expected = "Unconsumed message of 4 bytes")]
+fn test_no_more_messages_with_unconsumed_message() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    client.send_to(b"ping", server.socket_address()).unwrap();
+
+    // The received message is never popped by the test
+    server.assert_no_more_messages();
+}
+
+#[test]
+#[should_panic(expected = "Unconsumed message of 4 bytes")]
+fn test_no_more_messages_with_peeked_message() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    client.send_to(b"ping", server.socket_address()).unwrap();
+
+    // Peeking doesn't consume the message
+    assert_eq!(Some(b"ping".to_vec()), server.peek_received_message());
+    server.assert_no_more_messages();
+}
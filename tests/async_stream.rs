@@ -0,0 +1,66 @@
+//! Received messages and server errors as async streams
+#![cfg(feature = "futures")]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use futures_lite::StreamExt;
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{ReadInterruption, ServerMocker, ServerMockerError};
+
+#[tokio::test]
+async fn test_message_stream() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"ack".to_vec()),
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+
+    let server_addr = server.socket_address();
+    let client = tokio::task::spawn_blocking(move || {
+        let mut client = TcpStream::connect(server_addr).unwrap();
+        client.write_all(b"first").unwrap();
+        let mut ack = [0; 3];
+        client.read_exact(&mut ack).unwrap();
+        client.write_all(b"second").unwrap();
+    });
+
+    let mut messages = server.message_stream();
+    let mut received = Vec::new();
+    let mut client = Some(client);
+    // Wait for the client and the messages at the same time
+    loop {
+        tokio::select! {
+            Some(message) = messages.next() => received.push(message),
+            result = async { client.as_mut().unwrap().await }, if client.is_some() => {
+                result.unwrap();
+                client = None;
+            }
+            else => break,
+        }
+    }
+    assert_eq!(vec![b"first".to_vec(), b"second".to_vec()], received);
+}
+
+#[tokio::test]
+async fn test_error_stream() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+
+    // The client leaves without sending anything
+    let client = TcpStream::connect(server.socket_address()).unwrap();
+    drop(client);
+
+    let error = server.error_stream().next().await.unwrap();
+    assert!(matches!(
+        error,
+        ServerMockerError::ReadInterrupted(ReadInterruption::ConnectionClosed, _)
+    ));
+    assert!(server.message_stream().next().await.is_none());
+}
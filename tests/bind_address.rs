@@ -0,0 +1,218 @@
+//! Server mockers listening on other addresses than the IPv4 loopback, including IPv6
+
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{ServerMocker, ServerMockerError, TcpMocker, UdpMocker};
+
+#[test]
+fn test_tcp_at_any_address() {
+    let server = ServerMocker::tcp_at("0.0.0.0:0").unwrap();
+    assert!(server.socket_address().ip().is_unspecified());
+    assert_ne!(0, server.port());
+
+    // Reachable from any interface, including the loopback
+    let mut client = TcpStream::connect((Ipv4Addr::LOCALHOST, server.port())).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"pong", received.as_slice());
+    assert_eq!(b"ping", server.pop_received_message().unwrap().as_slice());
+}
+
+#[test]
+fn test_udp_at_any_address() {
+    let server = ServerMocker::udp_at("0.0.0.0:0").unwrap();
+    assert!(server.socket_address().ip().is_unspecified());
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .connect((Ipv4Addr::LOCALHOST, server.port()))
+        .unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    client.send(b"ping").unwrap();
+    let mut buffer = [0; 8];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"pong", &buffer[..received_size]);
+}
+
+#[test]
+fn test_tcp_at_given_address() {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    let server = ServerMocker::tcp_at(addr).unwrap();
+    assert_eq!(Ipv4Addr::LOCALHOST, server.socket_address().ip());
+}
+
+#[test]
+fn test_unresolved_address() {
+    assert!(matches!(
+        ServerMocker::tcp_at("not an address"),
+        Err(ServerMockerError::UnableToResolveAddress(_))
+    ));
+    assert!(matches!(
+        ServerMocker::udp_at(Vec::<SocketAddr>::new().as_slice()),
+        Err(ServerMockerError::UnableToResolveAddress(_))
+    ));
+}
+
+#[test]
+fn test_tcp_v6() {
+    let server = ServerMocker::tcp_v6().unwrap();
+    assert!(server.socket_address().is_ipv6());
+    assert_eq!(Ipv6Addr::LOCALHOST, server.socket_address().ip());
+
+    let mut client = TcpStream::connect(("::1", server.port())).unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let message = server.pop_received_message_with_meta().unwrap();
+    assert_eq!(b"ping", message.data.as_slice());
+    assert_eq!(Some(client.local_addr().unwrap()), message.client_addr);
+}
+
+#[test]
+fn test_udp_v6() {
+    let server = ServerMocker::udp_v6().unwrap();
+    assert_eq!(Ipv6Addr::LOCALHOST, server.socket_address().ip());
+
+    let client = UdpSocket::bind("[::1]:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    client.send(b"ping").unwrap();
+    let mut buffer = [0; 8];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"pong", &buffer[..received_size]);
+}
+
+#[test]
+#[should_panic(expected = "No client connected to [::1]:")]
+fn test_client_of_other_address_family() {
+    let server = ServerMocker::tcp_v6().unwrap();
+    // Nothing listens on the IPv4 loopback
+    assert!(TcpStream::connect((Ipv4Addr::LOCALHOST, server.port())).is_err());
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+}
+
+/// Ping the TCP server mocker listening on both loopbacks from the given address family
+fn tcp_dual_stack_ping(client_ip: IpAddr) {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        dual_stack: true,
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect((client_ip, server.port())).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"pong", received.as_slice());
+}
+
+#[test]
+fn test_tcp_dual_stack() {
+    tcp_dual_stack_ping(Ipv4Addr::LOCALHOST.into());
+    tcp_dual_stack_ping(Ipv6Addr::LOCALHOST.into());
+}
+
+#[test]
+fn test_udp_dual_stack() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        dual_stack: true,
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    let client_v4 = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client_v4
+        .connect((Ipv4Addr::LOCALHOST, server.port()))
+        .unwrap();
+    let client_v6 = UdpSocket::bind("[::1]:0").unwrap();
+    client_v6
+        .connect((Ipv6Addr::LOCALHOST, server.port()))
+        .unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong4".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"pong6".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // Each client is answered on its own address family
+    let mut buffer = [0; 8];
+    client_v4.send(b"ping4").unwrap();
+    let received_size = client_v4.recv(&mut buffer).unwrap();
+    assert_eq!(b"pong4", &buffer[..received_size]);
+    client_v6.send(b"ping6").unwrap();
+    let received_size = client_v6.recv(&mut buffer).unwrap();
+    assert_eq!(b"pong6", &buffer[..received_size]);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_reply_from_other_port() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        reply_from_other_port: true,
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"spoofed".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"spoofed".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // The response comes from the same address, but another port
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.send_to(b"query", server.socket_address()).unwrap();
+    let mut buffer = [0; 8];
+    let (received_size, sender_addr) = client.recv_from(&mut buffer).unwrap();
+    assert_eq!(b"spoofed", &buffer[..received_size]);
+    assert_eq!(server.socket_address().ip(), sender_addr.ip());
+    assert_ne!(server.port(), sender_addr.port());
+
+    // A connected client only accepts datagrams from the queried address and port
+    let strict_client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    strict_client.connect(server.socket_address()).unwrap();
+    strict_client
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    strict_client.send(b"query").unwrap();
+    assert!(strict_client.recv(&mut buffer).is_err());
+    assert!(server.pop_server_error().is_none());
+}
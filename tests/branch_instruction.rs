@@ -0,0 +1,77 @@
+//! Conditional branching depending on the message sent by the client
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+
+use socket_server_mocker::Instruction::{
+    Branch, ReceiveMessage, SendMessage, SendMessageDependingOnLastReceivedMessage, StopExchange,
+};
+use socket_server_mocker::ServerMocker;
+
+#[test]
+fn test_tcp_branch() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    let answer_greeting = Branch {
+        predicate: |message| message.starts_with(b"EHLO"),
+        if_true: vec![SendMessage(b"250-localhost\r\n250 8BITMIME\r\n".to_vec())],
+        if_false: vec![SendMessage(b"250 localhost\r\n".to_vec())],
+    };
+    let answer_command = Branch {
+        predicate: |message| message == b"QUIT\r\n",
+        if_true: vec![SendMessage(b"221 Bye\r\n".to_vec()), StopExchange],
+        if_false: vec![SendMessage(b"502 Unknown command\r\n".to_vec())],
+    };
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            answer_greeting,
+            ReceiveMessage,
+            answer_command,
+        ])
+        .unwrap();
+
+    let mut buffer = [0; 64];
+    client.write_all(b"HELO localhost\r\n").unwrap();
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"250 localhost\r\n", &buffer[..received_size]);
+
+    client.write_all(b"QUIT\r\n").unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"221 Bye\r\n", received.as_slice());
+
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_branch_before_any_message() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            // Nothing received yet, the false branch is taken
+            Branch {
+                predicate: |_| true,
+                if_true: vec![SendMessage(b"true".to_vec())],
+                if_false: vec![],
+            },
+            ReceiveMessage,
+            Branch {
+                predicate: |message| message.len() > 3,
+                if_true: vec![SendMessageDependingOnLastReceivedMessage(|message| message)],
+                if_false: vec![SendMessage(b"short".to_vec())],
+            },
+        ])
+        .unwrap();
+
+    client.send(b"echo").unwrap();
+    let mut buffer = [0; 16];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"echo", &buffer[..received_size]);
+
+    assert!(server.pop_server_error().is_none());
+}
@@ -0,0 +1,88 @@
+//! Typed messages encoded and decoded by a codec
+
+use std::net::UdpSocket;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, StopExchange};
+use socket_server_mocker::{Codec, ServerMocker, ServerMockerError, UdpMocker};
+
+/// Key-value store request
+#[derive(Debug, PartialEq)]
+enum Request {
+    Get(String),
+    Set(String, String),
+}
+
+/// Key-value store response
+enum Response {
+    Value(String),
+    Stored,
+}
+
+/// Text protocol with one space-separated command per datagram
+struct KvCodec;
+
+impl Codec for KvCodec {
+    type Request = Request;
+    type Response = Response;
+
+    fn encode(&self, response: &Response) -> Vec<u8> {
+        match response {
+            Response::Value(value) => format!("VALUE {value}").into_bytes(),
+            Response::Stored => b"STORED".to_vec(),
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Request, String> {
+        let command = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        match command.split(' ').collect::<Vec<_>>().as_slice() {
+            ["GET", key] => Ok(Request::Get((*key).to_string())),
+            ["SET", key, value] => Ok(Request::Set((*key).to_string(), (*value).to_string())),
+            _ => Err(format!("unknown command {command:?}")),
+        }
+    }
+}
+
+#[test]
+fn test_typed_messages() {
+    let server = ServerMocker::new_with_codec(UdpMocker::default(), KvCodec).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            server.send_typed(&Response::Stored),
+            ReceiveMessage,
+            server.send_typed(&Response::Value("blue".to_string())),
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+
+    let mut buffer = [0; 16];
+    client.send(b"SET color blue").unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"STORED", &buffer[..received_size]);
+    client.send(b"GET color").unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"VALUE blue", &buffer[..received_size]);
+    client.send(b"DEL color").unwrap();
+
+    assert_eq!(
+        Request::Set("color".to_string(), "blue".to_string()),
+        server.pop_received_typed().unwrap()
+    );
+    assert_eq!(
+        Request::Get("color".to_string()),
+        server.pop_received_typed().unwrap()
+    );
+    let error = server.pop_received_typed().unwrap_err();
+    assert!(matches!(
+        error,
+        ServerMockerError::UnableToDecodeMessage(_, _)
+    ));
+    assert_eq!(
+        "Non fatal: Failed to decode received message: unknown command \"DEL color\": b\"DEL color\"",
+        error.to_string()
+    );
+}
@@ -0,0 +1,129 @@
+//! Default response sent back for client data that no instruction expected
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{ServerMocker, TcpMocker, UdpMocker};
+
+#[test]
+fn test_tcp_default_response_while_idle() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        default_response: Some(b"PONG".to_vec()),
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    // No instruction yet, the keep-alive is answered with the default response
+    client.write_all(b"PING").unwrap();
+    let mut buffer = [0; 16];
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"PONG", &buffer[..received_size]);
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"response".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.write_all(b"request").unwrap();
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"response", &buffer[..received_size]);
+
+    // Only the scripted message is recorded
+    assert_eq!(
+        b"request",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_received_message().is_none());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_default_response_between_instructions() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        default_response: Some(b"ACK".to_vec()),
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"hello".to_vec())])
+        .unwrap();
+
+    client.send(b"first").unwrap();
+    let mut buffer = [0; 16];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"hello", &buffer[..received_size]);
+
+    // Telemetry not expected by the script
+    client.send(b"telemetry").unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"ACK", &buffer[..received_size]);
+
+    assert_eq!(b"first", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_default_response_set_on_server() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"welcome".to_vec())])
+        .unwrap();
+    // Only answered once the scripted exchange above is over
+    server.default_response(b"PONG").unwrap();
+
+    client.write_all(b"hello").unwrap();
+    let mut buffer = [0; 16];
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"welcome", &buffer[..received_size]);
+
+    client.write_all(b"PING").unwrap();
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"PONG", &buffer[..received_size]);
+
+    assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_received_message().is_none());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_default_response_from_closure() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(server.options().net_timeout))
+        .unwrap();
+
+    // Acknowledge each heartbeat with its sequence number, and ignore anything else
+    let mut acknowledged = 0;
+    server
+        .default_response_from_closure(move |data| {
+            let sequence_number = data?.strip_prefix(b"HEARTBEAT ")?.to_vec();
+            acknowledged += 1;
+            Some([format!("ACK{acknowledged} ").into_bytes(), sequence_number].concat())
+        })
+        .unwrap();
+
+    let mut buffer = [0; 16];
+    client.send(b"HEARTBEAT 7").unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"ACK1 7", &buffer[..received_size]);
+
+    client.send(b"garbage").unwrap();
+    client.send(b"HEARTBEAT 8").unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"ACK2 8", &buffer[..received_size]);
+
+    assert!(server.pop_received_message().is_none());
+    assert!(server.pop_server_error().is_none());
+}
@@ -0,0 +1,101 @@
+//! Canned DHCP server leasing an address to a client
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::Duration;
+
+use socket_server_mocker::protocols::dhcp::DhcpMockBuilder;
+use socket_server_mocker::ServerMocker;
+
+const XID: [u8; 4] = [0x3d, 0x1d, 0x5a, 0x7e];
+const CLIENT_MAC: [u8; 6] = [0x02, 0x42, 0xac, 0x11, 0x00, 0x02];
+
+/// DHCP message of the given type sent by the client, with the given requested address option
+fn client_message(message_type: u8, requested_ip: Option<Ipv4Addr>) -> Vec<u8> {
+    // BOOTREQUEST over Ethernet, broadcast flag set
+    let mut message = vec![1, 1, 6, 0];
+    message.extend_from_slice(&XID);
+    message.extend_from_slice(&[0, 0, 0x80, 0]);
+    message.extend_from_slice(&[0; 16]);
+    message.extend_from_slice(&CLIENT_MAC);
+    message.resize(236, 0);
+    message.extend_from_slice(&[99, 130, 83, 99, 53, 1, message_type]);
+    if let Some(requested_ip) = requested_ip {
+        message.extend_from_slice(&[50, 4]);
+        message.extend_from_slice(&requested_ip.octets());
+    }
+    message.push(255);
+    message
+}
+
+/// Value of the given option of a DHCP message
+fn option(message: &[u8], code: u8) -> Option<&[u8]> {
+    let mut options = &message[240..];
+    while let [option_code, length, rest @ ..] = options {
+        let (value, rest) = rest.split_at(usize::from(*length));
+        if *option_code == code {
+            return Some(value);
+        }
+        options = rest;
+    }
+    None
+}
+
+#[test]
+fn test_dhcp_lease() {
+    let server = ServerMocker::udp().unwrap();
+    server
+        .add_mock_instructions(
+            DhcpMockBuilder::new(Ipv4Addr::new(10, 0, 0, 42))
+                .server_ip(Ipv4Addr::new(10, 0, 0, 1))
+                .lease_time(Duration::from_secs(3600))
+                .router(Ipv4Addr::new(10, 0, 0, 1))
+                .dns_servers(&[Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 3)])
+                .build(),
+        )
+        .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    let mut buffer = [0; 576];
+
+    // DISCOVER answered by an OFFER
+    client.send(&client_message(1, None)).unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    let offer = &buffer[..received_size];
+    assert_eq!(2, offer[0]);
+    assert_eq!(XID, offer[4..8]);
+    assert_eq!([0x80, 0], offer[10..12]);
+    assert_eq!([10, 0, 0, 42], offer[16..20]);
+    assert_eq!(CLIENT_MAC, offer[28..34]);
+    assert_eq!(Some(&[2][..]), option(offer, 53));
+    assert_eq!(Some(&[10, 0, 0, 1][..]), option(offer, 54));
+    assert_eq!(Some(&3600u32.to_be_bytes()[..]), option(offer, 51));
+    assert_eq!(Some(&[255, 255, 255, 0][..]), option(offer, 1));
+    assert_eq!(Some(&[10, 0, 0, 1][..]), option(offer, 3));
+    assert_eq!(Some(&[10, 0, 0, 2, 10, 0, 0, 3][..]), option(offer, 6));
+
+    // REQUEST of the offered address answered by an ACK
+    client
+        .send(&client_message(3, Some(Ipv4Addr::new(10, 0, 0, 42))))
+        .unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    let ack = &buffer[..received_size];
+    assert_eq!(XID, ack[4..8]);
+    assert_eq!([10, 0, 0, 42], ack[16..20]);
+    assert_eq!(Some(&[5][..]), option(ack, 53));
+
+    // REQUEST of another address answered by a NAK
+    client
+        .send(&client_message(3, Some(Ipv4Addr::new(10, 0, 0, 7))))
+        .unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    let nak = &buffer[..received_size];
+    assert_eq!([0; 4], nak[16..20]);
+    assert_eq!(Some(&[6][..]), option(nak, 53));
+    assert_eq!(None, option(nak, 51));
+
+    assert_eq!(
+        client_message(1, None),
+        server.pop_received_message().unwrap()
+    );
+    assert!(server.pop_server_error().is_none());
+}
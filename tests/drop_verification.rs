@@ -0,0 +1,42 @@
+//! Verification, when the server mocker is dropped, that all instructions were executed
+
+use std::net::TcpStream;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::ServerMocker;
+
+#[test]
+#[should_panic(
+    expected = "Mocked server dropped before executing 2 instructions:\n#0 ReceiveMessage\n#1 StopExchange"
+)]
+fn test_client_never_connected() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+    // The client never connects, so the instructions never run
+}
+
+#[test]
+#[should_panic(expected = "#2 SendMessage (3 bytes)")]
+fn test_instructions_after_stop_exchange() {
+    let server = ServerMocker::tcp().unwrap();
+    let _client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            SendMessage(vec![1, 2, 3]),
+            StopExchange,
+            SendMessage(vec![4, 5, 6]),
+        ])
+        .unwrap();
+}
+
+#[test]
+fn test_all_instructions_executed() {
+    let server = ServerMocker::tcp().unwrap();
+    let _client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![SendMessage(vec![1, 2, 3]), StopExchange])
+        .unwrap();
+    // Dropping the server waits for the instructions to be executed, so no panic
+}
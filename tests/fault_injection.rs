@@ -0,0 +1,214 @@
+//! Network faults injected by the server mocker
+
+use std::io::Read;
+use std::net::{TcpStream, UdpSocket};
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, Repeat, SendMessage, StopExchange};
+use socket_server_mocker::{ChaosConfig, Matcher, ServerMocker, TcpMocker, Times, UdpMocker};
+
+#[test]
+fn test_udp_drop_all_datagrams() {
+    let server = ServerMocker::new_with_opts(UdpMocker::default().drop_probability(1.0)).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"lost".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.send(b"request").unwrap();
+    let mut buffer = [0; 8];
+    assert!(client.recv(&mut buffer).is_err());
+
+    // Only outbound datagrams are dropped
+    assert_eq!(
+        b"request",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_client_retransmission() {
+    let server = ServerMocker::new_with_opts(UdpMocker::default().chaos(ChaosConfig {
+        drop_probability: 0.5,
+        drop_inbound: true,
+        ..ChaosConfig::default()
+    }))
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+
+    server
+        .add_mock_instructions(vec![Repeat {
+            times: Times::at_least(1),
+            instructions: vec![ReceiveMessage, SendMessage(b"pong".to_vec())],
+        }])
+        .unwrap();
+
+    // The client retries until both the request and the response go through
+    let mut buffer = [0; 8];
+    let received_size = (0..100)
+        .find_map(|_| {
+            client.send(b"ping").unwrap();
+            client.recv(&mut buffer).ok()
+        })
+        .unwrap();
+    assert_eq!(b"pong", &buffer[..received_size]);
+}
+
+#[test]
+fn test_udp_reorder_datagrams() {
+    let server = ServerMocker::new_with_opts(UdpMocker::default().reorder(0.5, 1)).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    let mut instructions = vec![ReceiveMessage];
+    instructions.extend((0..50).map(|sequence_number| SendMessage(vec![sequence_number])));
+    instructions.push(StopExchange);
+    server.add_mock_instructions(instructions).unwrap();
+
+    client.send(b"stream").unwrap();
+    let mut buffer = [0; 8];
+    let received: Vec<u8> = (0..50)
+        .map(|_| {
+            let received_size = client.recv(&mut buffer).unwrap();
+            assert_eq!(1, received_size);
+            buffer[0]
+        })
+        .collect();
+    // Every datagram is received, but not in order
+    let mut sorted = received.clone();
+    sorted.sort_unstable();
+    assert_eq!((0..50).collect::<Vec<u8>>(), sorted);
+    assert_ne!(sorted, received);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_fragmented_messages() {
+    let server = ServerMocker::new_with_opts(TcpMocker::default().fragment(4)).unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    let message = b"HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\nHello world!".to_vec();
+    server
+        .add_mock_instructions(vec![SendMessage(message.clone()), StopExchange])
+        .unwrap();
+
+    // The message can't be received with a single read
+    let mut received = Vec::new();
+    let mut read_count = 0;
+    let mut buffer = [0; 1024];
+    while received.len() < message.len() {
+        let bytes_read = client.read(&mut buffer).unwrap();
+        received.extend_from_slice(&buffer[..bytes_read]);
+        read_count += 1;
+    }
+    assert!(read_count > 1);
+    assert_eq!(message, received);
+    assert!(server.pop_server_error().is_none());
+}
+
+/// Send 20 numbered datagrams with the given chaos, and return the ones received by the client
+fn receive_under_chaos(chaos: ChaosConfig) -> Vec<u8> {
+    let server = ServerMocker::new_with_opts(UdpMocker::default().chaos(chaos)).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+
+    let mut instructions = vec![ReceiveMessage];
+    instructions.extend((0..20).map(|sequence_number| SendMessage(vec![sequence_number])));
+    instructions.push(StopExchange);
+    server.add_mock_instructions(instructions).unwrap();
+
+    client.send(b"stream").unwrap();
+    let mut buffer = [0; 8];
+    let mut received = Vec::new();
+    while let Ok(received_size) = client.recv(&mut buffer) {
+        received.extend_from_slice(&buffer[..received_size]);
+    }
+    received
+}
+
+#[test]
+fn test_udp_chaos_replay() {
+    let chaos = ChaosConfig {
+        drop_probability: 0.3,
+        duplicate_probability: 0.3,
+        reorder_probability: 0.3,
+        ..ChaosConfig::with_seed(1234)
+    };
+    let received = receive_under_chaos(chaos.clone());
+    assert_ne!((0..20).collect::<Vec<u8>>(), received);
+
+    // The same seed injects the same faults
+    assert_eq!(received, receive_under_chaos(chaos));
+}
+
+#[test]
+fn test_udp_chaos_corruption() {
+    let received = receive_under_chaos(ChaosConfig {
+        corrupt_probability: 1.0,
+        ..ChaosConfig::default()
+    });
+    assert_eq!(20, received.len());
+    // A single bit of each datagram is flipped
+    for (sequence_number, byte) in (0..20).zip(received) {
+        assert_eq!(1, (sequence_number ^ byte).count_ones());
+    }
+}
+
+#[test]
+fn test_chaos_seed_in_failure_report() {
+    let server = ServerMocker::new_with_opts(UdpMocker::default().chaos(ChaosConfig {
+        max_delay: Duration::from_millis(1),
+        ..ChaosConfig::with_seed(98765)
+    }))
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+    client.send(b"hello").unwrap();
+
+    let mismatch = server
+        .verify_sequence(&[Matcher::Exact(b"bye".to_vec())])
+        .unwrap_err();
+    assert!(mismatch.to_string().ends_with("Chaos seed: 98765"));
+}
+
+#[test]
+fn test_chaos_seed_in_error_report() {
+    let server = ServerMocker::new_with_opts(TcpMocker::default().chaos(ChaosConfig {
+        fragment_max_size: Some(4),
+        ..ChaosConfig::with_seed(4321)
+    }))
+    .unwrap();
+    assert_eq!(Some(4321), server.options().chaos.replay_seed());
+    let client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+    // The client leaves without sending anything
+    drop(client);
+
+    let report = server.pop_server_error_with_context().unwrap();
+    assert_eq!(Some(4321), report.chaos_seed);
+    assert!(report.to_string().contains("chaos seed 4321"));
+}
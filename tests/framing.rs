@@ -0,0 +1,216 @@
+//! Split the data received by the server mocker into protocol messages
+
+use std::io::Write;
+use std::net::{Shutdown, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{
+    Endianness, Framer, Framing, ReadInterruption, ServerMocker, ServerMockerError, TcpMocker,
+    UdpMocker,
+};
+
+/// Messages terminated by a semicolon
+struct SemicolonFramer;
+
+impl Framer for SemicolonFramer {
+    fn split(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        let end = buf.iter().position(|&byte| byte == b';')?;
+        Some(buf.drain(..=end).collect())
+    }
+}
+
+#[test]
+fn test_tcp_custom_framer() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        framer: Some(Arc::new(Mutex::new(SemicolonFramer))),
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage, StopExchange])
+        .unwrap();
+
+    // The first write holds a whole message and the beginning of the next one
+    client.write_all(b"SET a 1;SET").unwrap();
+    client.flush().unwrap();
+    sleep(Duration::from_millis(20));
+    client.write_all(b" b 2;").unwrap();
+
+    assert_eq!(
+        b"SET a 1;",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        b"SET b 2;",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_line_framing() {
+    let server = ServerMocker::new_with_opts(TcpMocker::default().framing(Framing::Line)).unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            ReceiveMessage,
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+
+    // Pipelined SMTP commands, written at once
+    client
+        .write_all(b"MAIL FROM:<alice@localhost>\r\nRCPT TO:<bob@localhost>\r\nDATA\n")
+        .unwrap();
+
+    assert_eq!(
+        b"MAIL FROM:<alice@localhost>\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        b"RCPT TO:<bob@localhost>\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(b"DATA\n", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_length_prefixed_framing() {
+    let server =
+        ServerMocker::new_with_opts(TcpMocker::default().framing(Framing::LengthPrefixed {
+            header_len: 2,
+            endianness: Endianness::Big,
+            length_adjustment: 0,
+        }))
+        .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage, StopExchange])
+        .unwrap();
+
+    // The first message spans two TCP segments, the second one holding the next message as well
+    client.write_all(b"\x00\x05he").unwrap();
+    client.flush().unwrap();
+    sleep(Duration::from_millis(20));
+    client.write_all(b"llo\x00\x01!").unwrap();
+
+    assert_eq!(
+        b"\x00\x05hello",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        b"\x00\x01!",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_incomplete_message() {
+    let server = ServerMocker::new_with_opts(TcpMocker::default().framing(Framing::Line)).unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage])
+        .unwrap();
+
+    // The client disconnects in the middle of the second line
+    client.write_all(b"PING\r\nPI").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    assert_eq!(
+        b"PING\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_received_message().is_none());
+    let err = server.pop_server_error().unwrap();
+    assert_eq!(
+        "Non fatal: Read interrupted by the client closing the connection after receiving 2 bytes: b\"PI\"",
+        err.to_string()
+    );
+    match err {
+        ServerMockerError::ReadInterrupted(ReadInterruption::ConnectionClosed, received) => {
+            assert_eq!(b"PI", received.as_slice());
+        }
+        err => panic!("Unexpected server error: {err:?}"),
+    }
+}
+
+#[test]
+fn test_udp_custom_framer() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        framer: Some(Arc::new(Mutex::new(SemicolonFramer))),
+        ..Default::default()
+    })
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            ReceiveMessage,
+            SendMessage(b"OK".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // A single datagram holding two messages
+    client.send(b"cpu:1;mem:2;").unwrap();
+    let mut buffer = [0; 8];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"OK", &buffer[..received_size]);
+
+    assert_eq!(b"cpu:1;", server.pop_received_message().unwrap().as_slice());
+    assert_eq!(b"mem:2;", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_interleaved_clients() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        framer: Some(Arc::new(Mutex::new(SemicolonFramer))),
+        ..Default::default()
+    })
+    .unwrap();
+    let first_client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    first_client.connect(server.socket_address()).unwrap();
+    let second_client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    second_client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"first".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"second".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // The second client starts a message before the first one completes its own
+    first_client.send(b"cpu").unwrap();
+    second_client.send(b"mem").unwrap();
+    first_client.send(b":1;").unwrap();
+    let mut buffer = [0; 8];
+    let received_size = first_client.recv(&mut buffer).unwrap();
+    assert_eq!(b"first", &buffer[..received_size]);
+
+    second_client.send(b":2;").unwrap();
+    let received_size = second_client.recv(&mut buffer).unwrap();
+    assert_eq!(b"second", &buffer[..received_size]);
+
+    assert_eq!(b"cpu:1;", server.pop_received_message().unwrap().as_slice());
+    assert_eq!(b"mem:2;", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
@@ -0,0 +1,147 @@
+//! Mock a gRPC server called by a tonic client
+
+use prost::Message;
+use socket_server_mocker::protocols::grpc::{
+    GrpcMockBuilder, GrpcResponse, NOT_FOUND, UNAVAILABLE,
+};
+use socket_server_mocker::ServerMocker;
+use tonic::client::Grpc;
+use tonic::codec::{ProstCodec, Streaming};
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Response, Status};
+
+#[derive(Clone, PartialEq, Message)]
+struct HelloRequest {
+    #[prost(string, tag = "1")]
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct HelloReply {
+    #[prost(string, tag = "1")]
+    message: String,
+}
+
+/// Connect to the given server mocker over cleartext HTTP/2
+async fn connect(server: &ServerMocker<socket_server_mocker::TcpMocker>) -> Grpc<Channel> {
+    let channel = Channel::from_shared(format!("http://{}", server.socket_address()))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    Grpc::new(channel)
+}
+
+async fn say_hello(
+    client: &mut Grpc<Channel>,
+    path: &'static str,
+    name: &str,
+) -> Result<Response<HelloReply>, Status> {
+    client.ready().await.unwrap();
+    let request = Request::new(HelloRequest {
+        name: name.to_string(),
+    });
+    client
+        .unary(
+            request,
+            PathAndQuery::from_static(path),
+            ProstCodec::default(),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn test_grpc_unary() {
+    let server = ServerMocker::tcp().unwrap();
+    let grpc_mock = GrpcMockBuilder::new().respond_with("/helloworld.Greeter/SayHello", |call| {
+        let request = HelloRequest::decode(call.message().unwrap()).unwrap();
+        let reply = HelloReply {
+            message: format!("Hello {}", request.name),
+        };
+        GrpcResponse::ok(reply.encode_to_vec()).header("x-served-by", "mock")
+    });
+    let calls = grpc_mock.calls();
+    server.add_mock_instructions(grpc_mock.build()).unwrap();
+    let mut client = connect(&server).await;
+
+    let response = say_hello(&mut client, "/helloworld.Greeter/SayHello", "Alice")
+        .await
+        .unwrap();
+    assert_eq!("mock", response.metadata().get("x-served-by").unwrap());
+    assert_eq!("Hello Alice", response.into_inner().message);
+
+    let status = say_hello(&mut client, "/helloworld.Greeter/SayGoodbye", "Bob")
+        .await
+        .unwrap_err();
+    assert_eq!(Code::Unimplemented, status.code());
+    assert_eq!(
+        "Method not found: /helloworld.Greeter/SayGoodbye",
+        status.message()
+    );
+
+    let calls = calls.calls();
+    assert_eq!(2, calls.len());
+    assert_eq!("/helloworld.Greeter/SayHello", calls[0].path);
+    assert_eq!(Some("application/grpc"), calls[0].metadata("content-type"));
+    assert_eq!(
+        HelloRequest {
+            name: "Bob".to_string()
+        },
+        HelloRequest::decode(calls[1].message().unwrap()).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_grpc_error_status() {
+    let server = ServerMocker::tcp().unwrap();
+    let reply = HelloReply {
+        message: "Hello".to_string(),
+    }
+    .encode_to_vec();
+    server
+        .add_mock_instructions(
+            GrpcMockBuilder::new()
+                .respond(
+                    "/helloworld.Greeter/SayHello",
+                    GrpcResponse::error(UNAVAILABLE, "Serveur indisponible, réessayez à 100%")
+                        .trailer("retry-after", "1"),
+                )
+                .respond(
+                    "/helloworld.Greeter/SayHelloStream",
+                    GrpcResponse::stream(vec![reply.clone(), reply])
+                        .status(NOT_FOUND, "No more greetings"),
+                )
+                .build(),
+        )
+        .unwrap();
+    let mut client = connect(&server).await;
+
+    let status = say_hello(&mut client, "/helloworld.Greeter/SayHello", "Alice")
+        .await
+        .unwrap_err();
+    assert_eq!(Code::Unavailable, status.code());
+    assert_eq!("Serveur indisponible, réessayez à 100%", status.message());
+    assert_eq!("1", status.metadata().get("retry-after").unwrap());
+
+    // Server streaming call failing after its messages
+    client.ready().await.unwrap();
+    let request = Request::new(HelloRequest {
+        name: "Bob".to_string(),
+    });
+    let mut stream: Streaming<HelloReply> = client
+        .server_streaming(
+            request,
+            PathAndQuery::from_static("/helloworld.Greeter/SayHelloStream"),
+            ProstCodec::default(),
+        )
+        .await
+        .unwrap()
+        .into_inner();
+    for _ in 0..2 {
+        assert_eq!("Hello", stream.message().await.unwrap().unwrap().message);
+    }
+    let status = stream.message().await.unwrap_err();
+    assert_eq!(Code::NotFound, status.code());
+    assert_eq!("No more greetings", status.message());
+}
@@ -0,0 +1,117 @@
+//! Callbacks invoked on the lifecycle events of the exchange
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{Hooks, ServerMocker, ServerMockerError, TcpMocker, UdpMocker};
+
+/// Event observed by the hooks
+#[derive(Debug, PartialEq)]
+enum Event {
+    Connect,
+    Disconnect,
+    Received(Vec<u8>),
+    Sent(Vec<u8>),
+    Error,
+}
+
+/// Hooks forwarding every event to the returned receiver
+fn recording_hooks() -> (Hooks, mpsc::Receiver<Event>) {
+    let (event_tx, event_rx) = mpsc::channel();
+    let (connect_tx, disconnect_tx, received_tx, sent_tx, error_tx) = (
+        event_tx.clone(),
+        event_tx.clone(),
+        event_tx.clone(),
+        event_tx.clone(),
+        event_tx,
+    );
+    let hooks = Hooks::default()
+        .on_connect(move |_| connect_tx.send(Event::Connect).unwrap())
+        .on_disconnect(move |_| disconnect_tx.send(Event::Disconnect).unwrap())
+        .on_message_received(move |message| {
+            received_tx.send(Event::Received(message.to_vec())).unwrap();
+        })
+        .on_message_sent(move |message| sent_tx.send(Event::Sent(message.to_vec())).unwrap())
+        .on_error(move |error: &ServerMockerError| {
+            assert!(!error.is_fatal());
+            error_tx.send(Event::Error).unwrap();
+        });
+    (hooks, event_rx)
+}
+
+#[test]
+fn test_tcp_hooks() {
+    let (hooks, event_rx) = recording_hooks();
+    let server = ServerMocker::new_with_opts(TcpMocker::default().hooks(hooks)).unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    let next_event = || event_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+    // Invoked as soon as the connection is accepted, without waiting for instructions
+    assert_eq!(Event::Connect, next_event());
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            // The client doesn't send anything more
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let mut buffer = [0; 4];
+    client.read_exact(&mut buffer).unwrap();
+
+    assert_eq!(Event::Received(b"ping".to_vec()), next_event());
+    assert_eq!(Event::Sent(b"pong".to_vec()), next_event());
+    assert_eq!(Event::Error, next_event());
+    assert_eq!(Event::Disconnect, next_event());
+}
+
+#[test]
+fn test_udp_hooks() {
+    let (hooks, event_rx) = recording_hooks();
+    let server = ServerMocker::new_with_opts(UdpMocker::default().hooks(hooks)).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let next_event = || event_rx.recv_timeout(Duration::from_secs(1)).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+    let mut buffer = [0; 4];
+    for _ in 0..2 {
+        client.send_to(b"ping", server.socket_address()).unwrap();
+    }
+    client.recv(&mut buffer).unwrap();
+
+    // Only the first datagram of a client connects it
+    assert_eq!(Event::Connect, next_event());
+    assert_eq!(Event::Received(b"ping".to_vec()), next_event());
+    assert_eq!(Event::Sent(b"pong".to_vec()), next_event());
+    assert_eq!(Event::Received(b"ping".to_vec()), next_event());
+    assert!(server.pop_server_error().is_none());
+    assert!(event_rx.try_recv().is_err());
+}
+
+#[test]
+fn test_hooks_run_in_named_server_thread() {
+    let (name_tx, name_rx) = mpsc::channel();
+    let hooks = Hooks::default().on_connect(move |_| {
+        let name = std::thread::current().name().map(str::to_string);
+        name_tx.send(name).unwrap();
+    });
+    let server = ServerMocker::new_with_opts(TcpMocker::default().hooks(hooks)).unwrap();
+    let _client = TcpStream::connect(server.socket_address()).unwrap();
+
+    assert_eq!(
+        Some(format!("ssm-tcp-{}", server.socket_address())),
+        name_rx.recv_timeout(Duration::from_secs(1)).unwrap()
+    );
+}
@@ -0,0 +1,170 @@
+//! Canned HTTP responses routed by method and path
+
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+
+use socket_server_mocker::{HttpMock, ServerMocker};
+
+#[test]
+fn test_http_mocks_routing() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_http_mocks(vec![
+            HttpMock::new("GET", "/users/42")
+                .header("Content-Type", "application/json")
+                .body(r#"{"id":42}"#),
+            HttpMock::new("POST", "/users").status(201),
+        ])
+        .unwrap();
+
+    let client = Client::new();
+    let base_url = format!("http://localhost:{}", server.port());
+    let response = client
+        .get(format!("{base_url}/users/42?fields=id"))
+        .send()
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!("application/json", response.headers()["content-type"]);
+    assert_eq!(r#"{"id":42}"#, response.text().unwrap());
+
+    let response = client
+        .post(format!("{base_url}/users"))
+        .body("name=alice")
+        .send()
+        .unwrap();
+    assert_eq!(StatusCode::CREATED, response.status());
+
+    let response = client.get(format!("{base_url}/groups")).send().unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    assert!(server
+        .pop_received_message()
+        .unwrap()
+        .starts_with(b"GET /users/42?fields=id HTTP/1.1\r\n"));
+    assert!(server.pop_server_error().is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_har_replay() {
+    let server = ServerMocker::tcp().unwrap();
+    server.load_har("tests/fixtures/session.har").unwrap();
+
+    let client = Client::new();
+    let job_url = format!("http://localhost:{}/v1/jobs/7", server.port());
+    // Successive entries for the same request are replayed in turn, the last one being repeated
+    for expected_state in ["running", "done", "done"] {
+        let response = client.get(&job_url).send().unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        // The body is replayed decoded
+        assert!(response.headers().get("content-encoding").is_none());
+        assert_eq!(
+            format!(r#"{{"state":"{expected_state}"}}"#),
+            response.text().unwrap()
+        );
+    }
+
+    let response = client
+        .delete(format!("{job_url}?force=true"))
+        .send()
+        .unwrap();
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+    // The query string of the entry must match
+    let response = client.delete(&job_url).send().unwrap();
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_wiremock_stub_mappings() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .load_stub_mappings("tests/fixtures/wiremock/mappings")
+        .unwrap();
+
+    let client = Client::new();
+    let base_url = format!("http://localhost:{}", server.port());
+    let start = std::time::Instant::now();
+    let response = client.get(format!("{base_url}/export")).send().unwrap();
+    assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(2, response.headers().get_all("set-cookie").iter().count());
+    assert_eq!("id,name\n1,alice\n", response.text().unwrap());
+
+    // The most specific stub has the highest priority
+    let response = client
+        .post(format!("{base_url}/users"))
+        .header("Authorization", "Bearer secret")
+        .body(r#"{"name":"bob"}"#)
+        .send()
+        .unwrap();
+    assert_eq!(StatusCode::CREATED, response.status());
+    assert_eq!("/users/2", response.headers()["location"]);
+    assert_eq!(r#"{"id":2}"#, response.text().unwrap());
+
+    let response = client
+        .post(format!("{base_url}/users"))
+        .body(r#"{"name":"bob"}"#)
+        .send()
+        .unwrap();
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_unsupported_stub_mapping() {
+    let server = ServerMocker::tcp().unwrap();
+    let path = std::env::temp_dir().join(format!("stub-{}.json", server.port()));
+    std::fs::write(
+        &path,
+        r#"{"request": {"urlPattern": "/users/.*"}, "response": {"status": 200}}"#,
+    )
+    .unwrap();
+    let error = server.load_stub_mappings(&path).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert!(error
+        .to_string()
+        .ends_with("unsupported request matcher `urlPattern`"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_openapi_mocks() {
+    let server = ServerMocker::tcp().unwrap();
+    // Mocks put first override the generated ones
+    let mut mocks = vec![HttpMock::new("GET", "/v1/users/0").status(404)];
+    mocks.extend(HttpMock::from_openapi("tests/fixtures/openapi.yaml").unwrap());
+    server.add_http_mocks(mocks).unwrap();
+
+    let client = Client::new();
+    let base_url = format!("http://localhost:{}/v1", server.port());
+    let get = |path: &str| client.get(format!("{base_url}{path}")).send().unwrap();
+
+    // Synthesized from the schema
+    let response = get("/users");
+    assert_eq!("application/json", response.headers()["content-type"]);
+    assert_eq!(
+        r#"[{"id":7,"name":"string","role":"admin"}]"#,
+        response.text().unwrap()
+    );
+    // Literal paths aren't shadowed by templated ones
+    assert_eq!(
+        r#"{"id":1,"name":"alice"}"#,
+        get("/users/me").text().unwrap()
+    );
+    assert_eq!(StatusCode::OK, get("/users/42").status());
+    assert_eq!(StatusCode::NOT_FOUND, get("/users/0").status());
+    assert_eq!("OK", get("/health").text().unwrap());
+
+    let response = client.post(format!("{base_url}/users")).send().unwrap();
+    assert_eq!(StatusCode::CREATED, response.status());
+    assert_eq!(r#"{"id":2,"name":"bob"}"#, response.text().unwrap());
+    let response = client
+        .delete(format!("{base_url}/users/42"))
+        .send()
+        .unwrap();
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+    assert!(server.pop_server_error().is_none());
+}
@@ -1,5 +1,3 @@
-use std::str::from_utf8;
-
 use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
 use socket_server_mocker::ServerMocker;
 
@@ -36,7 +34,7 @@ fn http_get() {
             "GET / HTTP/1.1\r\naccept: */*\r\nhost: localhost:{}\r\n\r\n",
             server.port()
         ),
-        from_utf8(&server.pop_received_message().unwrap()).unwrap()
+        server.pop_received_string().unwrap()
     );
 
     // Check that no error has been raised by the mocked server
@@ -0,0 +1,113 @@
+//! Behavior of the server when no instruction is received for a while
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{Finish, IdlePolicy, ServerMocker, ServerMockerError, TcpMocker};
+
+#[test]
+fn test_keep_connection_open() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        idle_policy: IdlePolicy::KeepConnectionOpen,
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    let mut buffer = [0; 4];
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"pong".to_vec())])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    client.read_exact(&mut buffer).unwrap();
+
+    // The next batch comes much later than the rx timeout
+    sleep(3 * server.options().rx_timeout);
+    assert!(server.is_running());
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"pong", &buffer);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_close_connection_gracefully() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        idle_policy: IdlePolicy::CloseConnectionGracefully,
+        net_timeout: Duration::from_secs(1),
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![SendMessage(b"bye".to_vec())])
+        .unwrap();
+
+    // The client reads a clean end of stream after the response
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    assert_eq!(b"bye", response.as_slice());
+
+    // The server waits for the client to close its side
+    assert!(server.is_running());
+    drop(client);
+    sleep(Duration::from_millis(50));
+    assert!(!server.is_running());
+}
+
+#[test]
+fn test_finish_close_gracefully() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        finish_behavior: Finish::CloseGracefully,
+        // Never reached
+        rx_timeout: Duration::from_secs(10),
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"pong".to_vec())])
+        .unwrap();
+
+    // No trailing StopExchange is needed for the client to read the end of stream
+    let start = Instant::now();
+    client.write_all(b"ping").unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    assert_eq!(b"pong", response.as_slice());
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_finish_close_gracefully_refuses_late_instructions() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        finish_behavior: Finish::CloseGracefully,
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![SendMessage(b"pong".to_vec())])
+        .unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    assert_eq!(b"pong", response.as_slice());
+
+    // The script is over, so these instructions would never be executed
+    let error = server
+        .add_mock_instructions(vec![SendMessage(b"late".to_vec())])
+        .unwrap_err();
+    assert!(matches!(error, ServerMockerError::ServerStopped(_)));
+    assert!(server.pop_server_error().is_none());
+}
@@ -0,0 +1,239 @@
+//! Mock a Kafka broker, with requests encoded by hand
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use socket_server_mocker::protocols::kafka::{
+    KafkaMockBuilder, KafkaProducedRecords, NONE, NOT_LEADER_OR_FOLLOWER,
+    UNKNOWN_TOPIC_OR_PARTITION,
+};
+use socket_server_mocker::ServerMocker;
+
+/// Send the given request, and return the body of its response
+fn request(client: &mut TcpStream, api_key: i16, version: i16, body: &[u8]) -> Vec<u8> {
+    let mut request = Vec::new();
+    request.extend_from_slice(&api_key.to_be_bytes());
+    request.extend_from_slice(&version.to_be_bytes());
+    // Correlation ID and client ID
+    request.extend_from_slice(&42_i32.to_be_bytes());
+    push_string(&mut request, "test-client");
+    request.extend_from_slice(body);
+    let size = i32::try_from(request.len()).unwrap();
+    client.write_all(&size.to_be_bytes()).unwrap();
+    client.write_all(&request).unwrap();
+
+    let mut size = [0; 4];
+    client.read_exact(&mut size).unwrap();
+    let mut response = vec![0; usize::try_from(i32::from_be_bytes(size)).unwrap()];
+    client.read_exact(&mut response).unwrap();
+    assert_eq!(42_i32.to_be_bytes(), response[..4]);
+    response.split_off(4)
+}
+
+fn push_string(buffer: &mut Vec<u8>, value: &str) {
+    buffer.extend_from_slice(&i16::try_from(value.len()).unwrap().to_be_bytes());
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+/// Cursor over a response
+struct Response(Vec<u8>);
+
+impl Response {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let rest = self.0.split_off(N);
+        std::mem::replace(&mut self.0, rest).try_into().unwrap()
+    }
+
+    fn i16(&mut self) -> i16 {
+        i16::from_be_bytes(self.take())
+    }
+
+    fn i32(&mut self) -> i32 {
+        i32::from_be_bytes(self.take())
+    }
+
+    fn i64(&mut self) -> i64 {
+        i64::from_be_bytes(self.take())
+    }
+
+    fn string(&mut self) -> String {
+        let length = usize::try_from(self.i16()).unwrap();
+        let rest = self.0.split_off(length);
+        String::from_utf8(std::mem::replace(&mut self.0, rest)).unwrap()
+    }
+}
+
+fn connected_client(server: &ServerMocker<socket_server_mocker::TcpMocker>) -> TcpStream {
+    let client = TcpStream::connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    client
+}
+
+#[test]
+fn test_kafka_metadata() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            KafkaMockBuilder::new(server.socket_address())
+                .broker(1, "127.0.0.1:9093".parse().unwrap())
+                .topic("events", 2)
+                .partition_leader("events", 1, 1)
+                .build(),
+        )
+        .unwrap();
+    let mut client = connected_client(&server);
+
+    // ApiVersions version 0, listing Produce, Fetch, Metadata and ApiVersions
+    let mut response = Response(request(&mut client, 18, 0, &[]));
+    assert_eq!(NONE, response.i16());
+    assert_eq!(4, response.i32());
+
+    // Metadata version 1 for two topics
+    let mut body = 2_i32.to_be_bytes().to_vec();
+    push_string(&mut body, "events");
+    push_string(&mut body, "missing");
+    let mut response = Response(request(&mut client, 3, 1, &body));
+    assert_eq!(2, response.i32());
+    assert_eq!(0, response.i32());
+    assert_eq!("127.0.0.1", response.string());
+    assert_eq!(i32::from(server.port()), response.i32());
+    assert_eq!(-1, response.i16());
+    assert_eq!(1, response.i32());
+    assert_eq!("127.0.0.1", response.string());
+    assert_eq!(9093, response.i32());
+    assert_eq!(-1, response.i16());
+    // Controller ID
+    assert_eq!(0, response.i32());
+
+    assert_eq!(2, response.i32());
+    assert_eq!(NONE, response.i16());
+    assert_eq!("events", response.string());
+    assert_eq!([0], response.take());
+    assert_eq!(2, response.i32());
+    for (partition, leader) in [(0, 0), (1, 1)] {
+        assert_eq!(NONE, response.i16());
+        assert_eq!(partition, response.i32());
+        assert_eq!(leader, response.i32());
+        // Replicas and in-sync replicas
+        for _ in 0..2 {
+            assert_eq!(1, response.i32());
+            assert_eq!(leader, response.i32());
+        }
+    }
+    assert_eq!(UNKNOWN_TOPIC_OR_PARTITION, response.i16());
+    assert_eq!("missing", response.string());
+    assert_eq!([0], response.take());
+    assert_eq!(0, response.i32());
+    assert!(response.0.is_empty());
+}
+
+/// Record batch header of the given count of records, the records themselves being left out
+fn record_batch(count: i32) -> Vec<u8> {
+    let mut batch = 0_i64.to_be_bytes().to_vec();
+    // Batch length, partition leader epoch and magic
+    batch.extend_from_slice(&49_i32.to_be_bytes());
+    batch.extend_from_slice(&0_i32.to_be_bytes());
+    batch.push(2);
+    // CRC and attributes, then last offset delta
+    batch.extend_from_slice(&[0; 6]);
+    batch.extend_from_slice(&(count - 1).to_be_bytes());
+    batch.resize(61, 0);
+    batch
+}
+
+#[test]
+fn test_kafka_produce_not_leader() {
+    let server = ServerMocker::tcp().unwrap();
+    let kafka_mock = KafkaMockBuilder::new(server.socket_address())
+        .topic("events", 2)
+        .partition_error("events", 1, NOT_LEADER_OR_FOLLOWER);
+    let activity = kafka_mock.activity();
+    server.add_mock_instructions(kafka_mock.build()).unwrap();
+    let mut client = connected_client(&server);
+
+    // Produce version 3: no transactional ID, all acknowledgements and 1 second timeout
+    let mut body = (-1_i16).to_be_bytes().to_vec();
+    body.extend_from_slice(&(-1_i16).to_be_bytes());
+    body.extend_from_slice(&1000_i32.to_be_bytes());
+    body.extend_from_slice(&1_i32.to_be_bytes());
+    push_string(&mut body, "events");
+    body.extend_from_slice(&2_i32.to_be_bytes());
+    for partition in 0..2 {
+        body.extend_from_slice(&i32::to_be_bytes(partition));
+        body.extend_from_slice(&61_i32.to_be_bytes());
+        body.extend_from_slice(&record_batch(3));
+    }
+    let mut response = Response(request(&mut client, 0, 3, &body));
+    assert_eq!(1, response.i32());
+    assert_eq!("events", response.string());
+    assert_eq!(2, response.i32());
+    for (partition, error_code, base_offset) in [(0, NONE, 0), (1, NOT_LEADER_OR_FOLLOWER, -1)] {
+        assert_eq!(partition, response.i32());
+        assert_eq!(error_code, response.i16());
+        assert_eq!(base_offset, response.i64());
+        // Log append time
+        assert_eq!(-1, response.i64());
+    }
+
+    // The offsets of the next records follow the 3 produced ones
+    let mut response = Response(request(&mut client, 0, 3, &body));
+    // Topic count, name and partition count
+    response.take::<16>();
+    assert_eq!(0, response.i32());
+    assert_eq!(NONE, response.i16());
+    assert_eq!(3, response.i64());
+
+    let produced = activity.produced();
+    assert_eq!(4, produced.len());
+    assert_eq!(
+        KafkaProducedRecords {
+            topic: "events".to_string(),
+            partition: 1,
+            records: record_batch(3),
+        },
+        produced[1]
+    );
+}
+
+#[test]
+fn test_kafka_fetch() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            KafkaMockBuilder::new(server.socket_address())
+                .topic("events", 1)
+                .fetch_records("events", 0, 3, record_batch(3))
+                .build(),
+        )
+        .unwrap();
+    let mut client = connected_client(&server);
+
+    for (fetch_offset, expected_records) in [(0, record_batch(3)), (3, Vec::new())] {
+        // Fetch version 0: replica ID, maximum wait time and minimum bytes
+        let mut body = (-1_i32).to_be_bytes().to_vec();
+        body.extend_from_slice(&100_i32.to_be_bytes());
+        body.extend_from_slice(&1_i32.to_be_bytes());
+        body.extend_from_slice(&1_i32.to_be_bytes());
+        push_string(&mut body, "events");
+        body.extend_from_slice(&1_i32.to_be_bytes());
+        body.extend_from_slice(&0_i32.to_be_bytes());
+        body.extend_from_slice(&i64::to_be_bytes(fetch_offset));
+        body.extend_from_slice(&1_048_576_i32.to_be_bytes());
+
+        let mut response = Response(request(&mut client, 1, 0, &body));
+        assert_eq!(1, response.i32());
+        assert_eq!("events", response.string());
+        assert_eq!(1, response.i32());
+        assert_eq!(0, response.i32());
+        assert_eq!(NONE, response.i16());
+        assert_eq!(3, response.i64());
+        assert_eq!(
+            i32::try_from(expected_records.len()).unwrap(),
+            response.i32()
+        );
+        assert_eq!(expected_records, response.0);
+    }
+}
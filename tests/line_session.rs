@@ -0,0 +1,100 @@
+//! Script the telnet console of a router one line at a time
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use socket_server_mocker::protocols::line_session::LineSessionBuilder;
+use socket_server_mocker::ServerMocker;
+
+/// Read from the server until the given prompt is received, returning everything read
+fn read_until_prompt(client: &mut BufReader<TcpStream>, prompt: &str) -> String {
+    let mut received = Vec::new();
+    while !received.ends_with(prompt.as_bytes()) {
+        let mut byte = [0];
+        client.read_exact(&mut byte).unwrap();
+        received.push(byte[0]);
+    }
+    String::from_utf8(received).unwrap()
+}
+
+#[test]
+fn test_telnet_session() {
+    let server = ServerMocker::tcp().unwrap();
+    let session = LineSessionBuilder::new()
+        .strip_telnet_negotiation()
+        .mismatch_reply("% Invalid input detected")
+        .send_line("User Access Verification")
+        .prompt("Username: ")
+        .expect_line("admin")
+        .prompt("Password: ")
+        .receive_line()
+        .prompt("router> ")
+        .expect_line("show version")
+        .send_line("Cisco IOS Software, Version 15.2(4)M")
+        .prompt("router> ")
+        .expect_line("exit");
+    let lines = session.received_lines();
+    server.add_mock_instructions(session.build()).unwrap();
+
+    let mut client = BufReader::new(TcpStream::connect(server.socket_address()).unwrap());
+    client
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    assert_eq!(
+        "User Access Verification\r\nUsername: ",
+        read_until_prompt(&mut client, "Username: ")
+    );
+    // IAC DO ECHO, IAC SB NAWS 80x24 IAC SE, then the username
+    client
+        .get_mut()
+        .write_all(b"\xff\xfd\x01\xff\xfa\x1f\x00\x50\x00\x18\xff\xf0admin\r\n")
+        .unwrap();
+    read_until_prompt(&mut client, "Password: ");
+    client.get_mut().write_all(b"secret\r\n").unwrap();
+    read_until_prompt(&mut client, "router> ");
+    client.get_mut().write_all(b"show version\n").unwrap();
+    assert_eq!(
+        "Cisco IOS Software, Version 15.2(4)M\r\nrouter> ",
+        read_until_prompt(&mut client, "router> ")
+    );
+    client.get_mut().write_all(b"quit\r\n").unwrap();
+    let mut reply = String::new();
+    client.read_line(&mut reply).unwrap();
+    assert_eq!("% Invalid input detected\r\n", reply);
+
+    assert_eq!(
+        vec!["admin", "secret", "show version", "quit"],
+        lines.lines()
+    );
+    assert_eq!(
+        vec![("exit".to_string(), "quit".to_string())],
+        lines.mismatches()
+    );
+}
+
+#[test]
+fn test_line_session_without_telnet() {
+    let server = ServerMocker::tcp().unwrap();
+    let session = LineSessionBuilder::new()
+        .line_ending("\n")
+        .prompt("> ")
+        .expect_line("*IDN?")
+        .send_line("ACME,Oscilloscope,1234,1.0");
+    let lines = session.received_lines();
+    server.add_mock_instructions(session.build()).unwrap();
+
+    let mut client = BufReader::new(TcpStream::connect(server.socket_address()).unwrap());
+    read_until_prompt(&mut client, "> ");
+    // Lines split across writes
+    client.get_mut().write_all(b"*ID").unwrap();
+    client.get_mut().flush().unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    client.get_mut().write_all(b"N?\r\n").unwrap();
+    let mut reply = String::new();
+    client.read_line(&mut reply).unwrap();
+    assert_eq!("ACME,Oscilloscope,1234,1.0\n", reply);
+    assert_eq!(vec!["*IDN?"], lines.lines());
+    assert!(lines.mismatches().is_empty());
+}
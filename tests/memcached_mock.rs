@@ -0,0 +1,152 @@
+//! Mock a memcached server, in the text and the binary protocols
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use socket_server_mocker::protocols::memcached::MemcachedMockBuilder;
+use socket_server_mocker::ServerMocker;
+
+fn connected_client(server: &ServerMocker<socket_server_mocker::TcpMocker>) -> TcpStream {
+    let client = TcpStream::connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    client
+}
+
+/// Read exactly the expected reply
+fn assert_reply(client: &mut TcpStream, expected: &[u8]) {
+    let mut reply = vec![0; expected.len()];
+    client.read_exact(&mut reply).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(expected),
+        String::from_utf8_lossy(&reply)
+    );
+}
+
+#[test]
+fn test_memcached_text_protocol() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            MemcachedMockBuilder::new()
+                .item_with_flags("user:1", 5, b"alice".to_vec())
+                .error("user:3", "out of memory storing object")
+                .build(),
+        )
+        .unwrap();
+    let mut client = connected_client(&server);
+
+    // Hit and miss
+    client.write_all(b"get user:1 user:2\r\n").unwrap();
+    assert_reply(&mut client, b"VALUE user:1 5 5\r\nalice\r\nEND\r\n");
+
+    // The data block is sent separately from the command line
+    client.write_all(b"set user:2 0 0 3\r\n").unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    client.write_all(b"bob\r\n").unwrap();
+    assert_reply(&mut client, b"STORED\r\n");
+    client.write_all(b"gets user:2\r\n").unwrap();
+    assert_reply(&mut client, b"VALUE user:2 0 3 2\r\nbob\r\nEND\r\n");
+
+    // Pipelined commands
+    client
+        .write_all(b"delete user:1\r\ndelete user:1\r\nget user:1\r\n")
+        .unwrap();
+    assert_reply(&mut client, b"DELETED\r\nNOT_FOUND\r\nEND\r\n");
+
+    client.write_all(b"get user:3\r\n").unwrap();
+    assert_reply(
+        &mut client,
+        b"SERVER_ERROR out of memory storing object\r\n",
+    );
+    client.write_all(b"incr counter 1\r\n").unwrap();
+    assert_reply(&mut client, b"ERROR\r\n");
+
+    assert_eq!(
+        b"get user:1 user:2\r\n".to_vec(),
+        server.pop_received_message().unwrap()
+    );
+}
+
+/// Binary protocol request with the given opcode, extras, key and value
+fn binary_request(opcode: u8, extras: &[u8], key: &[u8], value: &[u8], opaque: u32) -> Vec<u8> {
+    let body_length = u32::try_from(extras.len() + key.len() + value.len()).unwrap();
+    let mut request = vec![0x80, opcode];
+    request.extend_from_slice(&u16::try_from(key.len()).unwrap().to_be_bytes());
+    request.extend_from_slice(&[u8::try_from(extras.len()).unwrap(), 0, 0, 0]);
+    request.extend_from_slice(&body_length.to_be_bytes());
+    request.extend_from_slice(&opaque.to_be_bytes());
+    request.extend_from_slice(&[0; 8]);
+    request.extend_from_slice(extras);
+    request.extend_from_slice(key);
+    request.extend_from_slice(value);
+    request
+}
+
+/// Status and value of the next binary response, after checking its opaque value
+fn read_binary_response(client: &mut TcpStream, opaque: u32) -> (u16, Vec<u8>) {
+    let mut header = [0; 24];
+    client.read_exact(&mut header).unwrap();
+    assert_eq!(0x81, header[0]);
+    assert_eq!(opaque.to_be_bytes(), header[12..16]);
+    let key_length = usize::from(u16::from_be_bytes([header[2], header[3]]));
+    let extras_length = usize::from(header[4]);
+    let body_length = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+    let mut body = vec![0; usize::try_from(body_length).unwrap()];
+    client.read_exact(&mut body).unwrap();
+    let status = u16::from_be_bytes([header[6], header[7]]);
+    (status, body[extras_length + key_length..].to_vec())
+}
+
+#[test]
+fn test_memcached_binary_protocol() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            MemcachedMockBuilder::new()
+                .item("user:1", b"alice".to_vec())
+                .error("user:3", "out of memory storing object")
+                .build(),
+        )
+        .unwrap();
+    let mut client = connected_client(&server);
+
+    // Get hit, then miss
+    client
+        .write_all(&binary_request(0x00, &[], b"user:1", &[], 1))
+        .unwrap();
+    assert_eq!((0, b"alice".to_vec()), read_binary_response(&mut client, 1));
+    client
+        .write_all(&binary_request(0x00, &[], b"user:2", &[], 2))
+        .unwrap();
+    assert_eq!(
+        (0x0001, b"Not found".to_vec()),
+        read_binary_response(&mut client, 2)
+    );
+
+    // Set with flags and no expiration, then get
+    let extras = [0, 0, 0, 7, 0, 0, 0, 0];
+    client
+        .write_all(&binary_request(0x01, &extras, b"user:2", b"bob", 3))
+        .unwrap();
+    assert_eq!((0, Vec::new()), read_binary_response(&mut client, 3));
+    client
+        .write_all(&binary_request(0x00, &[], b"user:2", &[], 4))
+        .unwrap();
+    assert_eq!((0, b"bob".to_vec()), read_binary_response(&mut client, 4));
+
+    // Delete, then the configured error
+    client
+        .write_all(&binary_request(0x04, &[], b"user:1", &[], 5))
+        .unwrap();
+    assert_eq!((0, Vec::new()), read_binary_response(&mut client, 5));
+    client
+        .write_all(&binary_request(0x00, &[], b"user:3", &[], 6))
+        .unwrap();
+    assert_eq!(
+        (0x0084, b"out of memory storing object".to_vec()),
+        read_binary_response(&mut client, 6)
+    );
+}
@@ -0,0 +1,94 @@
+//! Receive `StatsD` metrics over UDP and Graphite metrics over TCP, and assert on their aggregates
+
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+
+use socket_server_mocker::protocols::metrics::{MetricType, Metrics};
+use socket_server_mocker::Instruction::{ReceiveMessage, StopExchange};
+use socket_server_mocker::{Framing, ServerMocker, TcpMocker};
+
+#[test]
+fn test_statsd_metrics() {
+    let server = ServerMocker::udp().unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage, ReceiveMessage])
+        .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    client
+        .send(b"api.requests:1|c|#route:/login,method:post\napi.latency:12.5|ms|#route:/login")
+        .unwrap();
+    client
+        .send(b"api.requests:1|c|@0.25|#route:/users\napi.users:alice|s\nnot a metric")
+        .unwrap();
+    client
+        .send(b"api.connections:8|g\napi.connections:5|g\napi.latency:30|ms")
+        .unwrap();
+
+    let metrics = Metrics::parse_messages(&server.received_messages());
+    assert_eq!(7, metrics.0.len());
+    metrics.assert_counter("api.requests", 5.0..=5.0);
+    metrics
+        .with_tag("route", "/login")
+        .assert_counter("api.requests", 1.0..2.0);
+    metrics.assert_gauge("api.connections", ..6.0);
+    assert_eq!(vec![12.5, 30.0], metrics.values("api.latency"));
+
+    let set_member = metrics.named("api.users").next().unwrap();
+    assert_eq!(MetricType::Set, set_member.metric_type);
+    assert_eq!("alice", set_member.value);
+    assert_eq!(None, set_member.as_f64());
+    let first = &metrics.0[0];
+    assert_eq!(Some(Some("post")), first.tag("method"));
+    assert_eq!(None, first.tag("host"));
+}
+
+#[test]
+fn test_graphite_metrics() {
+    let server = ServerMocker::new_with_opts(TcpMocker::default().framing(Framing::Line)).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            ReceiveMessage,
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    // Lines split across writes
+    client
+        .write_all(b"servers.web01.load;dc=eu;env=prod 0.42 1700000000\nservers.web")
+        .unwrap();
+    client.flush().unwrap();
+    client
+        .write_all(b"01.load;dc=eu;env=prod 0.87 1700000060\nservers.web01.uptime 3600 -1\n")
+        .unwrap();
+
+    let metrics = Metrics::parse_messages(&server.received_messages());
+    assert_eq!(3, metrics.0.len());
+    metrics.assert_gauge("servers.web01.load", 0.8..0.9);
+    metrics
+        .with_tag("env", "prod")
+        .assert_gauge("servers.web01.load", 0.8..);
+    let load = metrics.named("servers.web01.load").next().unwrap();
+    assert_eq!(MetricType::Graphite, load.metric_type);
+    assert_eq!(Some(1_700_000_000), load.timestamp);
+    assert_eq!(Some(Some("eu")), load.tag("dc"));
+    // Timestamp left to the server
+    assert_eq!(None, metrics.0[2].timestamp);
+}
+
+#[test]
+#[should_panic(expected = "Counter api.errors is 2, expected in ..1.0")]
+fn test_counter_assertion_failure() {
+    let server = ServerMocker::udp().unwrap();
+    server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client
+        .send_to(b"api.errors:1|c\napi.errors:1|c", server.socket_address())
+        .unwrap();
+
+    Metrics::parse_messages(&server.received_messages()).assert_counter("api.errors", ..1.0);
+}
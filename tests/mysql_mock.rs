@@ -0,0 +1,107 @@
+//! Mock a `MySQL` server used by the mysql crate
+
+use std::time::Duration;
+
+use mysql::prelude::Queryable;
+use mysql::{Conn, OptsBuilder};
+use socket_server_mocker::protocols::mysql::{
+    MysqlMockBuilder, MysqlQueryBuilder, TYPE_LONG, TYPE_LONGLONG, TYPE_VAR_STRING,
+};
+use socket_server_mocker::ServerMocker;
+
+fn connect(port: u16, password: &str) -> mysql::Result<Conn> {
+    Conn::new(
+        OptsBuilder::new()
+            .ip_or_hostname(Some("127.0.0.1"))
+            .tcp_port(port)
+            .user(Some("root"))
+            .pass(Some(password))
+            .db_name(Some("mockeddatabase"))
+            // Don't look for a local Unix socket
+            .prefer_socket(false)
+            .tcp_connect_timeout(Some(Duration::from_secs(1)))
+            .read_timeout(Some(Duration::from_secs(1))),
+    )
+}
+
+/// The client reads the maximum packet size once authenticated
+fn max_allowed_packet_query() -> Vec<socket_server_mocker::Instruction> {
+    MysqlQueryBuilder::new()
+        .column("@@max_allowed_packet", TYPE_LONGLONG)
+        .row(&[Some("4194304")])
+        .build()
+}
+
+#[test]
+fn mysql_select_mock() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut instructions = MysqlMockBuilder::new()
+        .auth_switch()
+        .expect_credentials("root", "password")
+        .build();
+    instructions.extend(max_allowed_packet_query());
+    instructions.extend(
+        MysqlQueryBuilder::new()
+            .column("id", TYPE_LONG)
+            .column("name", TYPE_VAR_STRING)
+            .row(&[Some("1"), Some("alice")])
+            .row(&[Some("2"), None])
+            .build(),
+    );
+    server.add_mock_instructions(instructions).unwrap();
+
+    let mut conn = connect(server.port(), "password").unwrap();
+    let rows: Vec<(i32, Option<String>)> = conn.query("SELECT id, name FROM users").unwrap();
+    assert_eq!(vec![(1, Some("alice".to_string())), (2, None)], rows);
+
+    // Handshake response, then auth switch response and both queries
+    let handshake_response = server.pop_received_message().unwrap();
+    assert!(String::from_utf8_lossy(&handshake_response).contains("root\0"));
+    server.pop_received_message().unwrap();
+    server.pop_received_message().unwrap();
+    // COM_QUERY, with a sequence ID reset to 0
+    assert_eq!(
+        b"\x1b\x00\x00\x00\x03SELECT id, name FROM users",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn mysql_insert_mock() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut instructions = MysqlMockBuilder::new().build();
+    instructions.extend(max_allowed_packet_query());
+    instructions.extend(
+        MysqlQueryBuilder::new()
+            .affected_rows(1)
+            .last_insert_id(42)
+            .build(),
+    );
+    server.add_mock_instructions(instructions).unwrap();
+
+    let mut conn = connect(server.port(), "any password").unwrap();
+    conn.query_drop("INSERT INTO users (name) VALUES ('bob')")
+        .unwrap();
+    assert_eq!(1, conn.affected_rows());
+    assert_eq!(42, conn.last_insert_id());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn mysql_wrong_password() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            MysqlMockBuilder::new()
+                .expect_credentials("root", "password")
+                .build(),
+        )
+        .unwrap();
+
+    let Err(mysql::Error::MySqlError(error)) = connect(server.port(), "wrong") else {
+        panic!("The mocked server accepted a wrong password");
+    };
+    assert_eq!(1045, error.code);
+    assert_eq!("28000", error.state);
+}
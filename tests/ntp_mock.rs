@@ -0,0 +1,116 @@
+//! Canned NTP server answering the requests of an SNTP client
+
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use socket_server_mocker::protocols::ntp::NtpMockBuilder;
+use socket_server_mocker::ServerMocker;
+
+/// Current time as an NTP timestamp
+fn ntp_now() -> [u8; 8] {
+    let since_unix_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    // Seconds since 1900-01-01
+    let seconds = u32::try_from(since_unix_epoch.as_secs() + 2_208_988_800).unwrap();
+    let fraction = (u64::from(since_unix_epoch.subsec_nanos()) << 32) / 1_000_000_000;
+    let mut timestamp = [0; 8];
+    timestamp[..4].copy_from_slice(&seconds.to_be_bytes());
+    timestamp[4..].copy_from_slice(&u32::try_from(fraction).unwrap().to_be_bytes());
+    timestamp
+}
+
+/// Seconds since 1900 of the given NTP timestamp
+fn seconds(timestamp: &[u8]) -> f64 {
+    let seconds = u32::from_be_bytes(timestamp[..4].try_into().unwrap());
+    let fraction = u32::from_be_bytes(timestamp[4..8].try_into().unwrap());
+    f64::from(seconds) + f64::from(fraction) / 4_294_967_296.0
+}
+
+/// SNTP version 4 client request, with poll interval 6 and the given transmit timestamp
+fn client_request(transmit_timestamp: [u8; 8]) -> Vec<u8> {
+    let mut request = vec![0b0010_0011, 0, 6];
+    request.resize(40, 0);
+    request.extend_from_slice(&transmit_timestamp);
+    request
+}
+
+#[test]
+fn test_ntp_response_fields() {
+    let server = ServerMocker::udp().unwrap();
+    server
+        .add_mock_instructions(
+            NtpMockBuilder::new()
+                .stratum(2)
+                .reference_id(*b"GPS\0")
+                .root_delay(Duration::from_millis(1500))
+                .root_dispersion(Duration::from_millis(250))
+                .processing_delay(Duration::from_millis(5))
+                .build(),
+        )
+        .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    let transmit_timestamp = ntp_now();
+    client.send(&client_request(transmit_timestamp)).unwrap();
+    let mut buffer = [0; 128];
+    let received_size = client.recv(&mut buffer).unwrap();
+    let response = &buffer[..received_size];
+
+    assert_eq!(48, response.len());
+    // No leap warning, version 4 and server mode
+    assert_eq!(0b0010_0100, response[0]);
+    assert_eq!(2, response[1]);
+    assert_eq!(6, response[2]);
+    assert_eq!((-20_i8).to_be_bytes(), response[3..4]);
+    assert_eq!([0, 1, 0x80, 0], response[4..8]);
+    assert_eq!([0, 0, 0x40, 0], response[8..12]);
+    assert_eq!(*b"GPS\0", response[12..16]);
+    assert_eq!(transmit_timestamp, response[24..32]);
+    let receive_time = seconds(&response[32..40]);
+    assert!((receive_time - seconds(&transmit_timestamp)).abs() < 1.0);
+    assert!((seconds(&response[40..48]) - receive_time - 0.005).abs() < 0.001);
+
+    // Only client requests are answered
+    assert!(NtpMockBuilder::new().response(&[0b0010_0100; 48]).is_none());
+    assert_eq!(
+        client_request(transmit_timestamp),
+        *server.pop_received_message().unwrap()
+    );
+}
+
+#[test]
+fn test_ntp_clock_offset() {
+    let server = ServerMocker::udp().unwrap();
+    server
+        .add_mock_instructions(
+            NtpMockBuilder::new()
+                .behind_by(Duration::from_secs(90))
+                .leap_indicator(3)
+                .build(),
+        )
+        .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    let originate_timestamp = ntp_now();
+    client.send(&client_request(originate_timestamp)).unwrap();
+    let mut buffer = [0; 128];
+    let received_size = client.recv(&mut buffer).unwrap();
+    let destination_time = seconds(&ntp_now());
+    let response = &buffer[..received_size];
+
+    // Unsynchronized clock
+    assert_eq!(3, response[0] >> 6);
+    // Offset computed as in RFC 4330
+    let originate_time = seconds(&response[24..32]);
+    let receive_time = seconds(&response[32..40]);
+    let transmit_time = seconds(&response[40..48]);
+    let offset = ((receive_time - originate_time) + (transmit_time - destination_time)) / 2.0;
+    assert!((offset + 90.0).abs() < 0.1);
+}
@@ -0,0 +1,215 @@
+//! Export the traffic of the server mocker as a pcap capture, and replay captures
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{ServerMocker, ServerMockerError, TcpMocker, UdpMocker};
+
+/// Raw IP packets of a pcap capture with the `LINKTYPE_RAW` link type
+fn read_pcap(path: &std::path::Path) -> Vec<Vec<u8>> {
+    let capture = fs::read(path).unwrap();
+    assert_eq!(
+        0xa1b2_c3d4,
+        u32::from_le_bytes(capture[..4].try_into().unwrap())
+    );
+    assert_eq!(101, u32::from_le_bytes(capture[20..24].try_into().unwrap()));
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset < capture.len() {
+        let length = u32::from_le_bytes(capture[offset + 8..offset + 12].try_into().unwrap());
+        let start = offset + 16;
+        offset = start + length as usize;
+        packets.push(capture[start..offset].to_vec());
+    }
+    packets
+}
+
+#[test]
+fn test_export_tcp_pcap() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        capture: true,
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"+PONG\r\n".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.write_all(b"PING\r\n").unwrap();
+    let mut response = [0; 7];
+    client.read_exact(&mut response).unwrap();
+    assert!(server.pop_received_message().is_some());
+
+    let path = std::env::temp_dir().join(format!("tcp-{}.pcap", server.port()));
+    server.export_pcap(&path).unwrap();
+    let packets = read_pcap(&path);
+    fs::remove_file(&path).unwrap();
+
+    // Handshake, then the request and the response
+    assert_eq!(5, packets.len());
+    let client_port = client.local_addr().unwrap().port();
+    let request = &packets[3];
+    // IPv4 over TCP, from the client to the server
+    assert_eq!(0x45, request[0]);
+    assert_eq!(6, request[9]);
+    assert_eq!(client_port.to_be_bytes(), request[20..22]);
+    assert_eq!(server.port().to_be_bytes(), request[22..24]);
+    assert_eq!(b"PING\r\n", &request[40..]);
+    // The response follows the request in the TCP stream
+    let response = &packets[4];
+    assert_eq!(server.port().to_be_bytes(), response[20..22]);
+    assert_eq!(request[28..32], response[24..28]);
+    assert_eq!(b"+PONG\r\n", &response[40..]);
+}
+
+#[test]
+fn test_export_udp_pcap() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        capture: true,
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.send(b"ping").unwrap();
+    let mut buffer = [0; 4];
+    client.recv(&mut buffer).unwrap();
+
+    let path = std::env::temp_dir().join(format!("udp-{}.pcap", server.port()));
+    server.export_pcap(&path).unwrap();
+    let packets = read_pcap(&path);
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(2, packets.len());
+    assert_eq!(17, packets[0][9]);
+    assert_eq!(b"ping", &packets[0][28..]);
+    assert_eq!(b"pong", &packets[1][28..]);
+}
+
+#[test]
+fn test_no_capture_by_default() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+    client.send_to(b"ping", server.socket_address()).unwrap();
+    assert!(server.pop_received_message().is_some());
+
+    // Packets aren't kept in memory
+    let path = std::env::temp_dir().join(format!("empty-{}.pcap", server.port()));
+    server.export_pcap(&path).unwrap();
+    let packets = read_pcap(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(packets.is_empty());
+}
+
+#[test]
+fn test_replay_tcp_pcap() {
+    // Capture an exchange with a first server
+    let captured = ServerMocker::new_with_opts(TcpMocker {
+        capture: true,
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(captured.socket_address()).unwrap();
+    captured
+        .add_mock_instructions(vec![
+            SendMessage(b"+OK ready\r\n".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"+OK ".to_vec()),
+            SendMessage(b"bye\r\n".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    let mut greeting = [0; 11];
+    client.read_exact(&mut greeting).unwrap();
+    client.write_all(b"QUIT\r\n").unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    let path = std::env::temp_dir().join(format!("replay-{}.pcap", captured.port()));
+    captured.export_pcap(&path).unwrap();
+
+    // Replay it with a second one
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server.load_pcap(&path, captured.port()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    client.read_exact(&mut greeting).unwrap();
+    assert_eq!(b"+OK ready\r\n", &greeting);
+    client.write_all(b"QUIT\r\n").unwrap();
+    let mut replayed_response = Vec::new();
+    client.read_to_end(&mut replayed_response).unwrap();
+    assert_eq!(b"+OK bye\r\n", replayed_response.as_slice());
+    assert_eq!(
+        b"QUIT\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_replay_udp_pcap() {
+    let captured = ServerMocker::new_with_opts(UdpMocker {
+        capture: true,
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(captured.socket_address()).unwrap();
+    captured
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong 1".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"pong 2".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    let mut buffer = [0; 16];
+    for _ in 0..2 {
+        client.send(b"ping").unwrap();
+        client.recv(&mut buffer).unwrap();
+    }
+    let path = std::env::temp_dir().join(format!("replay-{}.pcap", captured.port()));
+    captured.export_pcap(&path).unwrap();
+
+    let server = ServerMocker::udp().unwrap();
+    client.connect(server.socket_address()).unwrap();
+    server.load_pcap(&path, captured.port()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    for expected in [b"pong 1", b"pong 2"] {
+        client.send(b"ping").unwrap();
+        let received_size = client.recv(&mut buffer).unwrap();
+        assert_eq!(expected, &buffer[..received_size]);
+    }
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_load_invalid_pcap() {
+    let server = ServerMocker::tcp().unwrap();
+    let path = std::env::temp_dir().join(format!("invalid-{}.pcap", server.port()));
+    fs::write(&path, b"not a capture").unwrap();
+    let error = server.load_pcap(&path, 80).unwrap_err();
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(error, ServerMockerError::InvalidPcap(_, _)));
+    assert!(!error.is_fatal());
+}
@@ -0,0 +1,149 @@
+//! UDP server mocker impersonating several servers, with instructions routed by peer
+
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{
+    ReceiveMessage, ReceiveMessages, RouteByPeer, SendMessage,
+    SendMessageDependingOnLastReceivedMessage, StopExchange,
+};
+use socket_server_mocker::{
+    Matcher, PeerRoute, PeerSelector, ServerMocker, ServerMockerError, UdpMocker,
+};
+
+/// Client connected to the given server
+fn connected_client(server: &ServerMocker<UdpMocker>) -> UdpSocket {
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    client
+}
+
+/// Receive a datagram as a string
+fn recv_string(client: &UdpSocket) -> String {
+    let mut buffer = [0; 32];
+    let received_size = client.recv(&mut buffer).unwrap();
+    String::from_utf8(buffer[..received_size].to_vec()).unwrap()
+}
+
+#[test]
+fn test_route_by_first_message() {
+    let server = ServerMocker::udp().unwrap();
+    let dns_client = connected_client(&server);
+    let ntp_client = connected_client(&server);
+
+    server
+        .add_mock_instructions(vec![
+            RouteByPeer(vec![
+                PeerRoute {
+                    peer: PeerSelector::FirstMessage(Matcher::StartsWith(b"DNS".to_vec())),
+                    instructions: vec![
+                        ReceiveMessage,
+                        SendMessage(b"DNS answer 1".to_vec()),
+                        ReceiveMessage,
+                        SendMessage(b"DNS answer 2".to_vec()),
+                    ],
+                },
+                PeerRoute {
+                    peer: PeerSelector::FirstMessage(Matcher::StartsWith(b"NTP".to_vec())),
+                    instructions: vec![ReceiveMessage, SendMessage(b"NTP answer".to_vec())],
+                },
+            ]),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // The NTP query is kept while the DNS route is served
+    dns_client.send(b"DNS query 1").unwrap();
+    ntp_client.send(b"NTP query").unwrap();
+    assert_eq!("DNS answer 1", recv_string(&dns_client));
+    dns_client.send(b"DNS query 2").unwrap();
+    assert_eq!("DNS answer 2", recv_string(&dns_client));
+    assert_eq!("NTP answer", recv_string(&ntp_client));
+
+    assert_eq!(Some("DNS query 1"), server.pop_received_string().as_deref());
+    assert_eq!(Some("DNS query 2"), server.pop_received_string().as_deref());
+    assert_eq!(Some("NTP query"), server.pop_received_string().as_deref());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_route_by_address() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        strict: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let first_client = connected_client(&server);
+    let second_client = connected_client(&server);
+    let unknown_client = connected_client(&server);
+
+    server
+        .add_mock_instructions(vec![
+            RouteByPeer(vec![
+                PeerRoute {
+                    peer: PeerSelector::Address(second_client.local_addr().unwrap()),
+                    instructions: vec![ReceiveMessage, SendMessage(b"second".to_vec())],
+                },
+                PeerRoute {
+                    peer: PeerSelector::Address(first_client.local_addr().unwrap()),
+                    instructions: vec![ReceiveMessage, SendMessage(b"first".to_vec())],
+                },
+            ]),
+            StopExchange,
+        ])
+        .unwrap();
+
+    unknown_client.send(b"hello").unwrap();
+    first_client.send(b"hello").unwrap();
+    assert_eq!("first", recv_string(&first_client));
+    second_client.send(b"hello").unwrap();
+    assert_eq!("second", recv_string(&second_client));
+
+    // The peer matching no route is reported in strict mode
+    assert!(matches!(
+        server.pop_server_error(),
+        Some(ServerMockerError::UnexpectedData(data)) if data == b"hello"
+    ));
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_route_answers_peer_request() {
+    let server = ServerMocker::udp().unwrap();
+    let first_client = connected_client(&server);
+    let second_client = connected_client(&server);
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessages(2),
+            RouteByPeer(vec![PeerRoute {
+                peer: PeerSelector::Address(first_client.local_addr().unwrap()),
+                // Echo the last request of the routed peer, not the one of the last sender
+                instructions: vec![
+                    SendMessageDependingOnLastReceivedMessage(|message| message),
+                    ReceiveMessage,
+                ],
+            }]),
+            StopExchange,
+        ])
+        .unwrap();
+
+    first_client.send(b"first request").unwrap();
+    second_client.send(b"second request").unwrap();
+    first_client.send(b"bye").unwrap();
+    assert_eq!("first request", recv_string(&first_client));
+
+    assert_eq!(
+        Some("first request"),
+        server.pop_received_string().as_deref()
+    );
+    assert_eq!(
+        Some("second request"),
+        server.pop_received_string().as_deref()
+    );
+    assert_eq!(Some("bye"), server.pop_received_string().as_deref());
+    assert!(server.pop_server_error().is_none());
+}
@@ -10,8 +10,11 @@
 use std::time::Duration;
 
 use postgres::{Client, NoTls};
+use socket_server_mocker::protocols::postgres::{
+    PostgresMockBuilder, PostgresQueryBuilder, INT4_OID, VARCHAR_OID,
+};
 use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
-use socket_server_mocker::ServerMocker;
+use socket_server_mocker::{ServerMocker, TcpMocker};
 
 #[test]
 fn postgres_insert_mock() {
@@ -147,3 +150,149 @@ on\x00S\x00\x00\x00\x1aTimeZone\x00Europe/Paris\x00K\x00\x00\x00\x0c\x00\x00\x0a
     // Check that no error has been raised by the mocked server
     assert!(server.pop_server_error().is_none());
 }
+
+/// Connect to the mocked server with the given password
+fn connect(port: u16, password: &str) -> Result<Client, postgres::Error> {
+    Client::configure()
+        .host("localhost")
+        .user("admin")
+        .password(password)
+        .dbname("mockeddatabase")
+        .connect_timeout(Duration::from_secs(1))
+        .tcp_user_timeout(Duration::from_secs(1))
+        .keepalives(false)
+        .port(port)
+        .connect(NoTls)
+}
+
+#[test]
+fn postgres_builder_insert_mock() {
+    // Mock PostgreSQL server on a random port (default PostgresSQL port is 5432)
+    let server = ServerMocker::tcp().unwrap();
+
+    // Client connection and authentication, with the password hashed with md5
+    server
+        .add_mock_instructions(
+            PostgresMockBuilder::new()
+                .md5_auth("password")
+                .parameter("TimeZone", "Europe/Paris")
+                .build(),
+        )
+        .unwrap();
+
+    // Connect to local mocked PostgreSQL server
+    let mut client = connect(server.port(), "password").unwrap();
+
+    // Check connection message sent by the client to mock server is correct
+    assert_eq!(
+        b"\x00\x00\x00A\x00\x03\x00\x00client_encoding\x00UTF8\x00user\x00admin\x00database\x00mockeddatabase\x00\x00",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    // md5("md5(password + user) + salt"), checked by the mocked server
+    assert_eq!(b'p', server.pop_received_message().unwrap()[0]);
+
+    // Add mock instructions corresponding to the client INSERT query
+    server
+        .add_mock_instructions(
+            PostgresQueryBuilder::new("INSERT 0 1")
+                .parameter(VARCHAR_OID)
+                .parameter(VARCHAR_OID)
+                .build(),
+        )
+        .unwrap();
+
+    // Execute the INSERT query
+    let inserted = client
+        .execute(
+            "INSERT INTO playground (data1, data2) VALUES ($1, $2)",
+            &[&"test1", &"test2"],
+        )
+        .unwrap();
+    assert_eq!(1, inserted);
+
+    // Check that the server received the query and its parameters
+    let parse = server.pop_received_message().unwrap();
+    assert_eq!(b'P', parse[0]);
+    assert!(String::from_utf8_lossy(&parse).contains("INSERT INTO playground"));
+    let bind = server.pop_received_message().unwrap();
+    assert_eq!(b'B', bind[0]);
+    assert!(String::from_utf8_lossy(&bind).contains("test1\0\0\0\x05test2"));
+
+    // Check that no error has been raised by the mocked server
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn postgres_builder_select_mock() {
+    // Deriving the SCRAM keys takes a while in debug builds, on both sides
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        net_timeout: Duration::from_secs(1),
+        ..TcpMocker::default()
+    })
+    .unwrap();
+
+    // Client connection and authentication with scram-sha-256, the default method of modern PostgreSQL
+    server
+        .add_mock_instructions(
+            PostgresMockBuilder::new()
+                .scram_sha256_auth("password")
+                .build(),
+        )
+        .unwrap();
+
+    // Connect to local mocked PostgreSQL server, which also checks the server signature
+    let mut client = connect(server.port(), "password").unwrap();
+
+    // Add mock instructions corresponding to the client SELECT query
+    server
+        .add_mock_instructions(
+            PostgresQueryBuilder::new("SELECT 1")
+                .column("id", INT4_OID)
+                .column("data1", VARCHAR_OID)
+                .column("data2", VARCHAR_OID)
+                .row(vec![
+                    Some(1_i32.to_be_bytes().to_vec()),
+                    Some(b"test1".to_vec()),
+                    Some(b"test2".to_vec()),
+                ])
+                .build(),
+        )
+        .unwrap();
+
+    // Execute the client SELECT query
+    let rows = client.query("SELECT * FROM playground", &[]).unwrap();
+
+    // Check the SELECT query result
+    assert_eq!(1, rows.len());
+    assert_eq!(1, rows[0].get::<_, i32>("id"));
+    assert_eq!("test1", rows[0].get::<_, String>("data1"));
+    assert_eq!("test2", rows[0].get::<_, String>("data2"));
+
+    // Check that no error has been raised by the mocked server
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn postgres_wrong_password() {
+    // Deriving the SCRAM keys takes a while in debug builds, on both sides
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        net_timeout: Duration::from_secs(1),
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    server
+        .add_mock_instructions(
+            PostgresMockBuilder::new()
+                .scram_sha256_auth("password")
+                .build(),
+        )
+        .unwrap();
+
+    let Err(error) = connect(server.port(), "wrong") else {
+        panic!("The mocked server accepted a wrong password");
+    };
+    assert_eq!(
+        Some(&postgres::error::SqlState::INVALID_PASSWORD),
+        error.code()
+    );
+}
@@ -0,0 +1,321 @@
+//! Receive instructions delimiting messages independently of the client reads and writes
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream, UdpSocket};
+use std::thread::sleep;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{
+    ReceiveChunksUntilClose, ReceiveExactBytes, ReceiveFor, ReceiveMessage,
+    ReceiveMessageWithMaxSize, ReceiveMessages, ReceiveUntilClose, ReceiveUntilDelimiter,
+    SendMessage, StopExchange,
+};
+use socket_server_mocker::ServerMocker;
+
+#[test]
+fn test_tcp_receive_exact_bytes() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveExactBytes(4),
+            // Exactly the size of the reader buffer
+            ReceiveExactBytes(1024),
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+
+    let mut record = vec![0x00, 0x00, 0x04, 0x00];
+    record.extend_from_slice(&[0xAB; 1024]);
+    record.extend_from_slice(b"next");
+    client.write_all(&record).unwrap();
+
+    assert_eq!(record[..4], server.pop_received_message().unwrap());
+    assert_eq!(record[4..1028], server.pop_received_message().unwrap());
+    // The remaining bytes are received by the next instruction
+    assert_eq!(b"next", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_receive_exact_bytes() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveExactBytes(6), StopExchange])
+        .unwrap();
+
+    // The record is split over several datagrams
+    client.send(b"abc").unwrap();
+    client.send(b"def").unwrap();
+
+    assert_eq!(b"abcdef", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_receive_messages() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessages(3),
+            SendMessage(b"ACK".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // A burst of datagrams answered at once
+    for packet in [b"one", b"two", b"six"] {
+        client.send(packet).unwrap();
+    }
+    let mut buffer = [0; 8];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"ACK", &buffer[..received_size]);
+
+    let received: Vec<Vec<u8>> = (0..3)
+        .map(|_| server.pop_received_message().unwrap())
+        .collect();
+    assert_eq!(
+        vec![b"one".to_vec(), b"two".to_vec(), b"six".to_vec()],
+        received
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_receive_until_delimiter() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveUntilDelimiter(b"\r\n.\r\n".to_vec()),
+            SendMessage(b"250 OK\r\n".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // SMTP DATA content, written line by line
+    for line in [&b"Subject: test\r\n"[..], b"\r\n", b"Hello\r\n", b".\r\n"] {
+        client.write_all(line).unwrap();
+    }
+    let mut buffer = [0; 8];
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"250 OK\r\n", &buffer);
+
+    assert_eq!(
+        b"Subject: test\r\n\r\nHello\r\n.\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_receive_until_close() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveUntilClose,
+            SendMessage(b"HTTP/1.0 201 Created\r\n\r\n".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // HTTP/1.0 upload without Content-Length, the body ends with the client write side
+    let header = b"PUT /file HTTP/1.0\r\n\r\n";
+    client.write_all(header).unwrap();
+    client.write_all(&[0x42; 3000]).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    assert_eq!(b"HTTP/1.0 201 Created\r\n\r\n", response.as_slice());
+
+    let received = server.pop_received_message().unwrap();
+    assert_eq!(header.len() + 3000, received.len());
+    assert!(received.starts_with(header));
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_receive_until_close() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveUntilClose, StopExchange])
+        .unwrap();
+
+    client.send(b"first block, ").unwrap();
+    client.send(b"last block").unwrap();
+    // End of transfer
+    client.send(b"").unwrap();
+
+    assert_eq!(
+        b"first block, last block",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_receive_for() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveFor(Duration::from_millis(200)),
+            ReceiveFor(Duration::from_millis(50)),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // Batched metrics, written separately within the time window
+    for metric in [&b"requests:1|c\n"[..], b"errors:0|c\n", b"latency:12|ms\n"] {
+        client.write_all(metric).unwrap();
+        sleep(Duration::from_millis(20));
+    }
+    // Wait for the end of the time window
+    sleep(Duration::from_millis(200));
+
+    assert_eq!(
+        b"requests:1|c\nerrors:0|c\nlatency:12|ms\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    // Nothing sent during the second time window
+    assert!(server.pop_received_message().unwrap().is_empty());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_receive_for() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveFor(Duration::from_millis(200)),
+            SendMessage(b"ack".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.send(b"a").unwrap();
+    client.send(b"b").unwrap();
+    client.send(b"c").unwrap();
+
+    // The response is sent to the last client
+    let mut buffer = [0; 8];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"ack", &buffer[..received_size]);
+
+    assert_eq!(b"abc", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_receive_chunks_until_close() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveChunksUntilClose, StopExchange])
+        .unwrap();
+
+    let upload_size = 256 * 1024;
+    client.write_all(&vec![0x42; upload_size]).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let mut chunk_count = 0;
+    let mut received_size = 0;
+    while let Some(chunk) = server.pop_received_chunk() {
+        assert!(chunk.len() <= server.options().reader_buffer_size);
+        assert!(chunk.iter().all(|&byte| byte == 0x42));
+        chunk_count += 1;
+        received_size += chunk.len();
+    }
+    assert_eq!(upload_size, received_size);
+    assert!(chunk_count > 1);
+
+    // The upload isn't buffered as a message
+    assert!(server.pop_received_message().is_none());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_receive_header_then_body() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            // Read the 4 bytes header only
+            ReceiveMessageWithMaxSize(4),
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.write_all(b"\x00\x00\x00\x05hello").unwrap();
+
+    assert_eq!(
+        b"\x00\x00\x00\x05",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_iter_received() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage, ReceiveMessage])
+        .unwrap();
+
+    // The client splits its payload in an unknown number of datagrams
+    for part in [b"ab", b"cd", b"ef"] {
+        client.send_to(part, server.socket_address()).unwrap();
+    }
+
+    let messages: Vec<Vec<u8>> = server.iter_received(Duration::from_millis(200)).collect();
+    assert_eq!(
+        vec![b"ab".to_vec(), b"cd".to_vec(), b"ef".to_vec()],
+        messages
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_pop_received_string() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage, ReceiveMessage])
+        .unwrap();
+
+    client.send_to(b"PING", server.socket_address()).unwrap();
+    client.send_to(b"caf\xe9", server.socket_address()).unwrap();
+    client.send_to(b"caf\xe9", server.socket_address()).unwrap();
+
+    assert_eq!(Some("PING".to_string()), server.pop_received_string());
+    // Latin-1 isn't valid UTF-8
+    assert_eq!(None, server.pop_received_string());
+    assert_eq!(
+        Some("caf\u{FFFD}".to_string()),
+        server.pop_received_string_lossy()
+    );
+}
@@ -0,0 +1,69 @@
+//! Received messages deserialized from JSON
+#![cfg(feature = "serde")]
+
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+
+use serde::Deserialize;
+use socket_server_mocker::Instruction::ReceiveMessage;
+use socket_server_mocker::{ServerMocker, ServerMockerError};
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Order {
+    id: u32,
+    items: Vec<String>,
+}
+
+#[test]
+fn test_json_over_udp() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage])
+        .unwrap();
+
+    client
+        .send_to(br#"{"id": 42, "items": ["tea"]}"#, server.socket_address())
+        .unwrap();
+    client
+        .send_to(br#"{"id": "42"}"#, server.socket_address())
+        .unwrap();
+
+    assert_eq!(
+        Order {
+            id: 42,
+            items: vec!["tea".to_string()],
+        },
+        server.pop_received_json().unwrap()
+    );
+    let error = server.pop_received_json::<Order>().unwrap_err();
+    assert!(matches!(error, ServerMockerError::InvalidJsonMessage(_, _)));
+    assert!(error.to_string().ends_with(r#": b"{\"id\": \"42\"}""#));
+    assert!(matches!(
+        server.pop_received_json::<Order>(),
+        Err(ServerMockerError::NoMessageReceived)
+    ));
+}
+
+#[test]
+fn test_json_http_body() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+
+    let body = r#"{"id": 7, "items": ["coffee", "cake"]}"#;
+    write!(
+        client,
+        "POST /orders HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .unwrap();
+
+    assert_eq!(
+        Order {
+            id: 7,
+            items: vec!["coffee".to_string(), "cake".to_string()],
+        },
+        server.pop_received_json().unwrap()
+    );
+}
@@ -0,0 +1,71 @@
+//! Received messages decoded as protobuf messages
+#![cfg(feature = "prost")]
+
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+
+use prost::Message;
+use socket_server_mocker::Instruction::ReceiveMessage;
+use socket_server_mocker::{decode_prost, ServerMocker, ServerMockerError};
+
+#[derive(Clone, PartialEq, Message)]
+struct Order {
+    #[prost(uint32, tag = "1")]
+    id: u32,
+    #[prost(string, repeated, tag = "2")]
+    items: Vec<String>,
+}
+
+fn order() -> Order {
+    Order {
+        id: 42,
+        items: vec!["apple".to_string(), "pear".to_string()],
+    }
+}
+
+#[test]
+fn test_pop_received_proto_tcp() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage])
+        .unwrap();
+
+    client
+        .write_all(&order().encode_length_delimited_to_vec())
+        .unwrap();
+    assert_eq!(order(), server.pop_received_proto().unwrap());
+
+    client.write_all(b"\x0a\xff\xff").unwrap();
+    assert!(matches!(
+        server.pop_received_proto::<Order>(),
+        Err(ServerMockerError::UnableToDecodeMessage(_, message)) if message == b"\x0a\xff\xff"
+    ));
+}
+
+#[test]
+fn test_pop_received_proto_udp() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    client
+        .send_to(&order().encode_to_vec(), server.socket_address())
+        .unwrap();
+    let received: Order = server.pop_received_proto().unwrap();
+    assert_eq!(42, received.id);
+    assert_eq!(vec!["apple", "pear"], received.items);
+}
+
+#[test]
+fn test_decode_prost_length_prefix() {
+    // Longer than 127 bytes, the length taking 2 bytes
+    let order = Order {
+        id: 7,
+        items: vec!["x".repeat(200)],
+    };
+    let delimited = order.encode_length_delimited_to_vec();
+    assert_eq!([0xCD, 0x01], delimited[..2]);
+    assert_eq!(Ok(order.clone()), decode_prost(&delimited));
+    assert_eq!(Ok(order.clone()), decode_prost(&order.encode_to_vec()));
+    assert_eq!(Ok(Order::default()), decode_prost(b""));
+}
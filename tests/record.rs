@@ -0,0 +1,74 @@
+//! Record the exchange with a real server, to replay it with a server mocker
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::ServerMocker;
+
+/// Exchange of a client with a simple greeting server
+fn greet(server_addr: SocketAddr) -> Vec<u8> {
+    let mut client = TcpStream::connect(server_addr).unwrap();
+    client.write_all(b"HELLO alice\r\n").unwrap();
+    let mut greeting = [0; 9];
+    client.read_exact(&mut greeting).unwrap();
+    client.write_all(b"QUIT\r\n").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    let mut response = greeting.to_vec();
+    client.read_to_end(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_record_and_replay() {
+    // The "real" server
+    let upstream = ServerMocker::tcp().unwrap();
+    upstream
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"Hi alice\n".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"Bye\n".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    let recorder = ServerMocker::record(upstream.socket_address()).unwrap();
+    assert_eq!(
+        b"Hi alice\nBye\n",
+        greet(recorder.socket_address()).as_slice()
+    );
+    assert!(recorder.pop_server_error().is_none());
+
+    assert_eq!(
+        r#"vec![
+    // b"HELLO alice\r\n"
+    ReceiveExactBytes(13),
+    SendMessage(b"Hi alice\n".to_vec()),
+    // b"QUIT\r\n"
+    ReceiveExactBytes(6),
+    SendMessage(b"Bye\n".to_vec()),
+    StopExchange,
+]"#,
+        recorder.rust_code()
+    );
+
+    // The recorded instructions replace the real server
+    let replay = ServerMocker::tcp().unwrap();
+    replay
+        .add_mock_instructions(recorder.instructions())
+        .unwrap();
+    assert_eq!(
+        b"Hi alice\nBye\n",
+        greet(replay.socket_address()).as_slice()
+    );
+    assert_eq!(
+        b"HELLO alice\r\n",
+        replay.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        b"QUIT\r\n",
+        replay.pop_received_message().unwrap().as_slice()
+    );
+    assert!(replay.pop_server_error().is_none());
+}
@@ -0,0 +1,239 @@
+//! Repeated instructions with an expected number of repetitions
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream, UdpSocket};
+use std::thread::sleep;
+
+use socket_server_mocker::Instruction::{
+    self, ReceiveMessage, Repeat, SendMessage, SendMessageFromClosure, StopExchange,
+};
+use socket_server_mocker::{
+    Framing, ReadInterruption, ServerMocker, ServerMockerError, TcpMocker, Times,
+};
+
+#[test]
+fn test_tcp_repeat_exactly() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    // The retrying client is expected to send exactly 3 requests
+    server
+        .add_mock_instructions(vec![
+            Repeat {
+                times: Times::exactly(3),
+                instructions: vec![ReceiveMessage, SendMessage(b"busy".to_vec())],
+            },
+            StopExchange,
+        ])
+        .unwrap();
+
+    let mut buffer = [0; 16];
+    for _ in 0..3 {
+        client.write_all(b"request").unwrap();
+        let received_size = client.read(&mut buffer).unwrap();
+        assert_eq!(b"busy", &buffer[..received_size]);
+    }
+
+    for _ in 0..3 {
+        assert_eq!(
+            b"request",
+            server.pop_received_message().unwrap().as_slice()
+        );
+    }
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_repeat_interrupted_mid_message() {
+    let server = ServerMocker::new_with_opts(TcpMocker::default().framing(Framing::Line)).unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![Repeat {
+            times: Times::at_least(0),
+            instructions: vec![ReceiveMessage, SendMessage(b"OK\n".to_vec())],
+        }])
+        .unwrap();
+
+    client.write_all(b"first\n").unwrap();
+    let mut buffer = [0; 16];
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"OK\n", &buffer[..received_size]);
+
+    // The client disconnects in the middle of the second line, which isn't a clean end of the repetition
+    client.write_all(b"sec").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    assert_eq!(
+        b"first\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    match server.pop_server_error() {
+        Some(ServerMockerError::ReadInterrupted(ReadInterruption::ConnectionClosed, received)) => {
+            assert_eq!(b"sec", received.as_slice());
+        }
+        err => panic!("Unexpected server error: {err:?}"),
+    }
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_repeat_at_least_not_reached() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![Repeat {
+            times: Times::at_least(2),
+            instructions: vec![ReceiveMessage, SendMessage(b"pong".to_vec())],
+        }])
+        .unwrap();
+
+    // The client only sends a single request
+    client.send(b"ping").unwrap();
+    let mut buffer = [0; 16];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"pong", &buffer[..received_size]);
+
+    assert_eq!(b"ping", server.pop_received_message().unwrap().as_slice());
+
+    // Wait for the second repetition to time out
+    sleep(2 * server.options().net_timeout);
+    let err = server.pop_server_error().unwrap();
+    assert!(matches!(
+        err,
+        ServerMockerError::UnexpectedRepeatCount(_, 1)
+    ));
+    assert_eq!(
+        "Non fatal: Repeated instructions were expected to run at least 2 times, but ran 1 times",
+        err.to_string()
+    );
+    assert!(!err.is_fatal());
+}
+
+#[test]
+fn test_udp_repeat_interrupted_mid_repetition() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    // Each repetition expects a request in two datagrams
+    server
+        .add_mock_instructions(vec![Repeat {
+            times: Times::at_least(0),
+            instructions: vec![ReceiveMessage, ReceiveMessage, SendMessage(b"OK".to_vec())],
+        }])
+        .unwrap();
+
+    // The client stops after the first half of the request
+    client.send(b"first half").unwrap();
+
+    assert_eq!(
+        b"first half",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    sleep(2 * server.options().net_timeout);
+    match server.pop_server_error() {
+        Some(ServerMockerError::UnableToReadUdpStream(_)) => {}
+        err => panic!("Unexpected server error: {err:?}"),
+    }
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+#[should_panic(
+    expected = "Mocked server dropped with unmet repetition counts:\nNon fatal: Repeated instructions were expected to run exactly 2 times, but ran 1 times"
+)]
+fn test_repeat_count_checked_on_drop() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![Repeat {
+            times: Times::exactly(2),
+            instructions: vec![ReceiveMessage],
+        }])
+        .unwrap();
+
+    // The client only sends a single request, and the test doesn't check the server errors
+    client.send(b"ping").unwrap();
+    assert_eq!(b"ping", server.pop_received_message().unwrap().as_slice());
+}
+
+#[test]
+fn test_repeat_stateful_closure() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    // The server is busy twice, then accepts the request
+    let mut attempts = 0;
+    let accepted_response = b"accepted".to_vec();
+    server
+        .add_mock_instructions(vec![
+            Repeat {
+                times: Times::exactly(3),
+                instructions: vec![
+                    ReceiveMessage,
+                    Instruction::send_from_closure(move |_| {
+                        attempts += 1;
+                        if attempts < 3 {
+                            Some(b"busy".to_vec())
+                        } else {
+                            Some(accepted_response.clone())
+                        }
+                    }),
+                ],
+            },
+            StopExchange,
+        ])
+        .unwrap();
+
+    let mut buffer = [0; 16];
+    for expected_response in [&b"busy"[..], b"busy", b"accepted"] {
+        client.write_all(b"request").unwrap();
+        let received_size = client.read(&mut buffer).unwrap();
+        assert_eq!(expected_response, &buffer[..received_size]);
+    }
+
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_clone_instructions() {
+    let mut count = 0u8;
+    let counter = Instruction::send_from_closure(move |_| {
+        count += 1;
+        Some(vec![count])
+    });
+    let instructions = vec![
+        Repeat {
+            times: Times::exactly(2),
+            instructions: vec![ReceiveMessage, counter.clone()],
+        },
+        StopExchange,
+    ];
+
+    // Clones are equal, closures being compared by identity
+    assert_eq!(instructions, instructions.clone());
+    assert_ne!(counter, Instruction::send_from_closure(|_| None));
+    assert_ne!(SendMessage(b"a".to_vec()), SendMessage(b"b".to_vec()));
+
+    // Clones share the state of the closure
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    server.add_mock_instructions(instructions).unwrap();
+    let mut buffer = [0; 1];
+    for expected in [1, 2] {
+        client.send(b"tick").unwrap();
+        client.recv(&mut buffer).unwrap();
+        assert_eq!(expected, buffer[0]);
+    }
+    assert!(server.pop_server_error().is_none());
+    let SendMessageFromClosure(responder) = counter else {
+        unreachable!()
+    };
+    assert_eq!(Some(vec![3]), responder.lock().unwrap()(None));
+}
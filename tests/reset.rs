@@ -0,0 +1,70 @@
+//! Server mocker reused across test cases
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::ServerMocker;
+
+#[test]
+fn test_tcp_reset_drops_pending_instructions() {
+    let mut server = ServerMocker::tcp().unwrap();
+    let socket_addr = server.socket_address();
+    let mut client = TcpStream::connect(socket_addr).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"first".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"never sent".to_vec()),
+        ])
+        .unwrap();
+
+    client.write_all(b"request").unwrap();
+    let mut buffer = [0; 5];
+    client.read_exact(&mut buffer).unwrap();
+    server.reset().unwrap();
+
+    // The client is disconnected
+    assert_eq!(0, client.read(&mut buffer).unwrap());
+    assert!(server.pop_received_message().is_none());
+    assert_eq!(0, server.stats().bytes_received);
+
+    // A new client is accepted on the same port
+    assert_eq!(socket_addr, server.socket_address());
+    let mut client = TcpStream::connect(socket_addr).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"again".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    client.write_all(b"request").unwrap();
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"again", &buffer);
+    assert_eq!(
+        b"request",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_reset_clears_errors() {
+    let mut server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    // No datagram is sent to the server
+    server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    assert!(server.pop_received_message().is_none());
+    server.reset().unwrap();
+    assert!(server.pop_server_error().is_none());
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+    client.send_to(b"ping", server.socket_address()).unwrap();
+    assert_eq!(b"ping", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}
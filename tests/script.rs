@@ -0,0 +1,60 @@
+//! Instruction scripts loaded from JSON or YAML files
+#![cfg(feature = "serde")]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+use socket_server_mocker::{ServerMocker, ServerMockerError};
+
+#[test]
+fn test_yaml_script() {
+    let server = ServerMocker::tcp().unwrap();
+    server.load_script("tests/scripts/pop3.yaml").unwrap();
+    let mut client = BufReader::new(TcpStream::connect(server.socket_address()).unwrap());
+
+    let mut line = String::new();
+    client.read_line(&mut line).unwrap();
+    assert_eq!("+OK POP3 ready\r\n", line);
+    for command in ["USER alice\r\n", "QUIT\r\n"] {
+        client.get_mut().write_all(command.as_bytes()).unwrap();
+        line.clear();
+        client.read_line(&mut line).unwrap();
+        assert_eq!("+OK\r\n", line);
+    }
+
+    assert_eq!(
+        b"USER alice\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        b"QUIT\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+}
+
+#[test]
+fn test_json_script() {
+    let server = ServerMocker::tcp().unwrap();
+    server.load_script("tests/scripts/binary.json").unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    client.write_all(&[0xCA, 0xFE, 0xBA, 0xBE]).unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    assert_eq!(vec![0, 1, 2, 3, 4, 5, 6, 7], response);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_invalid_script() {
+    let server = ServerMocker::tcp().unwrap();
+    assert!(matches!(
+        server.load_script("tests/scripts/missing.json"),
+        Err(ServerMockerError::UnableToReadScript(_, _))
+    ));
+    // Not a valid script
+    assert!(matches!(
+        server.load_script("Cargo.toml"),
+        Err(ServerMockerError::InvalidScript(_, _))
+    ));
+}
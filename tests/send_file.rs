@@ -0,0 +1,90 @@
+//! Fixture files sent by the server mocker
+
+use std::fs;
+use std::io::Read;
+use std::net::{TcpStream, UdpSocket};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{
+    ReceiveMessage, SendFile, SendFileChunked, SendMessage, StopExchange,
+};
+use socket_server_mocker::{ServerMocker, ServerMockerError};
+
+/// Write a binary fixture of the given size in the temporary directory
+fn write_fixture(name: &str, size: usize) -> (PathBuf, Vec<u8>) {
+    let content: Vec<u8> = (0..size).map(|i| u8::try_from(i % 251).unwrap()).collect();
+    let path = std::env::temp_dir().join(name);
+    fs::write(&path, &content).unwrap();
+    (path, content)
+}
+
+#[test]
+fn test_tcp_send_file() {
+    let server = ServerMocker::tcp().unwrap();
+    let (path, content) = write_fixture(&format!("firmware-{}.bin", server.port()), 100_000);
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![SendFile(path.clone()), StopExchange])
+        .unwrap();
+
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(content, received);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_send_file_chunked() {
+    let server = ServerMocker::udp().unwrap();
+    let (path, content) = write_fixture(&format!("chunks-{}.bin", server.port()), 1000);
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendFileChunked {
+                path: path.clone(),
+                chunk_size: 400,
+                interval: Duration::from_millis(1),
+            },
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.send(b"download").unwrap();
+    let mut buffer = [0; 1024];
+    let mut received = Vec::new();
+    for expected_size in [400, 400, 200] {
+        let received_size = client.recv(&mut buffer).unwrap();
+        assert_eq!(expected_size, received_size);
+        received.extend_from_slice(&buffer[..received_size]);
+    }
+    fs::remove_file(&path).unwrap();
+    assert_eq!(content, received);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_send_missing_file() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            SendFile(PathBuf::from("tests/fixtures/missing.bin")),
+            SendMessage(b"next".to_vec()),
+        ])
+        .unwrap();
+
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    // The error is reported, and the exchange goes on
+    assert_eq!(b"next", received.as_slice());
+    let error = server.pop_server_error().unwrap();
+    assert!(matches!(error, ServerMockerError::UnableToReadFile(_, _)));
+    assert!(!error.is_fatal());
+}
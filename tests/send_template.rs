@@ -0,0 +1,77 @@
+//! Responses built from parts of the last received message
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+
+use socket_server_mocker::Instruction::{self, ReceiveMessage, StopExchange};
+use socket_server_mocker::{ServerMocker, ServerMockerError};
+
+#[test]
+fn test_tcp_text_template() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            Instruction::send_template(
+                r"^(?P<method>[A-Z]+) (?P<path>\S+) .*\r\nX-Request-Id: (?P<id>[^\r]+)\r\n",
+                "HTTP/1.1 200 OK\r\nX-Request-Id: ${id}\r\nContent-Length: 0\r\nX-Echo: $method $path costs $$0\r\n\r\n",
+            ),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client
+        .write_all(b"GET /jobs HTTP/1.1\r\nX-Request-Id: 4f2a\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        "HTTP/1.1 200 OK\r\nX-Request-Id: 4f2a\r\nContent-Length: 0\r\nX-Echo: GET /jobs costs $0\r\n\r\n",
+        response
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_binary_template() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            // Copy the DNS transaction ID
+            Instruction::send_template(r"(?s-u)^(?P<id>.{2})", b"${id}\x81\x80".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.send(&[0xbe, 0xef, 0x01, 0x00]).unwrap();
+    let mut buffer = [0; 8];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!([0xbe, 0xef, 0x81, 0x80], buffer[..received_size]);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_unmatched_template_pattern() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            Instruction::send_template(r"^ID (?P<id>\d+)", "OK ${id}"),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.write_all(b"HELLO").unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+    assert!(response.is_empty());
+    assert!(matches!(
+        server.pop_server_error().unwrap(),
+        ServerMockerError::UnmatchedTemplatePattern(pattern) if pattern == r"^ID (?P<id>\d+)"
+    ));
+}
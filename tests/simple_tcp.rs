@@ -2,10 +2,11 @@ use std::io::{Read, Write};
 use std::net::TcpStream;
 use std::str::from_utf8;
 use std::thread::sleep;
+use std::time::Duration;
 
 use socket_server_mocker::Instruction::{
-    ReceiveMessage, ReceiveMessageWithMaxSize, SendMessage,
-    SendMessageDependingOnLastReceivedMessage, StopExchange,
+    self, ReceiveMessage, ReceiveMessageWithMaxSize, SendMessage,
+    SendMessageDependingOnLastReceivedMessage, SendMessageTo, SendPartialThenClose, StopExchange,
 };
 use socket_server_mocker::{ServerMocker, ServerMockerError, TcpMocker};
 
@@ -44,7 +45,7 @@ fn test_simple_tcp() {
     assert_eq!("hello from server", received_message);
 
     // Check that the mocked server received the message sent by the client
-    // The message is only 16 bytes, the letter 't' is dropped
+    // The message is only 16 bytes, the letter 't' is kept for the next receive instruction
     assert_eq!(
         "hello from clien",
         from_utf8(&server.pop_received_message().unwrap()).unwrap()
@@ -52,6 +53,8 @@ fn test_simple_tcp() {
 
     // New instructions for the mocked server
     let instructions = vec![
+        // Receive the remaining letter 't' of the first message
+        ReceiveMessage,
         // Wait for another message from the tested client
         ReceiveMessage,
         // No message is sent to the server
@@ -88,6 +91,10 @@ fn test_simple_tcp() {
 
     assert_eq!("hello2 from server", received_message);
 
+    assert_eq!(
+        "t",
+        from_utf8(&server.pop_received_message().unwrap()).unwrap()
+    );
     assert_eq!(
         "hello2 from client",
         from_utf8(&server.pop_received_message().unwrap()).unwrap()
@@ -135,3 +142,102 @@ fn test_receive_timeout() {
     let err = err.unwrap();
     assert!(!err.is_fatal());
 }
+
+#[test]
+fn test_send_to_unsupported() {
+    let server = ServerMocker::tcp().unwrap();
+    let client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![SendMessageTo(
+            client.local_addr().unwrap(),
+            b"hello".to_vec(),
+        )])
+        .unwrap();
+
+    let error = server.pop_server_error().unwrap();
+    assert!(matches!(
+        error,
+        ServerMockerError::UnsupportedInstruction("SendMessageTo")
+    ));
+    assert!(!error.is_fatal());
+}
+
+#[test]
+fn test_send_partial_then_close() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            // The download is interrupted after 21 bytes
+            SendPartialThenClose {
+                message: b"HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\nHello, world".to_vec(),
+                bytes_to_send: 21,
+            },
+        ])
+        .unwrap();
+
+    client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+    // The client reads until the server closes the connection
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"HTTP/1.1 200 OK\r\nCont", received.as_slice());
+
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_receive_message_of_buffer_size() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"OK".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // The message fills the reader buffer exactly
+    let message = vec![0x42; server.options().reader_buffer_size];
+    client.write_all(&message).unwrap();
+
+    let mut buffer = [0; 2];
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"OK", &buffer);
+    assert_eq!(Some(message), server.pop_received_message());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+#[should_panic(expected = "dropped before executing 1 instructions")]
+fn test_panicking_closure() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    assert!(server.is_running());
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            Instruction::send_from_closure(|_| panic!("unexpected request")),
+        ])
+        .unwrap();
+    client.write_all(b"request").unwrap();
+
+    // The client is disconnected
+    let mut buffer = [0; 8];
+    assert_eq!(0, client.read(&mut buffer).unwrap());
+    let error = server.pop_server_error().unwrap();
+    assert!(matches!(
+        &error,
+        ServerMockerError::ServerThreadPanicked(message) if message == "unexpected request"
+    ));
+    assert!(error.is_fatal());
+    sleep(Duration::from_millis(50));
+    assert!(!server.is_running());
+    // The closure never returned
+}
@@ -1,9 +1,11 @@
 use std::net::UdpSocket;
 use std::str::from_utf8;
 use std::thread::sleep;
+use std::time::Duration;
 
 use socket_server_mocker::Instruction::{
-    ReceiveMessageWithMaxSize, SendMessage, SendMessageDependingOnLastReceivedMessage,
+    ReceiveMessage, ReceiveMessageWithMaxSize, SendMessage,
+    SendMessageDependingOnLastReceivedMessage, SendMessageTo, StopExchange,
 };
 use socket_server_mocker::{ServerMocker, ServerMockerError, UdpMocker};
 
@@ -120,6 +122,32 @@ fn test_try_receive_before_send() {
     assert!(!mocked_server_error.is_fatal());
 }
 
+#[test]
+fn test_send_to_before_receive() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            // Notify the client before it sends anything
+            SendMessageTo(client.local_addr().unwrap(), b"NOTIFY".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"ACK".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    let mut buffer = [0; 8];
+    let (received_size, sender_addr) = client.recv_from(&mut buffer).unwrap();
+    assert_eq!(b"NOTIFY", &buffer[..received_size]);
+    assert_eq!(server.socket_address(), sender_addr);
+
+    client.send_to(b"OK", sender_addr).unwrap();
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"ACK", &buffer[..received_size]);
+    assert!(server.pop_server_error().is_none());
+}
+
 #[test]
 fn test_receive_timeout() {
     // Mock a UDP server listening on a random free port
@@ -141,3 +169,83 @@ fn test_receive_timeout() {
     assert!(mocked_server_error_received.is_some());
     assert!(!mocked_server_error_received.unwrap().is_fatal());
 }
+
+#[test]
+fn test_take_errors() {
+    let server = ServerMocker::udp().unwrap();
+
+    // The client never sends anything
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessageWithMaxSize(32),
+            ReceiveMessageWithMaxSize(32),
+        ])
+        .unwrap();
+
+    let errors = server.take_errors();
+    assert_eq!(2, errors.len());
+    assert!(errors
+        .iter()
+        .all(|error| matches!(error, ServerMockerError::UnableToReadUdpStream(_))));
+    assert!(server.take_errors().is_empty());
+}
+
+#[test]
+fn test_fatal_error_surfaced() {
+    // A zero read timeout is rejected by the socket
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        net_timeout: Duration::ZERO,
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    sleep(Duration::from_millis(50));
+
+    let error = server
+        .add_mock_instructions(vec![ReceiveMessageWithMaxSize(32)])
+        .unwrap_err();
+    assert!(matches!(error, ServerMockerError::ServerStopped(_)));
+    assert!(error.is_fatal());
+    assert!(error.to_string().contains("Failed to set read timeout"));
+
+    // The original error is still available
+    assert!(matches!(
+        server.pop_server_error(),
+        Some(ServerMockerError::UnableToSetReadTimeout(_))
+    ));
+}
+
+#[test]
+fn test_error_context() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessageWithMaxSize(32),
+            SendMessage(b"pong".to_vec()),
+            ReceiveMessageWithMaxSize(32),
+        ])
+        .unwrap();
+    client.send(b"ping").unwrap();
+    assert_eq!(b"ping", server.pop_received_message().unwrap().as_slice());
+
+    // The client never sends the second message
+    sleep(2 * server.options().rx_timeout);
+    let report = server.pop_server_error_with_context().unwrap();
+    assert!(matches!(
+        report.error,
+        ServerMockerError::UnableToReadUdpStream(_)
+    ));
+    assert_eq!(Some(2), report.instruction_index);
+    assert_eq!(
+        Some("ReceiveMessageWithMaxSize(32)"),
+        report.instruction.as_deref()
+    );
+    assert_eq!(Some(client.local_addr().unwrap()), report.client_addr);
+    assert_eq!((4, 4), (report.bytes_received, report.bytes_sent));
+    assert!(report.to_string().ends_with(&format!(
+        "(instruction #2 ReceiveMessageWithMaxSize(32), client {}, after 4 bytes received and 4 bytes sent)",
+        client.local_addr().unwrap()
+    )));
+}
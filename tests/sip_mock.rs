@@ -0,0 +1,160 @@
+//! Mock a SIP server registering and calling a user agent
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::time::Duration;
+
+use socket_server_mocker::protocols::sip::{SipMockBuilder, SipResponse};
+use socket_server_mocker::ServerMocker;
+
+/// Request of the user agent, with the given `Via` branch and `CSeq`
+fn request(method: &str, branch: &str, cseq: u32, extra_headers: &str, body: &str) -> Vec<u8> {
+    format!(
+        "{method} sip:bob@example.com SIP/2.0\r\n\
+         Via: SIP/2.0/UDP 127.0.0.1:5062;branch={branch}\r\n\
+         Via: SIP/2.0/UDP 10.0.0.1:5060;branch=z9hG4bKproxy\r\n\
+         Max-Forwards: 70\r\n\
+         From: <sip:alice@example.com>;tag=1928301774\r\n\
+         t: <sip:bob@example.com>\r\n\
+         Call-ID: a84b4c76e66710\r\n\
+         CSeq: {cseq} {method}\r\n\
+         {extra_headers}\
+         Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    )
+    .into_bytes()
+}
+
+fn receive(client: &UdpSocket) -> String {
+    let mut buffer = [0; 2048];
+    let received_size = client.recv(&mut buffer).unwrap();
+    String::from_utf8(buffer[..received_size].to_vec()).unwrap()
+}
+
+#[test]
+fn test_sip_udp_register_and_invite() {
+    let server = ServerMocker::udp().unwrap();
+    let sip_mock = SipMockBuilder::new(server.socket_address())
+        .respond(
+            "REGISTER",
+            vec![SipResponse::new(401, "Unauthorized")
+                .header("WWW-Authenticate", r#"Digest realm="mock", nonce="42""#)],
+        )
+        .respond("REGISTER", vec![SipResponse::ok()])
+        .respond(
+            "INVITE",
+            vec![
+                SipResponse::trying(),
+                SipResponse::ringing(),
+                SipResponse::ok().body("application/sdp", "v=0\r\n"),
+            ],
+        );
+    let requests = sip_mock.requests();
+    server.add_mock_instructions(sip_mock.build()).unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    let contact = "Contact: <sip:alice@127.0.0.1:5062>\r\nExpires: 3600\r\n";
+    client
+        .send(&request("REGISTER", "z9hG4bK1", 1, contact, ""))
+        .unwrap();
+    let challenge = receive(&client);
+    assert!(challenge.starts_with("SIP/2.0 401 Unauthorized\r\n"));
+    assert!(challenge.contains("\r\nWWW-Authenticate: Digest realm=\"mock\", nonce=\"42\"\r\n"));
+    client
+        .send(&request("REGISTER", "z9hG4bK2", 2, contact, ""))
+        .unwrap();
+    let registered = receive(&client);
+    assert_eq!(
+        "SIP/2.0 200 OK\r\n\
+         Via: SIP/2.0/UDP 127.0.0.1:5062;branch=z9hG4bK2\r\n\
+         Via: SIP/2.0/UDP 10.0.0.1:5060;branch=z9hG4bKproxy\r\n\
+         From: <sip:alice@example.com>;tag=1928301774\r\n\
+         To: <sip:bob@example.com>;tag=socket-server-mocker\r\n\
+         Call-ID: a84b4c76e66710\r\n\
+         CSeq: 2 REGISTER\r\n\
+         Contact: <sip:alice@127.0.0.1:5062>\r\n\
+         Expires: 3600\r\n\
+         Content-Length: 0\r\n\r\n",
+        registered
+    );
+
+    // Each response in its own datagram
+    client
+        .send(&request("INVITE", "z9hG4bK3", 3, "", "v=0\r\n"))
+        .unwrap();
+    let trying = receive(&client);
+    assert!(trying.starts_with("SIP/2.0 100 Trying\r\n"));
+    assert!(trying.contains("\r\nTo: <sip:bob@example.com>\r\n"));
+    assert!(trying.contains("\r\nCSeq: 3 INVITE\r\n"));
+    assert!(receive(&client).starts_with("SIP/2.0 180 Ringing\r\n"));
+    let answer = receive(&client);
+    assert!(answer.starts_with("SIP/2.0 200 OK\r\n"));
+    assert!(answer.contains(&format!(
+        "\r\nContact: <sip:mock@{}>\r\n",
+        server.socket_address()
+    )));
+    assert!(answer.ends_with("Content-Type: application/sdp\r\nContent-Length: 5\r\n\r\nv=0\r\n"));
+
+    // ACK isn't answered, BYE is
+    client.send(&request("ACK", "z9hG4bK4", 3, "", "")).unwrap();
+    client.send(&request("BYE", "z9hG4bK5", 4, "", "")).unwrap();
+    let bye = receive(&client);
+    assert!(bye.starts_with("SIP/2.0 200 OK\r\n"));
+    assert!(bye.contains("\r\nCSeq: 4 BYE\r\n"));
+
+    let requests = requests.requests();
+    let methods: Vec<&str> = requests.iter().map(|r| r.method.as_str()).collect();
+    assert_eq!(
+        vec!["REGISTER", "REGISTER", "INVITE", "ACK", "BYE"],
+        methods
+    );
+    assert_eq!(Some("<sip:bob@example.com>"), requests[2].header("To"));
+    assert_eq!(b"v=0\r\n", requests[2].body.as_slice());
+}
+
+#[test]
+fn test_sip_tcp_busy() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            SipMockBuilder::new(server.socket_address())
+                .respond(
+                    "INVITE",
+                    vec![SipResponse::trying(), SipResponse::new(486, "Busy Here")],
+                )
+                .build(),
+        )
+        .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+
+    // Request split across writes, after a keep-alive
+    let invite = request("INVITE", "z9hG4bK1", 1, "", "v=0\r\no=alice\r\n");
+    let (start, end) = invite.split_at(invite.len() - 6);
+    client.write_all(b"\r\n\r\n").unwrap();
+    client.write_all(start).unwrap();
+    client.flush().unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    client.write_all(end).unwrap();
+
+    let mut received = String::new();
+    let mut buffer = [0; 2048];
+    while !received.contains("486 Busy Here") || !received.ends_with("\r\n\r\n") {
+        let received_size = client.read(&mut buffer).unwrap();
+        received.push_str(std::str::from_utf8(&buffer[..received_size]).unwrap());
+    }
+    let responses: Vec<&str> = received
+        .split("\r\n\r\n")
+        .filter(|response| !response.is_empty())
+        .collect();
+    assert_eq!(2, responses.len());
+    assert!(responses[0].starts_with("SIP/2.0 100 Trying\r\n"));
+    assert!(responses[1].starts_with("SIP/2.0 486 Busy Here\r\n"));
+    assert!(responses[1].contains("\r\nTo: <sip:bob@example.com>;tag=socket-server-mocker\r\n"));
+}
@@ -1,9 +1,17 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
 use std::time::Duration;
 
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
 use lettre::transport::smtp::client::Tls;
 use lettre::{Message, SmtpTransport, Transport};
-use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
-use socket_server_mocker::ServerMocker;
+use md5::Md5;
+use socket_server_mocker::protocols::smtp::{SmtpAuthMechanism, SmtpMockBuilder};
+use socket_server_mocker::{ReadInterruption, ServerMocker, ServerMockerError, TcpMocker};
 
 #[test]
 fn test_smtp_mock() {
@@ -11,20 +19,15 @@ fn test_smtp_mock() {
     let server = ServerMocker::tcp_with_port(2525).unwrap();
 
     // Mocked server behavior
-    server.add_mock_instructions(vec![
-        SendMessage(b"220 smtp.localhost.mock ESMTP Mocker\r\n".to_vec()),
-        ReceiveMessage,
-        SendMessage(b"250-smtp.localhost.mock\r\n250-PIPELINING\r\n250-SIZE 20971520\r\n250-ETRN\r\n250-STARTTLS\r\n250-ENHANCEDSTATUSCODES\r\n250 8BITMIME\r\n".to_vec()),
-        ReceiveMessage,
-        SendMessage(b"250 2.1.0 Ok\r\n".to_vec()),
-        ReceiveMessage,
-        SendMessage(b"250 2.1.5 Ok\r\n".to_vec()),
-        ReceiveMessage,
-        SendMessage(b"354 End data with <CR><LF>.<CR><LF>\r\n".to_vec()),
-        ReceiveMessage,
-        SendMessage(b"250 2.0.0 Ok: queued as 1C1A1B1C1D1E1F1G1H1I1J1K1L1M1N1O1P1Q1R1S1T1U1V1W1X1Y1Z\r\n".to_vec()),
-        StopExchange,
-    ]).unwrap();
+    server
+        .add_mock_instructions(
+            SmtpMockBuilder::new()
+                .capability("SIZE 20971520")
+                .expect_sender("alice.dupont@localhost.mock")
+                .expect_recipient("bob.dupond@localhost.mock")
+                .build(),
+        )
+        .unwrap();
 
     // Create a client based on a SmtpTransport
     let email_builder = Message::builder()
@@ -68,8 +71,8 @@ fn test_smtp_mock() {
         server.pop_received_message().unwrap().as_slice()
     );
 
-    let mail_payload_str = String::from_utf8(server.pop_received_message().unwrap()).unwrap();
-    let mut mail_payload_lines = mail_payload_str.lines();
+    let mail_payload_lines = server.pop_received_lines().unwrap();
+    let mut mail_payload_lines = mail_payload_lines.iter().map(String::as_str);
 
     // Check that the server received the expected mail payload
     assert_eq!(
@@ -95,9 +98,191 @@ fn test_smtp_mock() {
     assert!(Option::is_some(&mail_payload_lines.next())); // Email date
     assert_eq!("", mail_payload_lines.next().unwrap());
     assert_eq!("Be happy!", mail_payload_lines.next().unwrap());
-    // Last message line with only a dot "." is not returned by lines() method
+    // The email content ends with a line with only a dot
+    assert_eq!(".", mail_payload_lines.next().unwrap());
     assert_eq!(None, mail_payload_lines.next());
 
     // Check that no error has been raised by the mocked server
     assert!(server.pop_server_error().is_none());
 }
+
+#[test]
+fn test_smtp_unexpected_recipient() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            SmtpMockBuilder::new()
+                .expect_recipient("bob.dupond@localhost.mock")
+                .build(),
+        )
+        .unwrap();
+
+    let email = Message::builder()
+        .from("alice.dupont@localhost.mock".parse().unwrap())
+        .to("eve@localhost.mock".parse().unwrap())
+        .subject("Hello")
+        .body(String::from("Hello"))
+        .unwrap();
+    let mailer = SmtpTransport::relay("127.0.0.1")
+        .unwrap()
+        .tls(Tls::None)
+        .port(server.port())
+        .timeout(Some(Duration::from_secs(1)))
+        .build();
+
+    // The envelope is checked by the server mocker
+    let error = mailer.send(&email).unwrap_err();
+    assert!(error.is_permanent());
+    assert_eq!(b"EHLO ", &server.pop_received_message().unwrap()[..5]);
+    assert_eq!(
+        b"MAIL FROM:<alice.dupont@localhost.mock>\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        b"RCPT TO:<eve@localhost.mock>\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+}
+
+fn hello_email() -> Message {
+    Message::builder()
+        .from("alice.dupont@localhost.mock".parse().unwrap())
+        .to("bob.dupond@localhost.mock".parse().unwrap())
+        .subject("Hello")
+        .body(String::from("Hello"))
+        .unwrap()
+}
+
+#[test]
+fn test_smtp_auth_login() {
+    let server = ServerMocker::tcp().unwrap();
+    let smtp_mock = SmtpMockBuilder::new()
+        .auth(SmtpAuthMechanism::Login)
+        .expect_credentials("alice", "secret");
+    let credentials = smtp_mock.captured_credentials();
+    server.add_mock_instructions(smtp_mock.build()).unwrap();
+
+    let mailer = SmtpTransport::relay("127.0.0.1")
+        .unwrap()
+        .tls(Tls::None)
+        .port(server.port())
+        .credentials(Credentials::new("alice".to_string(), "secret".to_string()))
+        .authentication(vec![Mechanism::Login])
+        .timeout(Some(Duration::from_secs(1)))
+        .build();
+    mailer.send(&hello_email()).unwrap();
+
+    let credentials = credentials.get().unwrap();
+    assert_eq!(SmtpAuthMechanism::Login, credentials.mechanism);
+    assert_eq!("alice", credentials.username);
+    assert_eq!(Some("secret".to_string()), credentials.password);
+
+    assert_eq!(b"EHLO ", &server.pop_received_message().unwrap()[..5]);
+    assert_eq!(
+        b"AUTH LOGIN\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_smtp_auth_rejected() {
+    let server = ServerMocker::tcp().unwrap();
+    let smtp_mock = SmtpMockBuilder::new()
+        .auth(SmtpAuthMechanism::Plain)
+        .reject_auth();
+    let credentials = smtp_mock.captured_credentials();
+    server.add_mock_instructions(smtp_mock.build()).unwrap();
+
+    let mailer = SmtpTransport::relay("127.0.0.1")
+        .unwrap()
+        .tls(Tls::None)
+        .port(server.port())
+        .credentials(Credentials::new("alice".to_string(), "secret".to_string()))
+        .authentication(vec![Mechanism::Plain])
+        .timeout(Some(Duration::from_secs(1)))
+        .build();
+    let error = mailer.send(&hello_email()).unwrap_err();
+    assert!(error.is_permanent());
+
+    // The credentials are captured even though they are rejected
+    let credentials = credentials.get().unwrap();
+    assert_eq!("alice", credentials.username);
+    assert_eq!(Some("secret".to_string()), credentials.password);
+
+    // The client leaves after the authentication, interrupting the rest of the conversation
+    assert_client_left(&server);
+}
+
+#[test]
+fn test_smtp_auth_cram_md5() {
+    let server = ServerMocker::tcp().unwrap();
+    let smtp_mock = SmtpMockBuilder::new()
+        .auth(SmtpAuthMechanism::CramMd5)
+        .expect_credentials("alice", "secret");
+    let credentials = smtp_mock.captured_credentials();
+    server.add_mock_instructions(smtp_mock.build()).unwrap();
+
+    // lettre doesn't support CRAM-MD5
+    let mut client = BufReader::new(TcpStream::connect(server.socket_address()).unwrap());
+    client
+        .get_mut()
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut line = String::new();
+    client.read_line(&mut line).unwrap();
+    client.get_mut().write_all(b"EHLO localhost\r\n").unwrap();
+    let mut capabilities = Vec::new();
+    while !line.starts_with("250 ") {
+        line.clear();
+        client.read_line(&mut line).unwrap();
+        capabilities.push(line.trim_end()[4..].to_string());
+    }
+    assert!(capabilities.contains(&"AUTH CRAM-MD5".to_string()));
+
+    client.get_mut().write_all(b"AUTH CRAM-MD5\r\n").unwrap();
+    line.clear();
+    client.read_line(&mut line).unwrap();
+    let challenge = STANDARD
+        .decode(line.strip_prefix("334 ").unwrap().trim_end())
+        .unwrap();
+
+    let mut mac = Hmac::<Md5>::new_from_slice(b"secret").unwrap();
+    mac.update(&challenge);
+    let mut response = String::from("alice ");
+    for byte in mac.finalize().into_bytes() {
+        write!(response, "{byte:02x}").unwrap();
+    }
+    let response = STANDARD.encode(response);
+    client
+        .get_mut()
+        .write_all(format!("{response}\r\n").as_bytes())
+        .unwrap();
+    line.clear();
+    client.read_line(&mut line).unwrap();
+    assert!(line.starts_with("235 "), "{line}");
+
+    let credentials = credentials.get().unwrap();
+    assert_eq!(SmtpAuthMechanism::CramMd5, credentials.mechanism);
+    assert_eq!("alice", credentials.username);
+    assert_eq!(None, credentials.password);
+
+    // The client leaves after the authentication, interrupting the rest of the conversation
+    drop(client);
+    assert_client_left(&server);
+}
+
+/// Check that the conversation was interrupted by the client closing the connection
+fn assert_client_left(server: &ServerMocker<TcpMocker>) {
+    let errors = server.take_errors();
+    assert!(
+        matches!(
+            errors.first(),
+            Some(ServerMockerError::ReadInterrupted(
+                ReadInterruption::ConnectionClosed,
+                _
+            ))
+        ),
+        "{errors:?}"
+    );
+}
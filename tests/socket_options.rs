@@ -0,0 +1,292 @@
+//! Socket options of the server mockers and of their accepted connections
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use socket_server_mocker::Instruction::{
+    ReceiveMessage, Repeat, SendMessage, SlowDrip, StopExchange,
+};
+use socket_server_mocker::{
+    ExcessConnections, Hooks, Instruction, Keepalive, ServerMocker, ServerMockerError, TcpMocker,
+    Times, UdpMocker,
+};
+
+#[test]
+fn test_tcp_nodelay() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        nodelay: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    client.set_nodelay(true).unwrap();
+
+    server.add_mock_instructions(vec![round_trips()]).unwrap();
+    assert_round_trips_not_delayed(&mut client);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_nodelay_after_slow_drip() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        nodelay: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    client.set_nodelay(true).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            SlowDrip {
+                byte_interval: Duration::from_millis(1),
+                instructions: vec![SendMessage(b"xy".to_vec())],
+            },
+            round_trips(),
+        ])
+        .unwrap();
+    let mut buffer = [0; 2];
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"xy", &buffer);
+    // The option is kept once the slow drip is over
+    assert_round_trips_not_delayed(&mut client);
+    assert!(server.pop_server_error().is_none());
+}
+
+/// Round trips answered with two small writes
+fn round_trips() -> Instruction {
+    Repeat {
+        times: Times::exactly(10),
+        instructions: vec![
+            ReceiveMessage,
+            SendMessage(b"a".to_vec()),
+            SendMessage(b"b".to_vec()),
+        ],
+    }
+}
+
+/// Check that the second small write of each round trip isn't held back until the first one is acknowledged
+fn assert_round_trips_not_delayed(client: &mut TcpStream) {
+    let start = Instant::now();
+    let mut buffer = [0; 2];
+    for _ in 0..10 {
+        client.write_all(b"?").unwrap();
+        client.read_exact(&mut buffer).unwrap();
+        assert_eq!(b"ab", &buffer);
+    }
+    assert!(start.elapsed() < Duration::from_millis(300));
+}
+
+#[test]
+fn test_tcp_linger_reset_on_close() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        linger: Some(Duration::ZERO),
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![SendMessage(b"bye".to_vec()), StopExchange])
+        .unwrap();
+
+    // The connection is reset instead of being closed gracefully
+    let mut received = Vec::new();
+    let error = client.read_to_end(&mut received).unwrap_err();
+    assert_eq!(ErrorKind::ConnectionReset, error.kind());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_linger_flush_before_close() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        linger: Some(Duration::from_secs(1)),
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![SendMessage(b"bye".to_vec()), StopExchange])
+        .unwrap();
+
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"bye", received.as_slice());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_keepalive() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        keepalive: Some(Keepalive {
+            time: Duration::from_secs(1),
+            interval: Some(Duration::from_secs(1)),
+            probes: Some(3),
+        }),
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.write_all(b"ping").unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"pong", received.as_slice());
+    // The keepalive parameters are accepted by the system
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_excess_connections_queued() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        backlog: Some(4),
+        net_timeout: Duration::from_secs(1),
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // The second connection waits in the backlog while the first one is served
+    let mut queued_client = TcpStream::connect(server.socket_address()).unwrap();
+    queued_client
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let mut buffer = [0; 4];
+    let error = queued_client.read(&mut buffer).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+
+    client.write_all(b"ping").unwrap();
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"pong", &buffer);
+}
+
+#[test]
+fn test_tcp_excess_connections_refused() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        excess_connections: ExcessConnections::Refuse,
+        net_timeout: Duration::from_secs(1),
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // The second connection is reset while the first one is served
+    let mut refused_client = TcpStream::connect(server.socket_address()).unwrap();
+    let mut buffer = [0; 4];
+    let error = refused_client.read(&mut buffer).unwrap_err();
+    assert_eq!(ErrorKind::ConnectionReset, error.kind());
+
+    client.write_all(b"ping").unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"pong", received.as_slice());
+    assert_eq!(1, server.stats().connections_accepted);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_socket_hook() {
+    let (socket_tx, socket_rx) = mpsc::channel();
+    let server = ServerMocker::new_with_opts(TcpMocker::default().hooks(
+        Hooks::default().on_socket(move |socket| {
+            socket.set_recv_buffer_size(64 * 1024)?;
+            socket_tx.send(socket.recv_buffer_size()?).unwrap();
+            Ok(())
+        }),
+    ))
+    .unwrap();
+    assert!(socket_rx.try_recv().unwrap() >= 64 * 1024);
+
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![SendMessage(b"hello".to_vec()), StopExchange])
+        .unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"hello", received.as_slice());
+}
+
+#[test]
+fn test_failing_socket_hook() {
+    let server = ServerMocker::new_with_opts(
+        UdpMocker::default()
+            .hooks(Hooks::default().on_socket(|_| Err(io::Error::other("unsupported option")))),
+    );
+    assert!(matches!(
+        server,
+        Err(ServerMockerError::UnableToBindListener(..))
+    ));
+}
+
+#[test]
+fn test_udp_ttl_and_tos() {
+    let (options_tx, options_rx) = mpsc::channel();
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        ttl: Some(3),
+        tos: Some(0xb8),
+        // The hook sees the options already applied
+        hooks: Hooks::default().on_socket(move |socket| {
+            options_tx
+                .send((socket.ttl_v4()?, socket.tos_v4()?))
+                .unwrap();
+            Ok(())
+        }),
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    assert_eq!((3, 0xb8), options_rx.try_recv().unwrap());
+
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    client.send(b"ping").unwrap();
+    let mut buffer = [0; 8];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"pong", &buffer[..received_size]);
+}
+
+#[test]
+fn test_udp_invalid_ttl() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        ttl: Some(256),
+        ..UdpMocker::default()
+    });
+    assert!(matches!(
+        server,
+        Err(ServerMockerError::UnableToBindListener(..))
+    ));
+}
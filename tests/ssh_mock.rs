@@ -0,0 +1,143 @@
+//! Mock an SSH server failing right after the identification string exchange
+
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use socket_server_mocker::protocols::ssh::{SshFailure, SshMockBuilder};
+use socket_server_mocker::{ServerMocker, TcpMocker};
+
+/// Connect to the server, exchange the identification strings and return the lines received before the client's one
+fn connect(server: &ServerMocker<TcpMocker>) -> (BufReader<TcpStream>, Vec<String>) {
+    let mut client = BufReader::new(TcpStream::connect(server.socket_address()).unwrap());
+    client
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        client.read_line(&mut line).unwrap();
+        let is_identification = line.starts_with("SSH-");
+        lines.push(line);
+        if is_identification {
+            break;
+        }
+    }
+    client
+        .get_mut()
+        .write_all(b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n")
+        .unwrap();
+    (client, lines)
+}
+
+/// Read a binary packet, returning its payload
+fn read_packet(client: &mut BufReader<TcpStream>) -> Vec<u8> {
+    let mut header = [0; 5];
+    client.read_exact(&mut header).unwrap();
+    let packet_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    assert_eq!(0, (packet_len + 4) % 8);
+    let mut rest = vec![0; packet_len - 1];
+    client.read_exact(&mut rest).unwrap();
+    rest.truncate(packet_len - 1 - header[4] as usize);
+    rest
+}
+
+#[test]
+fn test_ssh_unsupported_algorithms() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            SshMockBuilder::new()
+                .banner("Authorized access only")
+                .identification("SSH-2.0-MockSSH_1.0")
+                .then(SshFailure::UnsupportedAlgorithms)
+                .build(),
+        )
+        .unwrap();
+    let (mut client, lines) = connect(&server);
+    assert_eq!(
+        vec!["Authorized access only\r\n", "SSH-2.0-MockSSH_1.0\r\n"],
+        lines
+    );
+
+    let payload = read_packet(&mut client);
+    assert_eq!(20, payload[0]);
+    let kex_algorithms_len =
+        u32::from_be_bytes([payload[17], payload[18], payload[19], payload[20]]);
+    assert_eq!(
+        b"mock@socket-server-mocker",
+        &payload[21..21 + kex_algorithms_len as usize]
+    );
+    // The client gives up
+    drop(client);
+    assert_eq!(
+        b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+}
+
+#[test]
+fn test_ssh_malformed_packets() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            SshMockBuilder::new()
+                .then(SshFailure::MalformedKexinit)
+                .build(),
+        )
+        .unwrap();
+    let (mut client, lines) = connect(&server);
+    assert_eq!(vec!["SSH-2.0-OpenSSH_9.6\r\n"], lines);
+    let payload = read_packet(&mut client);
+    let name_list_len = u32::from_be_bytes([payload[17], payload[18], payload[19], payload[20]]);
+    assert!(name_list_len as usize > payload.len());
+
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            SshMockBuilder::new()
+                .then(SshFailure::OversizedPacket)
+                .build(),
+        )
+        .unwrap();
+    let (mut client, _) = connect(&server);
+    let mut header = [0; 5];
+    client.read_exact(&mut header).unwrap();
+    assert!(u32::from_be_bytes([header[0], header[1], header[2], header[3]]) > 256 * 1024);
+}
+
+#[test]
+fn test_ssh_close_and_stall() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(SshMockBuilder::new().build())
+        .unwrap();
+    let (mut client, _) = connect(&server);
+    let mut buffer = [0; 16];
+    assert_eq!(0, client.read(&mut buffer).unwrap());
+
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            SshMockBuilder::new()
+                .then(SshFailure::Stall(Duration::from_millis(500)))
+                .build(),
+        )
+        .unwrap();
+    let (mut client, _) = connect(&server);
+    client
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let error = client.read(&mut buffer).unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+    client
+        .get_ref()
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    assert_eq!(0, client.read(&mut buffer).unwrap());
+}
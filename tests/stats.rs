@@ -0,0 +1,141 @@
+//! Statistics on the traffic handled by the server mocker
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, Repeat, SendMessage, SendMessageChunked};
+use socket_server_mocker::{
+    ReadInterruption, ServerMocker, ServerMockerError, ServerStats, TcpMocker, TimelineEvent, Times,
+};
+
+#[test]
+fn test_tcp_stats() {
+    // Long enough for the client to never make the server time out, even on a loaded machine
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        net_timeout: Duration::from_secs(5),
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    assert_eq!(ServerStats::default(), server.stats());
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            Repeat {
+                times: Times::exactly(2),
+                instructions: vec![ReceiveMessage, SendMessage(b"pong".to_vec())],
+            },
+            SendMessageChunked {
+                message: b"bye!".to_vec(),
+                chunk_size: 2,
+                interval: Duration::ZERO,
+            },
+            // The client closes the connection instead of sending anything more
+            ReceiveMessage,
+        ])
+        .unwrap();
+    let mut buffer = [0; 4];
+    for _ in 0..2 {
+        client.write_all(b"ping").unwrap();
+        client.read_exact(&mut buffer).unwrap();
+    }
+    client.read_exact(&mut buffer).unwrap();
+    drop(client);
+    // Returned as soon as the last receive instruction fails, the stats being updated by then
+    assert!(matches!(
+        server.pop_server_error(),
+        Some(ServerMockerError::ReadInterrupted(
+            ReadInterruption::ConnectionClosed,
+            _
+        ))
+    ));
+
+    let stats = server.stats();
+    assert!(stats.max_client_latency.is_some());
+    assert!(stats.max_server_latency.is_some());
+    assert_eq!(
+        ServerStats {
+            bytes_received: 8,
+            bytes_sent: 12,
+            messages_received: 2,
+            messages_sent: 4,
+            connections_accepted: 1,
+            instructions_executed: 7,
+            errors_raised: 1,
+            ..stats
+        },
+        stats
+    );
+}
+
+#[test]
+fn test_udp_stats() {
+    let server = ServerMocker::udp().unwrap();
+    let clients = [
+        UdpSocket::bind("127.0.0.1:0").unwrap(),
+        UdpSocket::bind("127.0.0.1:0").unwrap(),
+    ];
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+        ])
+        .unwrap();
+    let mut buffer = [0; 4];
+    for client in &clients {
+        client.send_to(b"ping!", server.socket_address()).unwrap();
+        client.recv(&mut buffer).unwrap();
+    }
+    assert!(server.pop_server_error().is_none());
+
+    let stats = server.stats();
+    assert_eq!(10, stats.bytes_received);
+    assert_eq!(8, stats.bytes_sent);
+    assert_eq!(2, stats.messages_sent);
+    assert_eq!(2, stats.connections_accepted);
+    assert_eq!(4, stats.instructions_executed);
+    assert_eq!(0, stats.errors_raised);
+}
+
+#[test]
+fn test_latency() {
+    // Long enough for the client to never make the server time out, even on a loaded machine
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        net_timeout: Duration::from_secs(5),
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            SendMessage(b"hello".to_vec()),
+            ReceiveMessage,
+            SendMessage(b"bye".to_vec()),
+        ])
+        .unwrap();
+
+    let mut buffer = [0; 5];
+    client.read_exact(&mut buffer).unwrap();
+    // The client takes its time to answer
+    thread::sleep(Duration::from_millis(100));
+    client.write_all(b"ping").unwrap();
+    client.read_exact(&mut buffer[..3]).unwrap();
+
+    let stats = server.stats();
+    let client_latency = stats.max_client_latency.unwrap();
+    assert!(client_latency >= Duration::from_millis(100));
+    assert!(stats.max_server_latency.unwrap() < client_latency);
+
+    // The received data is timed after the data sent
+    let timeline = server.timeline();
+    let received = timeline
+        .iter()
+        .find(|entry| entry.event == TimelineEvent::Received(4))
+        .unwrap();
+    assert_eq!(Some(client_latency), received.latency);
+}
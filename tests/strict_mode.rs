@@ -0,0 +1,82 @@
+//! Strict mode, reporting data sent by the client that wasn't expected
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::thread::sleep;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{ServerMocker, ServerMockerError, TcpMocker, UdpMocker};
+
+#[test]
+fn test_tcp_strict_mode_unexpected_data() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        strict: true,
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"ok".to_vec())])
+        .unwrap();
+
+    client.write_all(b"hello").unwrap();
+    let mut buffer = [0; 16];
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"ok", &buffer[..received_size]);
+
+    // The client sends more than the test scripted
+    client.write_all(b"unexpected").unwrap();
+    sleep(Duration::from_millis(20));
+    server.add_mock_instructions(vec![StopExchange]).unwrap();
+
+    assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+    let err = server.pop_server_error().unwrap();
+    assert!(matches!(&err, ServerMockerError::UnexpectedData(data) if data == b"unexpected"));
+    assert!(!err.is_fatal());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_strict_mode_unexpected_data() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        strict: true,
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    client.send(b"first").unwrap();
+    client.send(b"second").unwrap();
+    sleep(Duration::from_millis(20));
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"ok".to_vec())])
+        .unwrap();
+
+    assert_eq!(b"first", server.pop_received_message().unwrap().as_slice());
+    let err = server.pop_server_error().unwrap();
+    assert!(matches!(&err, ServerMockerError::UnexpectedData(data) if data == b"second"));
+    assert_eq!(
+        "Non fatal: Received 6 bytes of unexpected data from client: b\"second\"",
+        err.to_string()
+    );
+}
+
+#[test]
+fn test_tcp_lenient_by_default() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    client.write_all(b"ignored").unwrap();
+    sleep(Duration::from_millis(20));
+    server
+        .add_mock_instructions(vec![SendMessage(b"ok".to_vec()), StopExchange])
+        .unwrap();
+
+    let mut buffer = [0; 16];
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"ok", &buffer[..received_size]);
+    assert!(server.pop_server_error().is_none());
+}
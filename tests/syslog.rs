@@ -0,0 +1,109 @@
+//! Receive syslog messages over UDP and TCP, and parse them
+
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+
+use socket_server_mocker::protocols::syslog;
+use socket_server_mocker::Instruction::ReceiveMessage;
+use socket_server_mocker::{Framing, ServerMocker, ServerMockerError, TcpMocker};
+
+#[test]
+fn test_udp_syslog() {
+    let server = ServerMocker::udp().unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage, ReceiveMessage])
+        .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    client
+        .send(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog 1234 ID47 \
+             [exampleSDID@32473 iut=\"3\" eventSource=\"Application\"][origin ip=\"10.0.0.1\" \
+             note=\"quoted \\\"value\\\" \\] here\"] \u{feff}An application event log entry\n"
+                .as_bytes(),
+        )
+        .unwrap();
+    client
+        .send(b"<34>Oct  1 22:14:15 mymachine su: 'su root' failed for lonvick on /dev/pts/8")
+        .unwrap();
+    client.send(b"not a syslog message").unwrap();
+
+    let message = syslog::parse(&server.pop_received_message().unwrap()).unwrap();
+    assert_eq!(20, message.facility);
+    assert_eq!(5, message.severity);
+    assert_eq!(Some(1), message.version);
+    assert_eq!(
+        Some("2003-10-11T22:14:15.003Z"),
+        message.timestamp.as_deref()
+    );
+    assert_eq!(Some("mymachine.example.com"), message.hostname.as_deref());
+    assert_eq!(Some("evntslog"), message.app_name.as_deref());
+    assert_eq!(Some("1234"), message.proc_id.as_deref());
+    assert_eq!(Some("ID47"), message.msg_id.as_deref());
+    assert_eq!(2, message.structured_data.len());
+    assert_eq!(
+        Some("Application"),
+        message.param("exampleSDID@32473", "eventSource")
+    );
+    assert_eq!(
+        Some("quoted \"value\" ] here"),
+        message.param("origin", "note")
+    );
+    assert_eq!("An application event log entry", message.message);
+
+    let message = syslog::parse(&server.pop_received_message().unwrap()).unwrap();
+    assert_eq!(4, message.facility);
+    assert_eq!(2, message.severity);
+    assert_eq!(None, message.version);
+    assert_eq!(Some("Oct  1 22:14:15"), message.timestamp.as_deref());
+    assert_eq!(Some("mymachine"), message.hostname.as_deref());
+    assert_eq!(Some("su"), message.app_name.as_deref());
+    assert_eq!(None, message.proc_id);
+    assert_eq!(
+        "'su root' failed for lonvick on /dev/pts/8",
+        message.message
+    );
+
+    assert!(matches!(
+        syslog::parse(&server.pop_received_message().unwrap()),
+        Err(ServerMockerError::InvalidSyslogMessage(data)) if data == b"not a syslog message"
+    ));
+}
+
+#[test]
+fn test_tcp_syslog_framing() {
+    let server =
+        ServerMocker::new_with_opts(TcpMocker::default().framing(Framing::Syslog)).unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage, ReceiveMessage])
+        .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    // Octet-counted messages may contain line feeds, and be split across writes
+    client
+        .write_all(b"48 <14>1 - web01 nginx - - - first line\nsecond l")
+        .unwrap();
+    client.flush().unwrap();
+    client
+        .write_all(b"ine<11>Oct 11 22:14:15 web01 app[42]: failure\n<15>1 - - - - - - last")
+        .unwrap();
+    drop(client);
+
+    let message = syslog::parse(&server.pop_received_message().unwrap()).unwrap();
+    assert_eq!(Some("web01"), message.hostname.as_deref());
+    assert_eq!(Some("nginx"), message.app_name.as_deref());
+    assert_eq!("first line\nsecond line", message.message);
+
+    let message = syslog::parse(&server.pop_received_message().unwrap()).unwrap();
+    assert_eq!(3, message.severity);
+    assert_eq!(Some("app"), message.app_name.as_deref());
+    assert_eq!(Some("42"), message.proc_id.as_deref());
+    assert_eq!("failure", message.message);
+
+    // The trailer of the last message is omitted as the connection is closed
+    let message = syslog::parse(&server.pop_received_message().unwrap()).unwrap();
+    assert_eq!(7, message.severity);
+    assert_eq!(None, message.hostname);
+    assert_eq!("last", message.message);
+}
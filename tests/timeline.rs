@@ -0,0 +1,149 @@
+//! Chronological account of the session
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use socket_server_mocker::Instruction::{
+    Pause, ReceiveMessage, ReceiveUntilClose, SendMessage, Silence,
+};
+use socket_server_mocker::{ServerMocker, ServerMockerError, TcpMocker, TimelineEvent};
+
+#[test]
+fn test_tcp_timeline() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    let client_addr = client.local_addr().unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            // The client doesn't send anything more
+            ReceiveMessage,
+        ])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let mut buffer = [0; 4];
+    client.read_exact(&mut buffer).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert!(server.pop_server_error().is_some());
+
+    let timeline = server.timeline();
+    assert_eq!(
+        vec![
+            TimelineEvent::Accepted,
+            TimelineEvent::Received(4),
+            TimelineEvent::Sent(4),
+            TimelineEvent::TimedOut,
+        ],
+        timeline[..4]
+            .iter()
+            .map(|entry| entry.event.clone())
+            .collect::<Vec<_>>()
+    );
+    assert!(matches!(timeline[4].event, TimelineEvent::Error(_)));
+    assert!(timeline
+        .windows(2)
+        .all(|entries| entries[0].elapsed <= entries[1].elapsed));
+
+    assert_eq!(Some(client_addr), timeline[1].client_addr);
+    let line = timeline[1].to_string();
+    assert!(line.starts_with('+'));
+    assert!(line.ends_with(&format!("s {client_addr} received 4 bytes")));
+}
+
+#[test]
+fn test_client_disconnected_gracefully() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"pong".to_vec())])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let mut buffer = [0; 4];
+    client.read_exact(&mut buffer).unwrap();
+    // Still connected after the first exchange
+    assert_eq!(None, server.client_disconnected());
+
+    server
+        .add_mock_instructions(vec![ReceiveUntilClose])
+        .unwrap();
+    drop(client);
+    assert_eq!(Some(true), server.client_disconnected());
+    assert!(server
+        .timeline()
+        .iter()
+        .any(|entry| entry.event == TimelineEvent::ClientDisconnected { graceful: true }));
+}
+
+#[test]
+fn test_client_reset_connection() {
+    let server = ServerMocker::tcp().unwrap();
+    let client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![SendMessage(b"unread".to_vec()), ReceiveMessage])
+        .unwrap();
+    // Closing the socket with unread data resets the connection
+    thread::sleep(Duration::from_millis(50));
+    drop(client);
+
+    assert_eq!(Some(false), server.client_disconnected());
+    let disconnection = server
+        .timeline()
+        .into_iter()
+        .find(|entry| matches!(entry.event, TimelineEvent::ClientDisconnected { .. }))
+        .unwrap();
+    assert!(disconnection
+        .to_string()
+        .ends_with("client reset the connection"));
+}
+
+#[test]
+fn test_client_disconnection_reported() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        strict: true,
+        ..TcpMocker::default()
+    })
+    .unwrap();
+    let client = TcpStream::connect(server.socket_address()).unwrap();
+    drop(client);
+    thread::sleep(Duration::from_millis(50));
+
+    // Strict mode checks the socket before the instruction, finding it closed
+    server
+        .add_mock_instructions(vec![Pause(Duration::from_millis(10))])
+        .unwrap();
+
+    let error = server.pop_server_error().unwrap();
+    assert!(matches!(
+        error,
+        ServerMockerError::ClientDisconnected { graceful: true }
+    ));
+    assert!(!error.is_fatal());
+}
+
+#[test]
+fn test_client_disconnection_during_silence() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"world".to_vec())])
+        .unwrap();
+    client.write_all(b"hello").unwrap();
+    let mut buffer = [0; 5];
+    client.read_exact(&mut buffer).unwrap();
+    assert_eq!(b"hello", server.pop_received_message().unwrap().as_slice());
+
+    // The client closes the connection cleanly before the final check
+    drop(client);
+    server
+        .add_mock_instructions(vec![Silence(Duration::from_millis(200))])
+        .unwrap();
+    server.assert_no_more_messages();
+    assert_eq!(Some(true), server.client_disconnected());
+}
@@ -0,0 +1,309 @@
+//! Instructions controlling the timing of the mocked server responses
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use socket_server_mocker::Instruction::{
+    Pause, ReceiveMessage, SendMessage, SendMessageChunked, Silence, SlowDrip, StopExchange,
+};
+use socket_server_mocker::{ServerMocker, TcpMocker, UdpMocker};
+
+#[test]
+fn test_pause_triggers_client_read_timeout() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            // Slow backend: the response comes after the client read timeout
+            Pause(Duration::from_millis(150)),
+            SendMessage(b"late response".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    let start = Instant::now();
+    client.write_all(b"request").unwrap();
+    let mut buffer = [0; 32];
+    let err = client.read(&mut buffer).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+
+    // The client retries reading and finally gets the response
+    client
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"late response", &buffer[..received_size]);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_send_message_chunked() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            SendMessageChunked {
+                message: b"HTTP/1.1 204 No Content\r\n\r\n".to_vec(),
+                chunk_size: 4,
+                interval: Duration::from_millis(30),
+            },
+            StopExchange,
+        ])
+        .unwrap();
+
+    // A single read only returns the first chunk
+    let mut buffer = [0; 64];
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"HTTP", &buffer[..received_size]);
+
+    // The whole message is eventually received
+    let mut received = buffer[..received_size].to_vec();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"HTTP/1.1 204 No Content\r\n\r\n", received.as_slice());
+
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_silence_keeps_connection_open() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            Silence(Duration::from_millis(200)),
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    // The hung server swallows the request and never answers
+    client.write_all(b"ping").unwrap();
+    let mut buffer = [0; 16];
+    let err = client.read(&mut buffer).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+
+    // The connection is still alive once the server wakes up
+    thread::sleep(Duration::from_millis(200));
+    client
+        .set_read_timeout(Some(Duration::from_millis(500)))
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let received_size = client.read(&mut buffer).unwrap();
+    assert_eq!(b"pong", &buffer[..received_size]);
+
+    // Only the message sent after the silence has been received
+    assert_eq!(b"ping", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_received_message().is_none());
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_latency() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        latency: Some(Duration::from_millis(100)),
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"first".to_vec()),
+            SendMessage(b"second".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    let start = Instant::now();
+    client.write_all(b"request").unwrap();
+    let mut received = Vec::new();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(b"firstsecond", received.as_slice());
+    // Each write is delayed
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_udp_latency() {
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        latency: Some(Duration::from_millis(100)),
+        ..Default::default()
+    })
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    let start = Instant::now();
+    client.send(b"ping").unwrap();
+    let mut buffer = [0; 8];
+    let received_size = client.recv(&mut buffer).unwrap();
+    assert_eq!(b"pong", &buffer[..received_size]);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_tcp_throttle() {
+    let server = ServerMocker::new_with_opts(TcpMocker {
+        throttle_bytes_per_sec: Some(4000),
+        ..Default::default()
+    })
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![SendMessage(vec![0x42; 1000]), StopExchange])
+        .unwrap();
+
+    let start = Instant::now();
+    // The download is received progressively
+    let mut buffer = [0; 1000];
+    let received_size = client.read(&mut buffer).unwrap();
+    assert!(received_size < 1000);
+
+    let mut received = buffer[..received_size].to_vec();
+    client.read_to_end(&mut received).unwrap();
+    assert_eq!(vec![0x42; 1000], received);
+    // 250ms at 4000 bytes per second
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_slow_drip() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    // Each read succeeds, but the whole response is longer than the read timeout
+    client
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SlowDrip {
+                byte_interval: Duration::from_millis(20),
+                instructions: vec![SendMessage(b"200 OK".to_vec())],
+            },
+            SendMessage(b"!".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    let start = Instant::now();
+    client.write_all(b"GET").unwrap();
+    let mut response = [0; 7];
+    client.read_exact(&mut response).unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(b"200 OK!", &response);
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_received_message_meta() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, ReceiveMessage, StopExchange])
+        .unwrap();
+
+    let start = Instant::now();
+    client.write_all(b"request").unwrap();
+    let first = server.pop_received_message_with_meta().unwrap();
+    // The client retries after a while
+    thread::sleep(Duration::from_millis(50));
+    client.write_all(b"retry").unwrap();
+    let retry = server.pop_received_message_with_meta().unwrap();
+
+    assert_eq!(b"request", first.data.as_slice());
+    assert_eq!(b"retry", retry.data.as_slice());
+    assert_eq!(Some(client.local_addr().unwrap()), first.client_addr);
+    assert!(start <= first.received_at);
+    assert!(retry.received_at - first.received_at >= Duration::from_millis(50));
+    assert!(server.pop_server_error().is_none());
+}
+
+#[test]
+fn test_pop_with_timeout() {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            // The server is slower than the default timeout
+            Pause(Duration::from_millis(300)),
+            ReceiveMessage,
+            Pause(Duration::from_millis(300)),
+            ReceiveMessage,
+        ])
+        .unwrap();
+
+    client.write_all(b"request").unwrap();
+    assert!(server.pop_received_message().is_none());
+    assert_eq!(
+        Some(b"request".to_vec()),
+        server.pop_received_message_timeout(Duration::from_secs(1))
+    );
+
+    // The client never sends the second message
+    assert!(server.pop_server_error().is_none());
+    assert!(server
+        .pop_server_error_timeout(Duration::from_secs(1))
+        .is_some());
+}
+
+#[test]
+fn test_try_pop_received_message() {
+    let server = ServerMocker::udp().unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    server
+        .add_mock_instructions(vec![ReceiveMessage, StopExchange])
+        .unwrap();
+
+    // Nothing has arrived yet, and the check doesn't wait
+    let start = Instant::now();
+    assert!(server.try_pop_received_message().is_none());
+    assert!(start.elapsed() < Duration::from_millis(50));
+
+    client.send_to(b"ping", server.socket_address()).unwrap();
+    let message = (0..100)
+        .find_map(|_| {
+            thread::sleep(Duration::from_millis(10));
+            server.try_pop_received_message()
+        })
+        .unwrap();
+    assert_eq!(b"ping", message.as_slice());
+}
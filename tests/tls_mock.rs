@@ -0,0 +1,100 @@
+//! TLS server mocker presenting the generated certificates, the client only trusting the generated authority
+#![cfg(feature = "tls")]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use rustls::crypto::ring::default_provider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{ServerMocker, ServerMockerError, TlsMocker};
+
+/// Connect to the given server mocker with the given server name, trusting its authority
+fn connect(
+    server: &ServerMocker<TlsMocker>,
+    server_name: &str,
+) -> StreamOwned<ClientConnection, TcpStream> {
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_slice(server.ca_cert_pem().as_bytes()).unwrap())
+        .unwrap();
+    let client_config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connection = ClientConnection::new(
+        Arc::new(client_config),
+        ServerName::try_from(server_name.to_string()).unwrap(),
+    )
+    .unwrap();
+    StreamOwned::new(
+        connection,
+        TcpStream::connect(server.socket_address()).unwrap(),
+    )
+}
+
+#[test]
+fn test_tls_mock() {
+    for server_name in ["localhost", "127.0.0.1", "::1"] {
+        let server = ServerMocker::tls().unwrap();
+        server
+            .add_mock_instructions(vec![
+                ReceiveMessage,
+                SendMessage(b"hello".to_vec()),
+                StopExchange,
+            ])
+            .unwrap();
+
+        let mut client = connect(&server, server_name);
+        client.write_all(b"ping").unwrap();
+        client.flush().unwrap();
+        let mut message = Vec::new();
+        // Ended by the close_notify alert sent by StopExchange
+        client.read_to_end(&mut message).unwrap();
+        assert_eq!(b"hello".to_vec(), message);
+
+        assert_eq!(Some(b"ping".to_vec()), server.pop_received_message());
+        assert!(server.take_errors().is_empty());
+    }
+}
+
+#[test]
+fn test_tls_mock_wrong_server_name() {
+    let server = ServerMocker::tls().unwrap();
+    server.add_mock_instructions(vec![ReceiveMessage]).unwrap();
+    let mut client = connect(&server, "example.com");
+    let error = client.write_all(b"ping").unwrap_err();
+    assert!(matches!(
+        *error
+            .into_inner()
+            .unwrap()
+            .downcast::<rustls::Error>()
+            .unwrap(),
+        rustls::Error::InvalidCertificate(_)
+    ));
+    // The server receives the alert of the client instead of its message
+    drop(client);
+    assert!(matches!(
+        server.pop_server_error(),
+        Some(ServerMockerError::UnableToReadTcpStream(_))
+    ));
+}
+
+#[test]
+fn test_tls_mock_certificates() {
+    let server = ServerMocker::tls().unwrap();
+    let other = ServerMocker::tls().unwrap();
+    // Each server mocker has its own authority
+    assert_ne!(server.ca_cert_pem(), other.ca_cert_pem());
+    assert!(server
+        .ca_cert_pem()
+        .ends_with("-----END CERTIFICATE-----\n"));
+    assert_eq!(
+        server.options().certificates.ca_cert_pem(),
+        server.ca_cert_pem()
+    );
+}
@@ -0,0 +1,88 @@
+//! Tracing instrumentation of the server threads, available with the `tracing` feature
+
+#![cfg(feature = "tracing")]
+
+use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::ServerMocker;
+
+/// Subscriber keeping the messages of the events emitted by every thread
+#[derive(Default, Clone)]
+struct MessageCollector(Arc<Mutex<Vec<String>>>);
+
+impl Visit for MessageCollector {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0.lock().unwrap().push(format!("{value:?}"));
+        }
+    }
+}
+
+impl Subscriber for MessageCollector {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target().starts_with("socket_server_mocker")
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        event.record(&mut self.clone());
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn test_tracing_events() {
+    let collector = MessageCollector::default();
+    // The server thread doesn't inherit a thread local subscriber
+    tracing::subscriber::set_global_default(collector.clone()).unwrap();
+
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            ReceiveMessage,
+            StopExchange,
+        ])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let mut buffer = [0; 4];
+    client.read_exact(&mut buffer).unwrap();
+    // The client doesn't send the second message
+    thread::sleep(Duration::from_millis(200));
+    assert!(server.pop_server_error().is_some());
+
+    let messages = collector.0.lock().unwrap().clone();
+    for expected in [
+        "TCP server mocker listening",
+        "connection accepted",
+        "executing instruction",
+        "data exchanged",
+        "timed out waiting for the client",
+        "instruction failed",
+        "exchange stopped",
+    ] {
+        assert!(messages.iter().any(|message| message == expected));
+    }
+}
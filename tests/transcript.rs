@@ -0,0 +1,77 @@
+//! Transcripts of the exchange written to files
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpStream, UdpSocket};
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{ServerMocker, TcpMocker, TranscriptFormat, UdpMocker};
+
+#[test]
+fn test_tcp_annotated_hex_transcript() {
+    let directory = std::env::temp_dir().join("socket-server-mocker-transcript-tcp");
+    let server = ServerMocker::new_with_opts(
+        TcpMocker::default().transcript(&directory, TranscriptFormat::AnnotatedHex),
+    )
+    .unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.write_all(b"ping").unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).unwrap();
+
+    // Written as the exchange goes, without any assertion on the server
+    let client_addr = client.local_addr().unwrap();
+    let path = directory.join(format!("tcp-127.0.0.1_{}.txt", client_addr.port()));
+    let transcript = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    let lines: Vec<&str> = transcript.lines().collect();
+    assert_eq!(4, lines.len());
+    assert!(lines[0].starts_with("# ") && lines[0].ends_with(" client -> server 4 bytes"));
+    assert!(lines[1].starts_with("00000000: 7069 6e67") && lines[1].ends_with(" ping"));
+    assert!(lines[2].ends_with(" server -> client 4 bytes"));
+    assert!(lines[3].ends_with(" pong"));
+}
+
+#[test]
+fn test_udp_binary_transcript() {
+    let directory = std::env::temp_dir().join("socket-server-mocker-transcript-udp");
+    let server = ServerMocker::new_with_opts(
+        UdpMocker::default().transcript(&directory, TranscriptFormat::Binary),
+    )
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.connect(server.socket_address()).unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+
+    client.send(b"ping").unwrap();
+    let mut buffer = [0; 4];
+    client.recv(&mut buffer).unwrap();
+
+    let client_addr = client.local_addr().unwrap();
+    let path = directory.join(format!("udp-127.0.0.1_{}.bin", client_addr.port()));
+    let transcript = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(2 * (13 + 4), transcript.len());
+    let (request, response) = transcript.split_at(17);
+    assert_eq!(0, request[0]);
+    assert_eq!(4_u32.to_be_bytes(), request[9..13]);
+    assert_eq!(b"ping", &request[13..]);
+    assert_eq!(1, response[0]);
+    assert_eq!(b"pong", &response[13..]);
+    // Timestamps in microseconds, in order
+    assert!(request[1..9] <= response[1..9]);
+}
@@ -0,0 +1,160 @@
+//! Ordered verification of the messages received by the server mocker
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{Matcher, ServerMocker, ServerMockerError, TcpMocker};
+
+/// Run a small SMTP-like exchange where the client sends the given commands
+fn smtp_like_exchange(commands: &[&[u8]]) -> ServerMocker<TcpMocker> {
+    let server = ServerMocker::tcp().unwrap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    for _ in commands {
+        server
+            .add_mock_instructions(vec![ReceiveMessage, SendMessage(b"250 OK\r\n".to_vec())])
+            .unwrap();
+    }
+    server.add_mock_instructions(vec![StopExchange]).unwrap();
+
+    let mut buffer = [0; 8];
+    for command in commands {
+        client.write_all(command).unwrap();
+        client.read_exact(&mut buffer).unwrap();
+    }
+    server
+}
+
+#[test]
+fn test_verify_sequence() {
+    let server = smtp_like_exchange(&[
+        b"EHLO localhost\r\n",
+        b"MAIL FROM:<alice@localhost>\r\n",
+        b"QUIT\r\n",
+    ]);
+
+    // Messages can be popped before the verification
+    assert_eq!(
+        b"EHLO localhost\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+
+    server
+        .verify_sequence(&[
+            Matcher::StartsWith(b"EHLO ".to_vec()),
+            Matcher::Contains(b"alice@localhost".to_vec()),
+            Matcher::Exact(b"QUIT\r\n".to_vec()),
+        ])
+        .unwrap();
+}
+
+#[test]
+fn test_verify_sequence_lenient() {
+    let server = smtp_like_exchange(&[b"EHLO localhost\r\n", b"NOOP\r\n", b"QUIT\r\n"]);
+
+    // NOOP is ignored in lenient mode only
+    let matchers = [
+        Matcher::StartsWith(b"EHLO ".to_vec()),
+        Matcher::Exact(b"QUIT\r\n".to_vec()),
+    ];
+    server.verify_sequence_lenient(&matchers).unwrap();
+
+    let err = server.verify_sequence(&matchers).unwrap_err();
+    assert!(matches!(err, ServerMockerError::MessageSequenceMismatch(_)));
+    assert_eq!(
+        "Non fatal: Received messages don't match the expected sequence:
+  [0] b\"EHLO localhost\\r\\n\"
+- [1] expected exactly b\"QUIT\\r\\n\"
++ [1] received b\"NOOP\\r\\n\"
+    first difference at offset 0 (0x0)
+      offset    expected                           received
+    ! 00000000  51 55 49 54 0d 0a        QUIT..    4e 4f 4f 50 0d 0a        NOOP..
++ [2] unexpected b\"QUIT\\r\\n\"
+",
+        err.to_string()
+    );
+}
+
+#[test]
+fn test_verify_sequence_missing_message() {
+    let server = smtp_like_exchange(&[b"EHLO localhost\r\n"]);
+
+    let err = server
+        .verify_sequence_lenient(&[
+            Matcher::Any,
+            Matcher::Custom(|message| message.ends_with(b"\r\n")),
+        ])
+        .unwrap_err();
+    assert_eq!(
+        "Non fatal: Received messages don't match the expected sequence:
+  [0] b\"EHLO localhost\\r\\n\"
+- expected matching a custom predicate
++ no matching message received
+",
+        err.to_string()
+    );
+}
+
+#[test]
+fn test_verify_sequence_hex_diff() {
+    let server = smtp_like_exchange(&[b"MAIL FROM:<alice@localhost>\r\n"]);
+
+    let err = server
+        .verify_sequence(&[Matcher::StartsWith(b"MAIL FROM:<bob@".to_vec())])
+        .unwrap_err();
+    // Context around the first difference, the lines that differ being marked
+    assert!(err.to_string().ends_with(
+        "    first difference at offset 11 (0xb)
+      offset    expected                           received
+      00000000  4d 41 49 4c 20 46 52 4f  MAIL FRO  4d 41 49 4c 20 46 52 4f  MAIL FRO
+    ! 00000008  4d 3a 3c 62 6f 62 40     M:<bob@   4d 3a 3c 61 6c 69 63     M:<alic
+"
+    ));
+}
+
+#[test]
+fn test_received_messages_history() {
+    let server = smtp_like_exchange(&[b"EHLO localhost\r\n", b"NOOP\r\n", b"QUIT\r\n"]);
+
+    // Popped messages are kept in the history
+    assert_eq!(
+        b"EHLO localhost\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        vec![
+            b"EHLO localhost\r\n".to_vec(),
+            b"NOOP\r\n".to_vec(),
+            b"QUIT\r\n".to_vec(),
+        ],
+        server.received_messages()
+    );
+    assert_eq!(
+        b"NOOP\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+}
+
+#[test]
+fn test_peek_received_message() {
+    let server = smtp_like_exchange(&[b"EHLO localhost\r\n", b"QUIT\r\n"]);
+
+    // The client may have greeted the server either way
+    let greeting = server.peek_received_message().unwrap();
+    assert!(greeting.starts_with(b"EHLO ") || greeting.starts_with(b"HELO "));
+    assert_eq!(greeting, server.peek_received_message().unwrap());
+
+    assert_eq!(
+        b"EHLO localhost\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        b"QUIT\r\n",
+        server.peek_received_message().unwrap().as_slice()
+    );
+    assert_eq!(
+        b"QUIT\r\n",
+        server.pop_received_message().unwrap().as_slice()
+    );
+}
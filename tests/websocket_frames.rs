@@ -0,0 +1,127 @@
+//! Script a WebSocket server with raw frames, tested with tungstenite
+
+use socket_server_mocker::protocols::websocket::{
+    handshake_response, WebSocketFrame, CLOSE, PONG, TEXT,
+};
+use socket_server_mocker::Instruction::{
+    ReceiveMessage, ReceiveUntilDelimiter, SendMessage, SendMessageDependingOnLastReceivedMessage,
+    StopExchange,
+};
+use socket_server_mocker::{Instruction, ServerMocker, TcpMocker};
+use tungstenite::error::ProtocolError;
+use tungstenite::{Error, Message};
+
+/// Server accepting the opening handshake, then executing the given instructions
+fn websocket_server(instructions: Vec<Instruction>) -> ServerMocker<TcpMocker> {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(vec![
+            ReceiveUntilDelimiter(b"\r\n\r\n".to_vec()),
+            SendMessageDependingOnLastReceivedMessage(handshake_response),
+        ])
+        .unwrap();
+    server.add_mock_instructions(instructions).unwrap();
+    server
+}
+
+#[test]
+fn test_websocket_fragments_and_close() {
+    let server = websocket_server(vec![
+        SendMessage(WebSocketFrame::text("Hello ").fin(false).encode()),
+        SendMessage(WebSocketFrame::continuation(b"world".to_vec()).encode()),
+        SendMessage(WebSocketFrame::ping(b"beat".to_vec()).encode()),
+        ReceiveMessage,
+        ReceiveMessage,
+        // Reserved for internal use, never sent on the wire
+        SendMessage(WebSocketFrame::close(1005, "").encode()),
+        ReceiveMessage,
+        StopExchange,
+    ]);
+    let (mut socket, response) =
+        tungstenite::connect(format!("ws://{}/chat", server.socket_address())).unwrap();
+    assert_eq!(101, response.status().as_u16());
+
+    assert_eq!(Message::Text("Hello world".into()), socket.read().unwrap());
+    assert_eq!(Message::Ping(b"beat".to_vec()), socket.read().unwrap());
+    // Send the pong
+    socket.flush().unwrap();
+    socket.send(Message::Text("hi".into())).unwrap();
+    assert!(matches!(socket.read().unwrap(), Message::Close(_)));
+    // Send the close reply
+    let _ = socket.read();
+
+    // Skip the handshake request
+    server.pop_received_message().unwrap();
+    let mut data = Vec::new();
+    while let Some(message) = server.pop_received_message() {
+        data.extend(message);
+    }
+    let mut frames = Vec::new();
+    let mut rest = data.as_slice();
+    while let Some((frame, size)) = WebSocketFrame::decode(rest) {
+        frames.push(frame);
+        rest = &rest[size..];
+    }
+    assert!(rest.is_empty());
+    assert_eq!(3, frames.len());
+    assert!(frames.iter().all(|frame| frame.fin && frame.mask.is_some()));
+    assert_eq!(
+        (PONG, b"beat".as_slice()),
+        (frames[0].opcode, frames[0].payload.as_slice())
+    );
+    assert_eq!(
+        (TEXT, b"hi".as_slice()),
+        (frames[1].opcode, frames[1].payload.as_slice())
+    );
+    // Protocol error
+    assert_eq!(CLOSE, frames[2].opcode);
+    assert_eq!(Some(1002), frames[2].close_code());
+}
+
+#[test]
+fn test_websocket_invalid_frames() {
+    let invalid_frames = [
+        (
+            WebSocketFrame::text("masked").masked([1, 2, 3, 4]),
+            ProtocolError::MaskedFrameFromServer,
+        ),
+        (
+            WebSocketFrame::binary(vec![0; 8]).rsv(0b100),
+            ProtocolError::NonZeroReservedBits,
+        ),
+        (
+            WebSocketFrame::ping(vec![0; 126]),
+            ProtocolError::ControlFrameTooBig,
+        ),
+        (
+            WebSocketFrame::ping(Vec::new()).fin(false),
+            ProtocolError::FragmentedControlFrame,
+        ),
+    ];
+    for (frame, expected_error) in invalid_frames {
+        let server = websocket_server(vec![SendMessage(frame.encode())]);
+        let (mut socket, _) =
+            tungstenite::connect(format!("ws://{}/", server.socket_address())).unwrap();
+        match socket.read() {
+            Err(Error::Protocol(error)) => assert_eq!(expected_error, error),
+            other => panic!("Expected {expected_error}, got {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_websocket_frame_encoding() {
+    let payload = vec![0x42; 300];
+    let encoded = WebSocketFrame::binary(payload.clone()).encode();
+    assert_eq!([0x82, 126, 0x01, 0x2C], encoded[..4]);
+    assert_eq!(payload, encoded[4..]);
+
+    let frame = WebSocketFrame::text("x".repeat(70_000)).masked([9, 8, 7, 6]);
+    let encoded = frame.encode();
+    assert_eq!([0x81, 0xFF, 0, 0, 0, 0, 0, 1, 0x11, 0x70], encoded[..10]);
+    assert_eq!(
+        Some((frame, encoded.len())),
+        WebSocketFrame::decode(&encoded)
+    );
+    assert_eq!(None, WebSocketFrame::decode(&encoded[..encoded.len() - 1]));
+}
@@ -0,0 +1,74 @@
+//! Mock WHOIS servers answering a batch of lookups
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use socket_server_mocker::protocols::whois::WhoisMockBuilder;
+use socket_server_mocker::{ServerMocker, TcpMocker};
+
+/// Look up the given query, as a WHOIS client does
+fn whois(server: &ServerMocker<TcpMocker>, query: &str) -> String {
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+    client.write_all(format!("{query}\r\n").as_bytes()).unwrap();
+    let mut record = String::new();
+    client.read_to_string(&mut record).unwrap();
+    record
+}
+
+#[test]
+fn test_whois_batch() {
+    let whois_mock = WhoisMockBuilder::new()
+        .record(
+            "example.com",
+            "Domain Name: EXAMPLE.COM\r\nRegistry Expiry Date: 2025-08-13T04:00:00Z\r\n",
+        )
+        .record("example.net", "Domain Name: EXAMPLE.NET\r\n");
+    let queries = whois_mock.queries();
+
+    let domains = ["EXAMPLE.com", "example.net", "unregistered.org"];
+    let servers: Vec<_> = domains
+        .iter()
+        .map(|_| {
+            let server = ServerMocker::tcp().unwrap();
+            server
+                .add_mock_instructions(whois_mock.clone().build())
+                .unwrap();
+            server
+        })
+        .collect();
+    let records: Vec<String> = servers
+        .iter()
+        .zip(domains)
+        .map(|(server, domain)| whois(server, domain))
+        .collect();
+
+    assert_eq!(
+        vec![
+            "Domain Name: EXAMPLE.COM\r\nRegistry Expiry Date: 2025-08-13T04:00:00Z\r\n",
+            "Domain Name: EXAMPLE.NET\r\n",
+            "No match for \"unregistered.org\".\r\n",
+        ],
+        records
+    );
+    assert_eq!(domains.to_vec(), queries.queries());
+}
+
+#[test]
+fn test_whois_not_found() {
+    let server = ServerMocker::tcp().unwrap();
+    server
+        .add_mock_instructions(
+            WhoisMockBuilder::new()
+                .not_found("% No entries found for the selected source(s).\r\n")
+                .build(),
+        )
+        .unwrap();
+    assert_eq!(
+        "% No entries found for the selected source(s).\r\n",
+        whois(&server, "-T inetnum 192.0.2.1")
+    );
+    assert_eq!(
+        Some(b"-T inetnum 192.0.2.1\r\n".to_vec()),
+        server.pop_received_message()
+    );
+}
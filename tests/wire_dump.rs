@@ -0,0 +1,72 @@
+//! Hex dumps of the traffic logged with the `log` crate
+
+use std::net::UdpSocket;
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::{ServerMocker, UdpMocker};
+
+/// Logger keeping the records of the crate
+struct RecordCollector(Mutex<Vec<String>>);
+
+impl Log for RecordCollector {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target().starts_with("socket_server_mocker")
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) && record.level() == Level::Debug {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: RecordCollector = RecordCollector(Mutex::new(Vec::new()));
+
+#[test]
+fn test_wire_dump() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    // Not dumped by default
+    let quiet_server = ServerMocker::udp().unwrap();
+    let server = ServerMocker::new_with_opts(UdpMocker {
+        wire_dump: true,
+        ..UdpMocker::default()
+    })
+    .unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let client_addr = client.local_addr().unwrap();
+
+    for server in [&quiet_server, &server] {
+        server
+            .add_mock_instructions(vec![
+                ReceiveMessage,
+                SendMessage(b"\x00\x01pong".to_vec()),
+                StopExchange,
+            ])
+            .unwrap();
+        client.send_to(b"ping", server.socket_address()).unwrap();
+        let mut buffer = [0; 8];
+        client.recv(&mut buffer).unwrap();
+        assert!(server.pop_server_error().is_none());
+    }
+
+    assert_eq!(
+        vec![
+            format!(
+                "UDP {client_addr} client -> server, 4 bytes\n\
+                 00000000: 7069 6e67                                ping"
+            ),
+            format!(
+                "UDP {client_addr} server -> client, 6 bytes\n\
+                 00000000: 0001 706f 6e67                           ..pong"
+            ),
+        ],
+        *LOGGER.0.lock().unwrap()
+    );
+}
@@ -0,0 +1,45 @@
+//! Live stream of the traffic, without consuming the received messages
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::SystemTime;
+
+use socket_server_mocker::Direction::{Inbound, Outbound};
+use socket_server_mocker::Instruction::{ReceiveMessage, SendMessage, StopExchange};
+use socket_server_mocker::ServerMocker;
+
+#[test]
+fn test_tcp_wiretap() {
+    let start = SystemTime::now();
+    let server = ServerMocker::tcp().unwrap();
+    let wiretap = server.wiretap();
+    let other_wiretap = server.wiretap();
+    let mut client = TcpStream::connect(server.socket_address()).unwrap();
+
+    server
+        .add_mock_instructions(vec![
+            ReceiveMessage,
+            SendMessage(b"pong".to_vec()),
+            StopExchange,
+        ])
+        .unwrap();
+    client.write_all(b"ping").unwrap();
+    let mut buffer = [0; 4];
+    client.read_exact(&mut buffer).unwrap();
+
+    let events: Vec<_> = wiretap.iter().take(2).collect();
+    assert_eq!(
+        vec![(Inbound, b"ping".to_vec()), (Outbound, b"pong".to_vec())],
+        events
+            .iter()
+            .map(|event| (event.direction, event.data.clone()))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(client.local_addr().unwrap(), events[0].client_addr);
+    assert!(start <= events[0].timestamp && events[0].timestamp <= events[1].timestamp);
+
+    // Every wiretap receives every event, the messages are still queued
+    assert_eq!(events, other_wiretap.iter().take(2).collect::<Vec<_>>());
+    assert_eq!(b"ping", server.pop_received_message().unwrap().as_slice());
+    assert!(server.pop_server_error().is_none());
+}